@@ -1,6 +1,9 @@
 use anyhow::{bail, Context};
 use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
-use builder::permissioned::init_node;
+use builder::{
+    permissioned::init_node, persistence::BuilderPersistenceOptions,
+    private::run_private_mempool_api, strategy::load_strategy,
+};
 use clap::Parser;
 use cld::ClDuration;
 use es_version::SEQUENCER_VERSION;
@@ -12,7 +15,9 @@ use hotshot_types::signature_key::BLSPrivKey;
 use hotshot_types::traits::metrics::NoMetrics;
 use hotshot_types::traits::node_implementation::ConsensusTime;
 use sequencer::persistence::no_storage::NoStorage;
-use sequencer::{BuilderParams, L1Params, NetworkParams};
+use sequencer::{
+    block::ordering::OrderingOption, genesis::Genesis, BuilderParams, L1Params, NetworkParams,
+};
 use snafu::Snafu;
 use std::net::ToSocketAddrs;
 use std::num::NonZeroUsize;
@@ -136,11 +141,53 @@ pub struct PermissionedBuilderOptions {
     #[clap(short, long, env = "BUILDER_SERVER_PORT")]
     pub port: u16,
 
+    /// Port to run the private mempool API on.
+    ///
+    /// If given, the builder accepts transactions and bundles submitted directly to it on this
+    /// port, and includes them ahead of transactions from the public mempool.
+    #[clap(long, env = "ESPRESSO_BUILDER_PRIVATE_MEMPOOL_PORT")]
+    pub private_mempool_port: Option<u16>,
+
+    /// Path to a fee schedule for the builder.
+    ///
+    /// If given, the builder only includes transactions from namespaces in the schedule, and bids
+    /// the fees they pay, or the base fee if that is more. Otherwise it includes all transactions
+    /// and bids the base fee. The bid is charged for each block this node proposes, and is the
+    /// value it reports for each block.
+    #[clap(long, env = "ESPRESSO_BUILDER_FEE_SCHEDULE")]
+    pub fee_schedule: Option<PathBuf>,
+
+    /// Compress the namespaces of the blocks this builder builds, where that makes them smaller.
+    #[clap(long, env = "ESPRESSO_BUILDER_COMPRESS_NAMESPACES")]
+    pub compress_namespaces: bool,
+
+    /// How to order the transactions of the blocks this builder builds.
+    #[clap(
+        long,
+        env = "ESPRESSO_BUILDER_TX_ORDERING",
+        value_enum,
+        default_value = "namespace-id"
+    )]
+    pub tx_ordering: OrderingOption,
+
+    /// Path to the genesis file of the chain (TOML, or JSON with a `.json` extension).
+    ///
+    /// The builder takes the chain config, any scheduled upgrades and the genesis state from this
+    /// file, so it must be the genesis file the rest of the network uses.
+    #[clap(long, env = "ESPRESSO_SEQUENCER_GENESIS_FILE")]
+    pub genesis_file: PathBuf,
+
+    #[clap(flatten)]
+    pub persistence: BuilderPersistenceOptions,
+
     /// Port to run the builder server on.
     #[clap(short, long, env = "BUILDER_ADDRESS")]
     pub address: Address,
 
     /// Bootstrapping View number
+    ///
+    /// Ignored if the builder has persistent storage with a saved decided leaf, in which case it
+    /// resumes from the view of that leaf.
     #[clap(short, long, env = "BUILDER_BOOTSTRAPPED_VIEW")]
     pub view_number: u64,
 
@@ -205,6 +252,8 @@ async fn main() -> anyhow::Result<()> {
         mnemonic: opt.eth_mnemonic,
         prefunded_accounts: vec![],
         eth_account_index: opt.eth_account_index,
+        compress_namespaces: opt.compress_namespaces,
+        tx_ordering: opt.tx_ordering,
     };
 
     // get from the private key
@@ -254,11 +303,19 @@ async fn main() -> anyhow::Result<()> {
         private_staking_key,
         bootstrapped_view,
         opt.channel_capacity,
+        load_strategy(opt.fee_schedule.as_deref())?,
         sequencer_version,
         NoStorage,
+        opt.persistence.create().await?,
+        Genesis::from_file(&opt.genesis_file)?,
     )
     .await?;
 
+    if let Some(port) = opt.private_mempool_port {
+        let url = format!("http://0.0.0.0:{port}").parse()?;
+        run_private_mempool_api(url, ctx.private_mempool.clone())?;
+    }
+
     // Start doing consensus.
     ctx.start_consensus().await;
 
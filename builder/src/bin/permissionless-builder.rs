@@ -1,15 +1,26 @@
 use anyhow::{bail, Context};
 use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
-use builder::non_permissioned::{build_instance_state, BuilderConfig};
+use builder::{
+    non_permissioned::{build_instance_state, BuilderConfig},
+    persistence::BuilderPersistenceOptions,
+    private::run_private_mempool_api,
+    strategy::load_strategy,
+};
 use clap::Parser;
 use cld::ClDuration;
-use es_version::SEQUENCER_VERSION;
+use es_version::{SequencerVersion, SEQUENCER_VERSION};
+use ethers::types::Address;
 use hotshot::types::{BLSPubKey, SignatureKey};
 use hotshot_types::data::ViewNumber;
 use hotshot_types::light_client::StateSignKey;
 use hotshot_types::signature_key::BLSPrivKey;
 use hotshot_types::traits::node_implementation::ConsensusTime;
-use sequencer::{BuilderParams, L1Params};
+use sequencer::{
+    block::{ordering::OrderingOption, BuildContext},
+    catchup::StatePeers,
+    genesis::Genesis,
+    BuilderParams, L1Params,
+};
 use snafu::Snafu;
 use std::num::NonZeroUsize;
 use std::{collections::HashMap, path::PathBuf, str::FromStr, time::Duration};
@@ -95,15 +106,70 @@ pub struct NonPermissionedBuilderOptions {
     #[clap(long, env = "ESPRESSO_BUILDER_L1_PROVIDER")]
     pub l1_provider_url: Url,
 
+    /// Address of the fee contract on L1, from which deposits into the fee ledger are read.
+    ///
+    /// This must be the fee contract validators read deposits from, or the builder will not see
+    /// the deposits that fund its account.
+    #[clap(long, env = "ESPRESSO_BUILDER_FEE_CONTRACT_ADDRESS")]
+    pub fee_contract_address: Address,
+
+    /// Path to the genesis file of the chain.
+    ///
+    /// If given, the builder takes the chain config and any scheduled upgrades from this file.
+    /// Otherwise, it uses the chain config of the latest block, fetched from the state peers, and
+    /// will not follow chain config upgrades scheduled after it starts.
+    #[clap(long, env = "ESPRESSO_SEQUENCER_GENESIS_FILE")]
+    pub genesis_file: Option<PathBuf>,
+
     /// Peer nodes use to fetch missing state
-    #[clap(long, env = "ESPRESSO_SEQUENCER_STATE_PEERS", value_delimiter = ',')]
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_STATE_PEERS",
+        value_delimiter = ',',
+        required = true
+    )]
     pub state_peers: Vec<Url>,
 
     /// Port to run the builder server on.
     #[clap(short, long, env = "ESPRESSO_BUILDER_SERVER_PORT")]
     pub port: u16,
 
+    /// Port to run the private mempool API on.
+    ///
+    /// If given, the builder accepts transactions and bundles submitted directly to it on this
+    /// port, and includes them ahead of transactions from the public mempool.
+    #[clap(long, env = "ESPRESSO_BUILDER_PRIVATE_MEMPOOL_PORT")]
+    pub private_mempool_port: Option<u16>,
+
+    /// Path to a fee schedule for the builder.
+    ///
+    /// If given, the builder only includes transactions from namespaces in the schedule, and bids
+    /// the fees they pay, or the base fee if that is more. Otherwise it includes all transactions
+    /// and bids the base fee. The bid is the value it offers each block at, but the nodes which
+    /// propose its blocks set the fee they charge it, by default the base fee.
+    #[clap(long, env = "ESPRESSO_BUILDER_FEE_SCHEDULE")]
+    pub fee_schedule: Option<PathBuf>,
+
+    /// Compress the namespaces of the blocks this builder builds, where that makes them smaller.
+    #[clap(long, env = "ESPRESSO_BUILDER_COMPRESS_NAMESPACES")]
+    pub compress_namespaces: bool,
+
+    /// How to order the transactions of the blocks this builder builds.
+    #[clap(
+        long,
+        env = "ESPRESSO_BUILDER_TX_ORDERING",
+        value_enum,
+        default_value = "namespace-id"
+    )]
+    pub tx_ordering: OrderingOption,
+
+    #[clap(flatten)]
+    pub persistence: BuilderPersistenceOptions,
+
     /// Bootstrapping View number
+    ///
+    /// Ignored if the builder has persistent storage with a saved decided leaf, in which case it
+    /// resumes from the view of that leaf.
     #[clap(short, long, env = "ESPRESSO_BUILDER_BOOTSTRAPPED_VIEW")]
     pub view_number: u64,
 
@@ -167,6 +233,8 @@ async fn main() -> anyhow::Result<()> {
         mnemonic: opt.eth_mnemonic,
         prefunded_accounts: vec![],
         eth_account_index: opt.eth_account_index,
+        compress_namespaces: opt.compress_namespaces,
+        tx_ordering: opt.tx_ordering,
     };
 
     // get from the private key
@@ -176,15 +244,21 @@ async fn main() -> anyhow::Result<()> {
 
     let builder_server_url: Url = format!("http://0.0.0.0:{}", opt.port).parse().unwrap();
 
+    let genesis = opt.genesis_file.map(Genesis::from_file).transpose()?;
+
     let instance_state = build_instance_state(
+        genesis,
         l1_params,
         builder_params,
-        opt.state_peers,
+        opt.fee_contract_address,
+        opt.state_peers.clone(),
         sequencer_version,
     )
-    .unwrap();
+    .await?
+    .with_build_context(BuildContext::new(opt.compress_namespaces).with_ordering(opt.tx_ordering));
+    let builder_account = instance_state.builder_account();
 
-    let _builder_config = BuilderConfig::init(
+    let builder_config = BuilderConfig::init(
         builder_pub_key,
         private_staking_key,
         bootstrapped_view,
@@ -192,8 +266,19 @@ async fn main() -> anyhow::Result<()> {
         instance_state,
         opt.sequencer_url,
         builder_server_url,
+        load_strategy(opt.fee_schedule.as_deref())?,
+        opt.persistence.create().await?,
     )
-    .await;
+    .await?;
+    builder_config.metrics.watch_fee_balance(
+        StatePeers::<SequencerVersion>::from_urls(opt.state_peers),
+        builder_account,
+    );
+
+    if let Some(port) = opt.private_mempool_port {
+        let url = format!("http://0.0.0.0:{port}").parse()?;
+        run_private_mempool_api(url, builder_config.private_mempool)?;
+    }
 
     // Sleep forever
     async_std::future::pending::<()>().await;
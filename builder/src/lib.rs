@@ -30,7 +30,10 @@ use hotshot_types::{
     traits::metrics::Metrics,
     HotShotConfig, PeerConfig, ValidatorConfig,
 };
+use metrics::BuilderMetrics;
+use private::Bundles;
 use std::fmt::Display;
+use strategy::{BuilderStrategy, StrategyDataSource};
 // Should move `STAKE_TABLE_CAPACITY` in the sequencer repo when we have variate stake table support
 use hotshot_stake_table::config::STAKE_TABLE_CAPACITY;
 
@@ -65,14 +68,35 @@ use std::{net::Ipv4Addr, thread::Builder};
 use tide_disco::{app, method::ReadState, App, Url};
 use versioned_binary_serialization::version::StaticVersionType;
 
+pub mod metrics;
 pub mod non_permissioned;
 pub mod permissioned;
+pub mod persistence;
+pub mod private;
+pub mod strategy;
 
 // It runs the api service for the builder
-pub fn run_builder_api_service(url: Url, source: Arc<RwLock<GlobalState<SeqTypes>>>) {
+pub fn run_builder_api_service(
+    url: Url,
+    source: Arc<RwLock<GlobalState<SeqTypes>>>,
+    instance_state: NodeState,
+    strategy: Arc<dyn BuilderStrategy>,
+    bundles: Arc<Bundles>,
+    metrics: BuilderMetrics,
+) {
+    // transaction selection and the value of each block come from the strategy, and blocks which
+    // split a bundle are not offered
+    let source = Arc::new(RwLock::new(StrategyDataSource::new(
+        source,
+        instance_state,
+        strategy,
+        bundles,
+        metrics,
+    )));
+
     // it is to serve hotshot
     let builder_api = hotshot_builder_api::builder::define_api::<
-        Arc<RwLock<GlobalState<SeqTypes>>>,
+        Arc<RwLock<StrategyDataSource>>,
         SeqTypes,
         Version01,
     >(&HotshotBuilderApiOptions::default())
@@ -80,13 +104,13 @@ pub fn run_builder_api_service(url: Url, source: Arc<RwLock<GlobalState<SeqTypes
 
     // it enables external clients to submit txn to the builder's private mempool
     let private_mempool_api = hotshot_builder_api::builder::submit_api::<
-        Arc<RwLock<GlobalState<SeqTypes>>>,
+        Arc<RwLock<StrategyDataSource>>,
         SeqTypes,
         Version01,
     >(&HotshotBuilderApiOptions::default())
     .expect("Failed to construct the builder API for private mempool txns");
 
-    let mut app: App<Arc<RwLock<GlobalState<SeqTypes>>>, BuilderApiError, Version01> =
+    let mut app: App<Arc<RwLock<StrategyDataSource>>, BuilderApiError, Version01> =
         App::with_state(source);
 
     app.register_module("block_info", builder_api)
@@ -95,6 +119,12 @@ pub fn run_builder_api_service(url: Url, source: Arc<RwLock<GlobalState<SeqTypes
     app.register_module("txn_submit", private_mempool_api)
         .expect("Failed to register the private mempool API");
 
+    // it reports the builder's metrics and status to operators
+    let status_api =
+        metrics::define_api::<Version01>().expect("Failed to construct the builder status API");
+    app.register_module("status", status_api)
+        .expect("Failed to register the builder status API");
+
     async_spawn(app.serve(url, STATIC_VER_0_1));
 }
 
@@ -568,6 +598,8 @@ pub mod testing {
                 node_state,
                 hotshot_events_streaming_api_url,
                 hotshot_builder_api_url,
+                Arc::new(strategy::BaseFeeStrategy),
+                None,
             )
             .await
             .unwrap();
@@ -636,6 +668,8 @@ pub mod testing {
                 channel_capacity,
                 node_state,
                 hotshot_builder_api_url,
+                Arc::new(strategy::BaseFeeStrategy),
+                None,
             )
             .await
             .unwrap();
@@ -0,0 +1,561 @@
+//! Builder metrics and status.
+//!
+//! [`BuilderMetrics`] tracks what the builder has offered to HotShot and what it has won, the
+//! transactions it is holding, and the health of its connections. Everything is exported as
+//! Prometheus metrics and summarized by the `status` API, served alongside the builder API.
+//!
+//! The builder groups the blocks it offers into rounds, one per view. HotShot does not tell a
+//! builder which view a request is for, only the parent block the leader is extending, so the view
+//! is taken from the quorum proposal of that parent: the leader of the next view builds on it.
+//! Empty blocks all have the same commitment, so the parent alone does not identify a view.
+//!
+//! Fees are reported in wei by the status API, and in gwei by the Prometheus metrics, whose values
+//! must fit in a `usize`.
+
+use crate::strategy::StrategyDataSource;
+use async_broadcast::Receiver as BroadcastReceiver;
+use async_compatibility_layer::art::{async_sleep, async_spawn};
+use async_std::sync::{Arc, RwLock};
+use commit::{Commitment, Committable};
+use ethers::types::U256;
+use futures::FutureExt;
+use hotshot_builder_api::builder::Error as BuilderApiError;
+use hotshot_builder_core::builder_state::MessageType;
+use hotshot_query_service::metrics::PrometheusMetrics;
+use hotshot_types::{
+    data::ViewNumber,
+    event::LeafInfo,
+    traits::{
+        block_contents::BlockHeader,
+        metrics::{Counter, Gauge, Histogram, Metrics},
+        node_implementation::ConsensusTime,
+        BlockPayload,
+    },
+    utils::BuilderCommitment,
+    vid::VidCommitment,
+};
+use sequencer::{
+    block::NsTable, catchup::StatePeers, state::FeeAccount, NamespaceId, Payload, SeqTypes,
+    Transaction,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tide_disco::Api;
+use versioned_binary_serialization::version::StaticVersionType;
+
+/// Maximum number of distinct namespaces for which we create Prometheus metrics.
+///
+/// Namespace IDs are chosen by users, so we bound the cardinality of the exported metrics. Pending
+/// transactions in additional namespaces are still reported by the status API.
+const MAX_NAMESPACE_METRICS: usize = 100;
+
+/// Maximum number of pending transactions tracked for inclusion latency.
+///
+/// Transactions which are never included in one of our blocks are forgotten, oldest first, once
+/// this many newer transactions have arrived.
+const MAX_TRACKED_TRANSACTIONS: usize = 100_000;
+
+/// Number of recent rounds reported by the status API.
+const MAX_ROUNDS: usize = 100;
+
+/// How often to refresh the fee account balance.
+const FEE_BALANCE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The blocks offered in one round, and whether the leader took one of them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RoundStatus {
+    /// The view of the round, if the builder has seen the proposal of its parent.
+    pub view: Option<u64>,
+    /// The block the round's blocks were built on.
+    pub parent: VidCommitment,
+    /// The number of distinct blocks offered.
+    pub blocks_built: usize,
+    /// The fee offered for the block the leader claimed, if it claimed one of ours.
+    pub won: Option<u64>,
+}
+
+/// A summary of the builder's activity.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuilderStatus {
+    /// Total number of distinct blocks offered to leaders.
+    pub blocks_built: u64,
+    /// Total number of blocks claimed by leaders.
+    pub blocks_won: u64,
+    /// Total fees offered for claimed blocks.
+    pub fees_paid: U256,
+    /// The latest known balance of the builder's fee account.
+    pub fee_balance: Option<U256>,
+    /// Transactions received but not yet included in a claimed block, by namespace.
+    pub pending_transactions: BTreeMap<NamespaceId, usize>,
+    /// Whether the builder is subscribed to the HotShot event stream, if it uses one.
+    pub event_stream_connected: Option<bool>,
+    /// The most recent rounds, oldest first.
+    pub rounds: Vec<RoundStatus>,
+}
+
+#[derive(Debug)]
+struct Round {
+    view: Option<ViewNumber>,
+    parent: VidCommitment,
+    offered: HashMap<BuilderCommitment, u64>,
+    won: Option<u64>,
+}
+
+#[derive(Debug)]
+struct PendingTransaction {
+    namespace: NamespaceId,
+    received: Instant,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    status: BuilderStatus,
+    rounds: VecDeque<Round>,
+    /// The view whose leader builds on each recently proposed block.
+    next_views: HashMap<VidCommitment, ViewNumber>,
+    pending: HashMap<Commitment<Transaction>, PendingTransaction>,
+    arrivals: VecDeque<Commitment<Transaction>>,
+    namespace_metrics: HashMap<NamespaceId, Box<dyn Gauge>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    registry: PrometheusMetrics,
+    blocks_built: Box<dyn Counter>,
+    blocks_won: Box<dyn Counter>,
+    fees_paid: Box<dyn Counter>,
+    fee_balance: Box<dyn Gauge>,
+    pending_transactions: Box<dyn Gauge>,
+    inclusion_latency: Box<dyn Histogram>,
+    event_stream_connected: Box<dyn Gauge>,
+    namespaces: Box<dyn Metrics>,
+    state: Mutex<State>,
+}
+
+/// Builder metrics, shared by the tasks and APIs which update and report them.
+#[derive(Clone, Debug)]
+pub struct BuilderMetrics(Arc<Inner>);
+
+impl Default for BuilderMetrics {
+    fn default() -> Self {
+        Self::new(PrometheusMetrics::default())
+    }
+}
+
+impl BuilderMetrics {
+    /// Export metrics to `registry`.
+    pub fn new(registry: PrometheusMetrics) -> Self {
+        let metrics = registry.subgroup("builder".into());
+        Self(Arc::new(Inner {
+            blocks_built: metrics.create_counter("blocks_built".into(), None),
+            blocks_won: metrics.create_counter("blocks_won".into(), None),
+            fees_paid: metrics.create_counter("fees_paid".into(), Some("gwei".into())),
+            fee_balance: metrics.create_gauge("fee_balance".into(), Some("gwei".into())),
+            pending_transactions: metrics.create_gauge("pending_transactions".into(), None),
+            inclusion_latency: metrics
+                .create_histogram("inclusion_latency".into(), Some("s".into())),
+            event_stream_connected: metrics.create_gauge("event_stream_connected".into(), None),
+            namespaces: metrics.subgroup("namespace".into()),
+            state: Default::default(),
+            registry,
+        }))
+    }
+
+    /// The Prometheus registry the metrics are exported to.
+    pub fn registry(&self) -> &PrometheusMetrics {
+        &self.0.registry
+    }
+
+    /// A summary of the builder's activity.
+    pub fn status(&self) -> BuilderStatus {
+        let state = self.0.state.lock().unwrap();
+        BuilderStatus {
+            rounds: state
+                .rounds
+                .iter()
+                .map(|round| RoundStatus {
+                    view: round.view.map(|view| view.get_u64()),
+                    parent: round.parent,
+                    blocks_built: round.offered.len(),
+                    won: round.won,
+                })
+                .collect(),
+            ..state.status.clone()
+        }
+    }
+
+    /// Record a transaction received by the builder.
+    pub fn transaction_received(&self, tx: &Transaction) {
+        let mut state = self.0.state.lock().unwrap();
+        let hash = tx.commit();
+        if state.pending.contains_key(&hash) {
+            return;
+        }
+        state.pending.insert(
+            hash,
+            PendingTransaction {
+                namespace: tx.namespace(),
+                received: Instant::now(),
+            },
+        );
+        state.arrivals.push_back(hash);
+        self.update_pending(&mut state, tx.namespace(), 1);
+
+        while state.arrivals.len() > MAX_TRACKED_TRANSACTIONS {
+            let hash = state.arrivals.pop_front().unwrap();
+            if let Some(tx) = state.pending.remove(&hash) {
+                self.update_pending(&mut state, tx.namespace, -1);
+            }
+        }
+    }
+
+    /// Record the proposal of the block with commitment `payload_commitment` in `view`.
+    ///
+    /// The leader of the next view builds on this block.
+    pub fn block_proposed(&self, payload_commitment: VidCommitment, view: ViewNumber) {
+        let next_view = view + 1;
+        let mut state = self.0.state.lock().unwrap();
+        state.next_views.insert(payload_commitment, next_view);
+        // Forget proposals too old to be built on.
+        let oldest = next_view.get_u64().saturating_sub(MAX_ROUNDS as u64);
+        state.next_views.retain(|_, view| view.get_u64() >= oldest);
+    }
+
+    /// Record the blocks offered to a leader building on `parent`.
+    pub fn blocks_offered(
+        &self,
+        parent: VidCommitment,
+        blocks: impl IntoIterator<Item = (BuilderCommitment, u64)>,
+    ) {
+        let mut state = self.0.state.lock().unwrap();
+        let view = state.next_views.get(&parent).copied();
+        // A request may arrive before we see the proposal of its parent, in which case the view of
+        // the round is filled in by a later request.
+        let same_round = state.rounds.back().is_some_and(|round| {
+            round.parent == parent && (round.view.is_none() || round.view == view)
+        });
+        if same_round {
+            let round = state.rounds.back_mut().unwrap();
+            round.view = round.view.or(view);
+        } else {
+            state.rounds.push_back(Round {
+                view,
+                parent,
+                offered: Default::default(),
+                won: None,
+            });
+            if state.rounds.len() > MAX_ROUNDS {
+                state.rounds.pop_front();
+            }
+        }
+        let round = state.rounds.back_mut().unwrap();
+        let mut built = 0;
+        for (hash, fee) in blocks {
+            if round.offered.insert(hash, fee).is_none() {
+                built += 1;
+            }
+        }
+        state.status.blocks_built += built;
+        self.0.blocks_built.add(built as usize);
+    }
+
+    /// Record a block claimed by a leader.
+    pub fn block_claimed(&self, hash: &BuilderCommitment, payload: &Payload, ns_table: &NsTable) {
+        let mut state = self.0.state.lock().unwrap();
+        let Some(round) = state
+            .rounds
+            .iter_mut()
+            .rev()
+            .find(|round| round.offered.contains_key(hash))
+        else {
+            tracing::warn!("leader claimed block {hash:?} which we did not offer");
+            return;
+        };
+        if round.won.is_some() {
+            // The leader may claim the same block more than once, for example if it retries.
+            return;
+        }
+        let fee = round.offered[hash];
+        round.won = Some(fee);
+        state.status.blocks_won += 1;
+        // Count whole gwei in the metric, carrying the remainder over to later fees.
+        let gwei_before = state.status.fees_paid / U256::exp10(9);
+        state.status.fees_paid += U256::from(fee);
+        let gwei = state.status.fees_paid / U256::exp10(9) - gwei_before;
+        self.0.blocks_won.add(1);
+        self.0.fees_paid.add(gwei.as_usize());
+
+        let now = Instant::now();
+        for hash in payload.transaction_commitments(ns_table) {
+            if let Some(tx) = state.pending.remove(&hash) {
+                self.0
+                    .inclusion_latency
+                    .add_point((now - tx.received).as_secs_f64());
+                self.update_pending(&mut state, tx.namespace, -1);
+            }
+        }
+    }
+
+    /// Record that the transactions `hashes` were decided, in our blocks or anyone else's.
+    pub fn transactions_decided(&self, hashes: impl IntoIterator<Item = Commitment<Transaction>>) {
+        let mut state = self.0.state.lock().unwrap();
+        for hash in hashes {
+            if let Some(tx) = state.pending.remove(&hash) {
+                self.update_pending(&mut state, tx.namespace, -1);
+            }
+        }
+    }
+
+    /// Record the latest balance of the builder's fee account.
+    pub fn set_fee_balance(&self, balance: U256) {
+        let gwei = balance / U256::exp10(9);
+        self.0
+            .fee_balance
+            .set(gwei.min(usize::MAX.into()).as_usize());
+        self.0.state.lock().unwrap().status.fee_balance = Some(balance);
+    }
+
+    /// Record whether the builder is subscribed to the HotShot event stream.
+    pub fn set_event_stream_connected(&self, connected: bool) {
+        self.0.event_stream_connected.set(connected as usize);
+        self.0.state.lock().unwrap().status.event_stream_connected = Some(connected);
+    }
+
+    /// Record every transaction sent to the builder on `txs`.
+    pub fn track_transactions(&self, mut txs: BroadcastReceiver<MessageType<SeqTypes>>) {
+        let metrics = self.clone();
+        async_spawn(async move {
+            while let Ok(msg) = txs.recv().await {
+                if let MessageType::TransactionMessage(msg) = msg {
+                    metrics.transaction_received(&msg.tx);
+                }
+            }
+        });
+    }
+
+    /// Record the views of the blocks proposed on `qcs`, and the transactions decided on `decides`.
+    pub fn track_consensus(
+        &self,
+        mut qcs: BroadcastReceiver<MessageType<SeqTypes>>,
+        mut decides: BroadcastReceiver<MessageType<SeqTypes>>,
+    ) {
+        let metrics = self.clone();
+        async_spawn(async move {
+            while let Ok(msg) = qcs.recv().await {
+                if let MessageType::QCMessage(msg) = msg {
+                    let proposal = &msg.proposal.data;
+                    metrics.block_proposed(
+                        proposal.block_header.payload_commitment(),
+                        proposal.view_number,
+                    );
+                }
+            }
+        });
+        let metrics = self.clone();
+        async_spawn(async move {
+            while let Ok(msg) = decides.recv().await {
+                if let MessageType::DecideMessage(msg) = msg {
+                    for LeafInfo { leaf, .. } in msg.leaf_chain.iter() {
+                        let Some(payload) = leaf.get_block_payload() else {
+                            continue;
+                        };
+                        let ns_table = &leaf.get_block_header().ns_table;
+                        metrics.transactions_decided(payload.transaction_commitments(ns_table));
+                    }
+                }
+            }
+        });
+    }
+
+    /// Periodically refresh the balance of `account`, as reported by `peers`.
+    pub fn watch_fee_balance<Ver: StaticVersionType + 'static>(
+        &self,
+        peers: StatePeers<Ver>,
+        account: FeeAccount,
+    ) {
+        let metrics = self.clone();
+        async_spawn(async move {
+            loop {
+                match peers.fetch_latest_balance(account).await {
+                    Ok(balance) => metrics.set_fee_balance(balance),
+                    Err(err) => tracing::warn!("failed to fetch fee account balance: {err:#}"),
+                }
+                async_sleep(FEE_BALANCE_INTERVAL).await;
+            }
+        });
+    }
+
+    fn update_pending(&self, state: &mut State, namespace: NamespaceId, delta: i64) {
+        let count = state
+            .status
+            .pending_transactions
+            .entry(namespace)
+            .or_default();
+        *count = (*count as i64 + delta) as usize;
+        if *count == 0 {
+            state.status.pending_transactions.remove(&namespace);
+        }
+        self.0.pending_transactions.update(delta);
+
+        if !state.namespace_metrics.contains_key(&namespace) {
+            if state.namespace_metrics.len() >= MAX_NAMESPACE_METRICS {
+                tracing::debug!("not exporting metrics for namespace {namespace}, limit reached");
+                return;
+            }
+            let gauge = self
+                .0
+                .namespaces
+                .create_gauge(format!("{namespace}_pending_transactions"), None);
+            state.namespace_metrics.insert(namespace, gauge);
+        }
+        state.namespace_metrics[&namespace].update(delta);
+    }
+}
+
+/// Define the builder status API.
+pub fn define_api<Ver: StaticVersionType + 'static>(
+) -> anyhow::Result<Api<Arc<RwLock<StrategyDataSource>>, BuilderApiError, Ver>> {
+    let toml = toml::from_str::<toml::Value>(include_str!("../api/status.toml"))?;
+    let mut api = Api::<Arc<RwLock<StrategyDataSource>>, BuilderApiError, Ver>::new(toml)?;
+
+    api.get("status", |_, source| {
+        async move { Ok(source.metrics().status()) }.boxed()
+    })?
+    .metrics("metrics", |_, source| {
+        async move { Ok(Cow::Borrowed(source.metrics().registry())) }.boxed()
+    })?;
+
+    Ok(api)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hotshot_types::traits::block_contents::{vid_commitment, GENESIS_VID_NUM_STORAGE_NODES};
+    use tide_disco::metrics::Metrics as _;
+
+    fn block(txs: &[Transaction]) -> (Payload, NsTable, BuilderCommitment) {
+        let (payload, ns_table) = Payload::from_transactions(txs.to_vec()).unwrap();
+        let hash = payload.builder_commitment(&ns_table);
+        (payload, ns_table, hash)
+    }
+
+    #[test]
+    fn test_rounds() {
+        let metrics = BuilderMetrics::default();
+        let parent = vid_commitment(&vec![], GENESIS_VID_NUM_STORAGE_NODES);
+        let blocks = (0..4u64)
+            .map(|i| block(&[Transaction::new(i.into(), vec![i as u8])]))
+            .collect::<Vec<_>>();
+        let offer = |i: usize, fee: u64| (blocks[i].2.clone(), fee);
+        let claim = |i: usize| metrics.block_claimed(&blocks[i].2, &blocks[i].0, &blocks[i].1);
+
+        // Offering the same block twice counts it once.
+        metrics.blocks_offered(parent, [offer(0, 1), offer(1, 2)]);
+        metrics.blocks_offered(parent, [offer(1, 2), offer(2, 3)]);
+        claim(1);
+        claim(1);
+
+        // A claim for a block we never offered is ignored.
+        claim(3);
+
+        let status = metrics.status();
+        assert_eq!(status.blocks_built, 3);
+        assert_eq!(status.blocks_won, 1);
+        assert_eq!(status.fees_paid, 2.into());
+        assert_eq!(
+            status.rounds,
+            [RoundStatus {
+                view: None,
+                parent,
+                blocks_built: 3,
+                won: Some(2),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_rounds_per_view() {
+        let metrics = BuilderMetrics::default();
+        // Empty blocks all have this commitment, so consecutive views can have the same parent.
+        let parent = vid_commitment(&vec![], GENESIS_VID_NUM_STORAGE_NODES);
+        let (_, _, hash) = block(&[]);
+
+        // The view is filled in once we see the proposal of the parent.
+        metrics.blocks_offered(parent, [(hash.clone(), 0)]);
+        metrics.block_proposed(parent, ViewNumber::new(1));
+        metrics.blocks_offered(parent, [(hash.clone(), 0)]);
+        metrics.block_proposed(parent, ViewNumber::new(2));
+        metrics.blocks_offered(parent, [(hash.clone(), 0)]);
+
+        let views = metrics
+            .status()
+            .rounds
+            .into_iter()
+            .map(|round| round.view)
+            .collect::<Vec<_>>();
+        assert_eq!(views, [Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn test_fees_paid() {
+        let metrics = BuilderMetrics::default();
+        let parent = vid_commitment(&vec![], GENESIS_VID_NUM_STORAGE_NODES);
+        let blocks = (0..2u64)
+            .map(|i| block(&[Transaction::new(i.into(), vec![i as u8])]))
+            .collect::<Vec<_>>();
+
+        // Fees are exact in the status, and whole gwei in the metric.
+        let fee = 600_000_000;
+        for (payload, ns_table, hash) in &blocks {
+            metrics.blocks_offered(parent, [(hash.clone(), fee)]);
+            metrics.block_claimed(hash, payload, ns_table);
+        }
+        assert_eq!(metrics.status().fees_paid, (2 * fee).into());
+
+        let exported = metrics.registry().export().unwrap();
+        let fees_paid = exported
+            .lines()
+            .find(|line| line.starts_with("builder_fees_paid"))
+            .unwrap_or_else(|| panic!("fees_paid not exported: {exported}"));
+        assert!(fees_paid.ends_with(" 1"), "{fees_paid}");
+    }
+
+    #[test]
+    fn test_pending_transactions() {
+        let metrics = BuilderMetrics::default();
+        let txs = [
+            Transaction::new(1u64.into(), vec![0]),
+            Transaction::new(1u64.into(), vec![1]),
+            Transaction::new(2u64.into(), vec![2]),
+        ];
+        for tx in &txs {
+            metrics.transaction_received(tx);
+        }
+        // Receiving a transaction twice does not count it twice.
+        metrics.transaction_received(&txs[0]);
+        assert_eq!(
+            metrics.status().pending_transactions,
+            [(1u64.into(), 2), (2u64.into(), 1)].into()
+        );
+
+        // Transactions are no longer pending once included in a claimed block.
+        let (payload, ns_table, hash) = block(&txs[..2]);
+        let parent = vid_commitment(&vec![], GENESIS_VID_NUM_STORAGE_NODES);
+        metrics.blocks_offered(parent, [(hash.clone(), 0)]);
+        metrics.block_claimed(&hash, &payload, &ns_table);
+        assert_eq!(
+            metrics.status().pending_transactions,
+            [(2u64.into(), 1)].into()
+        );
+
+        // Transactions decided in blocks built by anyone are no longer pending either.
+        metrics.transactions_decided([txs[2].commit()]);
+        assert_eq!(metrics.status().pending_transactions, [].into());
+    }
+}
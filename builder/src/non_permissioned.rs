@@ -1,3 +1,4 @@
+use anyhow::ensure;
 use async_broadcast::{
     broadcast, Receiver as BroadcastReceiver, RecvError, Sender as BroadcastSender, TryRecvError,
 };
@@ -6,11 +7,13 @@ use async_compatibility_layer::{
     channel::{unbounded, UnboundedReceiver, UnboundedSender},
 };
 use async_std::sync::{Arc, RwLock};
+use commit::Committable;
 use ethers::{
     core::k256::ecdsa::SigningKey,
     signers::{coins_bip39::English, MnemonicBuilder, Signer as _, Wallet},
     types::{Address, U256},
 };
+use futures::FutureExt;
 use hotshot_builder_api::builder::{
     BuildError, Error as BuilderApiError, Options as HotshotBuilderApiOptions,
 };
@@ -22,14 +25,19 @@ use hotshot_builder_core::{
 use hotshot_types::{
     constants::{Version01, STATIC_VER_0_1},
     data::{fake_commitment, Leaf, ViewNumber},
+    event::LeafInfo,
     traits::{
         block_contents::{vid_commitment, GENESIS_VID_NUM_STORAGE_NODES},
         node_implementation::{ConsensusTime, NodeType},
     },
 };
 use sequencer::{
-    catchup::StatePeers, l1_client::L1Client, BuilderParams, ChainConfig, L1Params, NetworkParams,
-    NodeState, PrivKey, PubKey, SeqTypes,
+    block::{build_payloads_with, BuildContext},
+    catchup::{StateCatchup, StatePeers},
+    genesis::Genesis,
+    l1_client::L1Client,
+    BuilderParams, Header, HeaderUpgrade, L1Params, NetworkParams, NodeState, PrivKey, PubKey,
+    SeqTypes,
 };
 
 use hotshot_events_service::{
@@ -37,23 +45,48 @@ use hotshot_events_service::{
     events_source::{BuilderEvent, EventConsumer, EventsStreamer},
 };
 
-use crate::run_builder_api_service;
-use std::{num::NonZeroUsize, time::Duration};
+use crate::{
+    metrics::BuilderMetrics,
+    persistence::{BuilderPersistence, Resumed},
+    private::{merge_transactions, Bundles, PrivateMempool},
+    run_builder_api_service,
+    strategy::BuilderStrategy,
+};
+use std::{cmp::min, num::NonZeroUsize, panic::AssertUnwindSafe, time::Duration};
 use surf::http::headers::ACCEPT;
 use surf_disco::Client;
 use tide_disco::{app, method::ReadState, App, Url};
 use versioned_binary_serialization::version::StaticVersionType;
 
+/// Delay before the first attempt to reconnect to the HotShot event stream.
+const EVENT_STREAM_MIN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Maximum delay between attempts to reconnect to the HotShot event stream.
+const EVENT_STREAM_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
 #[derive(Clone, Debug)]
 pub struct BuilderConfig {
     pub global_state: Arc<RwLock<GlobalState<SeqTypes>>>,
     pub hotshot_events_api_url: Url,
     pub hotshot_builder_apis_url: Url,
+    pub private_mempool: PrivateMempool,
+    pub bundles: Arc<Bundles>,
+    pub metrics: BuilderMetrics,
 }
 
-pub fn build_instance_state<Ver: StaticVersionType + 'static>(
+/// Build the instance state of a permissionless builder.
+///
+/// The chain config, along with any scheduled chain config and header upgrades, is taken from
+/// `genesis` if given. Otherwise the builder follows the config of the chain's latest header,
+/// fetched from `state_peers`, and will not know of upgrades scheduled after it starts.
+///
+/// Either way, the builder refuses to start if its config does not match the chain's latest header,
+/// since validators would reject every block it builds.
+pub async fn build_instance_state<Ver: StaticVersionType + 'static>(
+    genesis: Option<Genesis>,
     l1_params: L1Params,
     builder_params: BuilderParams,
+    fee_contract_address: Address,
     state_peers: Vec<Url>,
     _: Ver,
 ) -> anyhow::Result<NodeState> {
@@ -65,17 +98,65 @@ pub fn build_instance_state<Ver: StaticVersionType + 'static>(
 
     tracing::info!("Builder account address {:?}", wallet.address());
 
-    let l1_client = L1Client::new(l1_params.url, Address::default());
-
-    let instance_state = NodeState::new(
-        ChainConfig::default(),
-        l1_client,
-        wallet,
-        Arc::new(StatePeers::<Ver>::from_urls(state_peers)),
+    ensure!(
+        !state_peers.is_empty(),
+        "at least one state peer is required to follow the chain"
     );
+
+    let l1_client = L1Client::new(l1_params.url, fee_contract_address);
+
+    let peers = StatePeers::<Ver>::from_urls(state_peers);
+    let latest = peers.fetch_latest_header().await?;
+    tracing::info!("Latest header is at height {}", latest.height);
+
+    let instance_state = match genesis {
+        Some(genesis) => {
+            peers.check_genesis(genesis.commit()).await?;
+            NodeState::new(genesis.chain_config, l1_client, wallet, Arc::new(peers))
+                .with_chain_config_upgrades(genesis.upgrades)
+                .with_header_upgrades(genesis.header_upgrades)
+                .with_genesis(genesis.validated_state())
+                .with_genesis_header(genesis.header())
+        }
+        None => {
+            let chain_config = match latest.chain_config.resolve() {
+                Some(chain_config) => chain_config,
+                None => peers.fetch_chain_config(latest.chain_config.commit()).await,
+            };
+            NodeState::new(chain_config, l1_client, wallet, Arc::new(peers)).with_header_upgrades([
+                HeaderUpgrade {
+                    height: latest.height,
+                    version: latest.version(),
+                },
+            ])
+        }
+    };
+    check_instance_state(&instance_state, &latest)?;
     Ok(instance_state)
 }
 
+/// Check that blocks built from `instance_state` would be valid at the height of `header`.
+pub fn check_instance_state(instance_state: &NodeState, header: &Header) -> anyhow::Result<()> {
+    let chain_config = instance_state.chain_config(header.height);
+    ensure!(
+        chain_config.commit() == header.chain_config.commit(),
+        "chain config {:?} ({}) does not match chain config {} of header {}",
+        chain_config,
+        chain_config.commit(),
+        header.chain_config.commit(),
+        header.height
+    );
+    let version = instance_state.header_version(header.height);
+    ensure!(
+        version == header.version(),
+        "header version {} does not match version {} of header {}",
+        version,
+        header.version(),
+        header.height
+    );
+    Ok(())
+}
+
 impl BuilderConfig {
     pub async fn init(
         pub_key: PubKey,
@@ -85,10 +166,27 @@ impl BuilderConfig {
         instance_state: NodeState,
         hotshot_events_api_url: Url,
         hotshot_builder_apis_url: Url,
+        strategy: Arc<dyn BuilderStrategy>,
+        persistence: Option<BuilderPersistence>,
     ) -> anyhow::Result<Self> {
+        // resume from where the builder left off before its last restart
+        let resumed = match persistence {
+            Some(persistence) => Some(persistence.resume().await?),
+            None => None,
+        };
+        let bootstrapped_view = resumed
+            .as_ref()
+            .and_then(Resumed::view)
+            .unwrap_or(bootstrapped_view);
+
         // tx channel
         let (tx_sender, tx_receiver) = broadcast::<MessageType<SeqTypes>>(channel_capacity.get());
 
+        // public tx channel, merged with the private mempool into the tx channel
+        let (public_tx_sender, public_tx_receiver) =
+            broadcast::<MessageType<SeqTypes>>(channel_capacity.get());
+        let (private_mempool, private_tx_receiver) = PrivateMempool::new(channel_capacity.get());
+
         // da channel
         let (da_sender, da_receiver) = broadcast::<MessageType<SeqTypes>>(channel_capacity.get());
 
@@ -135,57 +233,149 @@ impl BuilderConfig {
             bootstrapped_view,
         );
 
-        // create a client for it
-        // Start Client for the event streaming api
-        tracing::info!(
-            "Builder client connecting to hotshot events API at {}",
-            hotshot_events_api_url.to_string()
-        );
-        let client = Client::<EventStreamApiError, Version01>::new(hotshot_events_api_url.clone());
-
-        assert!(client.connect(Some(Duration::from_secs(60))).await);
-
-        tracing::info!("Builder client connected to the hotshot events api");
-
-        // client subscrive to hotshot events
-        let subscribed_events = client
-            .socket("hotshot-events/events")
-            .subscribe::<BuilderEvent<SeqTypes>>()
-            .await
-            .unwrap();
-
-        tracing::info!("Builder client subscribed to hotshot events");
+        // feed public and private transactions to the builder
+        let bundles = Arc::new(Bundles::default());
+        async_spawn(merge_transactions(
+            public_tx_receiver,
+            private_tx_receiver,
+            tx_sender.clone(),
+            strategy.clone(),
+            bundles.clone(),
+        ));
+
+        // build payloads with our settings, for the heights after the latest decided block
+        build_payloads_with(instance_state.clone());
+        async_spawn(track_decided_heights(
+            decide_sender.new_receiver(),
+            instance_state.build_context().clone(),
+            bundles.clone(),
+        ));
 
         // spawn the builder service
-        async_spawn(async move {
-            run_non_permissioned_standalone_builder_service(
-                tx_sender,
-                da_sender,
-                qc_sender,
-                decide_sender,
-                subscribed_events,
-                instance_state,
-            )
-            .await;
-        });
+        let metrics = BuilderMetrics::default();
+        metrics.track_transactions(tx_sender.new_receiver());
+        metrics.track_consensus(qc_sender.new_receiver(), decide_sender.new_receiver());
+        async_spawn(run_event_stream(
+            hotshot_events_api_url.clone(),
+            public_tx_sender,
+            da_sender,
+            qc_sender,
+            decide_sender,
+            instance_state.clone(),
+            metrics.clone(),
+        ));
 
         // spawn the builder event loop
         async_spawn(async move {
             builder_state.event_loop();
         });
 
+        // persist pending transactions, starting with those recovered from storage
+        if let Some(resumed) = resumed {
+            resumed.start(tx_sender.clone());
+        }
+
         // start the hotshot api service
-        run_builder_api_service(hotshot_builder_apis_url.clone(), global_state.clone());
+        // blocks are offered at our bid, but the nodes proposing them set the fee they are charged
+        run_builder_api_service(
+            hotshot_builder_apis_url.clone(),
+            global_state.clone(),
+            instance_state,
+            strategy,
+            bundles.clone(),
+            metrics.clone(),
+        );
 
         tracing::info!("Builder init finished");
         Ok(Self {
             global_state,
             hotshot_events_api_url,
             hotshot_builder_apis_url,
+            private_mempool,
+            bundles,
+            metrics,
         })
     }
 }
 
+/// Report each decided block to the payload build context `ctx` and to the builder's `bundles`.
+pub(crate) async fn track_decided_heights(
+    mut decides: BroadcastReceiver<MessageType<SeqTypes>>,
+    ctx: Arc<BuildContext>,
+    bundles: Arc<Bundles>,
+) {
+    while let Ok(msg) = decides.recv().await {
+        if let MessageType::DecideMessage(msg) = msg {
+            if let Some(LeafInfo { leaf, .. }) = msg.leaf_chain.first() {
+                let height = leaf.get_block_header().height;
+                ctx.record_decided(height);
+                bundles.record_decided(height);
+            }
+        }
+    }
+}
+
+/// Run the builder service on the HotShot event stream at `url`.
+///
+/// Whenever the builder cannot connect to the stream, or the stream ends, it reconnects with
+/// exponential backoff.
+async fn run_event_stream(
+    url: Url,
+    public_tx_sender: BroadcastSender<MessageType<SeqTypes>>,
+    da_sender: BroadcastSender<MessageType<SeqTypes>>,
+    qc_sender: BroadcastSender<MessageType<SeqTypes>>,
+    decide_sender: BroadcastSender<MessageType<SeqTypes>>,
+    instance_state: NodeState,
+    metrics: BuilderMetrics,
+) {
+    tracing::info!("Builder client connecting to hotshot events API at {url}");
+    let client = Client::<EventStreamApiError, Version01>::new(url);
+    metrics.set_event_stream_connected(false);
+
+    let mut delay = EVENT_STREAM_MIN_BACKOFF;
+    loop {
+        if client.connect(Some(Duration::from_secs(60))).await {
+            match client
+                .socket("hotshot-events/events")
+                .subscribe::<BuilderEvent<SeqTypes>>()
+                .await
+            {
+                Ok(subscribed_events) => {
+                    tracing::info!("Builder client subscribed to hotshot events");
+                    metrics.set_event_stream_connected(true);
+                    delay = EVENT_STREAM_MIN_BACKOFF;
+
+                    // The builder service panics on some stream errors. Catch the panic so that we
+                    // always reconnect.
+                    let res = AssertUnwindSafe(run_non_permissioned_standalone_builder_service(
+                        public_tx_sender.clone(),
+                        da_sender.clone(),
+                        qc_sender.clone(),
+                        decide_sender.clone(),
+                        subscribed_events,
+                        instance_state.clone(),
+                    ))
+                    .catch_unwind()
+                    .await;
+
+                    metrics.set_event_stream_connected(false);
+                    match res {
+                        Ok(()) => tracing::warn!("hotshot event stream ended"),
+                        Err(_) => tracing::error!("builder service panicked on hotshot events"),
+                    }
+                }
+                Err(err) => tracing::warn!("failed to subscribe to hotshot events: {err}"),
+            }
+        } else {
+            tracing::warn!("unable to connect to hotshot events API");
+        }
+
+        tracing::info!("reconnecting to hotshot events API in {delay:?}");
+        async_sleep(delay).await;
+        delay = min(delay * 2, EVENT_STREAM_MAX_BACKOFF);
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -212,6 +402,7 @@ mod test {
     use sequencer::persistence::no_storage::{self, NoStorage};
     use sequencer::persistence::PersistenceOptions;
     use sequencer::transaction::Transaction;
+    use sequencer::{ChainConfig, ChainConfigUpgrade, HeaderVersion};
     use std::time::Duration;
     use surf_disco::Client;
 
@@ -221,6 +412,45 @@ mod test {
         events::{Error as EventStreamApiError, Options as EventStreamingApiOptions},
         events_source::{BuilderEvent, EventConsumer, EventsStreamer},
     };
+    #[async_std::test]
+    async fn test_event_stream_reconnects() {
+        setup_logging();
+        setup_backtrace();
+
+        let (public_tx_sender, _public_tx_receiver) = broadcast(10);
+        let (da_sender, _da_receiver) = broadcast(10);
+        let (qc_sender, _qc_receiver) = broadcast(10);
+        let (decide_sender, _decide_receiver) = broadcast(10);
+        let metrics = BuilderMetrics::default();
+
+        // Start the builder before the events API is up.
+        let url = hotshot_builder_url();
+        async_spawn(run_event_stream(
+            url.clone(),
+            public_tx_sender,
+            da_sender,
+            qc_sender,
+            decide_sender,
+            NodeState::mock(),
+            metrics.clone(),
+        ));
+        async_sleep(Duration::from_secs(1)).await;
+        assert_eq!(metrics.status().event_stream_connected, Some(false));
+
+        // The builder connects once the events API comes up.
+        HotShotTestConfig::run_hotshot_event_streaming_api(
+            url,
+            Arc::new(RwLock::new(EventsStreamer::new(vec![], 0))),
+        );
+        async_std::future::timeout(Duration::from_secs(60), async {
+            while metrics.status().event_stream_connected != Some(true) {
+                async_sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .expect("builder did not connect to the events API");
+    }
+
     /// Test the non-permissioned builder core
     /// It creates a memory hotshot network and launches the hotshot event streaming api
     /// Builder subscrived to this api, and server the hotshot client request and the private mempool tx submission
@@ -381,4 +611,27 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_check_instance_state() {
+        let instance_state = NodeState::mock();
+        let header = Leaf::<SeqTypes>::genesis(&instance_state)
+            .get_block_header()
+            .clone();
+        check_instance_state(&instance_state, &header).unwrap();
+
+        // A builder with another chain config would build invalid blocks.
+        let other = NodeState::mock().with_chain_config_upgrades([ChainConfigUpgrade {
+            height: 0,
+            chain_config: ChainConfig::new(1, 10240, 0),
+        }]);
+        check_instance_state(&other, &header).unwrap_err();
+
+        // So would a builder using another header version.
+        let other = NodeState::mock().with_header_upgrades([HeaderUpgrade {
+            height: 0,
+            version: HeaderVersion::V2,
+        }]);
+        check_instance_state(&other, &header).unwrap_err();
+    }
 }
@@ -64,7 +64,11 @@ use jf_primitives::{
     signatures::bls_over_bn254::VerKey,
 };
 use sequencer::state_signature::StakeTableCommitmentType;
-use sequencer::{catchup::mock::MockStateCatchup, ChainConfig};
+use sequencer::{
+    block::{build_payloads_with, BuildContext},
+    catchup::mock::MockStateCatchup,
+    genesis::Genesis,
+};
 use sequencer::{
     catchup::StatePeers,
     context::{Consensus, SequencerContext},
@@ -72,7 +76,6 @@ use sequencer::{
     network,
     persistence::SequencerPersistence,
     state::FeeAccount,
-    state::ValidatedState,
     state_signature::{static_stake_table_commitment, StateSigner},
     BuilderParams, L1Params, NetworkParams, Node, NodeState, PrivKey, PubKey, SeqTypes,
 };
@@ -96,7 +99,14 @@ use hotshot_events_service::{
     events_source::{BuilderEvent, EventConsumer, EventsStreamer},
 };
 type ElectionConfig = StaticElectionConfig;
-use crate::run_builder_api_service;
+use crate::{
+    metrics::BuilderMetrics,
+    non_permissioned::track_decided_heights,
+    persistence::{BuilderPersistence, Resumed},
+    private::{merge_transactions, Bundles, PrivateMempool},
+    run_builder_api_service,
+    strategy::{BuilderStrategy, StrategyFee},
+};
 use std::{num::NonZeroUsize, time::Duration};
 use surf_disco::Client;
 
@@ -122,6 +132,15 @@ pub struct BuilderContext<
 
     /// hotshot builder api url
     pub hotshot_builder_api_url: Url,
+
+    /// private transaction submission
+    pub private_mempool: PrivateMempool,
+
+    /// private bundles, which the builder only offers in full
+    pub bundles: Arc<Bundles>,
+
+    /// builder metrics and status
+    pub metrics: BuilderMetrics,
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -135,8 +154,11 @@ pub async fn init_node<P: SequencerPersistence, Ver: StaticVersionType + 'static
     priv_key: PrivKey,
     bootstrapped_view: ViewNumber,
     channel_capacity: NonZeroUsize,
+    strategy: Arc<dyn BuilderStrategy>,
     bind_version: Ver,
     persistence: P,
+    builder_persistence: Option<BuilderPersistence>,
+    mut genesis: Genesis,
 ) -> anyhow::Result<BuilderContext<network::Production, P, Ver>> {
     // Orchestrator client
     let validator_args = ValidatorArgs {
@@ -178,6 +200,19 @@ pub async fn init_node<P: SequencerPersistence, Ver: StaticVersionType + 'static
 
     let node_index = config.node_index;
 
+    // Make sure we are joining the chain we think we are joining before doing anything else.
+    genesis.check_stake_table(&config.config.known_nodes_with_stake)?;
+    for address in &builder_params.prefunded_accounts {
+        tracing::warn!("Prefunding account {:?} for demo", address);
+        genesis
+            .accounts
+            .insert((*address).into(), U256::max_value().into());
+    }
+    let genesis_commitment = genesis.commit();
+    tracing::info!(%genesis_commitment, "starting from genesis");
+    let peers = StatePeers::<Ver>::from_urls(network_params.state_peers);
+    peers.check_genesis(genesis_commitment).await?;
+
     // Initialize the push CDN network (and perform the initial connection)
     let cdn_network = PushCdnNetwork::new(
         network_params.cdn_endpoint,
@@ -243,20 +278,24 @@ pub async fn init_node<P: SequencerPersistence, Ver: StaticVersionType + 'static
         .build()?;
     tracing::info!("Builder account address {:?}", wallet.address());
 
-    let mut genesis_state = ValidatedState::default();
-    for address in builder_params.prefunded_accounts {
-        tracing::warn!("Prefunding account {:?} for demo", address);
-        genesis_state.prefund_account(address.into(), U256::max_value().into());
-    }
-
     let l1_client = L1Client::new(l1_params.url, Address::default());
 
     let instance_state = NodeState::new(
-        ChainConfig::default(),
+        genesis.chain_config,
         l1_client,
         wallet,
-        Arc::new(StatePeers::<Ver>::from_urls(network_params.state_peers)),
+        Arc::new(peers.clone()),
+    )
+    .with_genesis(genesis.validated_state())
+    .with_genesis_header(genesis.header())
+    .with_chain_config_upgrades(genesis.upgrades)
+    .with_header_upgrades(genesis.header_upgrades)
+    .with_builder_fee(StrategyFee(strategy.clone()))
+    .with_build_context(
+        BuildContext::new(builder_params.compress_namespaces)
+            .with_ordering(builder_params.tx_ordering),
     );
+    let builder_account = instance_state.builder_account();
 
     let stake_table_commit =
         static_stake_table_commitment(&config.config.known_nodes_with_stake, STAKE_TABLE_CAPACITY);
@@ -285,8 +324,11 @@ pub async fn init_node<P: SequencerPersistence, Ver: StaticVersionType + 'static
         channel_capacity,
         instance_state,
         hotshot_builder_api_url,
+        strategy,
+        builder_persistence,
     )
     .await?;
+    ctx.metrics.watch_fee_balance(peers, builder_account);
 
     Ok(ctx)
 }
@@ -381,10 +423,27 @@ impl<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType + 'static
         channel_capacity: NonZeroUsize,
         instance_state: NodeState,
         hotshot_builder_api_url: Url,
+        strategy: Arc<dyn BuilderStrategy>,
+        persistence: Option<BuilderPersistence>,
     ) -> anyhow::Result<Self> {
+        // resume from where the builder left off before its last restart
+        let resumed = match persistence {
+            Some(persistence) => Some(persistence.resume().await?),
+            None => None,
+        };
+        let bootstrapped_view = resumed
+            .as_ref()
+            .and_then(Resumed::view)
+            .unwrap_or(bootstrapped_view);
+
         // tx channel
         let (tx_sender, tx_receiver) = broadcast::<MessageType<SeqTypes>>(channel_capacity.get());
 
+        // public tx channel, merged with the private mempool into the tx channel
+        let (public_tx_sender, public_tx_receiver) =
+            broadcast::<MessageType<SeqTypes>>(channel_capacity.get());
+        let (private_mempool, private_tx_receiver) = PrivateMempool::new(channel_capacity.get());
+
         // da channel
         let (da_sender, da_receiver) = broadcast::<MessageType<SeqTypes>>(channel_capacity.get());
 
@@ -395,6 +454,15 @@ impl<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType + 'static
         let (decide_sender, decide_receiver) =
             broadcast::<MessageType<SeqTypes>>(channel_capacity.get());
 
+        // build payloads with our settings, for the heights after the latest decided block
+        let bundles = Arc::new(Bundles::default());
+        build_payloads_with(instance_state.clone());
+        async_spawn(track_decided_heights(
+            decide_sender.new_receiver(),
+            instance_state.build_context().clone(),
+            bundles.clone(),
+        ));
+
         // builder api request channel
         let (req_sender, req_receiver) = broadcast::<MessageType<SeqTypes>>(channel_capacity.get());
 
@@ -432,15 +500,28 @@ impl<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType + 'static
         );
 
         let hotshot_handle_clone = hotshot_handle.clone();
+        // feed public and private transactions to the builder
+        async_spawn(merge_transactions(
+            public_tx_receiver,
+            private_tx_receiver,
+            tx_sender.clone(),
+            strategy.clone(),
+            bundles.clone(),
+        ));
+
         // spawn the builder service
+        let metrics = BuilderMetrics::default();
+        metrics.track_transactions(tx_sender.new_receiver());
+        metrics.track_consensus(qc_sender.new_receiver(), decide_sender.new_receiver());
+        let service_instance_state = instance_state.clone();
         async_spawn(async move {
             run_permissioned_standalone_builder_service(
-                tx_sender,
+                public_tx_sender,
                 da_sender,
                 qc_sender,
                 decide_sender,
                 hotshot_handle,
-                instance_state,
+                service_instance_state,
             )
             .await;
         });
@@ -450,7 +531,21 @@ impl<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType + 'static
             builder_state.event_loop();
         });
 
-        run_builder_api_service(hotshot_builder_api_url.clone(), global_state.clone());
+        // persist pending transactions, starting with those recovered from storage
+        if let Some(resumed) = resumed {
+            resumed.start(tx_sender.clone());
+        }
+
+        // blocks are offered at the bid of the strategy, which this node also charges for the
+        // blocks it proposes
+        run_builder_api_service(
+            hotshot_builder_api_url.clone(),
+            global_state.clone(),
+            instance_state,
+            strategy,
+            bundles.clone(),
+            metrics.clone(),
+        );
 
         let ctx = Self {
             hotshot_handle: hotshot_handle_clone,
@@ -459,6 +554,9 @@ impl<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType + 'static
             wait_for_orchestrator: None,
             global_state,
             hotshot_builder_api_url,
+            private_mempool,
+            bundles,
+            metrics,
         };
 
         Ok(ctx)
@@ -0,0 +1,439 @@
+//! Builder persistence.
+//!
+//! A builder keeps its pending transactions in memory, so without persistence a restart loses every
+//! transaction it has not yet included in a block. [`BuilderPersistence`] saves each transaction
+//! the builder receives, along with the last decided leaf, to one of the sequencer's persistence
+//! backends which implement [`PendingTransactionPersistence`]. To learn which pending transactions
+//! have been decided, it follows the stream of decided blocks from a query service.
+//!
+//! On restart, [`BuilderPersistence::resume`] first catches up on the blocks decided while the
+//! builder was down, dropping their transactions from the pending pool. The builder then resumes
+//! from the view of the last decided leaf, and the remaining pending transactions are fed back to
+//! it.
+//!
+//! A transaction which is never decided, for example because it is invalid or the builder dropped
+//! it, would otherwise stay pending forever. Pending transactions therefore expire after a
+//! configurable time.
+
+use anyhow::{ensure, Context};
+use async_broadcast::{Receiver as BroadcastReceiver, Sender as BroadcastSender};
+use async_compatibility_layer::art::{async_sleep, async_spawn};
+use async_std::sync::{Arc, RwLock};
+use clap::Parser;
+use commit::Committable;
+use es_version::SequencerVersion;
+use futures::stream::{BoxStream, StreamExt};
+use hotshot_builder_core::builder_state::{MessageType, TransactionMessage, TransactionSource};
+use hotshot_query_service::{
+    availability::{BlockQueryData, LeafQueryData},
+    types::HeightIndexed,
+    Error as QueryServiceError,
+};
+use hotshot_types::data::ViewNumber;
+use sequencer::{
+    options::parse_duration,
+    persistence::{fs, sql, PendingTransactionPersistence, PersistenceOptions},
+    Leaf, SeqTypes, Transaction,
+};
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+use surf_disco::Client;
+use tide_disco::Url;
+
+type QueryServiceClient = Client<QueryServiceError, SequencerVersion>;
+
+/// Command line options for builder persistence.
+#[derive(Parser, Clone, Debug)]
+pub struct BuilderPersistenceOptions {
+    /// Storage path for the builder's pending transactions and last decided leaf.
+    #[clap(
+        long,
+        env = "ESPRESSO_BUILDER_STORAGE_PATH",
+        conflicts_with = "postgres_uri",
+        requires = "query_service_url"
+    )]
+    pub storage_path: Option<PathBuf>,
+
+    /// Postgres URI for storing the builder's pending transactions and last decided leaf.
+    #[clap(
+        long,
+        env = "ESPRESSO_BUILDER_POSTGRES_URI",
+        requires = "query_service_url"
+    )]
+    pub postgres_uri: Option<String>,
+
+    /// URL of a query service, used to learn which pending transactions have been decided.
+    #[clap(long, env = "ESPRESSO_BUILDER_QUERY_SERVICE_URL")]
+    pub query_service_url: Option<Url>,
+
+    /// How long to keep a pending transaction which has not been decided.
+    ///
+    /// Expired transactions are no longer fed back to the builder after a restart.
+    #[clap(
+        long,
+        env = "ESPRESSO_BUILDER_PENDING_TRANSACTION_TTL",
+        value_parser = parse_duration,
+        default_value = "1h"
+    )]
+    pub pending_transaction_ttl: Duration,
+}
+
+impl Default for BuilderPersistenceOptions {
+    fn default() -> Self {
+        Self::parse_from(std::iter::empty::<String>())
+    }
+}
+
+impl BuilderPersistenceOptions {
+    /// Connect to the configured storage, if any.
+    pub async fn create(self) -> anyhow::Result<Option<BuilderPersistence>> {
+        let Some(query_service_url) = self.query_service_url else {
+            return Ok(None);
+        };
+        let storage: Box<dyn PendingTransactionPersistence> =
+            match (self.storage_path, self.postgres_uri) {
+                (Some(path), _) => Box::new(fs::Options { path }.create().await?),
+                (None, Some(uri)) => Box::new(
+                    sql::Options {
+                        uri: Some(uri),
+                        ..Default::default()
+                    }
+                    .create()
+                    .await?,
+                ),
+                (None, None) => return Ok(None),
+            };
+        Ok(Some(BuilderPersistence::new(
+            storage,
+            query_service_url,
+            self.pending_transaction_ttl,
+        )))
+    }
+}
+
+/// Persistent storage for a builder's pending transactions.
+pub struct BuilderPersistence {
+    storage: Arc<RwLock<Box<dyn PendingTransactionPersistence>>>,
+    query: QueryServiceClient,
+    ttl: Duration,
+}
+
+impl BuilderPersistence {
+    /// Persist builder state in `storage`, following decided blocks from `query_service_url`.
+    ///
+    /// Pending transactions expire `ttl` after they are saved.
+    pub fn new(
+        storage: Box<dyn PendingTransactionPersistence>,
+        query_service_url: Url,
+        ttl: Duration,
+    ) -> Self {
+        Self {
+            storage: Arc::new(RwLock::new(storage)),
+            query: Client::new(query_service_url),
+            ttl,
+        }
+    }
+
+    /// Recover the builder state saved before the last restart.
+    ///
+    /// This catches up on all blocks decided since the saved leaf, so that the pending transactions
+    /// returned do not include any which were decided while the builder was down.
+    pub async fn resume(self) -> anyhow::Result<Resumed> {
+        ensure!(
+            self.query.connect(Some(Duration::from_secs(60))).await,
+            "unable to connect to query service"
+        );
+        let block_height: u64 = self
+            .query
+            .get("status/block-height")
+            .send()
+            .await
+            .context("fetching block height")?;
+
+        // Catch up on blocks decided since our last saved leaf. If there is no saved leaf, this
+        // storage is new, so there is nothing to catch up on and we start from the current height.
+        let mut leaf = self.storage.read().await.load_anchor_leaf().await?;
+        let from = match &leaf {
+            Some(leaf) => leaf.get_height() + 1,
+            None => block_height,
+        };
+        let mut decided = subscribe_decided(&self.query, from).await?;
+        for _ in from..block_height {
+            let (next_leaf, block) = decided
+                .next()
+                .await
+                .context("decided block stream ended")??;
+            record_decided(&mut **self.storage.write().await, &next_leaf, &block).await?;
+            leaf = Some(next_leaf.leaf().clone());
+        }
+
+        let mut storage = self.storage.write().await;
+        storage
+            .expire_pending_transactions(expiry(self.ttl))
+            .await?;
+        let pending = storage.load_pending_transactions().await?;
+        drop(storage);
+        tracing::info!(
+            height = leaf.as_ref().map(|leaf| leaf.get_height()),
+            pending = pending.len(),
+            "resuming builder"
+        );
+        Ok(Resumed {
+            persistence: self,
+            leaf,
+            pending,
+            decided,
+            height: from.max(block_height),
+        })
+    }
+}
+
+/// Builder state recovered by [`BuilderPersistence::resume`].
+pub struct Resumed {
+    persistence: BuilderPersistence,
+    leaf: Option<Leaf>,
+    pending: Vec<Transaction>,
+    decided: BoxStream<'static, anyhow::Result<Decided>>,
+    /// The height of the next block on `decided`.
+    height: u64,
+}
+
+impl Resumed {
+    /// The view of the last decided leaf, if the builder has seen one.
+    pub fn view(&self) -> Option<ViewNumber> {
+        self.leaf.as_ref().map(|leaf| leaf.get_view_number())
+    }
+
+    /// Start persisting the builder's state.
+    ///
+    /// The recovered pending transactions are fed back to the builder through `tx_sender`, and
+    /// every transaction sent on `tx_sender` from now on is saved until it is decided.
+    pub fn start(self, tx_sender: BroadcastSender<MessageType<SeqTypes>>) {
+        let BuilderPersistence {
+            storage,
+            query,
+            ttl,
+        } = self.persistence;
+        async_spawn(persist_transactions(
+            storage.clone(),
+            tx_sender.new_receiver(),
+        ));
+        async_spawn(follow_decided(
+            storage,
+            query,
+            self.decided,
+            self.height,
+            ttl,
+        ));
+        async_spawn(async move {
+            for tx in self.pending {
+                let msg = MessageType::TransactionMessage(TransactionMessage {
+                    tx,
+                    tx_type: TransactionSource::External,
+                });
+                if tx_sender.broadcast(msg).await.is_err() {
+                    tracing::warn!("builder transaction channel closed");
+                    return;
+                }
+            }
+        });
+    }
+}
+
+type Decided = (LeafQueryData<SeqTypes>, BlockQueryData<SeqTypes>);
+
+/// Subscribe to leaves and blocks decided from `height` on.
+async fn subscribe_decided(
+    client: &QueryServiceClient,
+    height: u64,
+) -> anyhow::Result<BoxStream<'static, anyhow::Result<Decided>>> {
+    let leaves = client
+        .socket(&format!("availability/stream/leaves/{height}"))
+        .subscribe::<LeafQueryData<SeqTypes>>()
+        .await
+        .context("subscribing to leaves")?;
+    let blocks = client
+        .socket(&format!("availability/stream/blocks/{height}"))
+        .subscribe::<BlockQueryData<SeqTypes>>()
+        .await
+        .context("subscribing to blocks")?;
+    Ok(leaves
+        .zip(blocks)
+        .map(|(leaf, block)| Ok((leaf?, block?)))
+        .boxed())
+}
+
+/// Drop the transactions in a decided block from the pending pool and save its leaf.
+async fn record_decided(
+    storage: &mut dyn PendingTransactionPersistence,
+    leaf: &LeafQueryData<SeqTypes>,
+    block: &BlockQueryData<SeqTypes>,
+) -> anyhow::Result<()> {
+    ensure!(
+        leaf.height() == block.height(),
+        "leaf {} does not match block {}",
+        leaf.height(),
+        block.height()
+    );
+    let decided = block
+        .enumerate()
+        .map(|(_, tx)| tx.commit())
+        .collect::<Vec<_>>();
+    storage.remove_pending_transactions(&decided).await?;
+    storage.save_anchor_leaf(leaf.leaf()).await
+}
+
+/// Record decided blocks as they are produced, starting with the block at `height`.
+///
+/// Pending transactions older than `ttl` are expired after each block. If the stream of decided
+/// blocks ends or fails, it is resubscribed from the next block.
+async fn follow_decided(
+    storage: Arc<RwLock<Box<dyn PendingTransactionPersistence>>>,
+    query: QueryServiceClient,
+    mut decided: BoxStream<'static, anyhow::Result<Decided>>,
+    mut height: u64,
+    ttl: Duration,
+) {
+    loop {
+        while let Some(next) = decided.next().await {
+            let (leaf, block) = match next {
+                Ok(next) => next,
+                Err(err) => {
+                    tracing::warn!("error in decided block stream: {err:#}");
+                    break;
+                }
+            };
+            height = leaf.height() + 1;
+            let mut storage = storage.write().await;
+            if let Err(err) = record_decided(&mut **storage, &leaf, &block).await {
+                tracing::error!("failed to record decided block: {err:#}");
+            }
+            if let Err(err) = storage.expire_pending_transactions(expiry(ttl)).await {
+                tracing::error!("failed to expire pending transactions: {err:#}");
+            }
+        }
+
+        tracing::warn!("decided block stream ended, resubscribing from height {height}");
+        decided = loop {
+            match subscribe_decided(&query, height).await {
+                Ok(decided) => break decided,
+                Err(err) => {
+                    tracing::warn!("failed to resubscribe to decided blocks: {err:#}");
+                    async_sleep(RESUBSCRIBE_INTERVAL).await;
+                }
+            }
+        };
+    }
+}
+
+/// How long to wait before retrying a failed subscription to decided blocks.
+const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The current time, in seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::UNIX_EPOCH
+        .elapsed()
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// The time before which pending transactions have expired, given they live for `ttl`.
+fn expiry(ttl: Duration) -> u64 {
+    now().saturating_sub(ttl.as_secs())
+}
+
+/// Save transactions sent to the builder.
+async fn persist_transactions(
+    storage: Arc<RwLock<Box<dyn PendingTransactionPersistence>>>,
+    mut txs: BroadcastReceiver<MessageType<SeqTypes>>,
+) {
+    while let Ok(msg) = txs.recv().await {
+        // Save whatever else is already queued in the same write.
+        let mut batch = vec![];
+        let mut next = Some(msg);
+        while let Some(msg) = next {
+            if let MessageType::TransactionMessage(msg) = msg {
+                batch.push(msg.tx);
+            }
+            next = txs.try_recv().ok();
+        }
+        if batch.is_empty() {
+            continue;
+        }
+        if let Err(err) = storage
+            .write()
+            .await
+            .append_pending_transactions(&batch, now())
+            .await
+        {
+            tracing::error!(
+                "failed to save {} pending transactions: {err:#}",
+                batch.len()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_broadcast::broadcast;
+    use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+    use tempfile::TempDir;
+
+    #[async_std::test]
+    async fn test_persist_transactions() {
+        setup_logging();
+        setup_backtrace();
+
+        let tmp = TempDir::new().unwrap();
+        let storage = fs::Options {
+            path: tmp.path().into(),
+        }
+        .create()
+        .await
+        .unwrap();
+        let storage: Arc<RwLock<Box<dyn PendingTransactionPersistence>>> =
+            Arc::new(RwLock::new(Box::new(storage)));
+
+        let (sender, receiver) = broadcast(10);
+        let task = async_spawn(persist_transactions(storage.clone(), receiver));
+
+        let txs = (0..3u64)
+            .map(|i| Transaction::new(i.into(), vec![i as u8]))
+            .collect::<Vec<_>>();
+        for tx in &txs {
+            sender
+                .broadcast(MessageType::TransactionMessage(TransactionMessage {
+                    tx: tx.clone(),
+                    tx_type: TransactionSource::External,
+                }))
+                .await
+                .unwrap();
+        }
+
+        // Closing the channel stops the task once it has saved everything.
+        drop(sender);
+        task.await;
+        assert_eq!(
+            storage
+                .read()
+                .await
+                .load_pending_transactions()
+                .await
+                .unwrap(),
+            txs
+        );
+
+        // Saved transactions are reloaded by a fresh connection.
+        let storage = fs::Options {
+            path: tmp.path().into(),
+        }
+        .create()
+        .await
+        .unwrap();
+        assert_eq!(storage.load_pending_transactions().await.unwrap(), txs);
+    }
+}
@@ -0,0 +1,544 @@
+//! Private transaction submission.
+//!
+//! Transactions submitted to the public mempool are gossiped to every node before they reach a
+//! builder, exposing them to front-running. The private mempool lets users hand transactions
+//! directly to this builder instead, either one at a time or as bundles: lists of transactions in
+//! a single namespace which must be included together and in the given order.
+//!
+//! The builder core consumes transactions from a single channel, in the order they arrive. We
+//! place private transactions ahead of public ones by interposing on that channel:
+//! [`merge_transactions`] forwards public transactions to the builder, but always forwards any
+//! pending private transactions first, and never interleaves a bundle with other transactions.
+//!
+//! The builder core may still split a bundle across candidate blocks, and the namespace limit may
+//! cut one short, so forwarded bundles are also recorded in the builder's [`Bundles`]. The builder
+//! does not offer or hand out any block which contains only part of a bundle.
+
+use crate::strategy::BuilderStrategy;
+use async_broadcast::{Receiver as BroadcastReceiver, Sender as BroadcastSender};
+use async_compatibility_layer::art::async_spawn;
+use async_std::{
+    channel::{bounded, Receiver, Sender, TrySendError},
+    sync::Arc,
+};
+use async_trait::async_trait;
+use commit::{Commitment, Committable};
+use futures::{future::BoxFuture, select_biased, FutureExt};
+use hotshot_builder_core::builder_state::{MessageType, TransactionMessage, TransactionSource};
+use hotshot_types::constants::{Version01, STATIC_VER_0_1};
+use sequencer::{SeqTypes, Transaction};
+use snafu::Snafu;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+use tide_disco::{error::ServerError, method::ReadState, Api, App, Error as _, StatusCode, Url};
+use versioned_binary_serialization::version::StaticVersionType;
+
+/// An error submitting private transactions.
+#[derive(Clone, Debug, Snafu)]
+pub enum SubmitError {
+    #[snafu(display("invalid bundle: {reason}"))]
+    InvalidBundle { reason: String },
+    /// Too many private submissions are waiting for the builder.
+    #[snafu(display("private mempool is full, try again later"))]
+    Full,
+    #[snafu(display("builder is no longer accepting private transactions"))]
+    Closed,
+}
+
+impl SubmitError {
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::InvalidBundle { .. } => StatusCode::BadRequest,
+            Self::Full => StatusCode::ServiceUnavailable,
+            Self::Closed => StatusCode::InternalServerError,
+        }
+    }
+}
+
+impl From<SubmitError> for ServerError {
+    fn from(err: SubmitError) -> Self {
+        Self::catch_all(err.status(), err.to_string())
+    }
+}
+
+/// Handle for submitting private transactions to the builder.
+#[derive(Clone, Debug)]
+pub struct PrivateMempool {
+    sender: Sender<Vec<Transaction>>,
+}
+
+impl PrivateMempool {
+    /// Create a private mempool, along with the stream of bundles submitted to it.
+    ///
+    /// At most `capacity` submissions wait for the builder at a time; further submissions fail
+    /// with [`SubmitError::Full`] until the builder catches up. The receiver should be passed to
+    /// [`merge_transactions`].
+    pub fn new(capacity: usize) -> (Self, Receiver<Vec<Transaction>>) {
+        let (sender, receiver) = bounded(capacity);
+        (Self { sender }, receiver)
+    }
+
+    /// Submit a single private transaction.
+    pub fn submit(&self, tx: Transaction) -> Result<Commitment<Transaction>, SubmitError> {
+        let hash = tx.commit();
+        self.send(vec![tx])?;
+        Ok(hash)
+    }
+
+    /// Submit a bundle of private transactions.
+    ///
+    /// The bundle must be non-empty and all of its transactions must belong to the same namespace.
+    pub fn submit_bundle(
+        &self,
+        bundle: Vec<Transaction>,
+    ) -> Result<Vec<Commitment<Transaction>>, SubmitError> {
+        validate_bundle(&bundle)?;
+        let hashes = bundle.iter().map(|tx| tx.commit()).collect();
+        self.send(bundle)?;
+        Ok(hashes)
+    }
+
+    fn send(&self, bundle: Vec<Transaction>) -> Result<(), SubmitError> {
+        self.sender.try_send(bundle).map_err(|err| match err {
+            TrySendError::Full(_) => SubmitError::Full,
+            TrySendError::Closed(_) => SubmitError::Closed,
+        })
+    }
+}
+
+// Submissions only need a shared reference to the mempool, so the API serves it without a lock.
+#[async_trait]
+impl ReadState for PrivateMempool {
+    type State = Self;
+
+    async fn read<T>(
+        &self,
+        op: impl Send + for<'a> FnOnce(&'a Self::State) -> BoxFuture<'a, T> + 'async_trait,
+    ) -> T {
+        op(self).await
+    }
+}
+
+/// The number of blocks decided after a bundle is recorded before it is forgotten.
+///
+/// A bundle which has not been included by then most likely never will be, since the builder only
+/// builds on recent transactions.
+pub const BUNDLE_TTL: u64 = 100;
+
+/// The bundles this builder has forwarded, which it must only offer all together or not at all.
+#[derive(Debug, Default)]
+pub struct Bundles {
+    inner: Mutex<BundlesInner>,
+}
+
+#[derive(Debug, Default)]
+struct BundlesInner {
+    /// The bundle each bundled transaction belongs to, with the value of `next_height` when the
+    /// bundle was recorded.
+    bundles: HashMap<Commitment<Transaction>, (Arc<[Commitment<Transaction>]>, u64)>,
+    /// One more than the height of the latest decided block.
+    next_height: u64,
+}
+
+impl Bundles {
+    /// Record that the transactions of `txs` must be included all together or not at all.
+    ///
+    /// The bundle is forgotten once [`BUNDLE_TTL`] more blocks have been decided.
+    pub fn add(&self, txs: &[Transaction]) {
+        if txs.len() < 2 {
+            return;
+        }
+        let bundle: Arc<[_]> = txs.iter().map(|tx| tx.commit()).collect();
+        let mut inner = self.inner.lock().unwrap();
+        let added = inner.next_height;
+        for tx in bundle.iter() {
+            inner.bundles.insert(*tx, (bundle.clone(), added));
+        }
+    }
+
+    /// Record that the block at `height` has been decided, forgetting expired bundles.
+    pub fn record_decided(&self, height: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_height = inner.next_height.max(height.saturating_add(1));
+        let next_height = inner.next_height;
+        inner
+            .bundles
+            .retain(|_, (_, added)| added.saturating_add(BUNDLE_TTL) > next_height);
+    }
+
+    /// Whether a block with transactions `txs` contains every bundle it contains part of.
+    pub fn complete(&self, txs: &[Commitment<Transaction>]) -> bool {
+        let inner = self.inner.lock().unwrap();
+        if inner.bundles.is_empty() {
+            return true;
+        }
+        let present: HashSet<_> = txs.iter().collect();
+        txs.iter().all(|tx| match inner.bundles.get(tx) {
+            Some((bundle, _)) => bundle.iter().all(|tx| present.contains(tx)),
+            None => true,
+        })
+    }
+}
+
+fn validate_bundle(bundle: &[Transaction]) -> Result<(), SubmitError> {
+    let Some(first) = bundle.first() else {
+        return Err(SubmitError::InvalidBundle {
+            reason: "bundle is empty".into(),
+        });
+    };
+    let ns = first.namespace();
+    for (i, tx) in bundle.iter().enumerate() {
+        if tx.namespace() != ns {
+            return Err(SubmitError::InvalidBundle {
+                reason: format!(
+                    "transaction {i} is in namespace {}, expected {ns}",
+                    tx.namespace()
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Feed public and private transactions to the builder, private transactions first.
+///
+/// Transactions from `public` are forwarded to `builder` in order. Before each public transaction,
+/// all bundles pending in `private` are forwarded, each one back to back. Transactions `strategy`
+/// does not include are dropped; a bundle is dropped entirely unless all its transactions are
+/// included.
+///
+/// Each bundle is recorded in `bundles` before it is forwarded, so that the builder can refuse
+/// blocks which include only part of it. Returns when the public transaction stream ends or the
+/// builder stops listening.
+pub async fn merge_transactions(
+    mut public: BroadcastReceiver<MessageType<SeqTypes>>,
+    mut private: Receiver<Vec<Transaction>>,
+    builder: BroadcastSender<MessageType<SeqTypes>>,
+    strategy: Arc<dyn BuilderStrategy>,
+    bundles: Arc<Bundles>,
+) {
+    let mut private_open = true;
+    loop {
+        // Drain the private mempool before taking the next public transaction. If the private
+        // channel has closed, we find out below when waiting on it.
+        while private_open {
+            let Ok(bundle) = private.try_recv() else {
+                break;
+            };
+            if !forward_bundle(&builder, bundle, &*strategy, &bundles).await {
+                return;
+            }
+        }
+
+        let msg = if private_open {
+            select_biased! {
+                bundle = private.recv().fuse() => {
+                    match bundle {
+                        Ok(bundle) => {
+                            if !forward_bundle(&builder, bundle, &*strategy, &bundles).await {
+                                return;
+                            }
+                        }
+                        Err(_) => {
+                            tracing::warn!("private mempool closed");
+                            private_open = false;
+                        }
+                    }
+                    continue;
+                }
+                msg = public.recv().fuse() => msg,
+            }
+        } else {
+            public.recv().await
+        };
+
+        let Ok(msg) = msg else {
+            tracing::info!("public transaction stream ended");
+            return;
+        };
+        if let MessageType::TransactionMessage(msg) = &msg {
+            if !strategy.include(&msg.tx) {
+                tracing::debug!("strategy excludes transaction {}", msg.tx.commit());
+                continue;
+            }
+        }
+        if builder.broadcast(msg).await.is_err() {
+            tracing::warn!("builder stopped receiving transactions");
+            return;
+        }
+    }
+}
+
+async fn forward_bundle(
+    builder: &BroadcastSender<MessageType<SeqTypes>>,
+    bundle: Vec<Transaction>,
+    strategy: &dyn BuilderStrategy,
+    bundles: &Bundles,
+) -> bool {
+    if !bundle.iter().all(|tx| strategy.include(tx)) {
+        tracing::warn!(
+            "strategy excludes private bundle of {} transactions",
+            bundle.len()
+        );
+        return true;
+    }
+    bundles.add(&bundle);
+    tracing::debug!("forwarding private bundle of {} transactions", bundle.len());
+    for tx in bundle {
+        let msg = MessageType::TransactionMessage(TransactionMessage {
+            tx,
+            tx_type: TransactionSource::External,
+        });
+        if builder.broadcast(msg).await.is_err() {
+            tracing::warn!("builder stopped receiving transactions");
+            return false;
+        }
+    }
+    true
+}
+
+/// Define the private mempool API.
+pub fn define_api<Ver: StaticVersionType + 'static>(
+) -> anyhow::Result<Api<PrivateMempool, ServerError, Ver>> {
+    let toml = toml::from_str::<toml::Value>(include_str!("../api/private_mempool.toml"))?;
+    let mut api = Api::<PrivateMempool, ServerError, Ver>::new(toml)?;
+
+    // Both routes are POST, but only read the state, so they are registered with `at`.
+    api.at("submit", |req, mempool| {
+        async move {
+            let tx = req
+                .body_auto::<Transaction, Ver>(Ver::instance())
+                .map_err(ServerError::from_request_error)?;
+            Ok(mempool.submit(tx)?)
+        }
+        .boxed()
+    })?
+    .at("bundle", |req, mempool| {
+        async move {
+            let bundle = req
+                .body_auto::<Vec<Transaction>, Ver>(Ver::instance())
+                .map_err(ServerError::from_request_error)?;
+            Ok(mempool.submit_bundle(bundle)?)
+        }
+        .boxed()
+    })?;
+
+    Ok(api)
+}
+
+/// Serve the private mempool API at `url`.
+pub fn run_private_mempool_api(url: Url, mempool: PrivateMempool) -> anyhow::Result<()> {
+    let api = define_api::<Version01>()?;
+    let mut app = App::<_, ServerError, Version01>::with_state(mempool);
+    app.register_module("private", api)?;
+    async_spawn(app.serve(url, STATIC_VER_0_1));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::strategy::{BaseFeeStrategy, FeeSchedule, FeeScheduleStrategy, NamespaceFee};
+    use async_broadcast::broadcast;
+    use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+    use sequencer::NamespaceId;
+    use surf_disco::Client;
+
+    fn tx(ns: u64, payload: u8) -> Transaction {
+        Transaction::new(NamespaceId::from(ns), vec![payload])
+    }
+
+    fn unwrap_tx(msg: MessageType<SeqTypes>) -> Transaction {
+        match msg {
+            MessageType::TransactionMessage(msg) => msg.tx,
+            _ => panic!("expected a transaction message"),
+        }
+    }
+
+    #[async_std::test]
+    async fn test_bundle_validation() {
+        setup_logging();
+        setup_backtrace();
+
+        let (mempool, _receiver) = PrivateMempool::new(1);
+        mempool.submit_bundle(vec![]).unwrap_err();
+        mempool.submit_bundle(vec![tx(1, 0), tx(2, 1)]).unwrap_err();
+
+        let bundle = vec![tx(1, 0), tx(1, 1)];
+        let hashes = mempool.submit_bundle(bundle.clone()).unwrap();
+        assert_eq!(
+            hashes,
+            bundle.iter().map(|tx| tx.commit()).collect::<Vec<_>>()
+        );
+
+        // The builder has not taken the bundle yet, so there is no room for another submission.
+        assert!(matches!(mempool.submit(tx(1, 2)), Err(SubmitError::Full)));
+    }
+
+    #[async_std::test]
+    async fn test_private_transactions_first() {
+        setup_logging();
+        setup_backtrace();
+
+        let (public_sender, public_receiver) = broadcast(10);
+        let (builder_sender, mut builder_receiver) = broadcast(10);
+        let (mempool, private_receiver) = PrivateMempool::new(10);
+
+        // Queue up public and private transactions before the merge task starts, so that both
+        // are pending at once.
+        let public = [tx(1, 0), tx(2, 1)];
+        for tx in &public {
+            public_sender
+                .broadcast(MessageType::TransactionMessage(TransactionMessage {
+                    tx: tx.clone(),
+                    tx_type: TransactionSource::External,
+                }))
+                .await
+                .unwrap();
+        }
+        let single = tx(3, 2);
+        let bundle = vec![tx(4, 3), tx(4, 4), tx(4, 5)];
+        mempool.submit(single.clone()).unwrap();
+        mempool.submit_bundle(bundle.clone()).unwrap();
+
+        async_spawn(merge_transactions(
+            public_receiver,
+            private_receiver,
+            builder_sender,
+            Arc::new(BaseFeeStrategy),
+            Default::default(),
+        ));
+
+        let mut received = vec![];
+        for _ in 0..6 {
+            received.push(unwrap_tx(builder_receiver.recv().await.unwrap()));
+        }
+        let expected = [vec![single], bundle, public.to_vec()].concat();
+        assert_eq!(received, expected);
+
+        // Once the public stream ends, so does the merged stream.
+        drop(public_sender);
+        builder_receiver.recv().await.unwrap_err();
+    }
+
+    #[async_std::test]
+    async fn test_strategy_filters_transactions() {
+        setup_logging();
+        setup_backtrace();
+
+        let (public_sender, public_receiver) = broadcast(10);
+        let (builder_sender, mut builder_receiver) = broadcast(10);
+        let (mempool, private_receiver) = PrivateMempool::new(10);
+
+        // Only serve namespace 1.
+        let strategy = FeeScheduleStrategy::new(FeeSchedule {
+            default_fee: None,
+            namespaces: vec![NamespaceFee {
+                namespace: 1u64.into(),
+                fee: 1u64.into(),
+            }],
+        });
+
+        for tx in [tx(2, 0), tx(1, 1)] {
+            public_sender
+                .broadcast(MessageType::TransactionMessage(TransactionMessage {
+                    tx,
+                    tx_type: TransactionSource::External,
+                }))
+                .await
+                .unwrap();
+        }
+        // A bundle is only included if all of its transactions are.
+        mempool.submit_bundle(vec![tx(2, 2), tx(2, 3)]).unwrap();
+        mempool.submit(tx(1, 4)).unwrap();
+
+        async_spawn(merge_transactions(
+            public_receiver,
+            private_receiver,
+            builder_sender,
+            Arc::new(strategy),
+            Default::default(),
+        ));
+
+        assert_eq!(unwrap_tx(builder_receiver.recv().await.unwrap()), tx(1, 4));
+        assert_eq!(unwrap_tx(builder_receiver.recv().await.unwrap()), tx(1, 1));
+        drop(public_sender);
+        builder_receiver.recv().await.unwrap_err();
+    }
+
+    #[test]
+    fn test_bundles() {
+        setup_logging();
+        setup_backtrace();
+
+        let bundles = Bundles::default();
+        let bundle = [tx(1, 0), tx(1, 1)].map(|tx| tx.commit());
+        let other = tx(2, 2).commit();
+        bundles.add(&[tx(1, 0), tx(1, 1)]);
+        // Single transactions need no bookkeeping.
+        bundles.add(&[tx(3, 3)]);
+
+        // A block may contain a bundle in full, or not at all, but not in part.
+        assert!(bundles.complete(&[bundle[0], bundle[1], other]));
+        assert!(bundles.complete(&[other, tx(3, 3).commit()]));
+        assert!(!bundles.complete(&[bundle[0], other]));
+        assert!(!bundles.complete(&[bundle[1]]));
+
+        // Once the bundle is forgotten, its transactions are offered like any other.
+        bundles.record_decided(BUNDLE_TTL - 2);
+        assert!(!bundles.complete(&[bundle[0], other]));
+        bundles.record_decided(BUNDLE_TTL - 1);
+        assert!(bundles.complete(&[bundle[0], other]));
+    }
+
+    #[async_std::test]
+    async fn test_private_mempool_api() {
+        setup_logging();
+        setup_backtrace();
+
+        let (mempool, receiver) = PrivateMempool::new(10);
+        let port = portpicker::pick_unused_port().unwrap();
+        let url: Url = format!("http://localhost:{port}").parse().unwrap();
+        run_private_mempool_api(url.clone(), mempool).unwrap();
+
+        let client = Client::<ServerError, Version01>::new(url.join("private").unwrap());
+        assert!(client.connect(None).await);
+
+        let single = tx(1, 0);
+        let hash: Commitment<Transaction> = client
+            .post("submit")
+            .body_json(&single)
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(hash, single.commit());
+        assert_eq!(receiver.recv().await.unwrap(), vec![single]);
+
+        let bundle = vec![tx(2, 1), tx(2, 2)];
+        let hashes: Vec<Commitment<Transaction>> = client
+            .post("bundle")
+            .body_json(&bundle)
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            hashes,
+            bundle.iter().map(|tx| tx.commit()).collect::<Vec<_>>()
+        );
+        assert_eq!(receiver.recv().await.unwrap(), bundle);
+
+        // Bundles spanning namespaces are rejected without reaching the builder.
+        client
+            .post::<Vec<Commitment<Transaction>>>("bundle")
+            .body_json(&vec![tx(1, 0), tx(2, 0)])
+            .unwrap()
+            .send()
+            .await
+            .unwrap_err();
+        receiver.try_recv().unwrap_err();
+    }
+}
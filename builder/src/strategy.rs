@@ -0,0 +1,478 @@
+//! Pluggable block building strategies.
+//!
+//! A [`BuilderStrategy`] decides which transactions the builder includes in its block candidates
+//! and what fee it bids for the blocks it proposes. Operators can plug in their own strategy; the
+//! builder ships with two:
+//! * [`BaseFeeStrategy`], the default, includes every transaction and bids the base fee.
+//! * [`FeeScheduleStrategy`] includes only namespaces with an entry in an operator-configured
+//!   [`FeeSchedule`], and bids the fees those namespaces pay, or the base fee if that is more.
+//!
+//! The builder offers each candidate to HotShot at its bid. The fee in the header of a block is set
+//! by the node which proposes it, though: a permissioned builder charges itself its bid for the
+//! blocks it proposes, while a node proposing a block from a permissionless builder charges the
+//! fee of its own instance state, by default the base fee.
+
+use crate::{metrics::BuilderMetrics, private::Bundles};
+use anyhow::Context;
+use async_std::sync::{Arc, RwLock};
+use async_trait::async_trait;
+use hotshot_builder_api::{
+    block_info::{AvailableBlockData, AvailableBlockHeaderInput, AvailableBlockInfo},
+    builder::BuildError,
+    data_source::{AcceptsTxnSubmits, BuilderDataSource},
+};
+use hotshot_builder_core::service::GlobalState;
+use hotshot_types::{
+    traits::{node_implementation::NodeType, signature_key::SignatureKey, BlockPayload},
+    utils::BuilderCommitment,
+    vid::VidCommitment,
+};
+use sequencer::{
+    block::NsTable,
+    state::{BaseFee, BuilderFee, FeeAmount},
+    ChainConfig, NamespaceId, NodeState, Payload, SeqTypes, Transaction,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Debug, path::Path};
+
+/// Decides what the builder builds and what it bids.
+pub trait BuilderStrategy: Send + Sync + Debug {
+    /// Whether to consider `tx` for inclusion in block candidates.
+    fn include(&self, tx: &Transaction) -> bool;
+
+    /// The fee to bid for a block with namespace table `ns_table`.
+    ///
+    /// This is the value at which the builder offers the block. It must be at least the base fee,
+    /// which validators require of every block.
+    fn bid(&self, chain_config: &ChainConfig, ns_table: &NsTable) -> FeeAmount;
+}
+
+/// The default strategy: include every transaction and bid the base fee.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BaseFeeStrategy;
+
+impl BuilderStrategy for BaseFeeStrategy {
+    fn include(&self, _tx: &Transaction) -> bool {
+        true
+    }
+
+    fn bid(&self, chain_config: &ChainConfig, ns_table: &NsTable) -> FeeAmount {
+        BaseFee.builder_fee(chain_config, ns_table)
+    }
+}
+
+/// The fees rollups pay the builder for including their namespaces.
+///
+/// Each namespace pays a flat fee for every block it appears in. The schedule is read from a TOML
+/// file of the form
+///
+/// ```toml
+/// # Fee for namespaces without an entry. If omitted, those namespaces are not included.
+/// default_fee = "0x1"
+///
+/// [[namespace]]
+/// namespace = 1
+/// fee = "0x10"
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FeeSchedule {
+    /// The fee for namespaces without an entry in `namespaces`.
+    ///
+    /// If [`None`], transactions in those namespaces are not included.
+    #[serde(default)]
+    pub default_fee: Option<FeeAmount>,
+
+    /// Per-namespace fees.
+    #[serde(default, rename = "namespace")]
+    pub namespaces: Vec<NamespaceFee>,
+}
+
+/// The fee paid by a single namespace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct NamespaceFee {
+    pub namespace: NamespaceId,
+    pub fee: FeeAmount,
+}
+
+impl FeeSchedule {
+    pub fn from_toml(toml: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(toml)?)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let toml = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read fee schedule {}", path.display()))?;
+        Self::from_toml(&toml)
+    }
+
+    /// The fee paid by `namespace`, if it is served at all.
+    ///
+    /// If a namespace has more than one entry, the last one wins.
+    pub fn fee(&self, namespace: NamespaceId) -> Option<FeeAmount> {
+        self.namespaces
+            .iter()
+            .rev()
+            .find(|entry| entry.namespace == namespace)
+            .map(|entry| entry.fee)
+            .or(self.default_fee)
+    }
+
+    /// The total fee paid by the namespaces in a block.
+    pub fn block_fee(&self, ns_table: &NsTable) -> FeeAmount {
+        (0..ns_table.len())
+            .filter_map(|i| self.fee(ns_table.get_table_entry(i).0))
+            .fold(FeeAmount::default(), |total, fee| total + fee)
+    }
+}
+
+/// Include only namespaces in a [`FeeSchedule`], and bid everything they pay.
+///
+/// The bid never falls below the base fee, which validators require of every block.
+#[derive(Clone, Debug)]
+pub struct FeeScheduleStrategy {
+    schedule: FeeSchedule,
+}
+
+impl FeeScheduleStrategy {
+    pub fn new(schedule: FeeSchedule) -> Self {
+        Self { schedule }
+    }
+}
+
+impl BuilderStrategy for FeeScheduleStrategy {
+    fn include(&self, tx: &Transaction) -> bool {
+        self.schedule.fee(tx.namespace()).is_some()
+    }
+
+    fn bid(&self, chain_config: &ChainConfig, ns_table: &NsTable) -> FeeAmount {
+        self.schedule
+            .block_fee(ns_table)
+            .max(BaseFee.builder_fee(chain_config, ns_table))
+    }
+}
+
+/// Load the strategy configured by an optional fee schedule file.
+///
+/// Uses [`FeeScheduleStrategy`] if a schedule is given, and [`BaseFeeStrategy`] otherwise.
+pub fn load_strategy(fee_schedule: Option<&Path>) -> anyhow::Result<Arc<dyn BuilderStrategy>> {
+    Ok(match fee_schedule {
+        Some(path) => Arc::new(FeeScheduleStrategy::new(FeeSchedule::from_file(path)?)),
+        None => Arc::new(BaseFeeStrategy),
+    })
+}
+
+/// Adapts a [`BuilderStrategy`] to set the fee of the blocks a node proposes.
+#[derive(Clone, Debug)]
+pub struct StrategyFee(pub Arc<dyn BuilderStrategy>);
+
+impl BuilderFee for StrategyFee {
+    fn builder_fee(&self, chain_config: &ChainConfig, ns_table: &NsTable) -> FeeAmount {
+        self.0.bid(chain_config, ns_table)
+    }
+}
+
+type BlockSignature =
+    <<SeqTypes as NodeType>::SignatureKey as SignatureKey>::PureAssembledSignatureType;
+
+/// The value at which to offer a candidate block, or [`None`] if it must not be offered.
+///
+/// The block is offered at the bid of `strategy`, unless it contains only part of one of
+/// `bundles`.
+fn offered_fee(
+    strategy: &dyn BuilderStrategy,
+    bundles: &Bundles,
+    chain_config: &ChainConfig,
+    payload: &Payload,
+    ns_table: &NsTable,
+) -> Option<u64> {
+    if !bundles.complete(&payload.transaction_commitments(ns_table)) {
+        return None;
+    }
+    Some(saturating_u64(strategy.bid(chain_config, ns_table)))
+}
+
+/// The builder API data source, with transaction selection from a strategy.
+///
+/// Each block is offered at the bid of the strategy, in the chain config in force for new blocks of
+/// the builder's instance state. Blocks which contain only part of one of `bundles` are neither
+/// offered nor handed out. Blocks offered to and claimed by leaders are recorded in the builder's
+/// metrics.
+#[derive(Clone, Debug)]
+pub struct StrategyDataSource {
+    global_state: Arc<RwLock<GlobalState<SeqTypes>>>,
+    instance_state: NodeState,
+    strategy: Arc<dyn BuilderStrategy>,
+    bundles: Arc<Bundles>,
+    metrics: BuilderMetrics,
+}
+
+impl StrategyDataSource {
+    pub fn new(
+        global_state: Arc<RwLock<GlobalState<SeqTypes>>>,
+        instance_state: NodeState,
+        strategy: Arc<dyn BuilderStrategy>,
+        bundles: Arc<Bundles>,
+        metrics: BuilderMetrics,
+    ) -> Self {
+        Self {
+            global_state,
+            instance_state,
+            strategy,
+            bundles,
+            metrics,
+        }
+    }
+
+    pub fn metrics(&self) -> &BuilderMetrics {
+        &self.metrics
+    }
+}
+
+#[async_trait]
+impl BuilderDataSource<SeqTypes> for StrategyDataSource {
+    async fn get_available_blocks(
+        &self,
+        for_parent: &VidCommitment,
+    ) -> Result<Vec<AvailableBlockInfo<SeqTypes>>, BuildError> {
+        let global_state = self.global_state.read().await;
+        let mut blocks = global_state.get_available_blocks(for_parent).await?;
+        let chain_config = self.instance_state.next_chain_config();
+        blocks.retain_mut(|block| {
+            let Some((payload, ns_table, ..)) =
+                global_state.block_hash_to_block.get(&block.block_hash)
+            else {
+                return true;
+            };
+            match offered_fee(
+                &*self.strategy,
+                &self.bundles,
+                &chain_config,
+                payload,
+                ns_table,
+            ) {
+                Some(fee) => {
+                    block.offered_fee = fee;
+                    true
+                }
+                None => {
+                    tracing::warn!(
+                        "not offering block {:?} with a partial bundle",
+                        block.block_hash
+                    );
+                    false
+                }
+            }
+        });
+        self.metrics.blocks_offered(
+            *for_parent,
+            blocks
+                .iter()
+                .map(|block| (block.block_hash.clone(), block.offered_fee)),
+        );
+        Ok(blocks)
+    }
+
+    async fn claim_block(
+        &self,
+        block_hash: &BuilderCommitment,
+        signature: &BlockSignature,
+    ) -> Result<AvailableBlockData<SeqTypes>, BuildError> {
+        let block = self
+            .global_state
+            .read()
+            .await
+            .claim_block(block_hash, signature)
+            .await?;
+        if !self
+            .bundles
+            .complete(&block.block_payload.transaction_commitments(&block.metadata))
+        {
+            return Err(BuildError::Error {
+                message: format!("block {block_hash:?} contains only part of a bundle"),
+            });
+        }
+        self.metrics
+            .block_claimed(block_hash, &block.block_payload, &block.metadata);
+        Ok(block)
+    }
+
+    async fn claim_block_header_input(
+        &self,
+        block_hash: &BuilderCommitment,
+        signature: &BlockSignature,
+    ) -> Result<AvailableBlockHeaderInput<SeqTypes>, BuildError> {
+        self.global_state
+            .read()
+            .await
+            .claim_block_header_input(block_hash, signature)
+            .await
+    }
+
+    async fn get_builder_address(
+        &self,
+    ) -> Result<<SeqTypes as NodeType>::SignatureKey, BuildError> {
+        self.global_state.read().await.get_builder_address().await
+    }
+}
+
+#[async_trait]
+impl AcceptsTxnSubmits<SeqTypes> for StrategyDataSource {
+    async fn submit_txn(&mut self, txn: Transaction) -> Result<(), BuildError> {
+        if !self.strategy.include(&txn) {
+            return Err(BuildError::Error {
+                message: format!(
+                    "namespace {} is not served by this builder",
+                    txn.namespace()
+                ),
+            });
+        }
+        self.global_state.write().await.submit_txn(txn).await
+    }
+}
+
+fn saturating_u64(amount: FeeAmount) -> u64 {
+    let amount: ethers::types::U256 = amount.into();
+    if amount > u64::MAX.into() {
+        u64::MAX
+    } else {
+        amount.as_u64()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+
+    fn fee(amount: u64) -> FeeAmount {
+        amount.into()
+    }
+
+    fn tx(ns: u64) -> Transaction {
+        Transaction::new(NamespaceId::from(ns), vec![ns as u8])
+    }
+
+    fn ns_table(namespaces: &[u64]) -> NsTable {
+        Payload::from_txs(namespaces.iter().map(|ns| tx(*ns)), &ChainConfig::default())
+            .unwrap()
+            .get_ns_table()
+            .clone()
+    }
+
+    #[test]
+    fn test_fee_schedule() {
+        setup_logging();
+        setup_backtrace();
+
+        let schedule = FeeSchedule::from_toml(
+            r#"
+            [[namespace]]
+            namespace = 1
+            fee = "0x10"
+
+            [[namespace]]
+            namespace = 2
+            fee = "0x3"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(schedule.default_fee, None);
+        assert_eq!(schedule.fee(1u64.into()), Some(fee(16)));
+        assert_eq!(schedule.fee(2u64.into()), Some(fee(3)));
+        assert_eq!(schedule.fee(3u64.into()), None);
+        assert_eq!(schedule.block_fee(&ns_table(&[1, 2, 3])), fee(19));
+
+        let schedule = FeeSchedule {
+            default_fee: Some(fee(1)),
+            ..schedule
+        };
+        assert_eq!(schedule.fee(3u64.into()), Some(fee(1)));
+        assert_eq!(schedule.block_fee(&ns_table(&[1, 2, 3])), fee(20));
+        assert_eq!(schedule.block_fee(&ns_table(&[])), fee(0));
+    }
+
+    #[test]
+    fn test_strategies() {
+        setup_logging();
+        setup_backtrace();
+
+        let chain_config = ChainConfig::default();
+        let table = ns_table(&[1, 2]);
+
+        // The default strategy behaves like a builder without a strategy.
+        let strategy = BaseFeeStrategy;
+        assert!(strategy.include(&tx(1)));
+        assert!(strategy.include(&tx(3)));
+        assert_eq!(
+            strategy.bid(&chain_config, &table),
+            BaseFee.builder_fee(&chain_config, &table)
+        );
+
+        let strategy = FeeScheduleStrategy::new(FeeSchedule {
+            default_fee: None,
+            namespaces: vec![
+                NamespaceFee {
+                    namespace: 1u64.into(),
+                    fee: fee(5),
+                },
+                NamespaceFee {
+                    namespace: 2u64.into(),
+                    fee: fee(7),
+                },
+            ],
+        });
+        assert!(strategy.include(&tx(1)));
+        assert!(!strategy.include(&tx(3)));
+        assert_eq!(strategy.bid(&chain_config, &table), fee(12));
+
+        // Whatever the schedule, the bid covers the base fee.
+        let pricey = ChainConfig::new(1, 10240, 100);
+        assert!(pricey.block_fee(&table) > fee(12));
+        assert_eq!(strategy.bid(&pricey, &table), pricey.block_fee(&table));
+        assert_eq!(
+            StrategyFee(Arc::new(strategy)).builder_fee(&chain_config, &table),
+            fee(12)
+        );
+    }
+
+    #[test]
+    fn test_offered_fee() {
+        setup_logging();
+        setup_backtrace();
+
+        let chain_config = ChainConfig::new(1, 10240, 1u64);
+        let txs = [tx(1), tx(2)];
+        let payload = Payload::from_txs(txs, &chain_config).unwrap();
+        let table = payload.get_ns_table().clone();
+        let strategy = FeeScheduleStrategy::new(FeeSchedule {
+            default_fee: Some(fee(100)),
+            namespaces: vec![],
+        });
+        let bundles = Bundles::default();
+
+        // Blocks are offered at the bid, even when it exceeds the base fee.
+        assert!(chain_config.block_fee(&table) < fee(200));
+        assert_eq!(
+            offered_fee(&strategy, &bundles, &chain_config, &payload, &table),
+            Some(200)
+        );
+        assert_eq!(
+            offered_fee(&BaseFeeStrategy, &bundles, &chain_config, &payload, &table),
+            Some(saturating_u64(chain_config.block_fee(&table)))
+        );
+
+        // Blocks with part of a bundle are not offered at all.
+        bundles.add(&[tx(1), tx(3)]);
+        assert_eq!(
+            offered_fee(&strategy, &bundles, &chain_config, &payload, &table),
+            None
+        );
+    }
+
+    #[test]
+    fn test_saturating_u64() {
+        assert_eq!(saturating_u64(fee(12)), 12);
+        assert_eq!(saturating_u64(ethers::types::U256::MAX.into()), u64::MAX);
+    }
+}
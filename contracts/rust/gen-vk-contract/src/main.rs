@@ -1,32 +1,83 @@
 //! This executable generates the solidity files with hardcoded verifying keys for
 //! LightClient updates by running `cargo run -p gen-vk-contract --release`.
 //! Adapted from [CAPE project][https://github.com/EspressoSystems/cape/blob/main/contracts/rust/src/bin/gen-vk-libraries.rs]
+//!
+//! With `--check`, the verifying key of the LightClient deployed on L1, as returned by its
+//! `getVerifyingKey` getter, is compared against the verifying key instead of the contract being
+//! overwritten. Combined with `--key-dir`, this checks that the keys cached by
+//! the state prover match the deployed verifier.
 
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::path::PathBuf;
 use std::process::Command;
 
+use anyhow::{ensure, Context};
+use clap::Parser;
+use ethers::{
+    abi::{AbiDecode, AbiEncode},
+    providers::{Http, Middleware, Provider},
+    types::{Address, TransactionRequest},
+    utils::id,
+};
 use hotshot_contract_adapter::jellyfish::ParsedVerifyingKey;
 use hotshot_stake_table::config::STAKE_TABLE_CAPACITY;
 use jf_primitives::pcs::prelude::UnivariateUniversalParams;
+use url::Url;
 
-fn main() {
-    let srs = {
-        // load SRS from Aztec's ceremony
-        let srs = ark_srs::kzg10::aztec20::setup(2u64.pow(20) as usize + 2)
-            .expect("Aztec SRS fail to load");
-        // convert to Jellyfish type
-        // TODO: (alex) use constructor instead https://github.com/EspressoSystems/jellyfish/issues/440
-        UnivariateUniversalParams {
-            powers_of_g: srs.powers_of_g,
-            h: srs.h,
-            beta_h: srs.beta_h,
-            powers_of_h: vec![srs.h, srs.beta_h],
+#[derive(Parser)]
+struct Args {
+    /// Load the verifying key from the state prover key cache in this directory.
+    ///
+    /// If the directory holds no valid keys for the current circuit, they are generated and cached.
+    #[clap(long, env = "ESPRESSO_STATE_PROVER_KEY_DIR")]
+    key_dir: Option<PathBuf>,
+
+    /// Check that the LightClient contract deployed on L1 verifies proofs with this key, instead
+    /// of regenerating the verifying key contract.
+    #[clap(long, requires = "light_client_address")]
+    check: bool,
+
+    /// URL of layer 1 Ethereum JSON-RPC provider, used with `--check`.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_L1_PROVIDER",
+        default_value = "http://localhost:8545"
+    )]
+    l1_provider: Url,
+
+    /// Address of the LightClient contract (or its proxy) on layer 1, used with `--check`.
+    #[clap(long, env = "ESPRESSO_SEQUENCER_LIGHTCLIENT_ADDRESS")]
+    light_client_address: Option<Address>,
+}
+
+#[async_std::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let vk = match &args.key_dir {
+        Some(key_dir) => {
+            hotshot_state_prover::keys::load_or_generate_keys(Some(key_dir), STAKE_TABLE_CAPACITY)?
+                .1
+        }
+        None => {
+            let srs = {
+                // load SRS from Aztec's ceremony
+                let srs = ark_srs::kzg10::aztec20::setup(2u64.pow(20) as usize + 2)
+                    .expect("Aztec SRS fail to load");
+                // convert to Jellyfish type
+                // TODO: (alex) use constructor instead https://github.com/EspressoSystems/jellyfish/issues/440
+                UnivariateUniversalParams {
+                    powers_of_g: srs.powers_of_g,
+                    h: srs.h,
+                    beta_h: srs.beta_h,
+                    powers_of_h: vec![srs.h, srs.beta_h],
+                }
+            };
+            hotshot_state_prover::preprocess(&srs, STAKE_TABLE_CAPACITY)
+                .expect("Circuit preprocess failed")
+                .1
         }
     };
-    let (_, vk) = hotshot_state_prover::preprocess(&srs, STAKE_TABLE_CAPACITY)
-        .expect("Circuit preprocess failed");
     let vk: ParsedVerifyingKey = vk.into();
 
     // calculate the path to solidity file
@@ -40,6 +91,20 @@ fn main() {
     path.set_extension("sol");
     println!("Path:{:?}", path.to_str());
 
+    if args.check {
+        let address = args
+            .light_client_address
+            .context("checking the verifier requires the LightClient address")?;
+        let deployed = deployed_vk(&args.l1_provider, address).await?;
+        ensure!(
+            deployed.encode() == vk.clone().encode(),
+            "the LightClient deployed at {address:?} verifies proofs with a different verifying \
+             key: {deployed:?}"
+        );
+        println!("Verifying key matches the LightClient deployed at {address:?}");
+        return Ok(());
+    }
+
     // overwrite the file
     let mut file = OpenOptions::new()
         .write(true)
@@ -182,4 +247,23 @@ fn main() {
         .arg("sol-lint")
         .output()
         .expect("Failed to lint the contract code");
+    Ok(())
+}
+
+/// The verifying key of the LightClient at `address`, or of the implementation behind it if it is
+/// a proxy.
+///
+/// The getter is called by its selector, so that this works whether or not the generated bindings
+/// include it yet.
+async fn deployed_vk(l1_provider: &Url, address: Address) -> anyhow::Result<ParsedVerifyingKey> {
+    let provider = Provider::<Http>::try_from(l1_provider.to_string())?;
+    let call = TransactionRequest::new()
+        .to(address)
+        .data(id("getVerifyingKey()").to_vec());
+    // LightClients deployed before the getter was added revert here.
+    let output = provider
+        .call(&call.into(), None)
+        .await
+        .with_context(|| format!("calling getVerifyingKey on {address:?}"))?;
+    ParsedVerifyingKey::decode(output).context("decoding verifying key")
 }
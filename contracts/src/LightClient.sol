@@ -189,6 +189,33 @@ contract LightClient is Initializable, OwnableUpgradeable, UUPSUpgradeable {
         LightClientState memory newState,
         IPlonkVerifier.PlonkProof memory proof
     ) external {
+        _newFinalizedState(newState, proof);
+    }
+
+    /// @notice Update the latest finalized light client state several times in one transaction.
+    /// Each state is checked against the one before it, exactly as if `newFinalizedState` were
+    /// called with every state and its proof in turn, so a batch can span epochs as long as it
+    /// contains the last block of each epoch it passes.
+    /// @dev The whole batch reverts if any of the updates is invalid.
+    /// @param newStates new light client states, in the order they are finalized
+    /// @param proofs PlonkProofs, one for each state in `newStates`
+    function newFinalizedStates(
+        LightClientState[] memory newStates,
+        IPlonkVerifier.PlonkProof[] memory proofs
+    ) external {
+        if (newStates.length == 0 || newStates.length != proofs.length) {
+            revert InvalidArgs();
+        }
+        for (uint256 i = 0; i < newStates.length; i++) {
+            _newFinalizedState(newStates[i], proofs[i]);
+        }
+    }
+
+    /// @dev Verify and apply a single update of the finalized state.
+    function _newFinalizedState(
+        LightClientState memory newState,
+        IPlonkVerifier.PlonkProof memory proof
+    ) internal {
         if (
             newState.viewNum <= getFinalizedState().viewNum
                 || newState.blockHeight <= getFinalizedState().blockHeight
@@ -232,6 +259,12 @@ contract LightClient is Initializable, OwnableUpgradeable, UUPSUpgradeable {
         return states[finalizedState];
     }
 
+    /// @notice The verifying key that proofs of new states are checked against, marked as
+    /// `virtual` so that inherited contracts used in testing can swap it.
+    function getVerifyingKey() public pure virtual returns (IPlonkVerifier.VerifyingKey memory) {
+        return VkLib.getVk();
+    }
+
     // === Pure or View-only APIs ===
     /// @notice Transform a state into an array of field elements, prepared as public inputs of the
     /// plonk proof verification
@@ -253,14 +286,12 @@ contract LightClient is Initializable, OwnableUpgradeable, UUPSUpgradeable {
         return publicInput;
     }
 
-    /// @notice Verify the Plonk proof, marked as `virtual` for easier testing as we can swap VK
-    /// used
-    /// in inherited contracts.
+    /// @notice Verify the Plonk proof against the key returned by `getVerifyingKey`.
     function verifyProof(LightClientState memory state, IPlonkVerifier.PlonkProof memory proof)
         internal
         virtual
     {
-        IPlonkVerifier.VerifyingKey memory vk = VkLib.getVk();
+        IPlonkVerifier.VerifyingKey memory vk = getVerifyingKey();
         uint256[] memory publicInput = preparePublicInput(state);
 
         if (!PlonkVerifier.verify(vk, publicInput, proof, bytes(""))) {
@@ -11,6 +11,7 @@ import { IPlonkVerifier as V } from "../src/interfaces/IPlonkVerifier.sol";
 // Target contract
 import { LightClient as LC } from "../src/LightClient.sol";
 import { LightClientMock as LCTest } from "./mocks/LightClientMock.sol";
+import { LightClientStateUpdateVKMock as VkLib } from "./mocks/LightClientStateUpdateVKMock.sol";
 import { BN254 } from "bn254/BN254.sol";
 
 /// @dev Common helpers for LightClient tests
@@ -60,6 +61,7 @@ contract LightClient_constructor_Test is LightClientCommonTest {
         assert(lc.blocksPerEpoch() == BLOCKS_PER_EPOCH_TEST);
         assertEq(abi.encode(lc.getGenesisState()), abi.encode(genesis));
         assertEq(abi.encode(lc.getFinalizedState()), abi.encode(genesis));
+        assertEq(abi.encode(lc.getVerifyingKey()), abi.encode(VkLib.getVk()));
         assert(lc.currentEpoch() == 0);
 
         bytes32 stakeTableComm = lc.computeStakeTableComm(genesis);
@@ -428,3 +430,129 @@ contract LightClient_newFinalizedState_Test is LightClientCommonTest {
         lc.newFinalizedState(newState, proof);
     }
 }
+
+contract LightClient_newFinalizedStates_Test is LightClientCommonTest {
+    function setUp() public {
+        init();
+    }
+
+    /// @dev Generate (BLOCK_PER_EPOCH + 1) consecutive states and proofs, spanning an epoch
+    function mockConsecutiveStates()
+        internal
+        returns (LC.LightClientState[] memory, V.PlonkProof[] memory)
+    {
+        string[] memory cmds = new string[](6);
+        cmds[0] = "diff-test";
+        cmds[1] = "mock-consecutive-finalized-states";
+        cmds[2] = vm.toString(BLOCKS_PER_EPOCH_TEST);
+        cmds[3] = vm.toString(STAKE_TABLE_CAPACITY / 2);
+        cmds[4] = vm.toString(uint64(3));
+        cmds[5] = vm.toString(uint64(3));
+
+        bytes memory result = vm.ffi(cmds);
+        return abi.decode(result, (LC.LightClientState[], V.PlonkProof[]));
+    }
+
+    /// @dev Test that a batch of updates has the same effect as submitting them one at a time
+    function test_BatchedUpdate() external {
+        (LC.LightClientState[] memory states, V.PlonkProof[] memory proofs) =
+            mockConsecutiveStates();
+
+        LCTest sequential = new LCTest(genesis, BLOCKS_PER_EPOCH_TEST);
+        for (uint256 i = 0; i < states.length; i++) {
+            sequential.newFinalizedState(states[i], proofs[i]);
+        }
+
+        for (uint256 i = 0; i < states.length; i++) {
+            vm.expectEmit(true, true, true, true);
+            emit LC.NewState(states[i].viewNum, states[i].blockHeight, states[i].blockCommRoot);
+        }
+        lc.newFinalizedStates(states, proofs);
+
+        assertEq(abi.encode(lc.getFinalizedState()), abi.encode(states[states.length - 1]));
+        assertEq(lc.currentEpoch(), sequential.currentEpoch());
+        assertEq(lc.votingStakeTableCommitment(), sequential.votingStakeTableCommitment());
+        assertEq(lc.frozenStakeTableCommitment(), sequential.frozenStakeTableCommitment());
+        assertEq(lc.votingThreshold(), sequential.votingThreshold());
+        assertEq(lc.frozenThreshold(), sequential.frozenThreshold());
+    }
+
+    /// @dev Test unhappy path when the batch is empty or the numbers of states and proofs differ
+    function test_RevertWhen_MalformedBatch() external {
+        (LC.LightClientState[] memory states, V.PlonkProof[] memory proofs) =
+            mockConsecutiveStates();
+
+        vm.expectRevert(LC.InvalidArgs.selector);
+        lc.newFinalizedStates(new LC.LightClientState[](0), new V.PlonkProof[](0));
+
+        V.PlonkProof[] memory fewerProofs = new V.PlonkProof[](proofs.length - 1);
+        for (uint256 i = 0; i < fewerProofs.length; i++) {
+            fewerProofs[i] = proofs[i];
+        }
+        vm.expectRevert(LC.InvalidArgs.selector);
+        lc.newFinalizedStates(states, fewerProofs);
+    }
+
+    /// @dev Test that no update in a batch is applied if any of them is invalid
+    function test_RevertWhen_BatchContainsInvalidUpdate() external {
+        (LC.LightClientState[] memory states, V.PlonkProof[] memory proofs) =
+            mockConsecutiveStates();
+
+        states[2].blockCommRoot = BN254.ScalarField.wrap(1234);
+        vm.expectRevert(LC.InvalidProof.selector);
+        lc.newFinalizedStates(states, proofs);
+        assertEq(abi.encode(lc.getFinalizedState()), abi.encode(genesis));
+        assertEq(lc.currentEpoch(), 0);
+    }
+
+    /// @dev Test unhappy path when a batch skips the last block of an epoch
+    function test_RevertWhen_BatchSkipsEpochEndingBlock() external {
+        string[] memory cmds = new string[](3);
+        cmds[0] = "diff-test";
+        cmds[1] = "mock-miss-ending-block";
+        cmds[2] = vm.toString(BLOCKS_PER_EPOCH_TEST);
+
+        bytes memory result = vm.ffi(cmds);
+        (LC.LightClientState[] memory states, V.PlonkProof[] memory proofs) =
+            abi.decode(result, (LC.LightClientState[], V.PlonkProof[]));
+
+        vm.expectRevert(
+            abi.encodeWithSelector(
+                LC.MissingLastBlockForCurrentEpoch.selector, BLOCKS_PER_EPOCH_TEST
+            )
+        );
+        lc.newFinalizedStates(states, proofs);
+    }
+
+    /// @dev for benchmarking purposes only: compare the gas of submitting the same updates one at
+    /// a time and in one batch (run with `forge test -vv --match-test testBenchmark`).
+    ///
+    /// Only execution gas is measured, and all calls here share one transaction, so storage stays
+    /// warm across the sequential updates. Separate L1 transactions additionally pay 21000
+    /// intrinsic gas each and the cold storage accesses of every update, so the logged estimate
+    /// of the sequential cost is a lower bound.
+    function testBenchmark_BatchedUpdate() external {
+        (LC.LightClientState[] memory states, V.PlonkProof[] memory proofs) =
+            mockConsecutiveStates();
+
+        LCTest sequential = new LCTest(genesis, BLOCKS_PER_EPOCH_TEST);
+        uint256 sequentialGas = 0;
+        for (uint256 i = 0; i < states.length; i++) {
+            uint256 start = gasleft();
+            sequential.newFinalizedState(states[i], proofs[i]);
+            sequentialGas += start - gasleft();
+        }
+
+        uint256 batchStart = gasleft();
+        lc.newFinalizedStates(states, proofs);
+        uint256 batchedGas = batchStart - gasleft();
+
+        emit log_named_uint("updates", states.length);
+        emit log_named_uint("sequential execution gas", sequentialGas);
+        emit log_named_uint("batched execution gas", batchedGas);
+        emit log_named_uint(
+            "sequential gas, with intrinsic gas", sequentialGas + 21000 * states.length
+        );
+        emit log_named_uint("batched gas, with intrinsic gas", batchedGas + 21000);
+    }
+}
@@ -4,7 +4,6 @@ pragma solidity ^0.8.0;
 
 import { LightClient as LC } from "../../src/LightClient.sol";
 import { IPlonkVerifier } from "../../src/interfaces/IPlonkVerifier.sol";
-import { PlonkVerifier } from "../../src/libraries/PlonkVerifier.sol";
 import { LightClientStateUpdateVKMock as VkLib } from "./LightClientStateUpdateVKMock.sol";
 
 /// @dev A helper that wraps LightClient contract for testing
@@ -24,16 +23,7 @@ contract LightClientMock is LC {
     }
 
     /// @dev override the production-implementation with test VK.
-    function verifyProof(LC.LightClientState memory state, IPlonkVerifier.PlonkProof memory proof)
-        internal
-        view
-        override
-    {
-        IPlonkVerifier.VerifyingKey memory vk = VkLib.getVk();
-        uint256[] memory publicInput = preparePublicInput(state);
-
-        if (!PlonkVerifier.verify(vk, publicInput, proof, bytes(""))) {
-            revert InvalidProof();
-        }
+    function getVerifyingKey() public pure override returns (IPlonkVerifier.VerifyingKey memory) {
+        return VkLib.getVk();
     }
 }
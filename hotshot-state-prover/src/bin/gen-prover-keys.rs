@@ -0,0 +1,48 @@
+use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+use clap::Parser;
+use hotshot_stake_table::config::STAKE_TABLE_CAPACITY;
+use hotshot_state_prover::keys::{generate_keys, key_path, load_srs, read_keys, write_keys};
+use std::path::PathBuf;
+
+/// Generate the state prover keys ahead of time and store them in a key directory.
+///
+/// The state prover loads the keys from the same directory with `--key-dir`, instead of deriving
+/// them on every start.
+#[derive(Parser)]
+struct Args {
+    /// Directory in which to store the keys.
+    #[clap(long, env = "ESPRESSO_STATE_PROVER_KEY_DIR")]
+    key_dir: PathBuf,
+
+    /// Stake table capacity for the prover circuit
+    #[clap(short, long, env = "ESPRESSO_SEQUENCER_STAKE_TABLE_CAPACITY", default_value_t = STAKE_TABLE_CAPACITY)]
+    stake_table_capacity: usize,
+
+    /// Regenerate the keys even if valid keys are already cached.
+    #[clap(long)]
+    force: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    setup_logging();
+    setup_backtrace();
+
+    let args = Args::parse();
+    let srs = load_srs(args.stake_table_capacity)?;
+    let path = key_path(&args.key_dir, args.stake_table_capacity);
+
+    if !args.force && path.exists() {
+        match read_keys(&path, &srs, args.stake_table_capacity) {
+            Ok(_) => {
+                println!("Keys at {path:?} are up to date");
+                return Ok(());
+            }
+            Err(err) => println!("Replacing keys at {path:?}: {err:#}"),
+        }
+    }
+
+    let (pk, vk) = generate_keys(&srs, args.stake_table_capacity)?;
+    write_keys(&path, args.stake_table_capacity, &pk, &vk)?;
+    println!("Wrote keys to {path:?}");
+    Ok(())
+}
@@ -5,10 +5,12 @@ use es_version::SEQUENCER_VERSION;
 use ethers::providers::{Http, Middleware, Provider};
 use ethers::signers::{coins_bip39::English, MnemonicBuilder, Signer};
 use ethers::types::Address;
+use hotshot_query_service::metrics::PrometheusMetrics;
 use hotshot_stake_table::config::STAKE_TABLE_CAPACITY;
 use hotshot_state_prover::service::{run_prover_once, run_prover_service, StateProverConfig};
+use sequencer_utils::l1_sender::L1SenderOptions;
 use snafu::Snafu;
-use std::{str::FromStr as _, time::Duration};
+use std::{path::PathBuf, str::FromStr as _, sync::Arc, time::Duration};
 use url::Url;
 
 #[derive(Parser)]
@@ -64,13 +66,59 @@ struct Args {
 
     /// If daemon and provided, the service will run a basic HTTP server on the given port.
     ///
-    /// The server provides healthcheck and version endpoints.
+    /// The server provides healthcheck, version and metrics endpoints.
     #[clap(short, long, env = "ESPRESSO_PROVER_SERVICE_PORT")]
     pub port: Option<u16>,
 
     /// Stake table capacity for the prover circuit
     #[clap(short, long, env = "ESPRESSO_SEQUENCER_STAKE_TABLE_CAPACITY", default_value_t = STAKE_TABLE_CAPACITY)]
     pub stake_table_capacity: usize,
+
+    /// Directory in which to cache the proving key between runs.
+    ///
+    /// The key can be generated ahead of time with `gen-prover-keys`.
+    #[clap(long, env = "ESPRESSO_STATE_PROVER_KEY_DIR")]
+    pub key_dir: Option<PathBuf>,
+
+    /// File in which to persist generated proofs, so that they can be resubmitted after a restart.
+    #[clap(long, env = "ESPRESSO_STATE_PROVER_STORAGE_PATH")]
+    pub storage_path: Option<PathBuf>,
+
+    /// Number of threads generating proofs.
+    #[clap(long, env = "ESPRESSO_STATE_PROVER_WORKERS", default_value = "1")]
+    pub proof_workers: usize,
+
+    /// Maximum number of proofs waiting for a free worker.
+    #[clap(
+        long,
+        env = "ESPRESSO_STATE_PROVER_MAX_QUEUED_PROOFS",
+        default_value = "4"
+    )]
+    pub max_queued_proofs: usize,
+
+    /// How often to check the relay server for a new state, while there is none.
+    #[clap(long, value_parser = parse_duration, default_value = "10s", env = "ESPRESSO_STATE_PROVER_POLL_INTERVAL")]
+    pub poll_interval: Duration,
+
+    /// Prove new states in the background while the latest proof is being submitted.
+    #[clap(long, env = "ESPRESSO_STATE_PROVER_BACKGROUND_PROVING")]
+    pub background_proving: bool,
+
+    /// Maximum number of proofs submitted together in one L1 transaction.
+    ///
+    /// When the contract needs the ends of one or more epochs before the latest state, they are
+    /// proven along with it and submitted in order, up to this many in each transaction. If the
+    /// contract rejects batches, the proofs are submitted one at a time.
+    #[clap(
+        long,
+        env = "ESPRESSO_STATE_PROVER_MAX_BATCH_SIZE",
+        default_value = "1",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub max_batch_size: u64,
+
+    #[clap(flatten)]
+    pub l1_sender: L1SenderOptions,
 }
 
 #[derive(Clone, Debug, Snafu)]
@@ -113,6 +161,15 @@ async fn main() {
         orchestrator_url: args.orchestrator_url,
         port: args.port,
         stake_table_capacity: args.stake_table_capacity,
+        key_dir: args.key_dir,
+        storage_path: args.storage_path,
+        proof_workers: args.proof_workers,
+        max_queued_proofs: args.max_queued_proofs,
+        poll_interval: args.poll_interval,
+        background_proving: args.background_proving,
+        max_batch_size: args.max_batch_size as usize,
+        l1_sender: args.l1_sender,
+        metrics: Arc::new(PrometheusMetrics::default()),
     };
 
     if args.daemon {
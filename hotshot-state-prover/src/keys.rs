@@ -0,0 +1,382 @@
+//! On-disk cache of the keys for the state update circuit.
+//!
+//! Preprocessing the circuit for a realistic stake table capacity takes minutes, so the prover
+//! stores the resulting [`ProvingKey`] and [`VerifyingKey`] in a key directory and reloads them on
+//! later starts. Each key file starts with a [`KeyFingerprint`], a digest of its verifying key.
+//!
+//! Cached keys are only used if their verifying key is the one derived from the current circuit
+//! and SRS, see [`check_vk`]. Keys which fail the check are ignored and regenerated. Loading the
+//! SRS and checking the key take seconds, while preprocessing the circuit takes minutes.
+
+use crate::{
+    circuit::build_for_preprocessing,
+    snark::{preprocess, ProvingKey, UniversalSrs, VerifyingKey},
+};
+use anyhow::{anyhow, bail, ensure, Context};
+use ark_bn254::G1Projective;
+use ark_ec::VariableBaseMSM;
+use ark_ed_on_bn254::EdwardsConfig;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::{UniformRand, Zero};
+use hotshot_types::light_client::CircuitField;
+use jf_primitives::pcs::prelude::UnivariateUniversalParams;
+use jf_relation::{Arithmetization, Circuit as _};
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+use time::Instant;
+
+/// Magic bytes at the start of every key file.
+const KEY_FILE_MAGIC: &[u8; 4] = b"HSPK";
+
+/// Version of the key file layout.
+const KEY_FILE_VERSION: u32 = 3;
+
+/// Identifies the keys in a key file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyFingerprint {
+    /// Stake table capacity of the circuit.
+    pub stake_table_capacity: u64,
+    /// Digest of the verifying key.
+    pub vk_digest: [u8; 32],
+}
+
+impl KeyFingerprint {
+    /// The fingerprint of `vk`, a verifying key of the circuit for `stake_table_capacity`.
+    pub fn new(vk: &VerifyingKey, stake_table_capacity: usize) -> anyhow::Result<Self> {
+        let mut hasher = blake3::Hasher::new();
+        vk.serialize_uncompressed(&mut hasher)
+            .context("hashing verifying key")?;
+        Ok(Self {
+            stake_table_capacity: stake_table_capacity as u64,
+            vk_digest: *hasher.finalize().as_bytes(),
+        })
+    }
+
+    fn write(&self, w: &mut impl Write) -> std::io::Result<()> {
+        w.write_all(KEY_FILE_MAGIC)?;
+        w.write_all(&KEY_FILE_VERSION.to_le_bytes())?;
+        w.write_all(&self.stake_table_capacity.to_le_bytes())?;
+        w.write_all(&self.vk_digest)
+    }
+
+    fn read(r: &mut impl Read) -> anyhow::Result<Self> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        ensure!(&magic == KEY_FILE_MAGIC, "not a proving key file");
+        let mut version = [0; 4];
+        r.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        ensure!(
+            version == KEY_FILE_VERSION,
+            "unsupported key file version {version}"
+        );
+        let mut capacity = [0; 8];
+        r.read_exact(&mut capacity)?;
+        let mut vk_digest = [0; 32];
+        r.read_exact(&mut vk_digest)?;
+        Ok(Self {
+            stake_table_capacity: u64::from_le_bytes(capacity),
+            vk_digest,
+        })
+    }
+}
+
+/// Check that `vk` is the verifying key of the state update circuit for `stake_table_capacity`,
+/// preprocessed with `srs`.
+///
+/// The key holds a commitment to each selector and permutation polynomial of the circuit, and
+/// computing all of those commitments is most of the work of preprocessing. Instead, the
+/// polynomials are derived from the circuit, which is cheap, and a random linear combination of
+/// them is committed to. This matches the same combination of the commitments in `vk` if, and
+/// except with negligible probability only if, `vk` commits to the same polynomials with `srs`.
+pub fn check_vk(
+    srs: &UniversalSrs,
+    stake_table_capacity: usize,
+    vk: &VerifyingKey,
+) -> anyhow::Result<()> {
+    let (circuit, _) = build_for_preprocessing::<CircuitField, EdwardsConfig>(stake_table_capacity)
+        .context("building state update circuit")?;
+    let domain_size = circuit
+        .eval_domain_size()
+        .context("computing evaluation domain size")?;
+    ensure!(
+        vk.domain_size == domain_size,
+        "key has domain size {}, but the circuit has {domain_size}",
+        vk.domain_size
+    );
+    ensure!(
+        vk.num_inputs == circuit.num_inputs(),
+        "key has {} public inputs, but the circuit has {}",
+        vk.num_inputs,
+        circuit.num_inputs()
+    );
+
+    let mut polys = circuit
+        .compute_selector_polynomials()
+        .context("computing selector polynomials")?;
+    polys.extend(
+        circuit
+            .compute_extended_permutation_polynomials()
+            .context("computing permutation polynomials")?,
+    );
+    let comms = vk
+        .selector_comms
+        .iter()
+        .chain(&vk.sigma_comms)
+        .collect::<Vec<_>>();
+    ensure!(
+        comms.len() == polys.len(),
+        "key has {} commitments, but the circuit has {} polynomials",
+        comms.len(),
+        polys.len()
+    );
+
+    let mut rng = ark_std::rand::thread_rng();
+    let len = polys
+        .iter()
+        .map(|poly| poly.coeffs.len())
+        .max()
+        .unwrap_or(0);
+    let mut combined_poly = vec![CircuitField::zero(); len];
+    let mut combined_comm = G1Projective::zero();
+    for (poly, comm) in polys.iter().zip(comms) {
+        let r = CircuitField::rand(&mut rng);
+        for (coeff, poly_coeff) in combined_poly.iter_mut().zip(&poly.coeffs) {
+            *coeff += r * poly_coeff;
+        }
+        combined_comm += comm.0 * r;
+    }
+    ensure!(
+        srs.powers_of_g.len() >= len,
+        "SRS supports degree {}, but the circuit needs {}",
+        srs.powers_of_g.len().saturating_sub(1),
+        len.saturating_sub(1)
+    );
+    let commitment = G1Projective::msm(&srs.powers_of_g[..len], &combined_poly)
+        .map_err(|_| anyhow!("committing to circuit polynomials"))?;
+    ensure!(
+        commitment == combined_comm,
+        "key does not match the circuit and SRS"
+    );
+    Ok(())
+}
+
+/// The path of the cached keys for `stake_table_capacity` within `key_dir`.
+pub fn key_path(key_dir: &Path, stake_table_capacity: usize) -> PathBuf {
+    key_dir.join(format!("state-update-keys-{stake_table_capacity}.bin"))
+}
+
+/// Load the universal SRS from Aztec's ceremony, large enough for the state update circuit.
+pub fn load_srs(stake_table_capacity: usize) -> anyhow::Result<UniversalSrs> {
+    let num_gates = build_for_preprocessing::<CircuitField, EdwardsConfig>(stake_table_capacity)
+        .context("building state update circuit")?
+        .0
+        .num_gates();
+
+    std::println!("Loading SRS from Aztec's ceremony...");
+    let srs_timer = Instant::now();
+    let srs = ark_srs::kzg10::aztec20::setup(num_gates + 2)
+        .map_err(|err| anyhow::anyhow!("Aztec SRS fail to load: {err}"))?;
+    let srs_elapsed = srs_timer.elapsed();
+    std::println!("Done in {srs_elapsed:.3}");
+
+    // convert to Jellyfish type
+    // TODO: (alex) use constructor instead https://github.com/EspressoSystems/jellyfish/issues/440
+    Ok(UnivariateUniversalParams {
+        powers_of_g: srs.powers_of_g,
+        h: srs.h,
+        beta_h: srs.beta_h,
+        powers_of_h: vec![srs.h, srs.beta_h],
+    })
+}
+
+/// Generate the proving and verifying keys for the state update circuit from scratch.
+pub fn generate_keys(
+    srs: &UniversalSrs,
+    stake_table_capacity: usize,
+) -> anyhow::Result<(ProvingKey, VerifyingKey)> {
+    std::println!("Generating proving key and verification key.");
+    let key_gen_timer = Instant::now();
+    let keys =
+        preprocess(srs, stake_table_capacity).context("preprocessing state prover circuit")?;
+    let key_gen_elapsed = key_gen_timer.elapsed();
+    std::println!("Done in {key_gen_elapsed:.3}");
+    Ok(keys)
+}
+
+/// Write `pk` and `vk`, the keys of the circuit for `stake_table_capacity`, to `path`.
+///
+/// The keys are written to a temporary file first and then moved into place, so that a crash while
+/// writing never leaves a truncated key file behind.
+pub fn write_keys(
+    path: &Path,
+    stake_table_capacity: usize,
+    pk: &ProvingKey,
+    vk: &VerifyingKey,
+) -> anyhow::Result<()> {
+    let fingerprint = KeyFingerprint::new(vk, stake_table_capacity)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("creating key directory {dir:?}"))?;
+    }
+    let tmp = path.with_extension("tmp");
+    {
+        let mut w = BufWriter::new(
+            File::create(&tmp).with_context(|| format!("creating key file {tmp:?}"))?,
+        );
+        fingerprint.write(&mut w)?;
+        vk.serialize_uncompressed(&mut w)
+            .context("serializing verifying key")?;
+        pk.serialize_uncompressed(&mut w)
+            .context("serializing proving key")?;
+        w.flush()?;
+    }
+    fs::rename(&tmp, path).with_context(|| format!("moving key file into place at {path:?}"))?;
+    Ok(())
+}
+
+/// Read keys from `path`, checking that they are the keys of the circuit for
+/// `stake_table_capacity`, preprocessed with `srs`.
+pub fn read_keys(
+    path: &Path,
+    srs: &UniversalSrs,
+    stake_table_capacity: usize,
+) -> anyhow::Result<(ProvingKey, VerifyingKey)> {
+    let mut r =
+        BufReader::new(File::open(path).with_context(|| format!("opening key file {path:?}"))?);
+    let fingerprint = KeyFingerprint::read(&mut r)?;
+    if fingerprint.stake_table_capacity != stake_table_capacity as u64 {
+        bail!(
+            "key was generated for stake table capacity {}, expected {stake_table_capacity}",
+            fingerprint.stake_table_capacity,
+        );
+    }
+    // The key file is trusted local state, and the verifying key is checked against its
+    // fingerprint and the circuit below, so skip the expensive curve point validation.
+    let vk = VerifyingKey::deserialize_uncompressed_unchecked(&mut r)
+        .context("deserializing verifying key")?;
+    ensure!(
+        KeyFingerprint::new(&vk, stake_table_capacity)? == fingerprint,
+        "verifying key does not match the fingerprint of the key file"
+    );
+    check_vk(srs, stake_table_capacity, &vk)?;
+    let pk = ProvingKey::deserialize_uncompressed_unchecked(&mut r)
+        .context("deserializing proving key")?;
+    Ok((pk, vk))
+}
+
+/// Load the keys for `stake_table_capacity`, preprocessed with the SRS from Aztec's ceremony.
+///
+/// See [`load_or_generate_keys_with_srs`].
+pub fn load_or_generate_keys(
+    key_dir: Option<&Path>,
+    stake_table_capacity: usize,
+) -> anyhow::Result<(ProvingKey, VerifyingKey)> {
+    let srs = load_srs(stake_table_capacity)?;
+    load_or_generate_keys_with_srs(key_dir, &srs, stake_table_capacity)
+}
+
+/// Load the keys for `stake_table_capacity`, preprocessed with `srs`.
+///
+/// If `key_dir` is given, cached keys are loaded from it if they are the keys of the current
+/// circuit and SRS. Otherwise the keys are generated and, if `key_dir` is given, cached for next
+/// time.
+pub fn load_or_generate_keys_with_srs(
+    key_dir: Option<&Path>,
+    srs: &UniversalSrs,
+    stake_table_capacity: usize,
+) -> anyhow::Result<(ProvingKey, VerifyingKey)> {
+    let Some(key_dir) = key_dir else {
+        return generate_keys(srs, stake_table_capacity);
+    };
+
+    let path = key_path(key_dir, stake_table_capacity);
+    if path.exists() {
+        let load_timer = Instant::now();
+        match read_keys(&path, srs, stake_table_capacity) {
+            Ok(keys) => {
+                tracing::info!(
+                    "Loaded cached keys from {path:?} in {:.3}",
+                    load_timer.elapsed()
+                );
+                return Ok(keys);
+            }
+            Err(err) => {
+                tracing::warn!("Ignoring cached keys at {path:?}: {err:#}");
+            }
+        }
+    }
+
+    let (pk, vk) = generate_keys(srs, stake_table_capacity)?;
+    if let Err(err) = write_keys(&path, stake_table_capacity, &pk, &vk) {
+        // The prover can still run with the keys in memory.
+        tracing::warn!("Failed to cache keys at {path:?}: {err:#}");
+    } else {
+        tracing::info!("Cached keys at {path:?}");
+    }
+    Ok((pk, vk))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::universal_setup_for_testing;
+    use jf_utils::test_rng;
+
+    const ST_CAPACITY: usize = 20;
+
+    #[test]
+    fn test_proving_key_cache() {
+        let mut rng = test_rng();
+        let num_gates = build_for_preprocessing::<CircuitField, EdwardsConfig>(ST_CAPACITY + 1)
+            .unwrap()
+            .0
+            .num_gates();
+        let srs = universal_setup_for_testing(num_gates + 2, &mut rng).unwrap();
+        let (pk, vk) = generate_keys(&srs, ST_CAPACITY).unwrap();
+        check_vk(&srs, ST_CAPACITY, &vk).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = key_path(dir.path(), ST_CAPACITY);
+        write_keys(&path, ST_CAPACITY, &pk, &vk).unwrap();
+
+        let (loaded_pk, loaded_vk) = read_keys(&path, &srs, ST_CAPACITY).unwrap();
+        assert_eq!(loaded_vk, vk);
+        assert_eq!(serialized(&loaded_pk), serialized(&pk));
+
+        // A key file for a different capacity is rejected.
+        let err = read_keys(&path, &srs, ST_CAPACITY + 1).unwrap_err();
+        assert!(err.to_string().contains("stake table capacity"), "{err:#}");
+
+        // So is a key of a different circuit.
+        let (_, other_vk) = generate_keys(&srs, ST_CAPACITY + 1).unwrap();
+        check_vk(&srs, ST_CAPACITY, &other_vk).unwrap_err();
+
+        // And a key generated with a different SRS.
+        let other_srs = universal_setup_for_testing(num_gates + 2, &mut rng).unwrap();
+        let err = read_keys(&path, &other_srs, ST_CAPACITY).unwrap_err();
+        assert!(err.to_string().contains("does not match"), "{err:#}");
+
+        // A verifying key which does not match the fingerprint of its file is rejected.
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[16] ^= 1;
+        let corrupt = dir.path().join("corrupt.bin");
+        fs::write(&corrupt, bytes).unwrap();
+        let err = read_keys(&corrupt, &srs, ST_CAPACITY).unwrap_err();
+        assert!(err.to_string().contains("fingerprint"), "{err:#}");
+
+        // Cached keys for the current circuit and SRS are loaded instead of regenerated.
+        let (loaded_pk, loaded_vk) =
+            load_or_generate_keys_with_srs(Some(dir.path()), &srs, ST_CAPACITY).unwrap();
+        assert_eq!(loaded_vk, vk);
+        assert_eq!(serialized(&loaded_pk), serialized(&pk));
+    }
+
+    fn serialized(pk: &ProvingKey) -> Vec<u8> {
+        let mut bytes = vec![];
+        pk.serialize_uncompressed(&mut bytes).unwrap();
+        bytes
+    }
+}
@@ -2,12 +2,18 @@
 
 /// State verifier circuit builder
 pub mod circuit;
+/// On-disk cache of the circuit keys
+pub mod keys;
 /// Utilities for test
 pub mod mock_ledger;
+/// History of generated proofs
+pub mod persistence;
 /// Prover service related functionalities
 pub mod service;
 /// SNARK proof generation
 pub mod snark;
+/// Worker pool for proof generation
+pub mod worker;
 
 #[cfg(test)]
 mod test_utils;
@@ -0,0 +1,284 @@
+//! Persistent history of the proofs generated by the prover.
+//!
+//! Every generated proof is recorded together with its public input before it is submitted, so
+//! that a proof whose submission fails is not lost and can be submitted again later without
+//! proving the state again. The history also records how each proof was submitted, and is served
+//! by the prover's HTTP API.
+
+use crate::snark::Proof;
+use anyhow::Context;
+use ethers::{
+    abi::{AbiDecode, AbiEncode},
+    types::{Bytes, H256},
+};
+use hotshot_contract_adapter::{jellyfish::ParsedPlonkProof, light_client::ParsedLightClientState};
+use hotshot_types::light_client::PublicInput;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The maximum number of proofs kept in the history.
+///
+/// When the history is full, the proofs for the oldest states are dropped first.
+pub const MAX_PROOF_HISTORY: usize = 1000;
+
+/// A proof generated by the prover.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProofRecord {
+    /// View number of the proven light client state.
+    pub view_number: u64,
+    /// Block height of the proven light client state.
+    pub block_height: u64,
+    /// ABI encoding of the proof, as submitted to the light client contract.
+    pub proof: Bytes,
+    /// ABI encoding of the proven light client state, which is the public input of the proof.
+    pub public_input: Bytes,
+    /// When the proof was generated, in seconds since the Unix epoch.
+    pub generated_at: u64,
+    /// How long it took to generate the proof.
+    pub generation_time: Duration,
+    /// The successful submission of the proof, if any.
+    pub submission: Option<Submission>,
+    /// The number of times submission of this proof has been attempted.
+    pub submission_attempts: u32,
+    /// The error from the last failed submission attempt.
+    pub last_error: Option<String>,
+}
+
+/// A successful submission of a proof to the light client contract.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Submission {
+    /// Hash of the L1 transaction which submitted the proof.
+    pub tx_hash: H256,
+    /// The L1 block in which the transaction was included.
+    pub l1_block: u64,
+    /// How long the successful submission took, from sending the transaction to its inclusion.
+    pub submission_time: Duration,
+}
+
+impl ProofRecord {
+    pub fn new(proof: Proof, public_input: PublicInput, generation_time: Duration) -> Self {
+        let proof: ParsedPlonkProof = proof.into();
+        let state: ParsedLightClientState = public_input.into();
+        Self {
+            view_number: state.view_num,
+            block_height: state.block_height,
+            proof: proof.encode().into(),
+            public_input: state.encode().into(),
+            generated_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            generation_time,
+            submission: None,
+            submission_attempts: 0,
+            last_error: None,
+        }
+    }
+
+    /// The proof, in the form expected by the light client contract.
+    pub fn proof(&self) -> anyhow::Result<ParsedPlonkProof> {
+        ParsedPlonkProof::decode(&self.proof).context("malformed proof")
+    }
+
+    /// The proven light client state.
+    pub fn public_input(&self) -> anyhow::Result<ParsedLightClientState> {
+        ParsedLightClientState::decode(&self.public_input).context("malformed public input")
+    }
+
+    /// Whether this proof has been submitted successfully.
+    pub fn is_submitted(&self) -> bool {
+        self.submission.is_some()
+    }
+}
+
+/// The history of proofs generated by the prover, indexed by block height.
+///
+/// If the history has a storage path, every change is written to disk, and the history is loaded
+/// from the same path on startup. Otherwise the history is kept in memory only.
+#[derive(Debug, Default)]
+pub struct ProofHistory {
+    path: Option<PathBuf>,
+    records: BTreeMap<u64, ProofRecord>,
+}
+
+impl ProofHistory {
+    /// Open the history stored at `path`, or an empty in-memory history if there is no `path`.
+    pub fn open(path: Option<PathBuf>) -> anyhow::Result<Self> {
+        let mut records = BTreeMap::new();
+        if let Some(path) = &path {
+            if path.exists() {
+                let bytes =
+                    fs::read(path).with_context(|| format!("reading proof history {path:?}"))?;
+                let stored: Vec<ProofRecord> = serde_json::from_slice(&bytes)
+                    .with_context(|| format!("parsing proof history {path:?}"))?;
+                records.extend(stored.into_iter().map(|r| (r.block_height, r)));
+                tracing::info!("Loaded {} proofs from {path:?}", records.len());
+            }
+        }
+        Ok(Self { path, records })
+    }
+
+    /// Record a newly generated proof.
+    pub fn insert(&mut self, record: ProofRecord) -> anyhow::Result<()> {
+        self.records.insert(record.block_height, record);
+        while self.records.len() > MAX_PROOF_HISTORY {
+            self.records.pop_first();
+        }
+        self.persist()
+    }
+
+    /// Record a successful submission of the proof for `block_height`.
+    pub fn record_submission(
+        &mut self,
+        block_height: u64,
+        submission: Submission,
+    ) -> anyhow::Result<()> {
+        if let Some(record) = self.records.get_mut(&block_height) {
+            record.submission_attempts += 1;
+            record.submission = Some(submission);
+            record.last_error = None;
+        }
+        self.persist()
+    }
+
+    /// Record a failed attempt to submit the proof for `block_height`.
+    pub fn record_failure(&mut self, block_height: u64, error: String) -> anyhow::Result<()> {
+        if let Some(record) = self.records.get_mut(&block_height) {
+            record.submission_attempts += 1;
+            record.last_error = Some(error);
+        }
+        self.persist()
+    }
+
+    /// The proof for the latest state, if it has not been submitted yet.
+    ///
+    /// Older unsubmitted proofs are superseded by the latest one, so they are never retried.
+    pub fn latest_pending(&self) -> Option<&ProofRecord> {
+        self.records
+            .last_key_value()
+            .map(|(_, record)| record)
+            .filter(|record| !record.is_submitted())
+    }
+
+    /// The proof for the state at `block_height`, if there is one.
+    pub fn get(&self, block_height: u64) -> Option<&ProofRecord> {
+        self.records.get(&block_height)
+    }
+
+    /// Up to `limit` of the most recent proofs, newest first.
+    pub fn list(&self, limit: usize) -> Vec<ProofRecord> {
+        self.records.values().rev().take(limit).cloned().collect()
+    }
+
+    fn persist(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let records = self.records.values().collect::<Vec<_>>();
+        write_atomic(path, &serde_json::to_vec(&records)?)
+            .with_context(|| format!("writing proof history {path:?}"))
+    }
+}
+
+/// Write `bytes` to a temporary file and move it into place, so that a crash never leaves a
+/// truncated file at `path`.
+fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::types::U256;
+
+    fn record(block_height: u64) -> ProofRecord {
+        let state = ParsedLightClientState {
+            block_height,
+            ..ParsedLightClientState::dummy_genesis()
+        };
+        ProofRecord {
+            view_number: block_height,
+            block_height,
+            proof: ParsedPlonkProof::default().encode().into(),
+            public_input: state.encode().into(),
+            generated_at: 0,
+            generation_time: Duration::from_secs(block_height),
+            submission: None,
+            submission_attempts: 0,
+            last_error: None,
+        }
+    }
+
+    #[test]
+    fn test_proof_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("proofs.json");
+
+        let mut history = ProofHistory::open(Some(path.clone())).unwrap();
+        assert_eq!(history.latest_pending(), None);
+
+        history.insert(record(1)).unwrap();
+        history.insert(record(2)).unwrap();
+        assert_eq!(history.latest_pending().unwrap().block_height, 2);
+        assert_eq!(
+            history
+                .latest_pending()
+                .unwrap()
+                .public_input()
+                .unwrap()
+                .block_height,
+            2
+        );
+
+        // A failed submission leaves the proof pending.
+        history.record_failure(2, "L1 unavailable".into()).unwrap();
+        let pending = history.latest_pending().unwrap();
+        assert_eq!(pending.submission_attempts, 1);
+        assert_eq!(pending.last_error.as_deref(), Some("L1 unavailable"));
+
+        let submission = Submission {
+            tx_hash: H256::from_low_u64_be(42),
+            l1_block: 7,
+            submission_time: Duration::from_secs(3),
+        };
+        history.record_submission(2, submission.clone()).unwrap();
+        assert_eq!(history.latest_pending(), None);
+
+        // The history survives a restart.
+        let reloaded = ProofHistory::open(Some(path)).unwrap();
+        assert_eq!(reloaded.list(10), history.list(10));
+        assert_eq!(
+            reloaded
+                .list(10)
+                .iter()
+                .map(|r| r.block_height)
+                .collect::<Vec<_>>(),
+            [2, 1]
+        );
+        let latest = reloaded.get(2).unwrap();
+        assert_eq!(latest.submission, Some(submission));
+        assert_eq!(latest.submission_attempts, 2);
+        assert_eq!(latest.last_error, None);
+        assert_eq!(latest.public_input().unwrap().threshold, U256::from(1));
+    }
+
+    #[test]
+    fn test_proof_history_limit() {
+        let mut history = ProofHistory::open(None).unwrap();
+        for height in 0..MAX_PROOF_HISTORY as u64 + 10 {
+            history.insert(record(height)).unwrap();
+        }
+        assert_eq!(history.list(usize::MAX).len(), MAX_PROOF_HISTORY);
+        assert_eq!(history.get(9), None);
+        assert!(history.get(10).is_some());
+    }
+}
@@ -1,51 +1,70 @@
 //! A light client prover service
 
-use crate::snark::{generate_state_update_proof, Proof, ProvingKey};
+use crate::{
+    persistence::{ProofHistory, ProofRecord, Submission},
+    snark::{generate_state_update_proof, Proof, ProvingKey},
+    worker::{CancelHandle, PoolFull, ProofTask, ProverPool},
+};
 use anyhow::anyhow;
 use async_std::{
     io,
-    sync::Arc,
+    sync::{Arc, RwLock},
     task::{sleep, spawn},
 };
-use contract_bindings::light_client::{LightClient, LightClientErrors};
+use contract_bindings::light_client::{
+    LightClient, LightClientErrors, LightClientState as ContractState, PlonkProof, LIGHTCLIENT_ABI,
+};
 use displaydoc::Display;
 use ethers::{
+    abi::{Function, Param, ParamType},
+    contract::{Contract, ContractCall},
     core::k256::ecdsa::SigningKey,
     middleware::SignerMiddleware,
     providers::Http,
     providers::{Middleware, Provider, ProviderError},
     signers::{LocalWallet, Signer, Wallet},
-    types::{Address, U256},
+    types::{Address, H256, U256},
 };
 use futures::FutureExt;
 use hotshot_contract_adapter::jellyfish::{u256_to_field, ParsedPlonkProof};
 use hotshot_contract_adapter::light_client::ParsedLightClientState;
 use hotshot_orchestrator::OrchestratorVersion;
+use hotshot_query_service::metrics::PrometheusMetrics;
 use hotshot_stake_table::vec_based::config::FieldType;
 use hotshot_stake_table::vec_based::StakeTable;
 use hotshot_types::signature_key::BLSPubKey;
 use hotshot_types::traits::stake_table::{SnapshotVersion, StakeTableError, StakeTableScheme as _};
 use hotshot_types::{
     light_client::{
-        CircuitField, GenericPublicInput, LightClientState, PublicInput, StateSignaturesBundle,
-        StateVerKey,
+        CircuitField, GenericPublicInput, LightClientState, PublicInput, StateSignature,
+        StateSignaturesBundle, StateVerKey,
     },
     traits::signature_key::StakeTableEntryType,
 };
 
 use jf_plonk::errors::PlonkError;
 use jf_primitives::constants::CS_ID_SCHNORR;
-use jf_primitives::pcs::prelude::UnivariateUniversalParams;
-use jf_relation::Circuit as _;
-use std::{iter, time::Duration};
+use sequencer_utils::l1_sender::{L1Sender, L1SenderMetrics, L1SenderOptions};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    iter,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 use surf_disco::Client;
-use tide_disco::{error::ServerError, Api};
-use time::Instant;
+use tide_disco::{error::ServerError, Api, Error as _, StatusCode};
 use url::Url;
 use versioned_binary_serialization::version::StaticVersionType;
 
 type F = ark_ed_on_bn254::Fq;
 
+/// The number of proofs listed by the `proofs` endpoint if no limit is given.
+const DEFAULT_PROOF_LIST_LIMIT: usize = 100;
+
+/// The number of times the prover tries to submit a proof before proving a newer state instead.
+const MAX_SUBMISSION_ATTEMPTS: u32 = 5;
+
 /// A wallet with local signer and connected to network via http
 pub type L1Wallet = SignerMiddleware<Provider<Http>, LocalWallet>;
 
@@ -71,10 +90,37 @@ pub struct StateProverConfig {
     pub orchestrator_url: Url,
     /// If daemon and provided, the service will run a basic HTTP server on the given port.
     ///
-    /// The server provides healthcheck and version endpoints.
+    /// The server provides healthcheck, version and metrics endpoints.
     pub port: Option<u16>,
     /// Stake table capacity for the prover circuit.
     pub stake_table_capacity: usize,
+    /// Directory in which to cache the proving key between runs.
+    ///
+    /// If not provided, the key is regenerated on every start.
+    pub key_dir: Option<PathBuf>,
+    /// File in which to persist generated proofs and their submissions.
+    ///
+    /// If not provided, the proof history is kept in memory only.
+    pub storage_path: Option<PathBuf>,
+    /// Number of threads generating proofs.
+    pub proof_workers: usize,
+    /// Maximum number of proofs waiting for a free worker.
+    pub max_queued_proofs: usize,
+    /// Interval between checks of the relay server for a new state, while there is none.
+    pub poll_interval: Duration,
+    /// Prove new states in the background while the latest proof is being submitted, instead of
+    /// proving and submitting one state at a time.
+    pub background_proving: bool,
+    /// Maximum number of proofs submitted together in one L1 transaction.
+    ///
+    /// When the contract needs the ends of one or more epochs before the latest state, they are
+    /// all proven and submitted, in order, in batches of up to this many proofs. Zero is treated
+    /// as 1.
+    pub max_batch_size: usize,
+    /// Options for submitting proofs to layer 1.
+    pub l1_sender: L1SenderOptions,
+    /// Registry of the prover's metrics, served by the HTTP server.
+    pub metrics: Arc<PrometheusMetrics>,
 }
 
 pub fn init_stake_table(
@@ -170,39 +216,16 @@ pub async fn light_client_genesis(
     Ok(pi.into())
 }
 
-pub fn load_proving_key(stake_table_capacity: usize) -> ProvingKey {
-    let srs = {
-        let num_gates = crate::circuit::build_for_preprocessing::<
-            CircuitField,
-            ark_ed_on_bn254::EdwardsConfig,
-        >(stake_table_capacity)
-        .unwrap()
-        .0
-        .num_gates();
-
-        std::println!("Loading SRS from Aztec's ceremony...");
-        let srs_timer = Instant::now();
-        let srs = ark_srs::kzg10::aztec20::setup(num_gates + 2).expect("Aztec SRS fail to load");
-        let srs_elapsed = srs_timer.elapsed();
-        std::println!("Done in {srs_elapsed:.3}");
-
-        // convert to Jellyfish type
-        // TODO: (alex) use constructor instead https://github.com/EspressoSystems/jellyfish/issues/440
-        UnivariateUniversalParams {
-            powers_of_g: srs.powers_of_g,
-            h: srs.h,
-            beta_h: srs.beta_h,
-            powers_of_h: vec![srs.h, srs.beta_h],
-        }
-    };
+/// Load the history of generated proofs from the configured storage path, if any.
+pub fn load_proof_history(config: &StateProverConfig) -> ProofHistory {
+    ProofHistory::open(config.storage_path.clone()).expect("Fail to load proof history")
+}
 
-    std::println!("Generating proving key and verification key.");
-    let key_gen_timer = Instant::now();
-    let (pk, _) = crate::snark::preprocess(&srs, stake_table_capacity)
-        .expect("Fail to preprocess state prover circuit");
-    let key_gen_elapsed = key_gen_timer.elapsed();
-    std::println!("Done in {key_gen_elapsed:.3}");
-    pk
+/// Load the proving key for the configured stake table capacity, from the key cache if possible.
+pub fn load_proving_key(config: &StateProverConfig) -> ProvingKey {
+    crate::keys::load_or_generate_keys(config.key_dir.as_deref(), config.stake_table_capacity)
+        .expect("Fail to load state prover keys")
+        .0
 }
 
 pub async fn fetch_latest_state<Ver: StaticVersionType>(
@@ -215,6 +238,22 @@ pub async fn fetch_latest_state<Ver: StaticVersionType>(
         .await
 }
 
+/// Fetch the available state signatures bundle at `block_height` from the relay server.
+///
+/// Apart from the latest state, the relay server only keeps the states at the end of recent epochs.
+pub async fn fetch_state_at<Ver: StaticVersionType>(
+    client: &Client<ServerError, Ver>,
+    block_height: u64,
+) -> Result<StateSignaturesBundle, ServerError> {
+    tracing::info!(
+        "Fetching the state signatures bundle at block {block_height} from relay server."
+    );
+    client
+        .get::<StateSignaturesBundle>(&format!("/api/state/{block_height}"))
+        .send()
+        .await
+}
+
 /// prepare a contract interface ready to be read from or written to
 async fn prepare_contract(
     config: &StateProverConfig,
@@ -229,6 +268,21 @@ async fn prepare_contract(
     Ok(contract)
 }
 
+/// Connect to layer 1 with the prover's signing key.
+///
+/// A single sender is used for every submission, so that it keeps track of the nonce of the
+/// prover's account, and of the transaction it is waiting on, from one submission to the next.
+pub async fn connect_l1_sender(
+    config: &StateProverConfig,
+) -> Result<L1Sender<L1Wallet>, ProverError> {
+    let contract = prepare_contract(config).await?;
+    Ok(L1Sender::new(
+        contract.client(),
+        config.l1_sender.clone(),
+        Arc::new(L1SenderMetrics::new(&*config.metrics)),
+    ))
+}
+
 /// get the `finalizedState` from the LightClient contract storage on L1
 pub async fn read_contract_state(
     config: &StateProverConfig,
@@ -246,21 +300,97 @@ pub async fn read_contract_state(
     Ok(state)
 }
 
+/// Where the LightClient contract stands in its epoch schedule.
+///
+/// The contract only advances to a new epoch once it has been given the state at the last block of
+/// the current epoch, and rejects any later state until then.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EpochSchedule {
+    /// Block height of the finalized state.
+    pub finalized_height: u64,
+    /// The current epoch.
+    pub current_epoch: u64,
+    /// The number of blocks in each epoch.
+    pub blocks_per_epoch: u64,
+}
+
+impl EpochSchedule {
+    /// The height of the last block of the current epoch.
+    pub fn epoch_ending_height(&self) -> u64 {
+        self.current_epoch.saturating_mul(self.blocks_per_epoch)
+    }
+
+    /// The heights of the states which must be submitted, in order, before the state at
+    /// `block_height`.
+    ///
+    /// These are the last blocks of every epoch, starting with the current one, which lie between
+    /// the finalized state and `block_height`, since the contract advances by only one epoch with
+    /// each epoch-ending state it is given.
+    pub fn epoch_endings(&self, block_height: u64) -> Vec<u64> {
+        if self.blocks_per_epoch == 0 {
+            return vec![];
+        }
+        (self.current_epoch..)
+            .map_while(|epoch| epoch.checked_mul(self.blocks_per_epoch))
+            .skip_while(|&epoch_end| epoch_end <= self.finalized_height)
+            .take_while(|&epoch_end| epoch_end < block_height)
+            .collect()
+    }
+}
+
+/// read the epoch schedule of the LightClient contract on L1
+pub async fn read_epoch_schedule(config: &StateProverConfig) -> Result<EpochSchedule, ProverError> {
+    let contract = prepare_contract(config).await?;
+    let finalized: ParsedLightClientState = contract
+        .get_finalized_state()
+        .call()
+        .await
+        .map_err(|e| ProverError::ContractError(e.into()))?
+        .into();
+    let current_epoch = contract
+        .current_epoch()
+        .call()
+        .await
+        .map_err(|e| ProverError::ContractError(e.into()))?;
+    let blocks_per_epoch = contract
+        .blocks_per_epoch()
+        .call()
+        .await
+        .map_err(|e| ProverError::ContractError(e.into()))?;
+    Ok(EpochSchedule {
+        finalized_height: finalized.block_height,
+        current_epoch,
+        blocks_per_epoch: blocks_per_epoch.into(),
+    })
+}
+
 /// submit the latest finalized state along with a proof to the L1 LightClient contract
+///
+/// Returns the hash of the submitted transaction and the L1 block in which it was included.
 pub async fn submit_state_and_proof(
     proof: Proof,
     public_input: PublicInput,
+    sender: &L1Sender<L1Wallet>,
     config: &StateProverConfig,
-) -> Result<(), ProverError> {
-    let contract = prepare_contract(config).await?;
+) -> Result<(H256, u64), ProverError> {
+    submit_parsed_state_and_proof(proof.into(), public_input.into(), sender, config).await
+}
+
+/// submit a new finalized state along with a proof, in the form expected by the contract
+pub async fn submit_parsed_state_and_proof(
+    proof: ParsedPlonkProof,
+    new_state: ParsedLightClientState,
+    sender: &L1Sender<L1Wallet>,
+    config: &StateProverConfig,
+) -> Result<(H256, u64), ProverError> {
+    let contract = LightClient::new(config.light_client_address, sender.client().clone());
 
     // prepare the input the contract call and the tx itself
-    let proof: ParsedPlonkProof = proof.into();
-    let new_state: ParsedLightClientState = public_input.into();
     let tx = contract.new_finalized_state(new_state.into(), proof.into());
 
     // send the tx
-    let (receipt, included_block) = sequencer_utils::contract_send::<_, _, LightClientErrors>(&tx)
+    let (receipt, included_block) = sender
+        .send_call::<_, LightClientErrors>(&tx)
         .await
         .map_err(ProverError::ContractError)?;
 
@@ -269,31 +399,245 @@ pub async fn submit_state_and_proof(
         receipt.transaction_hash,
     );
 
-    Ok(())
+    Ok((receipt.transaction_hash, included_block))
 }
 
-pub async fn sync_state<Ver: StaticVersionType>(
-    st: &StakeTable<BLSPubKey, StateVerKey, CircuitField>,
-    proving_key: &ProvingKey,
-    relay_server_client: &Client<ServerError, Ver>,
+/// The `newFinalizedStates` call of the LightClient contract, which takes a list of states and a
+/// list of proofs.
+///
+/// The generated bindings do not include this function until they are regenerated with
+/// `just gen-bindings`, so its ABI is derived from that of `newFinalizedState`, which takes one
+/// state and one proof.
+fn new_finalized_states_call(
+    proofs: Vec<ParsedPlonkProof>,
+    new_states: Vec<ParsedLightClientState>,
+    sender: &L1Sender<L1Wallet>,
     config: &StateProverConfig,
-) -> Result<(), ProverError> {
-    tracing::info!("Start syncing light client state.");
+) -> Result<ContractCall<L1Wallet, ()>, ProverError> {
+    let mut abi = LIGHTCLIENT_ABI.clone();
+    let single = abi
+        .function("newFinalizedState")
+        .map_err(|err| ProverError::Internal(err.to_string()))?
+        .clone();
+    let batch = Function {
+        name: "newFinalizedStates".into(),
+        inputs: single
+            .inputs
+            .iter()
+            .map(|param| Param {
+                name: format!("{}s", param.name),
+                kind: ParamType::Array(Box::new(param.kind.clone())),
+                internal_type: param.internal_type.as_ref().map(|ty| format!("{ty}[]")),
+            })
+            .collect(),
+        ..single
+    };
+    abi.functions.insert(batch.name.clone(), vec![batch]);
+
+    let contract = Contract::new(config.light_client_address, abi, sender.client().clone());
+    let new_states: Vec<ContractState> = new_states.into_iter().map(Into::into).collect();
+    let proofs: Vec<PlonkProof> = proofs.into_iter().map(Into::into).collect();
+    contract
+        .method("newFinalizedStates", (new_states, proofs))
+        .map_err(|err| ProverError::Internal(err.to_string()))
+}
+
+/// submit several consecutive finalized states, each with its proof, in one transaction
+///
+/// The contract applies the states in order, as if each had been submitted on its own.
+pub async fn submit_parsed_states_and_proofs(
+    proofs: Vec<ParsedPlonkProof>,
+    new_states: Vec<ParsedLightClientState>,
+    sender: &L1Sender<L1Wallet>,
+    config: &StateProverConfig,
+) -> Result<(H256, u64), ProverError> {
+    let num_states = new_states.len();
+    let tx = new_finalized_states_call(proofs, new_states, sender, config)?;
+
+    let (receipt, included_block) = sender
+        .send_call::<_, LightClientErrors>(&tx)
+        .await
+        .map_err(ProverError::ContractError)?;
 
-    let bundle = fetch_latest_state(relay_server_client).await?;
-    tracing::info!("Latest HotShot block height: {}", bundle.state.block_height);
-    let old_state = read_contract_state(config).await?;
     tracing::info!(
-        "Current HotShot block height on contract: {}",
-        old_state.block_height
+        "Submitted {num_states} states and proofs to L1: tx={:x} block={included_block}",
+        receipt.transaction_hash,
     );
-    if old_state.block_height >= bundle.state.block_height {
-        tracing::info!("No update needed.");
-        return Ok(());
+
+    Ok((receipt.transaction_hash, included_block))
+}
+
+/// Submit consecutive proofs from the history, in one transaction, and record the outcome.
+async fn submit_recorded_proofs(
+    records: &[ProofRecord],
+    history: &RwLock<ProofHistory>,
+    sender: &L1Sender<L1Wallet>,
+    config: &StateProverConfig,
+) -> Result<(), ProverError> {
+    let mut proofs = vec![];
+    let mut new_states = vec![];
+    for record in records {
+        proofs.push(
+            record
+                .proof()
+                .map_err(|err| ProverError::Internal(err.to_string()))?,
+        );
+        new_states.push(
+            record
+                .public_input()
+                .map_err(|err| ProverError::Internal(err.to_string()))?,
+        );
+    }
+
+    // A single proof goes through `newFinalizedState`, as it always has.
+    if records.len() == 1 {
+        let submission_start = Instant::now();
+        let res =
+            submit_parsed_state_and_proof(proofs.remove(0), new_states.remove(0), sender, config)
+                .await;
+        return record_outcome(records, res, submission_start.elapsed(), history).await;
+    }
+
+    // Simulate the batch first. Contracts deployed before `newFinalizedStates` was added revert
+    // it, and the proofs are then submitted one at a time instead. Any other failure counts as a
+    // failed submission of the whole batch, to be retried later.
+    let submission_start = Instant::now();
+    let simulated = new_finalized_states_call(proofs.clone(), new_states.clone(), sender, config)?
+        .call()
+        .await;
+    match simulated {
+        Ok(()) => {
+            let res = submit_parsed_states_and_proofs(proofs, new_states, sender, config).await;
+            record_outcome(records, res, submission_start.elapsed(), history).await
+        }
+        Err(err) if !err.is_revert() => {
+            let res = Err(ProverError::ContractError(err.into()));
+            record_outcome(records, res, submission_start.elapsed(), history).await
+        }
+        Err(err) => {
+            tracing::warn!(
+                "Contract rejected {} proofs in one transaction, submitting them one at a time: \
+                 {:?}",
+                records.len(),
+                err.decode_contract_revert::<LightClientErrors>()
+            );
+            for ((record, proof), new_state) in records.iter().zip(proofs).zip(new_states) {
+                let submission_start = Instant::now();
+                let res = submit_parsed_state_and_proof(proof, new_state, sender, config).await;
+                record_outcome(
+                    std::slice::from_ref(record),
+                    res,
+                    submission_start.elapsed(),
+                    history,
+                )
+                .await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Record the outcome of submitting `records` in the proof history.
+async fn record_outcome(
+    records: &[ProofRecord],
+    res: Result<(H256, u64), ProverError>,
+    submission_time: Duration,
+    history: &RwLock<ProofHistory>,
+) -> Result<(), ProverError> {
+    let mut history = history.write().await;
+    for record in records {
+        let block_height = record.block_height;
+        let persisted = match &res {
+            Ok((tx_hash, l1_block)) => history.record_submission(
+                block_height,
+                Submission {
+                    tx_hash: *tx_hash,
+                    l1_block: *l1_block,
+                    submission_time,
+                },
+            ),
+            Err(err) => history.record_failure(block_height, err.to_string()),
+        };
+        if let Err(err) = persisted {
+            tracing::warn!(
+                "Failed to record submission of proof for block {block_height}: {err:#}"
+            );
+        }
     }
-    tracing::debug!("Old state: {old_state:?}");
-    tracing::debug!("New state: {:?}", bundle.state);
+    res.map(|_| ())
+}
+
+/// The recorded proofs to submit next, in order, if any.
+///
+/// This is the latest recorded proof, if it has not been submitted yet and still advances the
+/// contract. If the contract needs the ends of one or more epochs before the latest proof, the
+/// recorded proofs for those come first, in order, as far as they have been recorded and not
+/// submitted yet. At most `max_batch_size` proofs are taken from the start of this sequence.
+///
+/// A proof which keeps failing is given up on, in case the contract rejects it.
+fn pending_batch(
+    schedule: &EpochSchedule,
+    history: &ProofHistory,
+    max_batch_size: usize,
+) -> Vec<ProofRecord> {
+    let Some(latest) = history.latest_pending() else {
+        return vec![];
+    };
+    if latest.block_height <= schedule.finalized_height {
+        return vec![];
+    }
+    schedule
+        .epoch_endings(latest.block_height)
+        .into_iter()
+        .map(|epoch_end| history.get(epoch_end))
+        .chain([Some(latest)])
+        .map_while(|record| record.filter(|record| !record.is_submitted()))
+        .take(max_batch_size.max(1))
+        .take_while(|record| record.submission_attempts < MAX_SUBMISSION_ATTEMPTS)
+        .cloned()
+        .collect()
+}
+
+/// Submit the pending recorded proofs, if any.
+///
+/// Returns whether a proof was submitted.
+async fn submit_pending_proof(
+    schedule: &EpochSchedule,
+    history: &RwLock<ProofHistory>,
+    sender: &L1Sender<L1Wallet>,
+    config: &StateProverConfig,
+) -> Result<bool, ProverError> {
+    let batch = pending_batch(schedule, &*history.read().await, config.max_batch_size);
+    if batch.is_empty() {
+        return Ok(false);
+    }
+    for record in &batch {
+        if record.submission_attempts > 0 {
+            tracing::info!(
+                "Retrying submission of the proof for block {} after {} failed attempts.",
+                record.block_height,
+                record.submission_attempts
+            );
+        }
+    }
+    submit_recorded_proofs(&batch, history, sender, config).await?;
+    Ok(true)
+}
 
+/// The inputs for proving one light client state, collected from a signature bundle.
+pub struct ProofJob {
+    entries: Vec<(StateVerKey, U256)>,
+    signer_bit_vec: Vec<bool>,
+    signatures: Vec<StateSignature>,
+    state: LightClientState,
+    threshold: U256,
+}
+
+/// Collect the valid signatures in `bundle` and check that they reach the threshold of `st`.
+pub fn prepare_proof_job(
+    st: &StakeTable<BLSPubKey, StateVerKey, CircuitField>,
+    bundle: &StateSignaturesBundle,
+) -> Result<ProofJob, ProverError> {
     let threshold = st.total_stake(SnapshotVersion::LastEpochStart)? * 2 / 3;
     tracing::info!("Threshold before syncing state: {}", threshold);
     let entries = st
@@ -328,37 +672,348 @@ pub async fn sync_state<Ver: StaticVersionType>(
     //     st.commitment(SnapshotVersion::LastEpochStart).unwrap()
     // );
 
-    tracing::info!("Collected latest state and signatures. Start generating SNARK proof.");
-    let proof_gen_start = time::Instant::now();
-    let (proof, public_input) = generate_state_update_proof::<_, _, _, _>(
-        &mut ark_std::rand::thread_rng(),
-        proving_key,
-        &entries,
+    Ok(ProofJob {
+        entries,
         signer_bit_vec,
         signatures,
-        &bundle.state,
-        &threshold,
-        config.stake_table_capacity,
-    )?;
-    let proof_gen_elapsed = proof_gen_start.elapsed();
-    tracing::info!("Proof generation completed. Elapsed: {proof_gen_elapsed:.3}");
+        state: bundle.state.clone(),
+        threshold,
+    })
+}
 
-    submit_state_and_proof(proof, public_input, config).await?;
+/// Queue the proof of `job` on the worker pool.
+pub fn queue_proof(
+    pool: &ProverPool,
+    proving_key: &Arc<ProvingKey>,
+    job: ProofJob,
+    stake_table_capacity: usize,
+) -> Result<ProofTask<Result<ProofRecord, ProverError>>, ProverError> {
+    let proving_key = proving_key.clone();
+    let block_height = job.state.block_height;
+    pool.submit(move || {
+        tracing::info!("Start generating SNARK proof for block {block_height}.");
+        let proof_gen_start = Instant::now();
+        let (proof, public_input) = generate_state_update_proof::<_, _, _, _>(
+            &mut ark_std::rand::thread_rng(),
+            &proving_key,
+            &job.entries,
+            job.signer_bit_vec,
+            job.signatures,
+            &job.state,
+            &job.threshold,
+            stake_table_capacity,
+        )?;
+        let proof_gen_elapsed = proof_gen_start.elapsed();
+        tracing::info!(
+            "Proof generation for block {block_height} completed. Elapsed: {proof_gen_elapsed:.3?}"
+        );
+        Ok(ProofRecord::new(proof, public_input, proof_gen_elapsed))
+    })
+    .map_err(|PoolFull| ProverError::Internal("proof queue is full".to_string()))
+}
 
-    tracing::info!("Successfully synced light client state.");
-    Ok(())
+pub async fn sync_state<Ver: StaticVersionType>(
+    st: &StakeTable<BLSPubKey, StateVerKey, CircuitField>,
+    proving_key: &Arc<ProvingKey>,
+    relay_server_client: &Client<ServerError, Ver>,
+    history: &RwLock<ProofHistory>,
+    pool: &ProverPool,
+    sender: &L1Sender<L1Wallet>,
+    config: &StateProverConfig,
+) -> Result<(), ProverError> {
+    tracing::info!("Start syncing light client state.");
+
+    // Each round advances the contract by up to `max_batch_size` states, until it reaches the
+    // latest state. This takes more than one round if the contract needs the ends of more epochs
+    // first than fit in one batch.
+    loop {
+        let schedule = read_epoch_schedule(config).await?;
+        tracing::info!(
+            "Current HotShot block height on contract: {} (epoch {})",
+            schedule.finalized_height,
+            schedule.current_epoch
+        );
+
+        // If a proof we generated earlier was never submitted, and it still advances the
+        // contract, submit it instead of proving a new state.
+        if submit_pending_proof(&schedule, history, sender, config).await? {
+            continue;
+        }
+
+        let latest = fetch_latest_state(relay_server_client).await?;
+        let latest_height = latest.state.block_height as u64;
+        tracing::info!("Latest HotShot block height: {latest_height}");
+        if schedule.finalized_height >= latest_height {
+            tracing::info!("No update needed.");
+            return Ok(());
+        }
+
+        // Prove the ends of the epochs the contract needs first, if any. Whatever does not fit in
+        // this round's batch, including the latest state, is left for the next round.
+        let batch_size = config.max_batch_size.max(1);
+        let epoch_endings = schedule.epoch_endings(latest_height);
+        if !epoch_endings.is_empty() {
+            tracing::info!("Contract needs the ends of epochs at blocks {epoch_endings:?} first.");
+        }
+        let mut bundles = vec![];
+        for epoch_end in epoch_endings.into_iter().take(batch_size) {
+            bundles.push(fetch_state_at(relay_server_client, epoch_end).await?);
+        }
+        if bundles.len() < batch_size {
+            bundles.push(latest);
+        }
+
+        let mut records = vec![];
+        for bundle in bundles {
+            tracing::debug!("New state: {:?}", bundle.state);
+            let job = prepare_proof_job(st, &bundle)?;
+            tracing::info!("Collected state and signatures. Start generating SNARK proof.");
+            let record = queue_proof(pool, proving_key, job, config.stake_table_capacity)?
+                .join()
+                .await
+                .ok_or_else(|| {
+                    ProverError::Internal("proof generation was cancelled".to_string())
+                })??;
+
+            // Record the proof before submitting it, so that it is not lost if submission fails.
+            if let Err(err) = history.write().await.insert(record.clone()) {
+                tracing::warn!("Failed to record proof: {err:#}");
+            }
+            records.push(record);
+        }
+        submit_recorded_proofs(&records, history, sender, config).await?;
+
+        if records.last().map(|record| record.block_height) == Some(latest_height) {
+            tracing::info!("Successfully synced light client state.");
+            return Ok(());
+        }
+    }
+}
+
+/// Prove and submit one state at a time, whenever the relay server has a new state.
+///
+/// Updates are spaced at least `update_interval` apart, measured from the start of each update, so
+/// that the time spent proving does not delay the schedule.
+async fn run_sequential_prover<Ver: StaticVersionType>(
+    st: Arc<StakeTable<BLSPubKey, StateVerKey, CircuitField>>,
+    proving_key: Arc<ProvingKey>,
+    relay_server_client: Arc<Client<ServerError, Ver>>,
+    history: Arc<RwLock<ProofHistory>>,
+    pool: Arc<ProverPool>,
+    sender: Arc<L1Sender<L1Wallet>>,
+    config: Arc<StateProverConfig>,
+) {
+    let mut last_synced_height = 0;
+    loop {
+        let height = match fetch_latest_state(&relay_server_client).await {
+            Ok(bundle) => bundle.state.block_height,
+            Err(err) => {
+                tracing::warn!("Cannot fetch the latest state from the relay server: {err}");
+                sleep(config.poll_interval).await;
+                continue;
+            }
+        };
+        if height <= last_synced_height {
+            tracing::debug!("No new state since block {last_synced_height}.");
+            sleep(config.poll_interval).await;
+            continue;
+        }
+
+        let update_start = Instant::now();
+        match sync_state(
+            &st,
+            &proving_key,
+            &relay_server_client,
+            &history,
+            &pool,
+            &sender,
+            &config,
+        )
+        .await
+        {
+            Ok(()) => last_synced_height = height,
+            Err(err) => tracing::error!("Cannot sync the light client state: {}", err),
+        }
+        let next_update = config
+            .update_interval
+            .saturating_sub(update_start.elapsed());
+        tracing::info!("Next update in {:?}", next_update);
+        sleep(next_update).await;
+    }
+}
+
+/// Queue proofs of new states in the background, while the latest finished proof is submitted.
+///
+/// A new state is queued for proving at most once every `update_interval`, as long as the pool has
+/// room for it. Queued proofs of states which the contract has already moved past are cancelled.
+async fn run_background_prover<Ver: StaticVersionType>(
+    st: Arc<StakeTable<BLSPubKey, StateVerKey, CircuitField>>,
+    proving_key: Arc<ProvingKey>,
+    relay_server_client: Arc<Client<ServerError, Ver>>,
+    history: Arc<RwLock<ProofHistory>>,
+    pool: Arc<ProverPool>,
+    sender: Arc<L1Sender<L1Wallet>>,
+    config: Arc<StateProverConfig>,
+) {
+    spawn(submit_proofs_loop(history.clone(), sender, config.clone()));
+
+    let mut queued = BTreeMap::<u64, CancelHandle>::new();
+    let mut last_queued_height = 0;
+    loop {
+        let update_start = Instant::now();
+        let schedule = match read_epoch_schedule(&config).await {
+            Ok(schedule) => {
+                let pending = queued.split_off(&(schedule.finalized_height + 1));
+                for (height, cancel) in std::mem::replace(&mut queued, pending) {
+                    tracing::debug!("Proof for block {height} is no longer needed.");
+                    cancel.cancel();
+                }
+                Some(schedule)
+            }
+            Err(err) => {
+                tracing::warn!("Cannot read the light client contract: {err}");
+                None
+            }
+        };
+
+        let bundle = match fetch_latest_state(&relay_server_client).await {
+            Ok(bundle) if bundle.state.block_height as u64 > last_queued_height => bundle,
+            Ok(_) => {
+                sleep(config.poll_interval).await;
+                continue;
+            }
+            Err(err) => {
+                tracing::warn!("Cannot fetch the latest state from the relay server: {err}");
+                sleep(config.poll_interval).await;
+                continue;
+            }
+        };
+        let height = bundle.state.block_height as u64;
+
+        // The contract accepts the latest state only after the ends of the epochs before it, so
+        // prove those as well, unless we already have.
+        let epoch_endings = schedule
+            .map(|schedule| schedule.epoch_endings(height))
+            .unwrap_or_default();
+        let mut missing_epoch_end = false;
+        for epoch_end in epoch_endings {
+            let proven = history.read().await.get(epoch_end).is_some();
+            if proven || queued.contains_key(&epoch_end) {
+                continue;
+            }
+            let res = match fetch_state_at(&relay_server_client, epoch_end).await {
+                Ok(bundle) => {
+                    queue_background_proof(&st, &proving_key, &pool, &history, &bundle, &config)
+                }
+                Err(err) => Err(err.into()),
+            };
+            match res {
+                Ok(cancel) => {
+                    queued.insert(epoch_end, cancel);
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        "Cannot queue proof for the end of an epoch at {epoch_end}: {err}"
+                    );
+                    missing_epoch_end = true;
+                    break;
+                }
+            }
+        }
+        if missing_epoch_end {
+            sleep(config.poll_interval).await;
+            continue;
+        }
+
+        match queue_background_proof(&st, &proving_key, &pool, &history, &bundle, &config) {
+            Ok(cancel) => {
+                queued.insert(height, cancel);
+                last_queued_height = height;
+            }
+            Err(err) => {
+                tracing::warn!("Cannot queue proof for block {height}: {err}");
+                sleep(config.poll_interval).await;
+                continue;
+            }
+        }
+
+        sleep(
+            config
+                .update_interval
+                .saturating_sub(update_start.elapsed()),
+        )
+        .await;
+    }
+}
+
+/// Queue the proof of `bundle`, and record the proof in `history` once it is done.
+fn queue_background_proof(
+    st: &StakeTable<BLSPubKey, StateVerKey, CircuitField>,
+    proving_key: &Arc<ProvingKey>,
+    pool: &ProverPool,
+    history: &Arc<RwLock<ProofHistory>>,
+    bundle: &StateSignaturesBundle,
+    config: &StateProverConfig,
+) -> Result<CancelHandle, ProverError> {
+    let height = bundle.state.block_height;
+    let job = prepare_proof_job(st, bundle)?;
+    let task = queue_proof(pool, proving_key, job, config.stake_table_capacity)?;
+    tracing::info!("Queued proof for block {height}.");
+    let cancel = task.cancel_handle();
+
+    let history = history.clone();
+    spawn(async move {
+        match task.join().await {
+            Some(Ok(record)) => {
+                if let Err(err) = history.write().await.insert(record) {
+                    tracing::warn!("Failed to record proof for block {height}: {err:#}");
+                }
+            }
+            Some(Err(err)) => tracing::error!("Cannot prove state at block {height}: {err}"),
+            None => tracing::info!("Proof for block {height} was cancelled."),
+        }
+    });
+    Ok(cancel)
+}
+
+/// Submit the latest proof whenever a new one is recorded.
+async fn submit_proofs_loop(
+    history: Arc<RwLock<ProofHistory>>,
+    sender: Arc<L1Sender<L1Wallet>>,
+    config: Arc<StateProverConfig>,
+) {
+    loop {
+        let res = match read_epoch_schedule(&config).await {
+            Ok(schedule) => submit_pending_proof(&schedule, &history, &sender, &config).await,
+            Err(err) => Err(err),
+        };
+        match res {
+            Ok(true) => tracing::info!("Successfully synced light client state."),
+            Ok(false) => {}
+            Err(err) => tracing::error!("Cannot sync the light client state: {}", err),
+        }
+        sleep(config.poll_interval).await;
+    }
+}
+
+/// State of the prover's HTTP server.
+struct ServerState {
+    history: Arc<RwLock<ProofHistory>>,
+    metrics: Arc<PrometheusMetrics>,
 }
 
 fn start_http_server<Ver: StaticVersionType + 'static>(
     port: u16,
     lightclient_address: Address,
+    state: ServerState,
     bind_version: Ver,
 ) -> io::Result<()> {
-    let mut app = tide_disco::App::<(), ServerError, Ver>::with_state(());
+    let mut app =
+        tide_disco::App::<RwLock<ServerState>, ServerError, Ver>::with_state(RwLock::new(state));
     let toml = toml::from_str::<toml::value::Value>(include_str!("../api/prover-service.toml"))
         .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
 
-    let mut api = Api::<(), ServerError, Ver>::new(toml)
+    let mut api = Api::<RwLock<ServerState>, ServerError, Ver>::new(toml)
         .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
 
     api.get("getlightclientcontract", move |_, _| {
@@ -366,6 +1021,42 @@ fn start_http_server<Ver: StaticVersionType + 'static>(
     })
     .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
 
+    api.get("proofs", |req, state| {
+        async move {
+            let limit = req
+                .opt_integer_param("limit")
+                .map_err(ServerError::from_request_error)?
+                .unwrap_or(DEFAULT_PROOF_LIST_LIMIT);
+            Ok(state.history.read().await.list(limit))
+        }
+        .boxed()
+    })
+    .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+
+    api.get("proof", |req, state| {
+        async move {
+            let height: u64 = req
+                .integer_param("height")
+                .map_err(ServerError::from_request_error)?;
+            state
+                .history
+                .read()
+                .await
+                .get(height)
+                .cloned()
+                .ok_or(ServerError::catch_all(
+                    StatusCode::NotFound,
+                    format!("no proof for block {height}"),
+                ))
+        }
+        .boxed()
+    })
+    .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?
+    .metrics("metrics", |_, state| {
+        async move { Ok(Cow::Borrowed(&*state.metrics)) }.boxed()
+    })
+    .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+
     app.register_module("api", api)
         .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
 
@@ -382,33 +1073,63 @@ pub async fn run_prover_service<Ver: StaticVersionType + 'static>(
         init_stake_table_from_orchestrator(&config.orchestrator_url, config.stake_table_capacity)
             .await,
     );
-    let proving_key = Arc::new(load_proving_key(config.stake_table_capacity));
+    let proving_key = Arc::new(load_proving_key(&config));
+    let history = Arc::new(RwLock::new(load_proof_history(&config)));
+    let pool = Arc::new(ProverPool::new(
+        config.proof_workers,
+        config.max_queued_proofs,
+    ));
     let relay_server_client =
         Arc::new(Client::<ServerError, Ver>::new(config.relay_server.clone()));
+    let sender = loop {
+        match connect_l1_sender(&config).await {
+            Ok(sender) => break Arc::new(sender),
+            Err(err) => {
+                tracing::warn!("Cannot connect to layer 1: {err}");
+                sleep(config.poll_interval).await;
+            }
+        }
+    };
     let config = Arc::new(config);
-    let update_interval = config.update_interval;
 
     tracing::info!("Light client address: {:?}", config.light_client_address);
 
     if let Some(port) = config.port {
-        if let Err(err) = start_http_server(port, config.light_client_address, bind_version) {
+        if let Err(err) = start_http_server(
+            port,
+            config.light_client_address,
+            ServerState {
+                history: history.clone(),
+                metrics: config.metrics.clone(),
+            },
+            bind_version,
+        ) {
             tracing::error!("Error starting http server: {}", err);
         }
     }
 
-    loop {
-        let st = st.clone();
-        let proving_key = proving_key.clone();
-        let relay_server_client = relay_server_client.clone();
-        let config = config.clone();
-        // Use block_on to avoid blocking the async runtime with this computationally heavy task
-        async_std::task::block_on(async move {
-            if let Err(err) = sync_state(&st, &proving_key, &relay_server_client, &config).await {
-                tracing::error!("Cannot sync the light client state: {}", err);
-            }
-        });
-        tracing::info!("Sleeping for {:?}", update_interval);
-        sleep(update_interval).await;
+    if config.background_proving {
+        run_background_prover(
+            st,
+            proving_key,
+            relay_server_client,
+            history,
+            pool,
+            sender,
+            config,
+        )
+        .await
+    } else {
+        run_sequential_prover(
+            st,
+            proving_key,
+            relay_server_client,
+            history,
+            pool,
+            sender,
+            config,
+        )
+        .await
     }
 }
 
@@ -417,12 +1138,25 @@ pub async fn run_prover_once<Ver: StaticVersionType>(config: StateProverConfig,
     let st =
         init_stake_table_from_orchestrator(&config.orchestrator_url, config.stake_table_capacity)
             .await;
-    let proving_key = load_proving_key(config.stake_table_capacity);
+    let proving_key = Arc::new(load_proving_key(&config));
+    let history = RwLock::new(load_proof_history(&config));
+    let pool = ProverPool::new(1, 1);
     let relay_server_client = Client::<ServerError, Ver>::new(config.relay_server.clone());
-
-    sync_state(&st, &proving_key, &relay_server_client, &config)
+    let sender = connect_l1_sender(&config)
         .await
-        .expect("Error syncing the light client state.");
+        .expect("Error connecting to layer 1.");
+
+    sync_state(
+        &st,
+        &proving_key,
+        &relay_server_client,
+        &history,
+        &pool,
+        &sender,
+        &config,
+    )
+    .await
+    .expect("Error syncing the light client state.");
 }
 
 #[derive(Debug, Display)]
@@ -481,7 +1215,10 @@ mod test {
     };
     use hotshot_stake_table::vec_based::StakeTable;
     use hotshot_types::light_client::StateSignKey;
-    use jf_primitives::signatures::{SchnorrSignatureScheme, SignatureScheme};
+    use jf_primitives::{
+        pcs::prelude::UnivariateUniversalParams,
+        signatures::{SchnorrSignatureScheme, SignatureScheme},
+    };
     use jf_utils::test_rng;
     use sequencer_utils::deployer;
 
@@ -626,6 +1363,15 @@ mod test {
                 orchestrator_url: Url::parse("http://localhost").unwrap(),
                 port: None,
                 stake_table_capacity: 10,
+                key_dir: None,
+                storage_path: None,
+                proof_workers: 1,
+                max_queued_proofs: 1,
+                poll_interval: Duration::default(),
+                background_proving: false,
+                max_batch_size: 1,
+                l1_sender: Default::default(),
+                metrics: Default::default(),
             }
         }
     }
@@ -647,10 +1393,124 @@ mod test {
         let mut config = StateProverConfig::default();
         config.update_l1_info(&anvil, contract.address());
         let state = super::read_contract_state(&config).await?;
-        assert_eq!(state, genesis.into());
+        assert_eq!(state, genesis.clone().into());
+
+        let schedule = super::read_epoch_schedule(&config).await?;
+        assert_eq!(schedule.finalized_height, genesis.block_height);
+        assert_eq!(schedule.blocks_per_epoch, BLOCKS_PER_EPOCH as u64);
+        assert_eq!(
+            schedule.current_epoch,
+            contract.current_epoch().call().await?
+        );
         Ok(())
     }
 
+    #[test]
+    fn test_epoch_schedule() {
+        let schedule = EpochSchedule {
+            finalized_height: 15,
+            current_epoch: 2,
+            blocks_per_epoch: BLOCKS_PER_EPOCH as u64,
+        };
+        assert_eq!(schedule.epoch_ending_height(), 20);
+        // States up to the end of the epoch can be submitted directly.
+        assert!(schedule.epoch_endings(16).is_empty());
+        assert!(schedule.epoch_endings(20).is_empty());
+        // Later states need the end of every epoch before them first.
+        assert_eq!(schedule.epoch_endings(21), [20]);
+        assert_eq!(schedule.epoch_endings(30), [20]);
+        assert_eq!(schedule.epoch_endings(35), [20, 30]);
+        assert_eq!(schedule.epoch_endings(55), [20, 30, 40, 50]);
+
+        // Once the contract is at the end of the epoch, it still needs the end of the next one.
+        let schedule = EpochSchedule {
+            finalized_height: 20,
+            ..schedule
+        };
+        assert!(schedule.epoch_endings(30).is_empty());
+        assert_eq!(schedule.epoch_endings(35), [30]);
+
+        // A contract without epochs never needs an intermediate state.
+        let schedule = EpochSchedule {
+            finalized_height: 15,
+            current_epoch: 1,
+            blocks_per_epoch: u32::MAX as u64,
+        };
+        assert!(schedule.epoch_endings(u32::MAX as u64).is_empty());
+        let schedule = EpochSchedule {
+            blocks_per_epoch: 0,
+            ..schedule
+        };
+        assert!(schedule.epoch_endings(35).is_empty());
+    }
+
+    #[test]
+    fn test_pending_batch() {
+        let record = |block_height| ProofRecord {
+            view_number: block_height,
+            block_height,
+            proof: Default::default(),
+            public_input: Default::default(),
+            generated_at: 0,
+            generation_time: Duration::default(),
+            submission: None,
+            submission_attempts: 0,
+            last_error: None,
+        };
+        let heights = |batch: Vec<ProofRecord>| {
+            batch
+                .into_iter()
+                .map(|record| record.block_height)
+                .collect::<Vec<_>>()
+        };
+        let schedule = EpochSchedule {
+            finalized_height: 15,
+            current_epoch: 2,
+            blocks_per_epoch: BLOCKS_PER_EPOCH as u64,
+        };
+
+        let mut history = ProofHistory::open(None).unwrap();
+        assert!(pending_batch(&schedule, &history, 2).is_empty());
+
+        // The latest state needs the end of the epoch, which has not been proven.
+        history.insert(record(25)).unwrap();
+        assert!(pending_batch(&schedule, &history, 2).is_empty());
+
+        // Once it has, both are submitted together, or the end of the epoch alone.
+        history.insert(record(20)).unwrap();
+        assert_eq!(heights(pending_batch(&schedule, &history, 2)), [20, 25]);
+        assert_eq!(heights(pending_batch(&schedule, &history, 3)), [20, 25]);
+        assert_eq!(heights(pending_batch(&schedule, &history, 1)), [20]);
+        assert_eq!(heights(pending_batch(&schedule, &history, 0)), [20]);
+
+        // A proof which keeps failing is left out.
+        for _ in 0..MAX_SUBMISSION_ATTEMPTS {
+            history.record_failure(25, "error".into()).unwrap();
+        }
+        assert_eq!(heights(pending_batch(&schedule, &history, 2)), [20]);
+
+        // A later state needs the ends of two epochs. Only those which have been proven are
+        // submitted, in order.
+        history.insert(record(35)).unwrap();
+        assert_eq!(heights(pending_batch(&schedule, &history, 3)), [20]);
+        history.insert(record(30)).unwrap();
+        assert_eq!(heights(pending_batch(&schedule, &history, 3)), [20, 30, 35]);
+        assert_eq!(heights(pending_batch(&schedule, &history, 4)), [20, 30, 35]);
+        assert_eq!(heights(pending_batch(&schedule, &history, 2)), [20, 30]);
+
+        // Once the contract reaches the end of the epoch, only later states are pending.
+        let schedule = EpochSchedule {
+            finalized_height: 20,
+            ..schedule
+        };
+        assert_eq!(heights(pending_batch(&schedule, &history, 2)), [30, 35]);
+        let schedule = EpochSchedule {
+            finalized_height: 35,
+            ..schedule
+        };
+        assert!(pending_batch(&schedule, &history, 2).is_empty());
+    }
+
     // This test is temporarily ignored. We are unifying the contract deployment in #1071.
     #[async_std::test]
     async fn test_submit_state_and_proof() -> Result<()> {
@@ -674,7 +1534,8 @@ mod test {
         let (pi, proof) = gen_state_proof(&genesis, new_state.clone(), &state_keys, &st);
         tracing::info!("Successfully generated proof for new state.");
 
-        super::submit_state_and_proof(proof, pi, &config).await?;
+        let sender = connect_l1_sender(&config).await?;
+        super::submit_state_and_proof(proof, pi, &sender, &config).await?;
         tracing::info!("Successfully submitted new finalized state to L1.");
         // test if new state is updated in l1
         let finalized_l1: ParsedLightClientState = contract.get_finalized_state().await?.into();
@@ -0,0 +1,185 @@
+//! A bounded pool of threads for generating proofs.
+//!
+//! Proof generation takes seconds to minutes of CPU time, so it must not run on the async runtime
+//! which also serves the prover's HTTP API. Proofs are instead generated on a fixed number of
+//! dedicated threads, fed from a bounded queue, and the async side waits for the result on a
+//! channel.
+
+use futures::channel::oneshot;
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The queue of the pool is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolFull;
+
+/// A fixed number of threads executing jobs from a bounded queue.
+#[derive(Debug)]
+pub struct ProverPool {
+    jobs: SyncSender<Job>,
+}
+
+impl ProverPool {
+    /// Start a pool of `num_workers` threads, which queues at most `queue_capacity` jobs that have
+    /// not yet started.
+    pub fn new(num_workers: usize, queue_capacity: usize) -> Self {
+        let (jobs, rx) = mpsc::sync_channel::<Job>(queue_capacity);
+        let rx = Arc::new(Mutex::new(rx));
+        for i in 0..num_workers.max(1) {
+            let rx = rx.clone();
+            thread::Builder::new()
+                .name(format!("prover-worker-{i}"))
+                .spawn(move || worker(rx))
+                .expect("failed to spawn prover worker");
+        }
+        Self { jobs }
+    }
+
+    /// Queue `f` to run on a worker thread.
+    ///
+    /// Fails without blocking if the queue is full.
+    pub fn submit<T, F>(&self, f: F) -> Result<ProofTask<T>, PoolFull>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let cancel = CancelHandle::default();
+        let job_cancel = cancel.clone();
+        let job: Job = Box::new(move || {
+            if job_cancel.is_cancelled() {
+                return;
+            }
+            // The receiver may have been dropped in the meantime, in which case nobody is
+            // interested in the result any more.
+            tx.send(f()).ok();
+        });
+        match self.jobs.try_send(job) {
+            Ok(()) => Ok(ProofTask { result: rx, cancel }),
+            Err(TrySendError::Full(_)) => Err(PoolFull),
+            Err(TrySendError::Disconnected(_)) => {
+                // Workers only exit when the pool is dropped, and survive panicking jobs, so this
+                // cannot happen while we hold `self`.
+                unreachable!("prover workers exited while the pool is alive")
+            }
+        }
+    }
+}
+
+fn worker(jobs: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        // Hold the lock only while waiting for the next job, not while running it.
+        let job = match jobs.lock() {
+            Ok(jobs) => jobs.recv(),
+            Err(_) => return,
+        };
+        match job {
+            Ok(job) => {
+                // A panicking job drops its result channel, so its task resolves to `None`, but
+                // the worker survives.
+                if catch_unwind(AssertUnwindSafe(job)).is_err() {
+                    tracing::error!("prover job panicked");
+                }
+            }
+            // The pool has been dropped.
+            Err(_) => return,
+        }
+    }
+}
+
+/// A handle to cancel a queued job.
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    /// Cancel the job.
+    ///
+    /// A job which has not started yet is skipped. A job which is already running runs to
+    /// completion, but its result is discarded.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The result of a job queued on a [`ProverPool`].
+///
+/// Dropping the task cancels the job.
+#[derive(Debug)]
+pub struct ProofTask<T> {
+    result: oneshot::Receiver<T>,
+    cancel: CancelHandle,
+}
+
+impl<T> ProofTask<T> {
+    /// A handle which can cancel this job without consuming the task.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// Wait for the job to finish.
+    ///
+    /// Returns [`None`] if the job was cancelled.
+    pub async fn join(mut self) -> Option<T> {
+        let result = (&mut self.result).await.ok();
+        if self.cancel.is_cancelled() {
+            return None;
+        }
+        result
+    }
+}
+
+impl<T> Drop for ProofTask<T> {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[async_std::test]
+    async fn test_prover_pool() {
+        let pool = ProverPool::new(1, 1);
+
+        // Block the only worker until we release it.
+        let (started, wait_started) = channel::<()>();
+        let (release, wait_release) = channel::<()>();
+        let running = pool
+            .submit(move || {
+                started.send(()).unwrap();
+                wait_release.recv().unwrap();
+                1
+            })
+            .unwrap();
+        wait_started.recv().unwrap();
+
+        // One more job fits in the queue.
+        let queued = pool.submit(|| 2).unwrap();
+        assert_eq!(pool.submit(|| 2).unwrap_err(), PoolFull);
+
+        // Cancel the queued job before it starts.
+        queued.cancel_handle().cancel();
+        release.send(()).unwrap();
+        assert_eq!(running.join().await, Some(1));
+        assert_eq!(queued.join().await, None);
+
+        // The pool keeps working after cancellations.
+        let task = pool.submit(|| 3).unwrap();
+        assert_eq!(task.join().await, Some(3));
+    }
+}
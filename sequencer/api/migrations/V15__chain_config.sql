@@ -0,0 +1,6 @@
+-- Full chain configs, keyed by commitment, so that headers which only carry the commitment can be
+-- resolved locally and served to peers.
+CREATE TABLE chain_config (
+    commitment VARCHAR PRIMARY KEY,
+    data       BYTEA NOT NULL
+);
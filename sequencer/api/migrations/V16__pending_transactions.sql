@@ -0,0 +1,11 @@
+-- Transactions pending in a builder, so that they survive a restart. `id` preserves the order in
+-- which transactions were received, and `created` is the time they were received, in seconds since
+-- the Unix epoch.
+CREATE TABLE pending_transaction (
+    id      BIGSERIAL PRIMARY KEY,
+    hash    VARCHAR NOT NULL UNIQUE,
+    data    BYTEA NOT NULL,
+    created BIGINT NOT NULL
+);
+
+CREATE INDEX pending_transaction_created_idx ON pending_transaction (created);
@@ -1,28 +1,45 @@
 use self::data_source::StateSignatureDataSource;
 use crate::{
-    context::SequencerContext, network, persistence::SequencerPersistence, state::ValidatedState,
-    state_signature::StateSigner, Node, SeqTypes,
+    context::SequencerContext, genesis::Genesis, network, persistence::SequencerPersistence,
+    state::ValidatedState, state_signature::StateSigner, ChainConfig, Header, NamespaceId, Node,
+    PubKey, SeqTypes,
 };
-use async_std::sync::Arc;
+use async_std::sync::{Arc, RwLock};
 use async_trait::async_trait;
-use data_source::{StateDataSource, SubmitDataSource};
+use commit::Commitment;
+use data_source::{NamespaceStatsDataSource, StateDataSource, SubmitDataSource};
 use hotshot::types::SystemContextHandle;
 use hotshot_query_service::data_source::ExtensibleDataSource;
 use hotshot_types::{data::ViewNumber, light_client::StateSignatureRequestBody};
+use participation::{ParticipationDataSource, ParticipationQueryData, VoteParticipation};
 use versioned_binary_serialization::version::StaticVersionType;
 
 pub mod data_source;
 pub mod endpoints;
 pub mod fs;
+pub mod namespace_stats;
 pub mod options;
+pub mod participation;
 pub mod sql;
 mod update;
 
+use namespace_stats::{NamespaceStats, NamespaceStatsQueryData};
 pub use options::Options;
 
 struct State<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType> {
     state_signer: Arc<StateSigner<Ver>>,
     handle: SystemContextHandle<SeqTypes, Node<N, P>>,
+    ns_stats: Arc<RwLock<NamespaceStats>>,
+    vote_participation: Arc<RwLock<VoteParticipation>>,
+    genesis: Option<Commitment<Genesis>>,
+    persistence: Arc<RwLock<P>>,
+}
+
+impl<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType> State<N, P, Ver> {
+    fn with_namespace_stats(mut self, ns_stats: NamespaceStats) -> Self {
+        self.ns_stats = Arc::new(RwLock::new(ns_stats));
+        self
+    }
 }
 
 impl<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType + 'static>
@@ -32,6 +49,10 @@ impl<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType + 'static
         Self {
             state_signer: ctx.state_signer(),
             handle: ctx.consensus().clone(),
+            ns_stats: Default::default(),
+            vote_participation: ctx.vote_participation(),
+            genesis: ctx.genesis(),
+            persistence: ctx.persistence(),
         }
     }
 }
@@ -60,9 +81,21 @@ impl<N: network::Type, D: Send + Sync, Ver: StaticVersionType, P: SequencerPersi
         self.as_ref().get_decided_state().await
     }
 
+    async fn get_decided_header(&self) -> Header {
+        self.as_ref().get_decided_header().await
+    }
+
     async fn get_undecided_state(&self, view: ViewNumber) -> Option<Arc<ValidatedState>> {
         self.as_ref().get_undecided_state(view).await
     }
+
+    async fn get_genesis(&self) -> Option<Commitment<Genesis>> {
+        self.as_ref().get_genesis().await
+    }
+
+    async fn get_chain_config(&self, commitment: Commitment<ChainConfig>) -> Option<ChainConfig> {
+        self.as_ref().get_chain_config(commitment).await
+    }
 }
 
 impl<N: network::Type, Ver: StaticVersionType, P: SequencerPersistence> StateDataSource
@@ -72,9 +105,37 @@ impl<N: network::Type, Ver: StaticVersionType, P: SequencerPersistence> StateDat
         self.handle.get_decided_state().await
     }
 
+    async fn get_decided_header(&self) -> Header {
+        self.handle
+            .get_decided_leaf()
+            .await
+            .get_block_header()
+            .clone()
+    }
+
     async fn get_undecided_state(&self, view: ViewNumber) -> Option<Arc<ValidatedState>> {
         self.handle.get_state(view).await
     }
+
+    async fn get_genesis(&self) -> Option<Commitment<Genesis>> {
+        self.genesis
+    }
+
+    async fn get_chain_config(&self, commitment: Commitment<ChainConfig>) -> Option<ChainConfig> {
+        match self
+            .persistence
+            .read()
+            .await
+            .load_chain_config(commitment)
+            .await
+        {
+            Ok(chain_config) => chain_config,
+            Err(err) => {
+                tracing::warn!(%commitment, "error loading chain config: {err:#}");
+                None
+            }
+        }
+    }
 }
 
 #[async_trait]
@@ -95,6 +156,46 @@ impl<N: network::Type, Ver: StaticVersionType, P: SequencerPersistence> StateSig
     }
 }
 
+impl<N: network::Type, D: Send + Sync, Ver: StaticVersionType, P: SequencerPersistence>
+    NamespaceStatsDataSource for StorageState<N, P, D, Ver>
+{
+    async fn get_namespace_stats(&self, ns_id: NamespaceId) -> NamespaceStatsQueryData {
+        self.as_ref().get_namespace_stats(ns_id).await
+    }
+
+    async fn get_all_namespace_stats(&self) -> Vec<NamespaceStatsQueryData> {
+        self.as_ref().get_all_namespace_stats().await
+    }
+}
+
+impl<N: network::Type, Ver: StaticVersionType, P: SequencerPersistence> NamespaceStatsDataSource
+    for State<N, P, Ver>
+{
+    async fn get_namespace_stats(&self, ns_id: NamespaceId) -> NamespaceStatsQueryData {
+        self.ns_stats.read().await.get(ns_id)
+    }
+
+    async fn get_all_namespace_stats(&self) -> Vec<NamespaceStatsQueryData> {
+        self.ns_stats.read().await.all()
+    }
+}
+
+impl<N: network::Type, D: Send + Sync, Ver: StaticVersionType, P: SequencerPersistence>
+    ParticipationDataSource<PubKey> for StorageState<N, P, D, Ver>
+{
+    async fn get_participation(&self) -> ParticipationQueryData<PubKey> {
+        self.as_ref().get_participation().await
+    }
+}
+
+impl<N: network::Type, Ver: StaticVersionType, P: SequencerPersistence>
+    ParticipationDataSource<PubKey> for State<N, P, Ver>
+{
+    async fn get_participation(&self) -> ParticipationQueryData<PubKey> {
+        self.vote_participation.read().await.query()
+    }
+}
+
 #[cfg(test)]
 mod test_helpers {
     use super::*;
@@ -586,6 +687,20 @@ mod api_tests {
         }
         assert!(found_txn);
         assert!(found_empty_block);
+
+        // The namespace statistics should account for the transaction we sent.
+        let stats: NamespaceStatsQueryData =
+            client.get("node/namespace/0/stats").send().await.unwrap();
+        assert_eq!(stats.namespace, NamespaceId::from(0));
+        let largest_window = stats.windows.last().unwrap();
+        assert!(largest_window.tx_count >= 1, "{largest_window:?}");
+        assert!(largest_window.bytes >= txn.payload().len() as u64);
+        let all: Vec<NamespaceStatsQueryData> =
+            client.get("node/namespaces/stats").send().await.unwrap();
+        assert!(
+            all.iter().any(|ns| ns.namespace == stats.namespace),
+            "{all:?}"
+        );
     }
 
     #[async_std::test]
@@ -786,16 +901,21 @@ mod test {
 
     use super::*;
     use crate::{
-        catchup::StatePeers, persistence::no_storage::NoStorage, testing::TestConfig, Header,
-        NodeState,
+        catchup::{StateCatchup, StatePeers},
+        persistence::{self, no_storage::NoStorage, PersistenceOptions},
+        state::HeaderInclusionProof,
+        state_signature::{block_comm_root, field_to_u256, LightClientHeaderProof},
+        testing::TestConfig,
+        ChainConfig, Header, NodeState,
     };
     use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
 
     use async_std::task::sleep;
     use commit::{Commitment, Committable};
+    use contract_bindings::light_client::LightClientState as ContractState;
     use es_version::SequencerVersion;
     use ethers::prelude::Signer;
-    use futures::stream::StreamExt;
+    use futures::{future::join_all, stream::StreamExt};
     use hotshot::types::EventType;
     use hotshot_query_service::availability::BlockQueryData;
     use hotshot_types::{event::LeafInfo, traits::block_contents::BlockHeader};
@@ -806,11 +926,13 @@ mod test {
     use portpicker::pick_unused_port;
     use std::time::Duration;
     use surf_disco::Client;
+    use tempfile::TempDir;
     use test_helpers::{
         state_signature_test_helper, state_test_helper, status_test_helper, submit_test_helper,
         TestNetwork,
     };
     use tide_disco::{app::AppHealth, error::ServerError, healthcheck::HealthStatus};
+    use url::Url;
 
     #[async_std::test]
     async fn test_healthcheck() {
@@ -894,6 +1016,236 @@ mod test {
                 .await
                 .is_ok())
         }
+
+        // Prove earlier headers against a later one.
+        let reference: Header = client.get("availability/header/4").send().await.unwrap();
+        for i in 0..4 {
+            let proof: HeaderInclusionProof = client
+                .get(&format!("block-merkle-tree/header/{i}/proof/4"))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(proof.verify_with_header(&reference).unwrap().height, i);
+        }
+        client
+            .get::<HeaderInclusionProof>("block-merkle-tree/header/4/proof/4")
+            .send()
+            .await
+            .unwrap_err();
+    }
+
+    #[async_std::test]
+    async fn test_light_client_header_proof_api() {
+        setup_logging();
+        setup_backtrace();
+
+        let port = pick_unused_port().expect("No ports free");
+
+        let storage = SqlDataSource::create_storage().await;
+        let options = SqlDataSource::options(
+            &storage,
+            Options::from(options::Http { port }).state(Default::default()),
+        );
+
+        let mut network = TestNetwork::new(options, [NoStorage; TestConfig::NUM_NODES]).await;
+
+        let url = format!("http://localhost:{port}").parse().unwrap();
+        let client: Client<ServerError, SequencerVersion> = Client::new(url);
+
+        client.connect(None).await;
+
+        // Wait until some blocks have been decided.
+        client
+            .socket("availability/stream/blocks/0")
+            .subscribe::<BlockQueryData<SeqTypes>>()
+            .await
+            .unwrap()
+            .take(4)
+            .collect::<Vec<_>>()
+            .await;
+
+        // sleep for few seconds so that state data is upserted
+        sleep(Duration::from_secs(5)).await;
+        network.stop_consensus().await;
+
+        // Check the proofs against light client states formed from the headers, as the prover
+        // would post them to the light client contract.
+        let light_client_height = 3;
+        let light_client_header = client
+            .get::<Header>(&format!("availability/header/{light_client_height}"))
+            .send()
+            .await
+            .unwrap();
+        let light_client_state = ContractState {
+            block_height: light_client_height,
+            block_comm_root: field_to_u256(
+                block_comm_root(&light_client_header.block_merkle_tree_root).unwrap(),
+            ),
+            ..Default::default()
+        };
+        for height in 0..light_client_height {
+            let proof = client
+                .get::<LightClientHeaderProof>(&format!(
+                    "light-client/header/{height}/proof/{light_client_height}"
+                ))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(proof.verify(&light_client_state).unwrap().height, height);
+        }
+
+        // A header is not committed to by the light client state at its own height.
+        client
+            .get::<LightClientHeaderProof>(&format!(
+                "light-client/header/{light_client_height}/proof/{light_client_height}"
+            ))
+            .send()
+            .await
+            .unwrap_err();
+    }
+
+    #[async_std::test]
+    async fn test_check_genesis() {
+        setup_logging();
+        setup_backtrace();
+
+        let port = pick_unused_port().expect("No ports free");
+        let _network = TestNetwork::new(
+            Options::from(options::Http { port }).catchup(Default::default()),
+            [NoStorage; TestConfig::NUM_NODES],
+        )
+        .await;
+        let url: Url = format!("http://localhost:{port}").parse().unwrap();
+        let client: Client<ServerError, SequencerVersion> = Client::new(url.clone());
+        client.connect(None).await;
+
+        // The test network starts from the default genesis.
+        let genesis = Genesis::new(ChainConfig::default());
+        assert_eq!(
+            client
+                .get::<Commitment<Genesis>>("catchup/genesis")
+                .send()
+                .await
+                .unwrap(),
+            genesis.commit()
+        );
+
+        let unreachable = format!("http://localhost:{}", pick_unused_port().unwrap());
+        let peers = StatePeers::<SequencerVersion>::from_urls(vec![
+            unreachable.parse().unwrap(),
+            url.clone(),
+        ]);
+        peers.check_genesis(genesis.commit()).await.unwrap();
+
+        // Without a reachable peer, the check does not pass, but waits for one.
+        let peers = StatePeers::<SequencerVersion>::from_urls(vec![unreachable.parse().unwrap()]);
+        async_std::future::timeout(
+            Duration::from_secs(3),
+            peers.check_genesis(genesis.commit()),
+        )
+        .await
+        .unwrap_err();
+
+        // A node with a different genesis refuses to join.
+        let peers = StatePeers::<SequencerVersion>::from_urls(vec![url]);
+        let other = Genesis::new(ChainConfig::new(1, 10240, 0));
+        peers.check_genesis(other.commit()).await.unwrap_err();
+    }
+
+    #[async_std::test]
+    async fn test_chain_config_catchup() {
+        setup_logging();
+        setup_backtrace();
+
+        // Use persistent storage, which is where nodes keep the chain configs they serve.
+        let tmp = (0..TestConfig::NUM_NODES)
+            .map(|_| TempDir::new().unwrap())
+            .collect::<Vec<_>>();
+        let persistence: [_; TestConfig::NUM_NODES] = join_all(tmp.iter().map(|dir| {
+            persistence::fs::Options {
+                path: dir.path().into(),
+            }
+            .create()
+        }))
+        .await
+        .into_iter()
+        .collect::<anyhow::Result<Vec<_>>>()
+        .unwrap()
+        .try_into()
+        .unwrap();
+
+        let port = pick_unused_port().expect("No ports free");
+        let _network = TestNetwork::new(
+            Options::from(options::Http { port }).catchup(Default::default()),
+            persistence,
+        )
+        .await;
+        let url = format!("http://localhost:{port}").parse().unwrap();
+        let client: Client<ServerError, SequencerVersion> = Client::new(url);
+        client.connect(None).await;
+
+        // The test network runs with the default chain config.
+        let chain_config = ChainConfig::default();
+        assert_eq!(
+            client
+                .get::<ChainConfig>(&format!("catchup/chain-config/{}", chain_config.commit()))
+                .send()
+                .await
+                .unwrap(),
+            chain_config
+        );
+
+        // Unknown configs are not found.
+        let unknown = ChainConfig::new(1, 10240, 0);
+        client
+            .get::<ChainConfig>(&format!("catchup/chain-config/{}", unknown.commit()))
+            .send()
+            .await
+            .unwrap_err();
+
+        // Peers resolve commitments through the catchup API.
+        let peers =
+            StatePeers::<SequencerVersion>::from_urls(vec![format!("http://localhost:{port}")
+                .parse()
+                .unwrap()]);
+        assert_eq!(
+            peers.fetch_chain_config(chain_config.commit()).await,
+            chain_config
+        );
+    }
+
+    #[async_std::test]
+    async fn test_latest_header_catchup() {
+        setup_logging();
+        setup_backtrace();
+
+        let port = pick_unused_port().expect("No ports free");
+        let network = TestNetwork::new(
+            Options::from(options::Http { port }).catchup(Default::default()),
+            [NoStorage; TestConfig::NUM_NODES],
+        )
+        .await;
+        let url = format!("http://localhost:{port}").parse().unwrap();
+        let client: Client<ServerError, SequencerVersion> = Client::new(url);
+        client.connect(None).await;
+
+        // Wait for a few blocks to be decided.
+        while network.server.consensus().get_decided_leaf().await.get_height() < 2 {
+            sleep(Duration::from_secs(1)).await;
+        }
+
+        // The latest header never goes backwards.
+        let header = client.get::<Header>("catchup/header").send().await.unwrap();
+        assert!(header.height >= 2);
+        let peers =
+            StatePeers::<SequencerVersion>::from_urls(vec![format!("http://localhost:{port}")
+                .parse()
+                .unwrap()]);
+        assert!(peers.fetch_latest_header().await.unwrap().height >= header.height);
+
+        // With no peers to ask, fetching fails instead of retrying forever.
+        let no_peers = StatePeers::<SequencerVersion>::default();
+        no_peers.fetch_latest_header().await.unwrap_err();
     }
 
     #[async_std::test]
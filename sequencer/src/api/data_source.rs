@@ -1,17 +1,20 @@
 use super::{
     fs,
+    namespace_stats::NamespaceStatsQueryData,
     options::{Options, Query},
     sql,
 };
 use crate::{
+    genesis::Genesis,
     network,
     persistence::{self, SequencerPersistence},
     state::{BlockMerkleTree, Delta, FeeAccount, FeeMerkleTree, ValidatedState},
-    Node, SeqTypes,
+    ChainConfig, Header, NamespaceId, Node, SeqTypes,
 };
 use anyhow::Context;
 use async_std::sync::Arc;
 use async_trait::async_trait;
+use commit::Commitment;
 use hotshot::types::SystemContextHandle;
 use hotshot_query_service::{
     availability::AvailabilityDataSource,
@@ -105,7 +108,16 @@ pub(crate) trait StateSignatureDataSource<N: network::Type> {
 #[trait_variant::make(StateDataSource: Send)]
 pub(crate) trait LocalStateDataSource {
     async fn get_decided_state(&self) -> Arc<ValidatedState>;
+    async fn get_decided_header(&self) -> Header;
     async fn get_undecided_state(&self, view: ViewNumber) -> Option<Arc<ValidatedState>>;
+    async fn get_genesis(&self) -> Option<Commitment<Genesis>>;
+    async fn get_chain_config(&self, commitment: Commitment<ChainConfig>) -> Option<ChainConfig>;
+}
+
+#[trait_variant::make(NamespaceStatsDataSource: Send)]
+pub(crate) trait LocalNamespaceStatsDataSource {
+    async fn get_namespace_stats(&self, ns_id: NamespaceId) -> NamespaceStatsQueryData;
+    async fn get_all_namespace_stats(&self) -> Vec<NamespaceStatsQueryData>;
 }
 
 #[async_trait]
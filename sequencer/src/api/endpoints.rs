@@ -2,7 +2,8 @@
 
 use super::{
     data_source::{
-        SequencerDataSource, StateDataSource, StateSignatureDataSource, SubmitDataSource,
+        NamespaceStatsDataSource, SequencerDataSource, StateDataSource, StateSignatureDataSource,
+        SubmitDataSource,
     },
     StorageState,
 };
@@ -10,23 +11,27 @@ use crate::{
     block::payload::{parse_ns_payload, NamespaceProof},
     network,
     persistence::SequencerPersistence,
-    state::{BlockMerkleTree, FeeAccountProof, ValidatedState},
-    NamespaceId, SeqTypes, Transaction,
+    state::{
+        BlockMerkleProof, BlockMerkleTree, FeeAccountProof, HeaderInclusionProof, ValidatedState,
+    },
+    state_signature::LightClientHeaderProof,
+    ChainConfig, Header, NamespaceId, SeqTypes, Transaction,
 };
 use anyhow::Result;
 use async_std::sync::{Arc, RwLock};
-use commit::Committable;
+use commit::{Commitment, Committable};
 use ethers::prelude::U256;
 use futures::{try_join, FutureExt};
 use hotshot_query_service::{
     availability::{self, AvailabilityDataSource, CustomSnafu, FetchBlockSnafu},
-    merklized_state::{self, MerklizedState, MerklizedStateDataSource},
+    merklized_state::{self, MerklizedState, MerklizedStateDataSource, Snapshot},
     node, Error,
 };
 use hotshot_types::{data::ViewNumber, traits::node_implementation::ConsensusTime};
 use jf_primitives::merkle_tree::MerkleTreeScheme;
 use serde::{Deserialize, Serialize};
 use snafu::OptionExt;
+use std::time::Duration;
 use tagged_base64::TaggedBase64;
 use tide_disco::{
     method::{ReadState, WriteState},
@@ -122,7 +127,12 @@ where
                 ..
             } = proof
             {
-                parse_ns_payload(ns_payload_flat, ns_id)
+                let ns_table = block.payload().get_ns_table();
+                let format = ns_table
+                    .lookup(ns_id)
+                    .map(|ns_index| ns_table.get_payload_format(ns_index))
+                    .unwrap_or_default();
+                parse_ns_payload(ns_payload_flat, ns_id, ns_table.version(), format)
             } else {
                 Vec::new()
             };
@@ -148,10 +158,24 @@ where
     D: SequencerDataSource + Send + Sync + 'static,
     P: SequencerPersistence,
 {
-    let api = node::define_api::<AvailState<N, P, D, Ver>, SeqTypes, Ver>(
-        &Default::default(),
-        bind_version,
-    )?;
+    let mut options = node::Options::default();
+    let extension = toml::from_str(include_str!("../../api/node.toml"))?;
+    options.extensions.push(extension);
+
+    let mut api =
+        node::define_api::<AvailState<N, P, D, Ver>, SeqTypes, Ver>(&options, bind_version)?;
+
+    api.get("namespacestats", |_, state| {
+        async move { Ok(state.get_all_namespace_stats().await) }.boxed()
+    })?
+    .get("getnamespacestats", |req, state| {
+        async move {
+            let ns_id: u64 = req.integer_param("namespace")?;
+            Ok(state.get_namespace_stats(ns_id.into()).await)
+        }
+        .boxed()
+    })?;
+
     Ok(api)
 }
 pub(super) fn submit<N, P, S, Ver: StaticVersionType + 'static>() -> Result<Api<S, Error, Ver>>
@@ -281,6 +305,33 @@ where
             Ok(frontier)
         }
         .boxed()
+    })?
+    .get("chain_config", |req, state| {
+        async move {
+            let commitment = req
+                .blob_param::<Commitment<ChainConfig>>("commitment")
+                .map_err(Error::from_request_error)?;
+            state
+                .get_chain_config(commitment)
+                .await
+                .ok_or(Error::catch_all(
+                    StatusCode::NotFound,
+                    format!("chain config {commitment} is not known"),
+                ))
+        }
+        .boxed()
+    })?
+    .get("header", |_, state| {
+        async move { Ok(state.get_decided_header().await) }.boxed()
+    })?
+    .get("genesis", |_, state| {
+        async move {
+            state.get_genesis().await.ok_or(Error::catch_all(
+                StatusCode::NotFound,
+                "genesis is not known".into(),
+            ))
+        }
+        .boxed()
     })?;
 
     Ok(api)
@@ -302,3 +353,134 @@ where
     )?;
     Ok(api)
 }
+
+/// Prove that the header at `height` is included in the block Merkle tree of the header at
+/// `reference_height`.
+///
+/// Returns the proof along with the reference header.
+async fn prove_header<S>(
+    state: &S,
+    height: u64,
+    reference_height: u64,
+    timeout: Duration,
+) -> Result<(HeaderInclusionProof, Header), Error>
+where
+    S: AvailabilityDataSource<SeqTypes>
+        + MerklizedStateDataSource<SeqTypes, BlockMerkleTree>
+        + Sync,
+{
+    if height >= reference_height {
+        return Err(Error::catch_all(
+            StatusCode::BadRequest,
+            format!(
+                "the header at height {reference_height} does not commit to the header at height \
+                 {height}"
+            ),
+        ));
+    }
+
+    let fetch_header = |height: u64| async move {
+        state
+            .get_leaf(height as usize)
+            .await
+            .with_timeout(timeout)
+            .await
+            .map(|leaf| leaf.leaf().get_block_header().clone())
+            .ok_or(Error::catch_all(
+                StatusCode::NotFound,
+                format!("header {height} not available"),
+            ))
+    };
+    let (header, reference) = try_join!(fetch_header(height), fetch_header(reference_height))?;
+
+    // The Merkle nodes stored at `reference_height` are those of the tree committed to by the
+    // header at that height.
+    let path = MerklizedStateDataSource::<SeqTypes, BlockMerkleTree>::get_path(
+        state,
+        Snapshot::Index(reference_height),
+        height,
+    )
+    .await
+    .map_err(|err| {
+        Error::catch_all(
+            StatusCode::NotFound,
+            format!("no proof for header {height} at height {reference_height}: {err}"),
+        )
+    })?;
+
+    let proof = HeaderInclusionProof::new(header, BlockMerkleProof::new(height, path));
+    Ok((proof, reference))
+}
+
+pub(super) fn block_merkle_tree<N, P, D, Ver: StaticVersionType + 'static>(
+    _: Ver,
+) -> Result<Api<AvailState<N, P, D, Ver>, Error, Ver>>
+where
+    N: network::Type,
+    D: SequencerDataSource
+        + MerklizedStateDataSource<SeqTypes, BlockMerkleTree>
+        + Send
+        + Sync
+        + 'static,
+    P: SequencerPersistence,
+{
+    let toml = toml::from_str::<toml::Value>(include_str!("../../api/block_merkle_tree.toml"))?;
+    let mut api = Api::<AvailState<N, P, D, Ver>, Error, Ver>::new(toml)?;
+    let timeout = availability::Options::default().fetch_timeout;
+
+    api.get("getheaderproof", move |req, state| {
+        async move {
+            let height = req
+                .integer_param("height")
+                .map_err(Error::from_request_error)?;
+            let reference_height = req
+                .integer_param("reference_height")
+                .map_err(Error::from_request_error)?;
+            let (proof, _) = prove_header(state, height, reference_height, timeout).await?;
+            Ok(proof)
+        }
+        .boxed()
+    })?;
+
+    Ok(api)
+}
+
+pub(super) fn light_client<N, P, D, Ver: StaticVersionType + 'static>(
+    _: Ver,
+) -> Result<Api<AvailState<N, P, D, Ver>, Error, Ver>>
+where
+    N: network::Type,
+    D: SequencerDataSource
+        + MerklizedStateDataSource<SeqTypes, BlockMerkleTree>
+        + Send
+        + Sync
+        + 'static,
+    P: SequencerPersistence,
+{
+    let toml = toml::from_str::<toml::Value>(include_str!("../../api/light_client.toml"))?;
+    let mut api = Api::<AvailState<N, P, D, Ver>, Error, Ver>::new(toml)?;
+    let timeout = availability::Options::default().fetch_timeout;
+
+    api.get("getheaderproof", move |req, state| {
+        async move {
+            let height = req
+                .integer_param("height")
+                .map_err(Error::from_request_error)?;
+            let light_client_height = req
+                .integer_param("light_client_height")
+                .map_err(Error::from_request_error)?;
+            // The light client state at a given height commits to the block Merkle tree of the
+            // header at that height.
+            let (inclusion, light_client_header) =
+                prove_header(state, height, light_client_height, timeout).await?;
+            Ok(LightClientHeaderProof {
+                light_client_height,
+                block_merkle_tree_root: light_client_header.block_merkle_tree_root,
+                inclusion,
+            })
+        }
+        .boxed()
+    })?;
+
+    Ok(api)
+}
@@ -0,0 +1,376 @@
+//! Per-namespace block space usage statistics.
+//!
+//! Statistics are aggregated from the namespace table of each decided header (and the decided
+//! payload, when it is available) over a set of rolling windows, measured in blocks. They are
+//! served by the `node` API and exported as Prometheus metrics.
+
+use crate::{Header, NamespaceId, Payload, SeqTypes};
+use ethers::types::U256;
+use hotshot::types::{Event, EventType};
+use hotshot_types::{
+    event::LeafInfo,
+    traits::metrics::{Counter, Gauge, Metrics, NoMetrics},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+/// Default rolling windows, in blocks, over which statistics are reported.
+pub const DEFAULT_WINDOWS: [usize; 3] = [10, 100, 1000];
+
+/// Maximum number of distinct namespaces for which we create Prometheus metrics.
+///
+/// Namespace IDs are chosen by users, so we bound the cardinality of the exported metrics. Stats
+/// for additional namespaces are still available from the API.
+const MAX_NAMESPACE_METRICS: usize = 100;
+
+/// Statistics for a single namespace over a single window.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct NamespaceWindowStats {
+    /// The requested size of the window, in blocks.
+    pub window: usize,
+    /// The number of blocks actually observed in this window.
+    ///
+    /// This may be less than `window` shortly after the node starts.
+    pub blocks: usize,
+    /// The number of transactions in this namespace.
+    pub tx_count: u64,
+    /// The number of payload bytes used by this namespace.
+    pub bytes: u64,
+    /// The fraction of all payload bytes in the window which were used by this namespace.
+    pub block_share: f64,
+    /// The builder fees paid for the blocks in the window, weighted by this namespace's share of
+    /// the bytes in each block.
+    pub fee_weighted_usage: U256,
+    /// The average throughput of this namespace, according to the header timestamps.
+    pub bytes_per_second: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NamespaceStatsQueryData {
+    pub namespace: NamespaceId,
+    pub windows: Vec<NamespaceWindowStats>,
+}
+
+/// Block space used by one namespace in one block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct NamespaceUsage {
+    tx_count: u64,
+    bytes: u64,
+}
+
+/// Block space used by each namespace in one decided block.
+#[derive(Clone, Debug)]
+struct BlockUsage {
+    timestamp: u64,
+    total_bytes: u64,
+    fee: U256,
+    namespaces: HashMap<NamespaceId, NamespaceUsage>,
+}
+
+impl BlockUsage {
+    fn new(header: &Header, payload: Option<&Payload>) -> Self {
+        let ns_table = &header.ns_table;
+
+        // Namespace offsets are not checked against the payload, so we bound every range by the
+        // largest offset in the table to make sure we never count a byte twice.
+        let payload_bound = (0..ns_table.len())
+            .map(|ns_index| ns_table.get_table_entry(ns_index).1)
+            .max()
+            .unwrap_or(0);
+
+        // A namespace listed more than once is only ever read from its first entry (see
+        // `NameSpaceTable::lookup`), so that is the only entry we count.
+        let mut namespaces: HashMap<NamespaceId, NamespaceUsage> = HashMap::new();
+        for ns_index in 0..ns_table.len() {
+            let (ns_id, range) = ns_table.get_payload_range(ns_index, payload_bound);
+            namespaces.entry(ns_id).or_insert(NamespaceUsage {
+                tx_count: 0,
+                bytes: range.len() as u64,
+            });
+        }
+
+        // Transaction counts require the transaction tables, which are only in the payload.
+        if let Some(payload) = payload {
+            for (ns_id, usage) in namespaces.iter_mut() {
+                usage.tx_count = payload.namespace(*ns_id).map_or(0, |txs| txs.len() as u64);
+            }
+        }
+
+        Self {
+            timestamp: header.timestamp,
+            total_bytes: namespaces.values().map(|usage| usage.bytes).sum(),
+            fee: header.fee_info.amount().into(),
+            namespaces,
+        }
+    }
+
+    /// The portion of this block's fee attributed to `usage`.
+    fn weighted_fee(&self, usage: &NamespaceUsage) -> U256 {
+        if self.total_bytes == 0 {
+            return U256::zero();
+        }
+        self.fee * usage.bytes / self.total_bytes
+    }
+}
+
+#[derive(Debug)]
+struct PerNamespaceMetrics {
+    tx_count: Box<dyn Counter>,
+    bytes: Box<dyn Counter>,
+    /// Share of block bytes over the shortest window, in basis points.
+    block_share: Box<dyn Gauge>,
+}
+
+/// Rolling per-namespace statistics.
+#[derive(Debug)]
+pub struct NamespaceStats {
+    windows: Vec<usize>,
+    blocks: VecDeque<BlockUsage>,
+    registry: Box<dyn Metrics>,
+    metrics: HashMap<NamespaceId, PerNamespaceMetrics>,
+}
+
+impl Default for NamespaceStats {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOWS.to_vec(), &NoMetrics)
+    }
+}
+
+impl NamespaceStats {
+    /// Track statistics over the given windows, exporting metrics to `metrics`.
+    pub fn new(mut windows: Vec<usize>, metrics: &dyn Metrics) -> Self {
+        windows.sort();
+        windows.dedup();
+        Self {
+            windows,
+            blocks: Default::default(),
+            registry: metrics.subgroup("namespace".into()),
+            metrics: Default::default(),
+        }
+    }
+
+    /// Record statistics for any blocks decided in `event`.
+    pub fn handle_event(&mut self, event: &Event<SeqTypes>) {
+        let EventType::Decide { leaf_chain, .. } = &event.event else {
+            return;
+        };
+        // The leaf chain is in reverse chronological order.
+        for LeafInfo { leaf, .. } in leaf_chain.iter().rev() {
+            self.record(leaf.get_block_header(), leaf.get_block_payload().as_ref());
+        }
+    }
+
+    /// Record statistics for a newly decided block.
+    pub fn record(&mut self, header: &Header, payload: Option<&Payload>) {
+        let block = BlockUsage::new(header, payload);
+        for (ns_id, usage) in &block.namespaces {
+            if let Some(metrics) = self.namespace_metrics(*ns_id) {
+                metrics.tx_count.add(usage.tx_count as usize);
+                metrics.bytes.add(usage.bytes as usize);
+            }
+        }
+
+        self.blocks.push_back(block);
+        let max_window = self.windows.last().copied().unwrap_or(0);
+        while self.blocks.len() > max_window {
+            self.blocks.pop_front();
+        }
+
+        if let Some(&window) = self.windows.first() {
+            for (ns_id, metrics) in &self.metrics {
+                let share = self.window_stats(*ns_id, window).block_share;
+                metrics.block_share.set((share * 10_000.0) as usize);
+            }
+        }
+    }
+
+    /// Statistics for `ns_id` over each window.
+    pub fn get(&self, ns_id: NamespaceId) -> NamespaceStatsQueryData {
+        NamespaceStatsQueryData {
+            namespace: ns_id,
+            windows: self
+                .windows
+                .iter()
+                .map(|&window| self.window_stats(ns_id, window))
+                .collect(),
+        }
+    }
+
+    /// Statistics for every namespace which appears in the largest window, ordered by ID.
+    pub fn all(&self) -> Vec<NamespaceStatsQueryData> {
+        self.blocks
+            .iter()
+            .flat_map(|block| block.namespaces.keys().copied())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|ns_id| self.get(ns_id))
+            .collect()
+    }
+
+    fn window_stats(&self, ns_id: NamespaceId, window: usize) -> NamespaceWindowStats {
+        let blocks = self.blocks.iter().rev().take(window).collect::<Vec<_>>();
+        let mut stats = NamespaceWindowStats {
+            window,
+            blocks: blocks.len(),
+            ..Default::default()
+        };
+
+        let mut total_bytes = 0;
+        for block in &blocks {
+            total_bytes += block.total_bytes;
+            if let Some(usage) = block.namespaces.get(&ns_id) {
+                stats.tx_count += usage.tx_count;
+                stats.bytes += usage.bytes;
+                stats.fee_weighted_usage += block.weighted_fee(usage);
+            }
+        }
+        if total_bytes > 0 {
+            stats.block_share = stats.bytes as f64 / total_bytes as f64;
+        }
+
+        // `blocks` is newest first. The elapsed time starts at the oldest block, so the bytes of
+        // that block were sequenced before the interval and do not count towards the rate.
+        if let Some((oldest, newer)) = blocks.split_last() {
+            let elapsed = blocks[0].timestamp.saturating_sub(oldest.timestamp);
+            if elapsed > 0 {
+                let bytes: u64 = newer
+                    .iter()
+                    .filter_map(|block| block.namespaces.get(&ns_id))
+                    .map(|usage| usage.bytes)
+                    .sum();
+                stats.bytes_per_second = bytes as f64 / elapsed as f64;
+            }
+        }
+
+        stats
+    }
+
+    fn namespace_metrics(&mut self, ns_id: NamespaceId) -> Option<&PerNamespaceMetrics> {
+        if !self.metrics.contains_key(&ns_id) {
+            if self.metrics.len() >= MAX_NAMESPACE_METRICS {
+                tracing::debug!("not exporting metrics for namespace {ns_id}, limit reached");
+                return None;
+            }
+            let metrics = PerNamespaceMetrics {
+                tx_count: self
+                    .registry
+                    .create_counter(format!("{ns_id}_tx_count"), None),
+                bytes: self
+                    .registry
+                    .create_counter(format!("{ns_id}_bytes"), Some("bytes".into())),
+                block_share: self
+                    .registry
+                    .create_gauge(format!("{ns_id}_block_share"), Some("bps".into())),
+            };
+            self.metrics.insert(ns_id, metrics);
+        }
+        self.metrics.get(&ns_id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{block::tables::NameSpaceTable, state::FeeInfo, NodeState, Transaction};
+    use ethers::types::Address;
+    use hotshot_types::traits::block_contents::{
+        vid_commitment, BlockHeader, BlockPayload, GENESIS_VID_NUM_STORAGE_NODES,
+    };
+
+    fn block(
+        timestamp: u64,
+        fee: u64,
+        txs: impl IntoIterator<Item = (u64, usize)>,
+    ) -> (Header, Payload) {
+        let payload = Payload::from_txs(
+            txs.into_iter()
+                .map(|(ns_id, len)| Transaction::new(ns_id.into(), vec![0; len])),
+            &Default::default(),
+        )
+        .unwrap();
+        let payload_bytes = payload.encode().unwrap().collect();
+        let mut header = Header::genesis(
+            &NodeState::mock(),
+            vid_commitment(&payload_bytes, GENESIS_VID_NUM_STORAGE_NODES),
+            payload.get_ns_table().clone(),
+        );
+        header.timestamp = timestamp;
+        header.fee_info = FeeInfo::new(Address::default(), fee);
+        (header, payload)
+    }
+
+    #[test]
+    fn test_namespace_stats_windows() {
+        let mut stats = NamespaceStats::new(vec![2, 1], &NoMetrics);
+
+        // Block 0 only contains namespace 1; block 1 splits its bytes evenly between namespaces 1
+        // and 2.
+        let (header, payload) = block(10, 100, [(1, 10)]);
+        stats.record(&header, Some(&payload));
+        let (header, payload) = block(12, 100, [(1, 10), (2, 10)]);
+        stats.record(&header, Some(&payload));
+
+        let ns1 = stats.get(1.into());
+        assert_eq!(ns1.windows.len(), 2);
+
+        // Window of 1 block.
+        let w = &ns1.windows[0];
+        assert_eq!((w.window, w.blocks, w.tx_count), (1, 1, 1));
+        assert_eq!(w.block_share, 0.5);
+        assert_eq!(w.fee_weighted_usage, 50.into());
+
+        // Window of 2 blocks.
+        let w = &ns1.windows[1];
+        assert_eq!((w.window, w.blocks, w.tx_count), (2, 2, 2));
+        assert_eq!(w.fee_weighted_usage, 150.into());
+        assert!(
+            w.block_share > 0.5 && w.block_share < 1.0,
+            "{}",
+            w.block_share
+        );
+        // Only the bytes of the newer block were sequenced in the 2 seconds between the blocks.
+        assert_eq!(w.bytes_per_second, ns1.windows[0].bytes as f64 / 2.0);
+
+        // The oldest block falls out of every window once a new block is recorded.
+        let (header, _) = block(14, 100, [(2, 10)]);
+        stats.record(&header, None);
+        let ns1 = stats.get(1.into());
+        let empty = NamespaceWindowStats {
+            window: 1,
+            blocks: 1,
+            ..Default::default()
+        };
+        assert_eq!(ns1.windows[0], empty);
+        assert_eq!(ns1.windows[1].tx_count, 1);
+        assert_eq!(
+            stats
+                .all()
+                .iter()
+                .map(|ns| ns.namespace)
+                .collect::<Vec<_>>(),
+            [1.into(), 2.into()]
+        );
+
+        // Transaction counts are unknown without the payload, but bytes are still counted.
+        let ns2 = stats.get(2.into());
+        assert_eq!(ns2.windows[0].tx_count, 0);
+        assert_eq!(ns2.windows[0].block_share, 1.0);
+    }
+
+    #[test]
+    fn test_namespace_stats_duplicate_namespace() {
+        let mut stats = NamespaceStats::new(vec![1], &NoMetrics);
+
+        // A namespace listed twice is only counted once, from its first entry, which is the one
+        // its transactions are read from.
+        let (mut header, _) = block(10, 100, []);
+        header.ns_table =
+            NameSpaceTable::from_namespace_offsets(vec![(1.into(), 10), (1.into(), 30)]).unwrap();
+        stats.record(&header, None);
+
+        let w = &stats.get(1.into()).windows[0];
+        assert_eq!(w.bytes, 10);
+        assert_eq!(w.block_share, 1.0);
+        assert_eq!(w.fee_weighted_usage, 100.into());
+    }
+}
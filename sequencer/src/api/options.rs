@@ -4,7 +4,10 @@ use super::{
     data_source::{
         provider, SequencerDataSource, StateDataSource, StateSignatureDataSource, SubmitDataSource,
     },
-    endpoints, fs, sql,
+    endpoints, fs,
+    namespace_stats::{NamespaceStats, DEFAULT_WINDOWS},
+    participation::{self, ParticipationDataSource},
+    sql,
     update::update_loop,
     StorageState,
 };
@@ -13,7 +16,7 @@ use crate::{
     network,
     persistence::{self, SequencerPersistence},
     state::{BlockMerkleTree, FeeMerkleTree},
-    SeqTypes,
+    PubKey, SeqTypes,
 };
 use anyhow::bail;
 use async_std::sync::{Arc, RwLock};
@@ -216,6 +219,7 @@ impl Options {
         D: SequencerDataSource + Send + Sync + 'static,
     {
         let metrics = ds.populate_metrics();
+        let ns_stats = NamespaceStats::new(DEFAULT_WINDOWS.to_vec(), &*metrics);
 
         // Start up handle
         let mut context = init_context(metrics).await;
@@ -227,9 +231,11 @@ impl Options {
         // the first events emitted by consensus.
         let events = context.get_event_stream();
 
-        let state: endpoints::AvailState<N, P, D, Ver> = Arc::new(RwLock::new(
-            ExtensibleDataSource::new(ds, (&context).into()),
-        ));
+        let state: endpoints::AvailState<N, P, D, Ver> =
+            Arc::new(RwLock::new(ExtensibleDataSource::new(
+                ds,
+                super::State::from(&context).with_namespace_stats(ns_stats),
+            )));
         let mut app = App::<_, Error, Ver>::with_state(state.clone());
 
         // Initialize status API
@@ -314,6 +320,15 @@ impl Options {
                 "state/fees",
                 endpoints::merklized_state::<N, P, D, FeeMerkleTree, _>(bind_version)?,
             )?;
+            // Initialize proofs of headers against later headers and light client states
+            app.register_module(
+                "block-merkle-tree",
+                endpoints::block_merkle_tree::<N, P, D, _>(bind_version)?,
+            )?;
+            app.register_module(
+                "light-client",
+                endpoints::light_client::<N, P, D, _>(bind_version)?,
+            )?;
         }
 
         if self.hotshot_events.is_some() {
@@ -339,8 +354,12 @@ impl Options {
     where
         S: 'static + Send + Sync + ReadState + WriteState,
         P: SequencerPersistence,
-        S::State:
-            Send + Sync + SubmitDataSource<N, P> + StateSignatureDataSource<N> + StateDataSource,
+        S::State: Send
+            + Sync
+            + SubmitDataSource<N, P>
+            + StateSignatureDataSource<N>
+            + StateDataSource
+            + ParticipationDataSource<PubKey>,
         N: network::Type,
     {
         let bind_version = Ver::instance();
@@ -360,6 +379,9 @@ impl Options {
         let state_signature_api = endpoints::state_signature(bind_version)?;
         app.register_module("state-signature", state_signature_api)?;
 
+        let participation_api = participation::define_api::<_, PubKey, Error, Ver>(bind_version)?;
+        app.register_module("participation", participation_api)?;
+
         Ok(())
     }
 
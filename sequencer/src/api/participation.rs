@@ -0,0 +1,272 @@
+//! Signing and voting participation of validators.
+//!
+//! A [`ParticipationTracker`] records which keys participated at each of the most recent block
+//! heights. The state relay server uses it to track which validators sign light client states, and
+//! the sequencer to track which validators vote for decided blocks. Both serve the result through
+//! the `participation` API module.
+
+use crate::{PubKey, SeqTypes};
+use futures::FutureExt;
+use hotshot::types::{Event, EventType};
+use hotshot_types::{event::LeafInfo, simple_certificate::QuorumCertificate};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    hash::Hash,
+};
+use tide_disco::{method::ReadState, Api};
+use versioned_binary_serialization::version::StaticVersionType;
+
+/// Default number of block heights over which participation is reported.
+pub const DEFAULT_PARTICIPATION_WINDOW: usize = 100;
+
+/// Participation of a single key over the window.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyParticipation<K> {
+    pub key: K,
+    /// The highest block height at which this key participated, if it participated since the
+    /// tracker started.
+    pub last_height: Option<u64>,
+    /// The number of heights in the window at which this key did not participate.
+    pub missed: u64,
+    /// The fraction of the heights in the window at which this key participated.
+    pub uptime: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParticipationQueryData<K> {
+    /// The number of heights in the window.
+    ///
+    /// This may be less than the configured window shortly after the tracker starts.
+    pub window: usize,
+    /// The lowest block height in the window.
+    pub from_height: Option<u64>,
+    /// The highest block height in the window.
+    pub to_height: Option<u64>,
+    /// Participation of each key.
+    pub keys: Vec<KeyParticipation<K>>,
+}
+
+/// Participation of keys at the most recent block heights.
+#[derive(Clone, Debug)]
+pub struct ParticipationTracker<K> {
+    window: usize,
+    /// The keys which participated at each height in the window.
+    heights: BTreeMap<u64, HashSet<K>>,
+    /// The highest height at which each key participated.
+    last_height: HashMap<K, u64>,
+}
+
+impl<K> Default for ParticipationTracker<K> {
+    fn default() -> Self {
+        Self::new(DEFAULT_PARTICIPATION_WINDOW)
+    }
+}
+
+impl<K> ParticipationTracker<K> {
+    /// Track participation over the `window` most recent heights.
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            heights: Default::default(),
+            last_height: Default::default(),
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash> ParticipationTracker<K> {
+    /// Record a height at which keys were expected to participate.
+    ///
+    /// Heights older than the window are ignored.
+    pub fn record_height(&mut self, height: u64) {
+        if self.heights.len() >= self.window
+            && self
+                .heights
+                .first_key_value()
+                .is_some_and(|(&h, _)| height < h)
+        {
+            return;
+        }
+        self.heights.entry(height).or_default();
+        while self.heights.len() > self.window {
+            self.heights.pop_first();
+        }
+    }
+
+    /// Record that `key` participated at `height`.
+    pub fn record(&mut self, height: u64, key: K) {
+        let last = self.last_height.entry(key.clone()).or_insert(height);
+        *last = (*last).max(height);
+
+        self.record_height(height);
+        if let Some(participants) = self.heights.get_mut(&height) {
+            participants.insert(key);
+        }
+    }
+
+    /// Forget keys which are not in `keys`, for instance after the stake table changes.
+    pub fn retain(&mut self, keys: &HashSet<K>) {
+        self.last_height.retain(|key, _| keys.contains(key));
+        for participants in self.heights.values_mut() {
+            participants.retain(|key| keys.contains(key));
+        }
+    }
+
+    /// Participation of each of `keys` at heights up to `up_to`.
+    ///
+    /// Heights after `up_to` are still in progress, so keys which have not participated in them
+    /// yet are not counted as having missed them.
+    pub fn query(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+        up_to: u64,
+    ) -> ParticipationQueryData<K> {
+        let heights = self
+            .heights
+            .range(..=up_to)
+            .map(|(_, participants)| participants)
+            .collect::<Vec<_>>();
+        let window = heights.len();
+        let keys = keys
+            .into_iter()
+            .map(|key| {
+                let participated = heights.iter().filter(|p| p.contains(&key)).count();
+                KeyParticipation {
+                    last_height: self.last_height.get(&key).copied(),
+                    missed: (window - participated) as u64,
+                    uptime: if window == 0 {
+                        0.0
+                    } else {
+                        participated as f64 / window as f64
+                    },
+                    key,
+                }
+            })
+            .collect();
+        ParticipationQueryData {
+            window,
+            from_height: self.heights.range(..=up_to).next().map(|(&h, _)| h),
+            to_height: self.heights.range(..=up_to).next_back().map(|(&h, _)| h),
+            keys,
+        }
+    }
+
+    /// All keys which participated at some point.
+    pub fn known_keys(&self) -> impl Iterator<Item = &K> {
+        self.last_height.keys()
+    }
+}
+
+/// Voting participation of the validators in the stake table, tracked from decide events.
+#[derive(Clone, Debug, Default)]
+pub struct VoteParticipation {
+    /// The stake table, in the order of the signers bit vector of quorum certificates.
+    stake_table: Vec<PubKey>,
+    tracker: ParticipationTracker<PubKey>,
+}
+
+impl VoteParticipation {
+    pub fn new(stake_table: Vec<PubKey>) -> Self {
+        Self {
+            stake_table,
+            tracker: Default::default(),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event<SeqTypes>) {
+        let EventType::Decide { leaf_chain, qc, .. } = &event.event else {
+            return;
+        };
+        // The certificate in the event is for the newest decided leaf, and each decided leaf
+        // carries the certificate for its parent.
+        if let Some(LeafInfo { leaf, .. }) = leaf_chain.first() {
+            self.record_certificate(leaf.get_height(), qc);
+        }
+        for LeafInfo { leaf, .. } in leaf_chain.iter() {
+            if let Some(parent_height) = leaf.get_height().checked_sub(1) {
+                self.record_certificate(parent_height, &leaf.get_justify_qc());
+            }
+        }
+    }
+
+    fn record_certificate(&mut self, height: u64, qc: &QuorumCertificate<SeqTypes>) {
+        // The genesis certificate has no signatures.
+        let Some((_, signers)) = &qc.signatures else {
+            return;
+        };
+        self.tracker.record_height(height);
+        for i in signers.iter_ones() {
+            if let Some(key) = self.stake_table.get(i) {
+                self.tracker.record(height, *key);
+            }
+        }
+    }
+
+    pub fn query(&self) -> ParticipationQueryData<PubKey> {
+        self.tracker
+            .query(self.stake_table.iter().copied(), u64::MAX)
+    }
+}
+
+#[trait_variant::make(ParticipationDataSource: Send)]
+pub(crate) trait LocalParticipationDataSource<K> {
+    async fn get_participation(&self) -> ParticipationQueryData<K>;
+}
+
+/// The `participation` API module, reporting the participation of keys of type `K`.
+pub(crate) fn define_api<S, K, E, Ver: StaticVersionType + 'static>(
+    _: Ver,
+) -> anyhow::Result<Api<S, E, Ver>>
+where
+    S: 'static + Send + Sync + ReadState,
+    S::State: Send + Sync + ParticipationDataSource<K>,
+    K: 'static + Send + Sync + Serialize + DeserializeOwned,
+    E: 'static + tide_disco::Error,
+{
+    let toml = toml::from_str::<toml::Value>(include_str!("../../api/participation.toml"))?;
+    let mut api = Api::<S, E, Ver>::new(toml)?;
+
+    api.get("validators", |_req, state| {
+        async move { Ok(state.get_participation().await) }.boxed()
+    })?;
+
+    Ok(api)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_participation_window() {
+        let mut tracker = ParticipationTracker::new(3);
+        tracker.record(1, "a");
+        tracker.record(1, "b");
+        tracker.record(2, "a");
+        tracker.record_height(3);
+        tracker.record(4, "a");
+        tracker.record(4, "b");
+
+        // Height 1 has left the window.
+        let data = tracker.query(["a", "b", "c"], u64::MAX);
+        assert_eq!(data.window, 3);
+        assert_eq!((data.from_height, data.to_height), (Some(2), Some(4)));
+        let [a, b, c] = &data.keys[..] else {
+            panic!("unexpected participation {data:?}");
+        };
+        assert_eq!((a.last_height, a.missed), (Some(4), 1));
+        assert_eq!((b.last_height, b.missed), (Some(4), 2));
+        assert_eq!((c.last_height, c.missed, c.uptime), (None, 3, 0.0));
+        assert!((a.uptime - 2.0 / 3.0).abs() < 1e-9);
+
+        // Heights in progress are not counted as missed.
+        let data = tracker.query(["b"], 3);
+        assert_eq!(data.window, 2);
+        assert_eq!(data.keys[0].missed, 2);
+
+        // Participation older than the window is ignored.
+        tracker.record(1, "c");
+        assert_eq!(tracker.query(["c"], u64::MAX).keys[0].last_height, Some(1));
+        assert_eq!(tracker.query(["c"], u64::MAX).keys[0].missed, 3);
+    }
+}
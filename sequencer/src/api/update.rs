@@ -36,6 +36,10 @@ pub(super) async fn update_loop<N, P, D, Ver: StaticVersionType>(
             );
             state.revert().await;
         }
+
+        // Namespace statistics only depend on the event itself, so we update them even if storing
+        // the event failed.
+        state.as_ref().ns_stats.write().await.handle_event(&event);
     }
     tracing::warn!("end of HotShot event stream, updater task will exit");
 }
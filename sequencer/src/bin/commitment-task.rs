@@ -1,15 +1,23 @@
 use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
-use async_std::task::spawn;
+use async_std::{sync::RwLock, task::spawn};
 use clap::Parser;
 use es_version::SEQUENCER_VERSION;
 use ethers::prelude::*;
 use futures::FutureExt;
-use sequencer::hotshot_commitment::{run_hotshot_commitment_task, CommitmentTaskOptions};
+use hotshot_query_service::metrics::PrometheusMetrics;
+use sequencer::hotshot_commitment::{
+    run_hotshot_commitment_task, run_light_client_commitment_task, CommitmentMode,
+    CommitmentTaskOptions, LatestBlockRoot,
+};
 use sequencer::options::parse_duration;
+use sequencer::state_signature::LightClientHeaderProof;
+use sequencer_utils::l1_sender::L1SenderOptions;
+use std::borrow::Cow;
 use std::io;
+use std::sync::Arc;
 use std::time::Duration;
 use tide_disco::error::ServerError;
-use tide_disco::Api;
+use tide_disco::{Api, Error as _, StatusCode};
 use url::Url;
 use versioned_binary_serialization::version::StaticVersionType;
 
@@ -24,9 +32,26 @@ pub struct Options {
     #[clap(long, env = "ESPRESSO_SEQUENCER_L1_PROVIDER")]
     pub l1_provider: Url,
 
+    /// What to commit to.
+    #[clap(
+        long,
+        env = "ESPRESSO_COMMITMENT_TASK_MODE",
+        value_enum,
+        default_value = "hot-shot"
+    )]
+    pub mode: CommitmentMode,
+
     /// Address of the HotShot contract on layer 1.
+    ///
+    /// Required in the `hot-shot` mode.
     #[clap(long, env = "ESPRESSO_SEQUENCER_HOTSHOT_ADDRESS")]
-    pub hotshot_address: Address,
+    pub hotshot_address: Option<Address>,
+
+    /// Address of the light client contract on layer 1.
+    ///
+    /// Required in the `light-client` mode.
+    #[clap(long, env = "ESPRESSO_SEQUENCER_LIGHTCLIENT_ADDRESS")]
+    pub light_client_address: Option<Address>,
 
     /// Mnemonic phrase for the commitment task  wallet.
     ///
@@ -57,20 +82,45 @@ pub struct Options {
     /// If specified, sequencing attempts will be delayed by duration sampled from an exponential distribution with mean DELAY.
     #[clap(long, name = "DELAY", value_parser = parse_duration, env = "ESPRESSO_COMMITMENT_TASK_DELAY")]
     pub delay: Option<Duration>,
+
+    #[clap(flatten)]
+    pub l1_sender: L1SenderOptions,
 }
+
+/// State of the commitment task's HTTP server.
+struct ServerState {
+    hotshot_address: Option<Address>,
+    latest_root: LatestBlockRoot,
+    metrics: Arc<PrometheusMetrics>,
+    /// The sequencer node from which header proofs are fetched.
+    sequencer_url: Url,
+}
+
 #[async_std::main]
 async fn main() {
     setup_logging();
     setup_backtrace();
 
     let opt = Options::parse();
+    let latest_root = LatestBlockRoot::default();
+    let metrics = Arc::new(PrometheusMetrics::default());
 
     if let Some(port) = opt.port {
-        start_http_server(port, opt.hotshot_address, SEQUENCER_VERSION).unwrap();
+        start_http_server(
+            port,
+            ServerState {
+                hotshot_address: opt.hotshot_address,
+                latest_root: latest_root.clone(),
+                metrics: metrics.clone(),
+                sequencer_url: opt.sequencer_url.clone(),
+            },
+            SEQUENCER_VERSION,
+        )
+        .unwrap();
     }
 
     let hotshot_contract_options = CommitmentTaskOptions {
-        hotshot_address: opt.hotshot_address,
+        hotshot_address: opt.hotshot_address.unwrap_or_default(),
         l1_chain_id: None,
         l1_provider: opt.l1_provider.clone(),
         delay: opt.delay,
@@ -78,25 +128,106 @@ async fn main() {
         sequencer_account_index: opt.hotshot_account_index,
         request_timeout: opt.request_timeout,
         query_service_url: Some(opt.sequencer_url),
+        l1_sender: opt.l1_sender,
     };
-    tracing::info!("Launching HotShot commitment task..");
-    run_hotshot_commitment_task::<es_version::SequencerVersion>(&hotshot_contract_options).await;
+    match opt.mode {
+        CommitmentMode::HotShot => {
+            assert!(
+                opt.hotshot_address.is_some(),
+                "the HotShot contract address is required in the hot-shot mode"
+            );
+            tracing::info!("Launching HotShot commitment task..");
+            run_hotshot_commitment_task::<es_version::SequencerVersion>(
+                &hotshot_contract_options,
+                &*metrics,
+            )
+            .await;
+        }
+        CommitmentMode::LightClient => {
+            let light_client_address = opt
+                .light_client_address
+                .expect("the light client contract address is required in the light-client mode");
+            tracing::info!("Launching light client commitment task..");
+            run_light_client_commitment_task::<es_version::SequencerVersion>(
+                &hotshot_contract_options,
+                light_client_address,
+                latest_root,
+            )
+            .await;
+        }
+    }
 }
 
 fn start_http_server<Ver: StaticVersionType + 'static>(
     port: u16,
-    hotshot_address: Address,
+    state: ServerState,
     bind_version: Ver,
 ) -> io::Result<()> {
-    let mut app = tide_disco::App::<(), ServerError, Ver>::with_state(());
+    let mut app =
+        tide_disco::App::<RwLock<ServerState>, ServerError, Ver>::with_state(RwLock::new(state));
     let toml = toml::from_str::<toml::value::Value>(include_str!("../../api/commitment_task.toml"))
         .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
 
-    let mut api = Api::<(), ServerError, Ver>::new(toml)
+    let mut api = Api::<RwLock<ServerState>, ServerError, Ver>::new(toml)
         .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
 
-    api.get("gethotshotcontract", move |_, _| {
-        async move { Ok(hotshot_address) }.boxed()
+    api.get("gethotshotcontract", |_, state| {
+        async move {
+            state.hotshot_address.ok_or(ServerError::catch_all(
+                StatusCode::NotFound,
+                "no HotShot contract configured".to_owned(),
+            ))
+        }
+        .boxed()
+    })
+    .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?
+    .get("getblockroot", |_, state| {
+        async move {
+            state
+                .latest_root
+                .read()
+                .await
+                .clone()
+                .ok_or(ServerError::catch_all(
+                    StatusCode::NotFound,
+                    "no block Merkle root has been verified by the light client yet".to_owned(),
+                ))
+        }
+        .boxed()
+    })
+    .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?
+    .get("getheaderproof", |req, state| {
+        async move {
+            let height: u64 = req
+                .integer_param("height")
+                .map_err(ServerError::from_request_error)?;
+            let root = state
+                .latest_root
+                .read()
+                .await
+                .clone()
+                .ok_or(ServerError::catch_all(
+                    StatusCode::NotFound,
+                    "no light client state has been read from the contract yet".to_owned(),
+                ))?;
+            let proof: LightClientHeaderProof =
+                surf_disco::Client::<ServerError, Ver>::new(state.sequencer_url.clone())
+                    .get(&format!(
+                        "light-client/header/{height}/proof/{}",
+                        root.height
+                    ))
+                    .send()
+                    .await?;
+            proof.verify(&root.light_client_state).map_err(|err| {
+                ServerError::catch_all(StatusCode::BadGateway, format!("{err:#}"))
+            })?;
+            Ok(proof)
+        }
+        .boxed()
+    })
+    .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?
+    .metrics("metrics", |_, state| {
+        async move { Ok(Cow::Borrowed(&*state.metrics)) }.boxed()
     })
     .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
 
@@ -117,6 +248,7 @@ mod test {
     use super::start_http_server;
     use super::Address;
     use super::ServerError;
+    use super::ServerState;
 
     #[async_std::test]
     async fn test_get_hotshot_contract() {
@@ -127,8 +259,17 @@ mod test {
         let expected_addr = "0xED15E1FE0789c524398137a066ceb2EF9884E5D8"
             .parse::<Address>()
             .unwrap();
-        start_http_server(port, expected_addr, SEQUENCER_VERSION)
-            .expect("Failed to start the server");
+        start_http_server(
+            port,
+            ServerState {
+                hotshot_address: Some(expected_addr),
+                latest_root: Default::default(),
+                metrics: Default::default(),
+                sequencer_url: "http://localhost".parse().unwrap(),
+            },
+            SEQUENCER_VERSION,
+        )
+        .expect("Failed to start the server");
 
         let client: Client<ServerError, SequencerVersion> =
             Client::new(format!("http://localhost:{port}").parse().unwrap());
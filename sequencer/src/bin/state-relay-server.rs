@@ -1,7 +1,17 @@
 use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
 use clap::Parser;
 use es_version::SEQUENCER_VERSION;
-use sequencer::state_signature::relay_server::run_relay_server;
+use ethers::types::Address;
+use hotshot_stake_table::config::STAKE_TABLE_CAPACITY;
+use sequencer::{
+    options::parse_duration,
+    state_signature::{
+        relay_server::run_relay_server,
+        stake_table::{LightClientContract, StakeTableEntries, StakeTableSource},
+    },
+};
+use std::{path::PathBuf, time::Duration};
+use url::Url;
 
 #[derive(Parser)]
 struct Args {
@@ -15,8 +25,8 @@ struct Args {
     port: u16,
 
     /// Threshold to form an available state signature package.
-    /// WARNING: this is a temporary flag, should remove after integrating with stake table.
-    /// Related issue: [https://github.com/EspressoSystems/espresso-sequencer/issues/1022]
+    ///
+    /// Only used without a stake table source, in which case every signature has weight one.
     #[clap(
         short,
         long,
@@ -24,6 +34,78 @@ struct Args {
         default_value = "3"
     )]
     threshold: u64,
+
+    /// Number of blocks per epoch of the light client contract.
+    ///
+    /// If set, signatures for the last block of each epoch are collected and served even after a
+    /// later state becomes available, since the contract requires them before any later state.
+    #[clap(long, env = "ESPRESSO_STATE_RELAY_SERVER_BLOCKS_PER_EPOCH")]
+    blocks_per_epoch: Option<u64>,
+
+    /// Load the stake table from the network config of this HotShot orchestrator.
+    #[clap(
+        long,
+        env = "ESPRESSO_STATE_RELAY_SERVER_ORCHESTRATOR_URL",
+        conflicts_with = "stake_table_file"
+    )]
+    orchestrator_url: Option<Url>,
+
+    /// Load the stake table from this TOML or JSON file.
+    ///
+    /// The file has a `known_nodes_with_stake` list in the same format as the orchestrator's
+    /// network config.
+    #[clap(long, env = "ESPRESSO_STATE_RELAY_SERVER_STAKE_TABLE_FILE")]
+    stake_table_file: Option<PathBuf>,
+
+    /// Check the stake table against the light client contract at this address.
+    ///
+    /// The contract then also determines the epoch and the signature threshold. Only used with a
+    /// stake table from the orchestrator or a file.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_LIGHTCLIENT_ADDRESS",
+        requires = "l1_provider"
+    )]
+    light_client_address: Option<Address>,
+
+    /// URL of the L1 provider, to read the light client contract.
+    #[clap(long, env = "ESPRESSO_SEQUENCER_L1_PROVIDER")]
+    l1_provider: Option<Url>,
+
+    /// Stake table capacity.
+    #[clap(long, env = "ESPRESSO_SEQUENCER_STAKE_TABLE_CAPACITY", default_value_t = STAKE_TABLE_CAPACITY)]
+    stake_table_capacity: usize,
+
+    /// How often to reload the stake table.
+    #[clap(
+        long,
+        env = "ESPRESSO_STATE_RELAY_SERVER_STAKE_TABLE_REFRESH_INTERVAL",
+        value_parser = parse_duration,
+        default_value = "1m"
+    )]
+    stake_table_refresh_interval: Duration,
+}
+
+impl Args {
+    fn stake_table_source(&self) -> Option<StakeTableSource> {
+        let entries = match (&self.orchestrator_url, &self.stake_table_file) {
+            (Some(url), _) => StakeTableEntries::Orchestrator(url.clone()),
+            (None, Some(path)) => StakeTableEntries::File(path.clone()),
+            (None, None) => return None,
+        };
+        let contract = self.light_client_address.zip(self.l1_provider.clone()).map(
+            |(address, l1_provider)| LightClientContract {
+                l1_provider,
+                address,
+            },
+        );
+        Some(StakeTableSource {
+            entries,
+            contract,
+            capacity: self.stake_table_capacity,
+            refresh_interval: self.stake_table_refresh_interval,
+        })
+    }
 }
 
 #[async_std::main]
@@ -37,6 +119,8 @@ async fn main() {
     run_relay_server(
         None,
         args.threshold,
+        args.blocks_per_epoch,
+        args.stake_table_source(),
         format!("http://0.0.0.0:{}", args.port).parse().unwrap(),
         SEQUENCER_VERSION,
     )
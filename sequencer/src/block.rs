@@ -1,4 +1,4 @@
-use crate::{BlockBuildingSnafu, Transaction};
+use crate::{BlockBuildingSnafu, ChainConfig, Error, NodeState, Transaction};
 use commit::{Commitment, Committable};
 use hotshot_query_service::availability::QueryablePayload;
 use hotshot_types::traits::BlockPayload;
@@ -6,20 +6,144 @@ use hotshot_types::utils::BuilderCommitment;
 use serde::{Deserialize, Serialize};
 use sha2::Digest;
 use snafu::OptionExt;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    RwLock,
+};
 
+pub mod compression;
 pub mod entry;
+pub mod ordering;
 pub mod payload;
 pub mod queryable;
 pub mod tables;
 pub mod tx_iterator;
 
-use entry::TxTableEntryWord;
-use payload::Payload;
-use tables::NameSpaceTable;
+use compression::NsPayloadFormat;
+use ordering::OrderingOption;
+use payload::{limit_namespace_bytes, Payload};
+use tables::{NameSpaceTable, PayloadVersion};
 
-pub type NsTable = NameSpaceTable<TxTableEntryWord>;
+pub type NsTable = NameSpaceTable;
 
-impl BlockPayload for Payload<TxTableEntryWord> {
+/// How a node builds the payloads of new blocks.
+///
+/// Every [`NodeState`] carries a build context, shared by all its clones, which holds the settings
+/// the node encodes new payloads with. The node reports each decided block to it with
+/// [`record_decided`](Self::record_decided).
+///
+/// HotShot builds a payload before it chooses the parent of the block, so the height of the block
+/// is not known exactly. It is, however, greater than that of any decided block, so payloads follow
+/// the rules in force from one past the latest decided height; see
+/// [`NodeState::build_payload`].
+#[derive(Debug, Default)]
+pub struct BuildContext {
+    /// One more than the height of the latest decided block.
+    min_height: AtomicU64,
+    /// The format in which to encode each namespace.
+    ///
+    /// A namespace is only stored compressed if that actually makes it smaller.
+    pub compression: NsPayloadFormat,
+    /// The policy ordering the transactions of each payload.
+    pub ordering: OrderingOption,
+}
+
+/// The node whose settings HotShot builds payloads with in this process.
+///
+/// HotShot builds payloads through [`BlockPayload::from_transactions`], which is given nothing but
+/// the transactions, so the instance state cannot be passed in. Each node installs its own when it
+/// starts, with [`build_payloads_with`]. In production a process runs a single node or builder. In
+/// tests which run several nodes in one process, payloads follow the settings of the node started
+/// last, so those nodes should share their settings.
+static PAYLOAD_INSTANCE: RwLock<Option<NodeState>> = RwLock::new(None);
+
+/// Build the payloads HotShot asks for in this process with the settings and rules of `instance`.
+///
+/// Replaces any instance installed before. Until an instance is installed, payloads are built with
+/// the default settings and chain config.
+pub fn build_payloads_with(instance: NodeState) {
+    *PAYLOAD_INSTANCE.write().unwrap() = Some(instance);
+}
+
+impl BuildContext {
+    /// A context which compresses namespaces with zstd if `compress_namespaces` is set.
+    ///
+    /// Transactions are ordered with the default policy; see
+    /// [`with_ordering`](Self::with_ordering).
+    pub fn new(compress_namespaces: bool) -> Self {
+        Self {
+            min_height: Default::default(),
+            ordering: Default::default(),
+            compression: if compress_namespaces {
+                NsPayloadFormat::Zstd
+            } else {
+                NsPayloadFormat::Raw
+            },
+        }
+    }
+
+    /// Order the transactions of each payload according to `ordering`.
+    pub fn with_ordering(mut self, ordering: OrderingOption) -> Self {
+        self.ordering = ordering;
+        self
+    }
+
+    /// Record that the block at `height` has been decided.
+    pub fn record_decided(&self, height: u64) {
+        self.min_height
+            .fetch_max(height.saturating_add(1), Ordering::Relaxed);
+    }
+
+    /// The lowest height a new payload may end up at: one more than the latest decided height.
+    pub fn min_height(&self) -> u64 {
+        self.min_height.load(Ordering::Relaxed)
+    }
+}
+
+impl NodeState {
+    /// The layout of new payloads.
+    ///
+    /// Payloads switch to the layout of a new header version once every block they could end up in
+    /// has that version. Headers of the new version accept the previous layout in the meantime; see
+    /// [`HeaderVersion::allows_payload_version`](crate::HeaderVersion::allows_payload_version).
+    pub fn payload_version(&self) -> PayloadVersion {
+        self.header_version(self.build_context().min_height())
+            .payload_version()
+    }
+
+    /// The chain config in force for a new payload.
+    ///
+    /// This is the config from one past the latest decided height.
+    pub fn next_chain_config(&self) -> ChainConfig {
+        self.chain_config(self.build_context().min_height())
+    }
+
+    /// The most bytes a namespace of a new payload may take up.
+    ///
+    /// This is the strictest limit of the chain configs which may be in force for the block.
+    pub fn max_namespace_bytes(&self) -> Option<u64> {
+        self.chain_configs_from(self.build_context().min_height())
+            .filter_map(|chain_config| chain_config.max_namespace_bytes())
+            .min()
+    }
+
+    /// Build a payload from `txs` with the settings of this node, following the rules in force
+    /// from one past the latest decided height.
+    pub fn build_payload(
+        &self,
+        txs: impl IntoIterator<Item = Transaction>,
+    ) -> Result<Payload, Error> {
+        let ctx = self.build_context();
+        Payload::from_txs_versioned(
+            limit_namespace_bytes(txs, self.max_namespace_bytes()),
+            self.payload_version(),
+            &ctx.ordering,
+            |_| ctx.compression,
+        )
+    }
+}
+
+impl BlockPayload for Payload {
     type Error = crate::Error;
     type Transaction = Transaction;
     type Metadata = NsTable;
@@ -41,17 +165,30 @@ impl BlockPayload for Payload<TxTableEntryWord> {
     /// Thus, for j>2 the jth namespace payload bytes range is word[2(j-1)]..word[2j].
     /// Edge case: for j=1 the jth namespace start index is implicitly 0.
     ///
-    /// Word type is `TxTableEntry`.
-    /// TODO(746) don't use `TxTableEntry`; make a different type for type safety.
+    /// If any namespace is compressed, the words are followed by one byte per namespace giving
+    /// its [`NsPayloadFormat`](compression::NsPayloadFormat). Tables without these bytes
+    /// store every namespace in the raw layout.
+    ///
+    /// Word type is `NsTableEntry`.
     ///
-    /// TODO final entry should be implicit:
-    /// https://github.com/EspressoSystems/espresso-sequencer/issues/757
+    /// This is the V0 layout. In the V1 layout the table is tagged with its version and the final
+    /// entry of each tx table is implicit; see [`NameSpaceTable`] and
+    /// [`PayloadVersion`](tables::PayloadVersion).
+    /// New blocks use the layout of their header version, and the settings of the node building
+    /// them; see [`build_payloads_with`].
     ///
-    /// TODO(746) refactor and make pretty "table" code for tx, namespace tables?
+    /// This is how a leader builds the block it proposes, from its own mempool. Leaders do not
+    /// choose among blocks offered by external builders: HotShot is given no builder URL, and
+    /// only hands us the transactions here, with the payload commitment fixed before
+    /// `Header::new` is called. Selecting the most valuable block from several builders needs a
+    /// HotShot whose proposal task queries builders, and is not supported until then.
     fn from_transactions(
         txs: impl IntoIterator<Item = Self::Transaction>,
     ) -> Result<(Self, Self::Metadata), Self::Error> {
-        let payload = Payload::from_txs(txs)?;
+        let payload = match PAYLOAD_INSTANCE.read().unwrap().as_ref() {
+            Some(instance) => instance.build_payload(txs)?,
+            None => Payload::from_txs(txs, &ChainConfig::default())?,
+        };
         let ns_table = payload.get_ns_table().clone(); // TODO don't clone ns_table
         Some((payload, ns_table)).context(BlockBuildingSnafu)
     }
@@ -63,11 +200,17 @@ impl BlockPayload for Payload<TxTableEntryWord> {
         Self {
             raw_payload: encoded_transactions.into_iter().collect(),
             ns_table: metadata.clone(), // TODO don't clone ns_table
+            decoded_namespaces: Default::default(),
         }
     }
 
+    /// The genesis payload is empty, in the V0 layout.
+    ///
+    /// It does not depend on the settings of any node, so that every node agrees on it.
     fn genesis() -> (Self, Self::Metadata) {
-        Self::from_transactions([]).unwrap()
+        let payload = Payload::from_txs([], &ChainConfig::default()).unwrap();
+        let ns_table = payload.get_ns_table().clone();
+        (payload, ns_table)
     }
 
     fn encode(&self) -> Result<Self::Encode<'_>, Self::Error> {
@@ -118,7 +261,7 @@ mod reference {
     //! the failing test.
 
     use super::*;
-    use crate::{Header, L1BlockInfo};
+    use crate::{Header, HeaderVersion, L1BlockInfo};
     use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
     use lazy_static::lazy_static;
     use sequencer_utils::commitment_to_u256;
@@ -134,7 +277,8 @@ mod reference {
     lazy_static! {
         pub static ref NS_TABLE: Value = load_reference!("ns_table");
         pub static ref L1_BLOCK: Value = load_reference!("l1_block");
-        pub static ref HEADER: Value = load_reference!("header");
+        pub static ref HEADER_V1: Value = load_reference!("v1/header");
+        pub static ref HEADER_V2: Value = load_reference!("v2/header");
         pub static ref TRANSACTION: Value = load_reference!("transaction");
     }
 
@@ -148,21 +292,24 @@ mod reference {
 
         let reference: T = serde_json::from_value(reference).unwrap();
         let actual = commit(&reference);
+        log_commitment(actual);
 
-        // Print information about the commitment that might be useful in generating tests for other
-        // languages.
+        assert_eq!(actual, expected.parse().unwrap());
+    }
+
+    /// Print information about a commitment that might be useful in generating tests for other
+    /// languages.
+    fn log_commitment<C: Committable>(actual: Commitment<C>) {
         let bytes: &[u8] = actual.as_ref();
         let u256 = commitment_to_u256(actual);
         tracing::info!("actual commitment: {}", actual);
         tracing::info!("commitment bytes: {:?}", bytes);
         tracing::info!("commitment U256: {}", u256);
-
-        assert_eq!(actual, expected.parse().unwrap());
     }
 
     #[test]
     fn test_reference_ns_table() {
-        reference_test::<NameSpaceTable<TxTableEntryWord>, _>(
+        reference_test::<NameSpaceTable, _>(
             NS_TABLE.clone(),
             "NSTABLE~GL-lEBAwNZDldxDpySRZQChNnmn9vNzdIAL8W9ENOuh_",
             |ns_table| ns_table.commit(),
@@ -179,14 +326,44 @@ mod reference {
     }
 
     #[test]
-    fn test_reference_header() {
+    fn test_reference_header_v1() {
         reference_test::<Header, _>(
-            HEADER.clone(),
+            HEADER_V1.clone(),
             "BLOCK~igildJMyCExvG88xSKxAn6MpHzOnnoFoeQWMsRbR07EP",
-            |header| header.commit(),
+            |header| {
+                assert_eq!(header.version(), HeaderVersion::V1);
+                header.commit()
+            },
         );
     }
 
+    #[test]
+    fn test_reference_header_v2() {
+        reference_test::<Header, _>(
+            HEADER_V2.clone(),
+            "BLOCK~K7cwupqziVnTYT4XyW5mUuUN5hFvA58G-6j049jsPiYt",
+            |header| {
+                assert_eq!(header.version(), HeaderVersion::V2);
+                header.commit()
+            },
+        );
+
+        let v1: Header = serde_json::from_value(HEADER_V1.clone()).unwrap();
+        let v2: Header = serde_json::from_value(HEADER_V2.clone()).unwrap();
+
+        // The V2 reference has the same fields as the V1 reference, but its commitment also binds
+        // the version.
+        assert_eq!(*v2, *v1);
+        let actual = v2.commit();
+        assert_ne!(actual, v1.commit());
+
+        // The version must survive serialization, or the commitment would change in transit.
+        let reserialized: Header =
+            serde_json::from_value(serde_json::to_value(&v2).unwrap()).unwrap();
+        assert_eq!(reserialized.version(), HeaderVersion::V2);
+        assert_eq!(reserialized.commit(), actual);
+    }
+
     #[test]
     fn test_reference_transaction() {
         reference_test::<Transaction, _>(
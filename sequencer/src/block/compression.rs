@@ -0,0 +1,168 @@
+//! Optional compression of namespace payloads.
+//!
+//! Each namespace in a block may be stored either in the raw layout (tx table followed by tx
+//! bodies) or compressed. The format of each namespace is signalled by a single byte in the
+//! namespace table; see [`NameSpaceTable`](super::tables::NameSpaceTable). Namespaces stored in the
+//! raw layout are byte-for-byte identical to blocks which predate compression.
+//!
+//! Format codes are never reused. A change to the layout of a compressed namespace (for example, a
+//! different compression level that requires a dictionary) gets a new code, so that old blocks
+//! remain decodable.
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Upper bound on the ratio of decoded to encoded size of a compressed namespace.
+///
+/// This protects nodes from decompression bombs: a small, highly compressed payload which would
+/// expand to an enormous amount of memory. Each compressed namespace decodes to at most this many
+/// times its encoded size, and all the compressed namespaces of a payload together decode to at
+/// most this many times the size of the payload (see [`max_decoded_bytes`]). Since a valid payload
+/// is no larger than `max_block_size`, decoding a block never takes more than a constant multiple
+/// of `max_block_size` in memory. Builders only compress a namespace if it stays within this ratio.
+pub const MAX_DECOMPRESSION_RATIO: usize = 32;
+
+/// The most bytes which `encoded_len` bytes of compressed namespaces may decode to.
+pub fn max_decoded_bytes(encoded_len: usize) -> usize {
+    encoded_len.saturating_mul(MAX_DECOMPRESSION_RATIO)
+}
+
+/// The encoding of a namespace payload.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NsPayloadFormat {
+    /// The tx table and tx bodies, uncompressed.
+    #[default]
+    Raw,
+    /// The raw layout, compressed as a single zstd frame (format version 1).
+    Zstd,
+    /// A format code not recognized by this version of the software.
+    ///
+    /// Namespaces with an unsupported format decode to an empty list of transactions.
+    Unsupported(u8),
+}
+
+impl NsPayloadFormat {
+    const RAW: u8 = 0;
+    const ZSTD_V1: u8 = 1;
+
+    /// Compression level used when encoding with zstd.
+    const ZSTD_LEVEL: i32 = 3;
+
+    pub fn from_byte(byte: u8) -> Self {
+        match byte {
+            Self::RAW => Self::Raw,
+            Self::ZSTD_V1 => Self::Zstd,
+            code => Self::Unsupported(code),
+        }
+    }
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Self::Raw => Self::RAW,
+            Self::Zstd => Self::ZSTD_V1,
+            Self::Unsupported(code) => code,
+        }
+    }
+
+    pub fn is_raw(self) -> bool {
+        self == Self::Raw
+    }
+
+    /// Encode a namespace payload in the raw layout using this format.
+    ///
+    /// Returns `None` if this format cannot be used for encoding.
+    pub fn encode(self, raw_ns_bytes: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Self::Raw => Some(raw_ns_bytes.to_vec()),
+            Self::Zstd => zstd::bulk::compress(raw_ns_bytes, Self::ZSTD_LEVEL).ok(),
+            Self::Unsupported(_) => None,
+        }
+    }
+
+    /// The size of the raw layout of a namespace payload encoded with this format, as declared by
+    /// the encoding, without decoding it.
+    ///
+    /// Returns `None` if the encoding does not declare its decoded size.
+    pub fn decoded_len(self, ns_bytes: &[u8]) -> Option<usize> {
+        match self {
+            Self::Raw => Some(ns_bytes.len()),
+            Self::Zstd => {
+                // `zstd::bulk::compress` always records the content size in the frame header.
+                let len = zstd::zstd_safe::get_frame_content_size(ns_bytes);
+                if len == zstd::zstd_safe::CONTENTSIZE_UNKNOWN
+                    || len == zstd::zstd_safe::CONTENTSIZE_ERROR
+                {
+                    return None;
+                }
+                len.try_into().ok()
+            }
+            Self::Unsupported(_) => None,
+        }
+    }
+
+    /// Whether a namespace payload of `raw_len` bytes may be stored in `encoded_len` bytes without
+    /// exceeding [`MAX_DECOMPRESSION_RATIO`].
+    pub fn within_ratio(raw_len: usize, encoded_len: usize) -> bool {
+        raw_len <= max_decoded_bytes(encoded_len)
+    }
+
+    /// Recover the raw layout of a namespace payload encoded with this format.
+    ///
+    /// Returns `None` if the bytes are not validly encoded, or if they decode to more than
+    /// [`MAX_DECOMPRESSION_RATIO`] times their size. Memory is only allocated for the declared
+    /// decoded size, once it has been checked.
+    pub fn decode(self, ns_bytes: &[u8]) -> Option<Cow<'_, [u8]>> {
+        match self {
+            Self::Raw => Some(Cow::Borrowed(ns_bytes)),
+            Self::Zstd => {
+                let len = self.decoded_len(ns_bytes)?;
+                if !Self::within_ratio(len, ns_bytes.len()) {
+                    return None;
+                }
+                zstd::bulk::decompress(ns_bytes, len).ok().map(Cow::Owned)
+            }
+            Self::Unsupported(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_byte_round_trip() {
+        for byte in 0..=u8::MAX {
+            assert_eq!(NsPayloadFormat::from_byte(byte).to_byte(), byte);
+        }
+        assert_eq!(NsPayloadFormat::from_byte(0), NsPayloadFormat::Raw);
+        assert_eq!(NsPayloadFormat::from_byte(1), NsPayloadFormat::Zstd);
+    }
+
+    #[test]
+    fn test_zstd_decode() {
+        // Compressible, but not by more than the maximum ratio.
+        let raw: Vec<u8> = (0..10_000u64)
+            .map(|i| ((i * 2_654_435_761) >> 7) as u8 % 16)
+            .collect();
+        let encoded = NsPayloadFormat::Zstd.encode(&raw).unwrap();
+        assert!(encoded.len() < raw.len());
+        assert_eq!(NsPayloadFormat::Zstd.decoded_len(&encoded), Some(raw.len()));
+        assert_eq!(NsPayloadFormat::Zstd.decode(&encoded).unwrap(), &raw[..]);
+
+        // Garbage does not decode.
+        assert!(NsPayloadFormat::Zstd.decode(&raw).is_none());
+        assert!(NsPayloadFormat::Zstd.decoded_len(&raw).is_none());
+
+        // Payloads which decompress to more than the ratio allows are rejected, without being
+        // decompressed.
+        let bomb = NsPayloadFormat::Zstd.encode(&vec![0; 1 << 20]).unwrap();
+        assert_eq!(NsPayloadFormat::Zstd.decoded_len(&bomb), Some(1 << 20));
+        assert!(!NsPayloadFormat::within_ratio(1 << 20, bomb.len()));
+        assert!(NsPayloadFormat::Zstd.decode(&bomb).is_none());
+
+        // Unsupported formats cannot be encoded or decoded.
+        assert!(NsPayloadFormat::Unsupported(42).encode(&raw).is_none());
+        assert!(NsPayloadFormat::Unsupported(42).decode(&raw).is_none());
+    }
+}
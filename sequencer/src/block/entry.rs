@@ -3,15 +3,19 @@ use crate::NamespaceId;
 use core::fmt;
 use std::mem::size_of;
 
-// Use newtype pattern so that tx table entries cannot be confused with other types.
+/// The word in which every entry of a namespace or tx table is encoded, in little-endian order.
+///
+/// The word size is part of the payload format: every payload version uses 32-bit words.
+type Word = u32;
+
+/// A word in a tx table: the number of transactions, or the offset at which a transaction ends.
+///
+/// Use newtype pattern so that tx table entries cannot be confused with other types.
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, Default)]
-pub struct TxTableEntry(TxTableEntryWord);
-// TODO Get rid of TxTableEntryWord. We might use const generics in order to parametrize the set of functions below with u32,u64  etc...
-// See https://github.com/EspressoSystems/espresso-sequencer/issues/1076
-pub type TxTableEntryWord = u32;
+pub struct TxTableEntry(Word);
 
 impl TxTableEntry {
-    pub const MAX: TxTableEntry = Self(TxTableEntryWord::MAX);
+    pub const MAX: TxTableEntry = Self(Word::MAX);
 
     /// Adds `rhs` to `self` in place. Returns `None` on overflow.
     pub fn checked_add_mut(&mut self, rhs: Self) -> Option<()> {
@@ -24,20 +28,18 @@ impl TxTableEntry {
     pub const fn one() -> Self {
         Self(1)
     }
-    pub const fn to_bytes(&self) -> [u8; size_of::<TxTableEntryWord>()] {
+    pub const fn to_bytes(&self) -> [u8; size_of::<Word>()] {
         self.0.to_le_bytes()
     }
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        Some(Self(TxTableEntryWord::from_le_bytes(
-            bytes.try_into().ok()?,
-        )))
+        Some(Self(Word::from_le_bytes(bytes.try_into().ok()?)))
     }
     /// Infallible constructor.
     pub fn from_bytes_array(bytes: [u8; TxTableEntry::byte_len()]) -> Self {
-        Self(TxTableEntryWord::from_le_bytes(bytes))
+        Self(Word::from_le_bytes(bytes))
     }
     pub const fn byte_len() -> usize {
-        size_of::<TxTableEntryWord>()
+        size_of::<Word>()
     }
 
     pub fn from_usize(val: usize) -> Self {
@@ -55,31 +57,87 @@ impl fmt::Display for TxTableEntry {
 }
 
 impl TryFrom<usize> for TxTableEntry {
-    type Error = <TxTableEntryWord as TryFrom<usize>>::Error;
+    type Error = <Word as TryFrom<usize>>::Error;
 
     fn try_from(value: usize) -> Result<Self, Self::Error> {
-        TxTableEntryWord::try_from(value).map(Self)
+        Word::try_from(value).map(Self)
     }
 }
 impl TryFrom<TxTableEntry> for usize {
-    type Error = <usize as TryFrom<TxTableEntryWord>>::Error;
+    type Error = <usize as TryFrom<Word>>::Error;
 
     fn try_from(value: TxTableEntry) -> Result<Self, Self::Error> {
         usize::try_from(value.0)
     }
 }
 
-impl TryFrom<NamespaceId> for TxTableEntry {
-    type Error = <TxTableEntryWord as TryFrom<u64>>::Error;
+/// A word in a namespace table: the number of namespaces, a namespace ID, or the offset at which a
+/// namespace payload ends.
+///
+/// Namespace table words have the same encoding as [`TxTableEntry`], but are a distinct type so
+/// that the two tables cannot be confused. Namespace IDs only convert to and from namespace table
+/// words.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, Default)]
+pub struct NsTableEntry(Word);
 
-    fn try_from(value: NamespaceId) -> Result<Self, Self::Error> {
-        TxTableEntryWord::try_from(u64::from(value)).map(Self)
+impl NsTableEntry {
+    /// Value of the first word of a namespace table which is not in the original (V0) layout.
+    ///
+    /// The tag is followed by a word containing the payload version. A V0 table never starts with
+    /// this word, since it would have to contain `u32::MAX` namespaces.
+    pub const VERSION_TAG: NsTableEntry = Self(Word::MAX);
+
+    pub const fn zero() -> Self {
+        Self(0)
+    }
+    pub const fn to_bytes(&self) -> [u8; size_of::<Word>()] {
+        self.0.to_le_bytes()
+    }
+    /// Parse a word from `bytes`, padding with zeros if `bytes` is too short.
+    pub fn from_bytes_padded(bytes: &[u8]) -> Self {
+        let mut word = [0u8; Self::byte_len()];
+        let len = std::cmp::min(bytes.len(), Self::byte_len());
+        word[..len].copy_from_slice(&bytes[..len]);
+        Self(Word::from_le_bytes(word))
+    }
+    pub const fn byte_len() -> usize {
+        size_of::<Word>()
+    }
+    pub const fn as_word(&self) -> u32 {
+        self.0
     }
 }
-impl TryFrom<TxTableEntry> for NamespaceId {
-    type Error = <u64 as TryFrom<TxTableEntryWord>>::Error;
 
-    fn try_from(value: TxTableEntry) -> Result<Self, Self::Error> {
-        Ok((value.0 as u64).into())
+impl From<u32> for NsTableEntry {
+    fn from(word: u32) -> Self {
+        Self(word)
+    }
+}
+
+impl TryFrom<usize> for NsTableEntry {
+    type Error = <Word as TryFrom<usize>>::Error;
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        Word::try_from(value).map(Self)
+    }
+}
+impl TryFrom<NsTableEntry> for usize {
+    type Error = <usize as TryFrom<Word>>::Error;
+
+    fn try_from(value: NsTableEntry) -> Result<Self, Self::Error> {
+        usize::try_from(value.0)
+    }
+}
+
+impl TryFrom<NamespaceId> for NsTableEntry {
+    type Error = <Word as TryFrom<u64>>::Error;
+
+    fn try_from(value: NamespaceId) -> Result<Self, Self::Error> {
+        Word::try_from(u64::from(value)).map(Self)
+    }
+}
+impl From<NsTableEntry> for NamespaceId {
+    fn from(value: NsTableEntry) -> Self {
+        (value.0 as u64).into()
     }
 }
@@ -0,0 +1,191 @@
+//! Policies for ordering the transactions in a block payload.
+//!
+//! A policy decides the order of the namespaces in the namespace table and the order of the
+//! transactions within each namespace. The policy used to build a V1 payload is recorded in its
+//! namespace table (see [`PayloadVersion`](super::tables::PayloadVersion)), so that rollups can
+//! audit it.
+
+use crate::{NamespaceId, Transaction};
+use clap::ValueEnum;
+use ethers::types::U256;
+use serde::{Deserialize, Serialize};
+
+/// The ordering of transactions in a payload, as recorded in the namespace table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TxOrdering {
+    /// The ordering is not recorded. This is the case for every V0 payload.
+    #[default]
+    Unspecified,
+    /// Namespaces in the order their first transaction arrived, transactions in arrival order.
+    Arrival,
+    /// Namespaces in increasing order of ID, transactions in arrival order.
+    NamespaceId,
+    /// Namespaces in increasing order of ID, transactions in decreasing order of priority fee.
+    /// Transactions with equal fees stay in arrival order.
+    PriorityFee,
+    /// An ordering code not recognized by this version of the software.
+    Unsupported(u32),
+}
+
+impl TxOrdering {
+    const UNSPECIFIED: u32 = 0;
+    const ARRIVAL: u32 = 1;
+    const NAMESPACE_ID: u32 = 2;
+    const PRIORITY_FEE: u32 = 3;
+
+    pub fn from_word(word: u32) -> Self {
+        match word {
+            Self::UNSPECIFIED => Self::Unspecified,
+            Self::ARRIVAL => Self::Arrival,
+            Self::NAMESPACE_ID => Self::NamespaceId,
+            Self::PRIORITY_FEE => Self::PriorityFee,
+            code => Self::Unsupported(code),
+        }
+    }
+
+    pub fn to_word(self) -> u32 {
+        match self {
+            Self::Unspecified => Self::UNSPECIFIED,
+            Self::Arrival => Self::ARRIVAL,
+            Self::NamespaceId => Self::NAMESPACE_ID,
+            Self::PriorityFee => Self::PRIORITY_FEE,
+            Self::Unsupported(code) => code,
+        }
+    }
+
+    /// Whether this ordering requires namespaces to appear in strictly increasing order of ID.
+    pub fn sorts_namespaces(self) -> bool {
+        matches!(self, Self::NamespaceId | Self::PriorityFee)
+    }
+}
+
+/// A policy for ordering the transactions in a payload.
+///
+/// Both methods must be deterministic, so that a payload built from the same transactions in the
+/// same arrival order is always identical.
+pub trait OrderingPolicy {
+    /// The ordering to record in the namespace table.
+    fn ordering(&self) -> TxOrdering;
+
+    /// Order the namespaces of a payload, given in the order their first transaction arrived.
+    fn order_namespaces(&self, _ns_ids: &mut [NamespaceId]) {}
+
+    /// Order the transactions of one namespace, given in arrival order.
+    fn order_transactions(&self, _txs: &mut [Transaction]) {}
+}
+
+/// Keep namespaces and transactions in the order they arrived.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ArrivalOrder;
+
+impl OrderingPolicy for ArrivalOrder {
+    fn ordering(&self) -> TxOrdering {
+        TxOrdering::Arrival
+    }
+}
+
+/// Sort namespaces by ID, keeping transactions within a namespace in arrival order.
+///
+/// This is the policy new payloads are built with unless a builder chooses another.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NamespaceIdOrder;
+
+impl OrderingPolicy for NamespaceIdOrder {
+    fn ordering(&self) -> TxOrdering {
+        TxOrdering::NamespaceId
+    }
+
+    fn order_namespaces(&self, ns_ids: &mut [NamespaceId]) {
+        ns_ids.sort();
+    }
+}
+
+/// Sort namespaces by ID, and transactions within a namespace by decreasing priority fee.
+///
+/// Transactions do not carry a fee, so the fee of each transaction is supplied by the builder.
+#[derive(Clone, Copy, Debug)]
+pub struct PriorityFeeOrder<F>(pub F);
+
+impl<F: Fn(&Transaction) -> U256> OrderingPolicy for PriorityFeeOrder<F> {
+    fn ordering(&self) -> TxOrdering {
+        TxOrdering::PriorityFee
+    }
+
+    fn order_namespaces(&self, ns_ids: &mut [NamespaceId]) {
+        ns_ids.sort();
+    }
+
+    fn order_transactions(&self, txs: &mut [Transaction]) {
+        // `sort_by` is stable, so transactions with equal fees stay in arrival order.
+        txs.sort_by(|a, b| (self.0)(b).cmp(&(self.0)(a)));
+    }
+}
+
+/// An ordering policy which nodes and builders can select in their options.
+///
+/// Ordering by priority fee cannot be selected this way, since transactions do not carry a fee. A
+/// builder which knows the fee of each transaction can build payloads with [`PriorityFeeOrder`]
+/// directly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+pub enum OrderingOption {
+    /// [`ArrivalOrder`].
+    Arrival,
+    /// [`NamespaceIdOrder`].
+    #[default]
+    NamespaceId,
+}
+
+impl OrderingPolicy for OrderingOption {
+    fn ordering(&self) -> TxOrdering {
+        match self {
+            Self::Arrival => ArrivalOrder.ordering(),
+            Self::NamespaceId => NamespaceIdOrder.ordering(),
+        }
+    }
+
+    fn order_namespaces(&self, ns_ids: &mut [NamespaceId]) {
+        match self {
+            Self::Arrival => ArrivalOrder.order_namespaces(ns_ids),
+            Self::NamespaceId => NamespaceIdOrder.order_namespaces(ns_ids),
+        }
+    }
+
+    fn order_transactions(&self, txs: &mut [Transaction]) {
+        match self {
+            Self::Arrival => ArrivalOrder.order_transactions(txs),
+            Self::NamespaceId => NamespaceIdOrder.order_transactions(txs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ordering_word_round_trip() {
+        for word in 0..10 {
+            assert_eq!(TxOrdering::from_word(word).to_word(), word);
+        }
+        assert_eq!(TxOrdering::from_word(0), TxOrdering::Unspecified);
+        assert_eq!(TxOrdering::from_word(42), TxOrdering::Unsupported(42));
+    }
+
+    #[test]
+    fn test_priority_fee_order() {
+        let fee = |tx: &Transaction| U256::from(tx.payload()[0]);
+        let mut txs = [3u8, 1, 3, 2]
+            .into_iter()
+            .enumerate()
+            .map(|(i, fee)| Transaction::new(Default::default(), vec![fee, i as u8]))
+            .collect::<Vec<_>>();
+        PriorityFeeOrder(fee).order_transactions(&mut txs);
+        let order = txs.iter().map(|tx| tx.payload()[1]).collect::<Vec<_>>();
+        // Highest fee first, ties in arrival order.
+        assert_eq!(order, [0, 2, 3, 1]);
+
+        let mut ns_ids = [3u64, 1, 2].map(NamespaceId::from);
+        PriorityFeeOrder(fee).order_namespaces(&mut ns_ids);
+        assert_eq!(ns_ids, [1u64, 2, 3].map(NamespaceId::from));
+    }
+}
@@ -1,7 +1,7 @@
-use crate::block::entry::{TxTableEntry, TxTableEntryWord};
-use crate::block::payload;
-use crate::{BlockBuildingSnafu, Error, NamespaceId, Transaction};
-use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use crate::block::compression::{max_decoded_bytes, NsPayloadFormat};
+use crate::block::entry::TxTableEntry;
+use crate::block::ordering::{NamespaceIdOrder, OrderingPolicy, TxOrdering};
+use crate::{ChainConfig, Error, NamespaceId, Transaction};
 use commit::Committable;
 use derivative::Derivative;
 use hotshot::traits::BlockPayload;
@@ -12,38 +12,15 @@ use jf_primitives::vid::{
     payload_prover::{PayloadProver, Statement},
     VidScheme,
 };
-use num_traits::PrimInt;
 use serde::{Deserialize, Serialize};
-use snafu::OptionExt;
 use std::default::Default;
-use std::{collections::HashMap, fmt::Display};
+use std::{borrow::Cow, collections::HashMap, fmt::Display, ops::Range, sync::OnceLock};
 
-use crate::block::tables::NameSpaceTable;
-use trait_set::trait_set;
+use crate::block::tables::{NameSpaceTable, PayloadVersion};
 
 use crate::block::tables::TxTable;
 
-trait_set! {
-
-    pub trait TableWordTraits = CanonicalSerialize
-        + CanonicalDeserialize
-        + TryFrom<usize>
-        + TryInto<usize>
-        + Default
-         + PrimInt
-        + std::marker::Sync;
-
-    // Note: this trait is not used yet as for now the Payload structs are only parametrized with the TableWord parameter.
-    pub trait OffsetTraits = CanonicalSerialize
-        + CanonicalDeserialize
-        + TryFrom<usize>
-        + TryInto<usize>
-        + Default
-        + std::marker::Sync;
-
-    // Note: this trait is not used yet as for now the Payload structs are only parametrized with the TableWord parameter.
-    pub trait NsIdTraits =CanonicalSerialize + CanonicalDeserialize + Default + std::marker::Sync;
-}
+#[derive(Default)]
 pub(super) struct NamespaceInfo {
     // `tx_table` is a bytes representation of the following table:
     // word[0]: [number n of entries in tx table]
@@ -54,8 +31,7 @@ pub(super) struct NamespaceInfo {
     //
     // Word type is `TxTableEntry`.
     //
-    // TODO final entry should be implicit:
-    // https://github.com/EspressoSystems/espresso-sequencer/issues/757
+    // In `PayloadVersion::V1` the final entry is implicit and dropped from the encoded table.
     pub(crate) tx_table: Vec<u8>,
     pub(crate) tx_bodies: Vec<u8>, // concatenation of all tx payloads
     pub(crate) tx_bytes_end: TxTableEntry, // TODO make this field a usize instead
@@ -63,16 +39,23 @@ pub(super) struct NamespaceInfo {
 }
 
 #[allow(dead_code)] // TODO temporary
-#[derive(Clone, Debug, Derivative, Deserialize, Eq, Serialize)]
+#[derive(Clone, Debug, Derivative, Deserialize, Serialize)]
 #[derivative(Hash, PartialEq)]
-// TODO remove the generic type param, use local constants instead
-pub struct Payload<TableWord: TableWordTraits> {
+pub struct Payload {
     // Sequence of bytes representing the concatenated payloads for each namespace
     #[serde(with = "base64_bytes")]
     pub(super) raw_payload: Vec<u8>,
 
     // Sequence of bytes representing the namespace table
-    pub(super) ns_table: NameSpaceTable<TableWord>,
+    pub(super) ns_table: NameSpaceTable,
+
+    // Decoded bytes of each compressed namespace, filled in the first time any namespace is read.
+    // Raw namespaces are read directly from `raw_payload` and have no entry. Without this, the
+    // `QueryablePayload` accessors would decompress a namespace once per transaction.
+    #[derivative(Hash = "ignore")]
+    #[derivative(PartialEq = "ignore")]
+    #[serde(skip)]
+    pub(super) decoded_namespaces: OnceLock<Vec<Option<Vec<u8>>>>,
     // TODO(X) Revisit caching of frequently used items
     //
     // TODO type should be `OnceLock<SmallRangeProofType>` instead of `OnceLock<Option<SmallRangeProofType>>`.
@@ -83,7 +66,9 @@ pub struct Payload<TableWord: TableWordTraits> {
     // pub tx_table_len_proof: OnceLock<Option<SmallRangeProofType>>,
 }
 
-impl<TableWord: TableWordTraits> Payload<TableWord> {
+impl Eq for Payload {}
+
+impl Payload {
     // TODO dead code even with `pub` because this module is private in lib.rs
     #[allow(dead_code)]
     pub fn num_namespaces(&self) -> usize {
@@ -106,9 +91,105 @@ impl<TableWord: TableWordTraits> Payload<TableWord> {
         Some(parse_ns_payload(
             self.raw_payload.get(ns_payload_range)?,
             ns_id,
+            self.ns_table.version(),
+            self.ns_table.get_payload_format(ns_index),
         ))
     }
 
+    /// Returns the ID, the range in the block payload, and the decoded bytes of the `ns_index`th
+    /// namespace in `ns_table`.
+    ///
+    /// The decoded bytes are always in the raw layout (tx table followed by tx bodies). If the
+    /// namespace payload cannot be decoded, the decoded bytes are empty, so the namespace contains no
+    /// transactions.
+    ///
+    /// Compressed namespaces are decoded once per payload and cached, as long as `ns_table` is the
+    /// payload's own namespace table. Before anything is decoded or cached, the sizes declared by
+    /// all compressed namespaces are checked against [`max_decoded_bytes`] of the payload. A
+    /// payload which exceeds that budget, or has a compressed namespace which does not declare its
+    /// size, is rejected, and all of its compressed namespaces decode as empty.
+    pub(crate) fn decoded_ns_payload(
+        &self,
+        ns_table: &NameSpaceTable,
+        ns_index: usize,
+    ) -> (NamespaceId, Range<usize>, Cow<'_, [u8]>) {
+        let (ns_id, ns_range) = ns_table.get_payload_range(ns_index, self.raw_payload.len());
+        let format = ns_table.get_payload_format(ns_index);
+        if format.is_raw() {
+            return (
+                ns_id,
+                ns_range.clone(),
+                Cow::Borrowed(&self.raw_payload[ns_range]),
+            );
+        }
+        if ns_table != &self.ns_table {
+            let ns_bytes = format
+                .decode(&self.raw_payload[ns_range.clone()])
+                .unwrap_or_default();
+            return (ns_id, ns_range, ns_bytes);
+        }
+        let decoded = self
+            .decoded_namespaces
+            .get_or_init(|| self.decode_namespaces());
+        let ns_bytes = decoded
+            .get(ns_index)
+            .and_then(Option::as_deref)
+            .unwrap_or_default();
+        (ns_id, ns_range, Cow::Borrowed(ns_bytes))
+    }
+
+    // Decode every compressed namespace in the payload's own namespace table, or none of them if
+    // together they would exceed the decoding budget of the payload.
+    //
+    // Namespace ranges may overlap in a malicious namespace table, so the per-namespace ratio
+    // enforced by `NsPayloadFormat::decode` alone does not bound the total.
+    fn decode_namespaces(&self) -> Vec<Option<Vec<u8>>> {
+        let namespaces: Vec<_> = (0..self.ns_table.len())
+            .map(|ns_index| {
+                let format = self.ns_table.get_payload_format(ns_index);
+                let ns_range = self
+                    .ns_table
+                    .get_payload_range(ns_index, self.raw_payload.len())
+                    .1;
+                (format, &self.raw_payload[ns_range])
+            })
+            .collect();
+
+        let declared = namespaces
+            .iter()
+            .filter(|(format, _)| !format.is_raw())
+            .try_fold(0usize, |total, (format, ns_bytes)| {
+                total.checked_add(format.decoded_len(ns_bytes)?)
+            });
+        let budget = max_decoded_bytes(self.raw_payload.len());
+        let within_budget = matches!(declared, Some(total) if total <= budget);
+        if !within_budget {
+            tracing::warn!(
+                ?declared,
+                budget,
+                "compressed namespaces exceed the decoding budget of the payload"
+            );
+        }
+
+        namespaces
+            .into_iter()
+            .map(|(format, ns_bytes)| {
+                if format.is_raw() {
+                    return None;
+                }
+                if !within_budget {
+                    return Some(vec![]);
+                }
+                Some(
+                    format
+                        .decode(ns_bytes)
+                        .map(Cow::into_owned)
+                        .unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+
     // TODO dead code even with `pub` because this module is private in lib.rs
     #[allow(dead_code)]
     /// Returns the flat bytes for namespace `ns_id`, along with a proof of correctness for those bytes.
@@ -119,7 +200,7 @@ impl<TableWord: TableWordTraits> Payload<TableWord> {
     pub fn namespace_with_proof(
         &self,
         // TODO don't need ns_table any more, it's part of self
-        ns_table: &NameSpaceTable<TxTableEntryWord>,
+        ns_table: &NameSpaceTable,
         ns_id: NamespaceId,
         vid_common: VidCommon,
     ) -> Option<NamespaceProof> {
@@ -149,39 +230,87 @@ impl<TableWord: TableWordTraits> Payload<TableWord> {
         })
     }
 
-    pub fn get_ns_table(&self) -> &NameSpaceTable<TableWord> {
+    pub fn get_ns_table(&self) -> &NameSpaceTable {
         &self.ns_table
     }
 
+    /// Build a payload with the default layout, with namespaces in increasing order of ID and
+    /// transactions in arrival order (see [`NamespaceIdOrder`]).
+    ///
+    /// Transactions which would take their namespace over the limit set by `chain_config` are
+    /// left out; see [`limit_namespace_bytes`].
     pub fn from_txs(
-        txs: impl IntoIterator<Item = <payload::Payload<TxTableEntryWord> as BlockPayload>::Transaction>,
+        txs: impl IntoIterator<Item = <Payload as BlockPayload>::Transaction>,
+        chain_config: &ChainConfig,
     ) -> Result<Self, Error> {
-        let mut namespaces: HashMap<NamespaceId, NamespaceInfo> = Default::default();
+        Self::from_txs_versioned(
+            limit_namespace_bytes(txs, chain_config.max_namespace_bytes()),
+            PayloadVersion::default(),
+            &NamespaceIdOrder,
+            |_| NsPayloadFormat::Raw,
+        )
+    }
+
+    /// Build a payload with the given layout `version`, ordering transactions according to
+    /// `ordering` and encoding each namespace with the format chosen by `compression`.
+    ///
+    /// A namespace is only stored compressed if that actually makes it smaller, and does not exceed
+    /// [`MAX_DECOMPRESSION_RATIO`](super::compression::MAX_DECOMPRESSION_RATIO); otherwise it keeps
+    /// the raw layout.
+    pub fn from_txs_versioned(
+        txs: impl IntoIterator<Item = <Payload as BlockPayload>::Transaction>,
+        version: PayloadVersion,
+        ordering: &impl OrderingPolicy,
+        compression: impl Fn(NamespaceId) -> NsPayloadFormat,
+    ) -> Result<Self, Error> {
+        // group txs by namespace, keeping namespaces in the order their first tx arrived
+        let mut ns_ids = vec![];
+        let mut ns_txs: HashMap<NamespaceId, Vec<Transaction>> = Default::default();
+        for tx in txs.into_iter() {
+            let ns_id = tx.namespace();
+            ns_txs
+                .entry(ns_id)
+                .or_insert_with(|| {
+                    ns_ids.push(ns_id);
+                    vec![]
+                })
+                .push(tx);
+        }
+
+        ordering.order_namespaces(&mut ns_ids);
+        let namespaces = ns_ids
+            .into_iter()
+            .map(|ns_id| {
+                let mut txs = ns_txs.remove(&ns_id).unwrap_or_default();
+                ordering.order_transactions(&mut txs);
+                let mut namespace = NamespaceInfo::default();
+                for tx in &txs {
+                    Payload::update_namespace_with_tx(&mut namespace, tx);
+                }
+                (ns_id, namespace)
+            })
+            .collect();
+
         let mut structured_payload = Self {
             raw_payload: vec![],
             ns_table: NameSpaceTable::default(),
+            decoded_namespaces: Default::default(),
         };
-        for tx in txs.into_iter() {
-            Payload::<TableWord>::update_namespace_with_tx(&mut namespaces, tx);
-        }
-
-        structured_payload.generate_raw_payload(namespaces)?;
+        structured_payload.generate_raw_payload(
+            namespaces,
+            version,
+            ordering.ordering(),
+            compression,
+        )?;
         Ok(structured_payload)
     }
 
     fn update_namespace_with_tx(
-        namespaces: &mut HashMap<NamespaceId, NamespaceInfo>,
-        tx: <Payload<TxTableEntryWord> as BlockPayload>::Transaction,
+        namespace: &mut NamespaceInfo,
+        tx: &<Payload as BlockPayload>::Transaction,
     ) {
         let tx_bytes_len: TxTableEntry = tx.payload().len().try_into().unwrap(); // TODO (Philippe) error handling
 
-        let namespace = namespaces.entry(tx.namespace()).or_insert(NamespaceInfo {
-            tx_table: Vec::new(),
-            tx_bodies: Vec::new(),
-            tx_bytes_end: TxTableEntry::zero(),
-            tx_table_len: TxTableEntry::zero(),
-        });
-
         namespace
             .tx_bytes_end
             .checked_add_mut(tx_bytes_len)
@@ -197,39 +326,57 @@ impl<TableWord: TableWordTraits> Payload<TableWord> {
 
     fn generate_raw_payload(
         &mut self,
-        namespaces: HashMap<NamespaceId, NamespaceInfo>,
+        namespaces: Vec<(NamespaceId, NamespaceInfo)>,
+        version: PayloadVersion,
+        ordering: TxOrdering,
+        compression: impl Fn(NamespaceId) -> NsPayloadFormat,
     ) -> Result<(), Error> {
         // fill payload and namespace table
         let mut payload = vec![];
 
-        self.ns_table = NameSpaceTable::from_bytes(Vec::from(
-            TxTableEntry::try_from(namespaces.len())
-                .ok()
-                .context(BlockBuildingSnafu)?
-                .to_bytes(),
-        ));
-
-        let mut namespaces_offsets = vec![];
-        for (id, namespace) in namespaces {
-            payload.extend(namespace.tx_table_len.to_bytes());
-            payload.extend(namespace.tx_table);
-            payload.extend(namespace.tx_bodies);
-            namespaces_offsets.push((id, payload.len()));
+        let mut namespaces_entries = vec![];
+        for (id, mut namespace) in namespaces {
+            if version == PayloadVersion::V1 {
+                // the final tx table entry is implicit
+                let implicit_len = namespace.tx_table.len() - TxTableEntry::byte_len();
+                namespace.tx_table.truncate(implicit_len);
+            }
+            let mut ns_payload = Vec::with_capacity(
+                TxTableEntry::byte_len() + namespace.tx_table.len() + namespace.tx_bodies.len(),
+            );
+            ns_payload.extend(namespace.tx_table_len.to_bytes());
+            ns_payload.extend(namespace.tx_table);
+            ns_payload.extend(namespace.tx_bodies);
+
+            let format = compression(id);
+            let (format, ns_payload) = match format.encode(&ns_payload) {
+                Some(encoded)
+                    if !format.is_raw()
+                        && encoded.len() < ns_payload.len()
+                        && NsPayloadFormat::within_ratio(ns_payload.len(), encoded.len()) =>
+                {
+                    (format, encoded)
+                }
+                _ => (NsPayloadFormat::Raw, ns_payload),
+            };
+            payload.extend(ns_payload);
+            namespaces_entries.push((id, payload.len(), format));
         }
-        self.ns_table = NameSpaceTable::from_namespace_offsets(namespaces_offsets).unwrap();
+        self.ns_table =
+            NameSpaceTable::from_namespace_entries(namespaces_entries, version, ordering)?;
 
         self.raw_payload = payload;
         Ok(())
     }
 }
 
-impl<TableWord: TableWordTraits + std::fmt::Debug> Display for Payload<TableWord> {
+impl Display for Payload {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{self:#?}")
     }
 }
 
-impl<TableWord: TableWordTraits> Committable for Payload<TableWord> {
+impl Committable for Payload {
     fn commit(&self) -> commit::Commitment<Self> {
         todo!()
     }
@@ -259,7 +406,7 @@ impl NamespaceProof {
         &self,
         vid: &VidSchemeType,
         commit: &VidCommitment,
-        ns_table: &NameSpaceTable<TxTableEntryWord>,
+        ns_table: &NameSpaceTable,
     ) -> Option<(Vec<Transaction>, NamespaceId)> {
         match self {
             NamespaceProof::Existence {
@@ -272,6 +419,8 @@ impl NamespaceProof {
 
                 let (ns_id, ns_payload_range) = ns_table
                     .get_payload_range(ns_index, VidSchemeType::get_payload_byte_len(vid_common));
+                let ns_version = ns_table.version();
+                let ns_format = ns_table.get_payload_format(ns_index);
 
                 // verify self against args
                 vid.payload_verify(
@@ -288,7 +437,10 @@ impl NamespaceProof {
 
                 // verification succeeded, return some data
                 // we know ns_id is correct because the corresponding ns_payload_range passed verification
-                Some((parse_ns_payload(ns_payload_flat, ns_id), ns_id))
+                Some((
+                    parse_ns_payload(ns_payload_flat, ns_id, ns_version, ns_format),
+                    ns_id,
+                ))
             }
             NamespaceProof::NonExistence { ns_id } => {
                 if ns_table.lookup(*ns_id).is_some() {
@@ -300,18 +452,61 @@ impl NamespaceProof {
     }
 }
 
-pub fn parse_ns_payload(ns_bytes: &[u8], ns_id: NamespaceId) -> Vec<Transaction> {
-    let num_txs = TxTable::get_tx_table_len(ns_bytes);
-    (0..TxTable::get_tx_table_len(ns_bytes))
-        .map(|tx_idx| TxTable::get_payload_range(ns_bytes, tx_idx, num_txs))
+/// Filter out transactions which would take their namespace over `max_namespace_bytes`.
+///
+/// The size of a namespace is measured in the V0 layout without compression, which is an upper
+/// bound on the size of the namespace in any layout. Transactions which do not fit are dropped from
+/// this payload, but later, smaller transactions in the same namespace may still be included.
+pub fn limit_namespace_bytes(
+    txs: impl IntoIterator<Item = Transaction>,
+    max_namespace_bytes: Option<u64>,
+) -> impl Iterator<Item = Transaction> {
+    let mut ns_bytes: HashMap<NamespaceId, u64> = Default::default();
+    txs.into_iter().filter(move |tx| {
+        let Some(max_namespace_bytes) = max_namespace_bytes else {
+            return true;
+        };
+        let word = TxTableEntry::byte_len() as u64;
+        // a namespace starts with its tx table length, and each tx costs a tx table entry
+        let used = ns_bytes.entry(tx.namespace()).or_insert(word);
+        let tx_bytes = word + tx.payload().len() as u64;
+        if *used + tx_bytes > max_namespace_bytes {
+            tracing::warn!(
+                namespace = %tx.namespace(),
+                tx_bytes,
+                max_namespace_bytes,
+                "namespace is full, excluding transaction from block"
+            );
+            return false;
+        }
+        *used += tx_bytes;
+        true
+    })
+}
+
+/// Parse the transactions in a namespace payload with layout `version`, encoded with `format`.
+///
+/// Compressed namespaces are decompressed first. A namespace which cannot be decoded contains no
+/// transactions.
+pub fn parse_ns_payload(
+    ns_bytes: &[u8],
+    ns_id: NamespaceId,
+    version: PayloadVersion,
+    format: NsPayloadFormat,
+) -> Vec<Transaction> {
+    let Some(ns_bytes) = format.decode(ns_bytes) else {
+        return Vec::new();
+    };
+    let ns_bytes = &ns_bytes[..];
+    let num_txs = TxTable::get_tx_table_len(ns_bytes, version);
+    (0..num_txs)
+        .map(|tx_idx| TxTable::get_payload_range(ns_bytes, tx_idx, num_txs, version))
         .map(|tx_range| Transaction::new(ns_id, ns_bytes[tx_range].to_vec()))
         .collect()
 }
 
 #[cfg(any(test, feature = "testing"))]
-impl hotshot_types::traits::block_contents::TestableBlock
-    for Payload<crate::block::entry::TxTableEntryWord>
-{
+impl hotshot_types::traits::block_contents::TestableBlock for Payload {
     fn genesis() -> Self {
         BlockPayload::genesis().0
     }
@@ -327,31 +522,34 @@ mod test {
     use super::NamespaceProof;
     use crate::{
         block::{
-            entry::{TxTableEntry, TxTableEntryWord},
-            payload::{parse_ns_payload, Payload, TableWordTraits},
+            compression::NsPayloadFormat,
+            entry::{NsTableEntry, TxTableEntry},
+            ordering::{ArrivalOrder, NamespaceIdOrder, PriorityFeeOrder, TxOrdering},
+            payload::{limit_namespace_bytes, parse_ns_payload, Payload},
             queryable,
-            tables::{test::TxTableTest, NameSpaceTable, Table, TxTable},
+            tables::{test::TxTableTest, NameSpaceTable, PayloadVersion, Table, TxTable},
             tx_iterator::TxIndex,
         },
         transaction::NamespaceId,
-        Transaction,
+        ChainConfig, ChainConfigUpgrade, HeaderUpgrade, HeaderVersion, NodeState, Transaction,
     };
     use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+    use ethers::types::U256;
     use helpers::*;
     use hotshot_query_service::availability::QueryablePayload;
     use hotshot_types::{traits::BlockPayload, vid::vid_scheme};
     use jf_primitives::vid::{payload_prover::PayloadProver, VidScheme};
     use rand::RngCore;
-    use std::{collections::HashMap, marker::PhantomData, ops::Range};
+    use std::{collections::HashMap, ops::Range};
 
     const NUM_STORAGE_NODES: usize = 10;
 
     #[test]
     fn basic_correctness() {
-        check_basic_correctness::<TxTableEntryWord>()
+        check_basic_correctness()
     }
 
-    fn check_basic_correctness<TableWord: TableWordTraits>() {
+    fn check_basic_correctness() {
         // play with this
         let test_cases = vec![
             // 1 namespace only
@@ -478,13 +676,13 @@ mod test {
             // TODO assert the final ns table entry offset == self.payload.len()
 
             // test namespace table length
-            let actual_ns_table_len =
-                TxTableEntry::from_bytes(&actual_ns_table.get_bytes()[..TxTableEntry::byte_len()])
-                    .unwrap();
+            let actual_ns_table_len = NsTableEntry::from_bytes_padded(
+                &actual_ns_table.get_bytes()[..NsTableEntry::byte_len()],
+            );
             assert_eq!(
                 actual_ns_table_len,
-                TxTableEntry::try_from(test_case.len()).unwrap(),
-                "namespace table length expect {} got {}",
+                NsTableEntry::try_from(test_case.len()).unwrap(),
+                "namespace table length expect {} got {:?}",
                 test_case.len(),
                 actual_ns_table_len
             );
@@ -493,11 +691,9 @@ mod test {
             // let mut tx_index_offset = 0;
             let mut ns_iter = block.namespace_iter();
             let mut block_iter = block.iter(&actual_ns_table); // test iterator correctness
-            let mut prev_entry = TxTableEntry::zero();
+            let mut prev_entry = NsTableEntry::zero();
             let mut derived_block_payload = Vec::new();
-            for (ns_idx, (ns_id, entry)) in
-                ns_table_iter::<TableWord>(actual_ns_table.get_bytes()).enumerate()
-            {
+            for (ns_idx, (ns_id, entry)) in ns_table_iter(actual_ns_table.get_bytes()).enumerate() {
                 // warning! ns_id may not equal NamespaceId(ns_idx) due to HashMap nondeterminism
 
                 let derived_ns = derived_nss.remove(&ns_id).unwrap();
@@ -508,8 +704,8 @@ mod test {
 
                 // test ns payload
                 let actual_ns_payload_range = Range {
-                    start: usize::try_from(prev_entry.clone()).unwrap(),
-                    end: usize::try_from(entry.clone()).unwrap(),
+                    start: usize::try_from(prev_entry).unwrap(),
+                    end: usize::try_from(entry).unwrap(),
                 };
                 let actual_ns_payload_flat = block
                     .raw_payload
@@ -629,18 +825,478 @@ mod test {
         }
     }
 
+    #[test]
+    fn compressed_namespaces() {
+        setup_logging();
+        setup_backtrace();
+
+        let mut rng = jf_utils::test_rng();
+        let compressible = NamespaceId::from(1);
+        let incompressible = NamespaceId::from(2);
+        let raw = NamespaceId::from(3);
+        let too_compressible = NamespaceId::from(4);
+        let mut txs = vec![];
+        for i in 0..5u8 {
+            let low_entropy = random_bytes(1000, &mut rng)
+                .into_iter()
+                .map(|byte| byte % 16)
+                .collect();
+            txs.push(Transaction::new(compressible, low_entropy));
+            txs.push(Transaction::new(
+                incompressible,
+                random_bytes(100, &mut rng),
+            ));
+            txs.push(Transaction::new(raw, vec![i; 1000]));
+            txs.push(Transaction::new(too_compressible, vec![i; 1000]));
+        }
+
+        let block =
+            Payload::from_txs_versioned(txs.clone(), PayloadVersion::V0, &ArrivalOrder, |ns_id| {
+                if ns_id == raw {
+                    NsPayloadFormat::Raw
+                } else {
+                    NsPayloadFormat::Zstd
+                }
+            })
+            .unwrap();
+        let ns_table = block.get_ns_table();
+        let format = |ns_id| ns_table.get_payload_format(ns_table.lookup(ns_id).unwrap());
+        assert_eq!(format(compressible), NsPayloadFormat::Zstd);
+        // Compression is skipped when it does not save space, or would exceed the maximum ratio.
+        assert_eq!(format(incompressible), NsPayloadFormat::Raw);
+        assert_eq!(format(raw), NsPayloadFormat::Raw);
+        assert_eq!(format(too_compressible), NsPayloadFormat::Raw);
+
+        // Raw namespaces keep the uncompressed layout.
+        let uncompressed = Payload::from_txs(txs.clone(), &ChainConfig::default()).unwrap();
+        let uncompressed_ns_table = uncompressed.get_ns_table();
+        let ns_bytes = |payload: &Payload, ns_table: &NameSpaceTable, ns_id| {
+            let range = ns_table
+                .get_payload_range(ns_table.lookup(ns_id).unwrap(), payload.raw_payload.len())
+                .1;
+            payload.raw_payload[range].to_vec()
+        };
+        assert_eq!(
+            ns_bytes(&block, ns_table, raw),
+            ns_bytes(&uncompressed, uncompressed_ns_table, raw)
+        );
+        assert!(block.raw_payload.len() < uncompressed.raw_payload.len());
+        assert_eq!(
+            uncompressed_ns_table.get_payload_format(0),
+            NsPayloadFormat::Raw
+        );
+
+        // Compression is transparent to readers.
+        assert_eq!(block.len(ns_table), txs.len());
+        // Compressed namespaces are decoded once and cached; raw ones are read in place.
+        let decoded = block.decoded_namespaces.get().unwrap();
+        assert!(decoded[ns_table.lookup(compressible).unwrap()].is_some());
+        assert!(decoded[ns_table.lookup(raw).unwrap()].is_none());
+        for ns_id in [compressible, incompressible, raw, too_compressible] {
+            assert_eq!(block.namespace(ns_id), uncompressed.namespace(ns_id));
+        }
+
+        let vid = vid_scheme(NUM_STORAGE_NODES);
+        let disperse_data = vid.disperse(&block.raw_payload).unwrap();
+        let ns_proof = block
+            .namespace_with_proof(ns_table, compressible, disperse_data.common.clone())
+            .unwrap();
+        let (ns_proof_txs, _) = ns_proof
+            .verify(&vid, &disperse_data.commit, ns_table)
+            .unwrap();
+        assert_eq!(ns_proof_txs, block.namespace(compressible).unwrap());
+
+        for index in block.iter(ns_table) {
+            let tx = block.transaction(ns_table, &index).unwrap();
+            let (tx_with_proof, proof) = block.transaction_with_proof(ns_table, &index).unwrap();
+            assert_eq!(tx, tx_with_proof);
+            if ns_table.get_payload_format(index.ns_idx).is_raw() {
+                // Raw tx proofs are not yet verifiable; see `TxInclusionProof::verify`.
+                continue;
+            }
+            assert_eq!(
+                proof.verify(
+                    &tx,
+                    index,
+                    &vid,
+                    &disperse_data.commit,
+                    &disperse_data.common
+                ),
+                Some(Ok(()))
+            );
+        }
+    }
+
+    #[test]
+    fn decompression_budget() {
+        setup_logging();
+        setup_backtrace();
+
+        // A namespace which stays within the maximum ratio on its own.
+        let ns_payload: Vec<u8> = (0..10_000u64)
+            .map(|i| ((i * 2_654_435_761) >> 7) as u8 % 16)
+            .collect();
+        let encoded = NsPayloadFormat::Zstd.encode(&ns_payload).unwrap();
+        assert!(NsPayloadFormat::within_ratio(
+            ns_payload.len(),
+            encoded.len()
+        ));
+
+        // A malicious namespace table can point many namespaces at the same bytes, so that every
+        // other namespace decodes the whole payload. One namespace fits in the budget; three do
+        // not.
+        let payload_with_table = |num_namespaces: u64| {
+            let entries = (0..num_namespaces)
+                .map(|i| {
+                    if i % 2 == 0 {
+                        (i.into(), encoded.len(), NsPayloadFormat::Zstd)
+                    } else {
+                        (i.into(), 0, NsPayloadFormat::Raw)
+                    }
+                })
+                .collect();
+            Payload {
+                raw_payload: encoded.clone(),
+                ns_table: NameSpaceTable::from_namespace_entries(
+                    entries,
+                    PayloadVersion::V0,
+                    TxOrdering::Unspecified,
+                )
+                .unwrap(),
+                decoded_namespaces: Default::default(),
+            }
+        };
+
+        let honest = payload_with_table(1);
+        let (_, _, decoded) = honest.decoded_ns_payload(&honest.ns_table, 0);
+        assert_eq!(decoded, &ns_payload[..]);
+
+        let bomb = payload_with_table(5);
+        for ns_index in 0..5 {
+            let (_, _, decoded) = bomb.decoded_ns_payload(&bomb.ns_table, ns_index);
+            assert!(decoded.is_empty());
+        }
+        // Nothing is kept in memory for the rejected payload.
+        assert!(bomb
+            .decoded_namespaces
+            .get()
+            .unwrap()
+            .iter()
+            .all(|ns| ns.as_ref().unwrap().is_empty()));
+    }
+
+    #[test]
+    fn v1_layout() {
+        setup_logging();
+        setup_backtrace();
+
+        let mut rng = jf_utils::test_rng();
+        let mut txs = vec![];
+        for (ns_id, lengths) in [(1u64, vec![5, 0, 8]), (2, vec![7]), (3, vec![9, 0])] {
+            for len in lengths {
+                txs.push(Transaction::new(ns_id.into(), random_bytes(len, &mut rng)));
+            }
+        }
+
+        let v0 = Payload::from_txs(txs.clone(), &ChainConfig::default()).unwrap();
+        let v1 =
+            Payload::from_txs_versioned(txs.clone(), PayloadVersion::V1, &ArrivalOrder, |_| {
+                NsPayloadFormat::Raw
+            })
+            .unwrap();
+        assert_eq!(v0.get_ns_table().version(), PayloadVersion::V0);
+        assert_eq!(v1.get_ns_table().version(), PayloadVersion::V1);
+
+        // V1 saves the final entry of each tx table, and pays for the version tag, version and
+        // ordering in the namespace table.
+        let word = TxTableEntry::byte_len();
+        assert_eq!(v1.raw_payload.len(), v0.raw_payload.len() - 3 * word);
+        assert_eq!(
+            v1.get_ns_table().get_bytes().len(),
+            v0.get_ns_table().get_bytes().len() + 3 * word
+        );
+
+        // Both layouts decode to the same transactions.
+        let ns_table = v1.get_ns_table();
+        assert_eq!(ns_table.len(), 3);
+        assert_eq!(v1.len(ns_table), txs.len());
+        for ns_id in [1u64, 2, 3] {
+            assert_eq!(v1.namespace(ns_id.into()), v0.namespace(ns_id.into()));
+        }
+
+        let vid = vid_scheme(NUM_STORAGE_NODES);
+        let disperse_data = vid.disperse(&v1.raw_payload).unwrap();
+        for ns_index in 0..ns_table.len() {
+            let ns_id = ns_table.get_table_entry(ns_index).0;
+            let (ns_proof_txs, _) = v1
+                .namespace_with_proof(ns_table, ns_id, disperse_data.common.clone())
+                .unwrap()
+                .verify(&vid, &disperse_data.commit, ns_table)
+                .unwrap();
+            assert_eq!(ns_proof_txs, v1.namespace(ns_id).unwrap());
+        }
+        for index in v1.iter(ns_table) {
+            let (tx, proof) = v1.transaction_with_proof(ns_table, &index).unwrap();
+            assert_eq!(v1.transaction(ns_table, &index).unwrap(), tx);
+            assert_eq!(
+                proof.verify(
+                    &tx,
+                    index,
+                    &vid,
+                    &disperse_data.commit,
+                    &disperse_data.common
+                ),
+                Some(Ok(()))
+            );
+        }
+
+        // An empty V1 block.
+        let empty = Payload::from_txs_versioned([], PayloadVersion::V1, &ArrivalOrder, |_| {
+            NsPayloadFormat::Raw
+        })
+        .unwrap();
+        assert_eq!(empty.get_ns_table().version(), PayloadVersion::V1);
+        assert_eq!(empty.len(empty.get_ns_table()), 0);
+
+        // A table with an unknown version contains no namespaces.
+        let mut unsupported = ns_table.get_bytes().to_vec();
+        unsupported[word..2 * word].copy_from_slice(&TxTableEntry::from_usize(7).to_bytes());
+        let unsupported = NameSpaceTable::from_bytes(unsupported);
+        assert_eq!(unsupported.version(), PayloadVersion::Unsupported(7));
+        assert!(unsupported.is_empty());
+        assert_eq!(v1.len(&unsupported), 0);
+    }
+
+    #[test]
+    fn build_context_follows_header_version() {
+        setup_logging();
+        setup_backtrace();
+
+        let instance = NodeState::mock().with_header_upgrades([HeaderUpgrade {
+            height: 5,
+            version: HeaderVersion::V2,
+        }]);
+        let ctx = instance.build_context().clone();
+        let txs = [Transaction::new(1u64.into(), vec![1, 2, 3])];
+        let version = || {
+            instance
+                .build_payload(txs.clone())
+                .unwrap()
+                .ns_table
+                .version()
+        };
+
+        // Until a block just below the upgrade is decided, the next block may still be a V1 block.
+        assert_eq!(version(), PayloadVersion::V0);
+        ctx.record_decided(3);
+        assert_eq!(version(), PayloadVersion::V0);
+        ctx.record_decided(4);
+        assert_eq!(version(), PayloadVersion::V1);
+
+        // Decides reported out of order do not move the context back.
+        ctx.record_decided(2);
+        assert_eq!(version(), PayloadVersion::V1);
+
+        // Clones of the instance share its context.
+        assert_eq!(instance.clone().payload_version(), PayloadVersion::V1);
+
+        // The genesis payload does not depend on the instance.
+        let (genesis, _) = <Payload as BlockPayload>::genesis();
+        assert_eq!(genesis.ns_table.version(), PayloadVersion::V0);
+        assert_eq!(
+            genesis.raw_payload,
+            Payload::from_txs([], &ChainConfig::default())
+                .unwrap()
+                .raw_payload
+        );
+
+        assert!(HeaderVersion::V1.allows_payload_version(PayloadVersion::V0));
+        assert!(!HeaderVersion::V1.allows_payload_version(PayloadVersion::V1));
+        assert!(HeaderVersion::V2.allows_payload_version(PayloadVersion::V0));
+        assert!(HeaderVersion::V2.allows_payload_version(PayloadVersion::V1));
+    }
+
+    #[test]
+    fn build_context_namespace_limit() {
+        setup_logging();
+        setup_backtrace();
+
+        // Unlimited until height 5, then a quarter of the block, then half of it from height 10.
+        let chain_config = ChainConfig::default();
+        let instance = NodeState::mock().with_chain_config_upgrades([
+            ChainConfigUpgrade {
+                height: 5,
+                chain_config: chain_config.with_max_namespace_share_bps(2_500),
+            },
+            ChainConfigUpgrade {
+                height: 10,
+                chain_config: chain_config.with_max_namespace_share_bps(5_000),
+            },
+        ]);
+        let quarter = chain_config.max_block_size() / 4;
+        let half = chain_config.max_block_size() / 2;
+        let ctx = instance.build_context().clone();
+
+        // Each tx fills a quarter of the block, counting its tx table entries.
+        let tx_bytes = quarter as usize - 2 * TxTableEntry::byte_len();
+        let txs = [
+            Transaction::new(1u64.into(), vec![0; tx_bytes]),
+            Transaction::new(1u64.into(), vec![0; tx_bytes]),
+        ];
+        let num_txs = || {
+            let block = instance.build_payload(txs.clone()).unwrap();
+            block.len(block.get_ns_table())
+        };
+
+        // The block may end up at any height, so the strictest upcoming limit applies.
+        assert_eq!(instance.max_namespace_bytes(), Some(quarter));
+        assert_eq!(num_txs(), 1);
+        ctx.record_decided(8);
+        assert_eq!(instance.max_namespace_bytes(), Some(quarter));
+        ctx.record_decided(9);
+        assert_eq!(instance.max_namespace_bytes(), Some(half));
+        assert_eq!(num_txs(), 2);
+
+        // Without upgrades, the default chain config applies.
+        assert_eq!(NodeState::mock().max_namespace_bytes(), None);
+    }
+
+    #[test]
+    fn ordering_policies() {
+        setup_logging();
+        setup_backtrace();
+
+        // (namespace, priority fee), in arrival order
+        let txs = [(3u64, 1u8), (1, 5), (3, 7), (2, 2), (1, 9), (3, 7)]
+            .into_iter()
+            .enumerate()
+            .map(|(i, (ns_id, fee))| Transaction::new(ns_id.into(), vec![fee, i as u8]))
+            .collect::<Vec<_>>();
+        let ns_order = |block: &Payload| {
+            let ns_table = block.get_ns_table();
+            (0..ns_table.len())
+                .map(|ns_index| u64::from(ns_table.get_table_entry(ns_index).0))
+                .collect::<Vec<_>>()
+        };
+        let arrival_indices = |block: &Payload, ns_id: u64| {
+            block
+                .namespace(ns_id.into())
+                .unwrap()
+                .iter()
+                .map(|tx| tx.payload()[1])
+                .collect::<Vec<_>>()
+        };
+
+        // Payloads are built in namespace ID order by default, and V0 does not record the ordering.
+        let block = Payload::from_txs(txs.clone(), &ChainConfig::default()).unwrap();
+        assert_eq!(ns_order(&block), [1, 2, 3]);
+        assert_eq!(arrival_indices(&block, 3), [0, 2, 5]);
+        assert_eq!(block.get_ns_table().ordering(), TxOrdering::Unspecified);
+        assert_eq!(
+            block,
+            Payload::from_txs(txs.clone(), &ChainConfig::default()).unwrap()
+        );
+
+        let block =
+            Payload::from_txs_versioned(txs.clone(), PayloadVersion::V1, &ArrivalOrder, |_| {
+                NsPayloadFormat::Raw
+            })
+            .unwrap();
+        assert_eq!(ns_order(&block), [3, 1, 2]);
+        assert_eq!(block.get_ns_table().ordering(), TxOrdering::Arrival);
+        assert!(block.get_ns_table().is_consistent_with_ordering());
+
+        let block =
+            Payload::from_txs_versioned(txs.clone(), PayloadVersion::V1, &NamespaceIdOrder, |_| {
+                NsPayloadFormat::Raw
+            })
+            .unwrap();
+        assert_eq!(ns_order(&block), [1, 2, 3]);
+        assert_eq!(arrival_indices(&block, 3), [0, 2, 5]);
+        assert_eq!(block.get_ns_table().ordering(), TxOrdering::NamespaceId);
+        assert!(block.get_ns_table().is_consistent_with_ordering());
+
+        let fee = |tx: &Transaction| U256::from(tx.payload()[0]);
+        let block = Payload::from_txs_versioned(
+            txs.clone(),
+            PayloadVersion::V1,
+            &PriorityFeeOrder(fee),
+            |_| NsPayloadFormat::Raw,
+        )
+        .unwrap();
+        assert_eq!(ns_order(&block), [1, 2, 3]);
+        assert_eq!(arrival_indices(&block, 1), [4, 1]);
+        assert_eq!(arrival_indices(&block, 3), [2, 5, 0]);
+        assert_eq!(block.get_ns_table().ordering(), TxOrdering::PriorityFee);
+        assert!(block.get_ns_table().is_consistent_with_ordering());
+
+        // A table claiming sorted namespaces which are not sorted fails the audit.
+        let unsorted = NameSpaceTable::from_namespace_entries(
+            vec![
+                (2u64.into(), 10, NsPayloadFormat::Raw),
+                (1u64.into(), 20, NsPayloadFormat::Raw),
+            ],
+            PayloadVersion::V1,
+            TxOrdering::NamespaceId,
+        )
+        .unwrap();
+        assert!(!unsorted.is_consistent_with_ordering());
+    }
+
+    #[test]
+    fn namespace_limit() {
+        setup_logging();
+        setup_backtrace();
+
+        // 2500 bps of 1000 bytes leaves 250 bytes per namespace: the tx table length, then 4
+        // bytes of tx table and 96 bytes of payload per transaction fits two transactions.
+        let chain_config =
+            ChainConfig::new(35353u16, 1000, 0u64).with_max_namespace_share_bps(2500);
+        let txs = (0..4u8)
+            .map(|i| Transaction::new(NamespaceId::from(1), vec![i; 96]))
+            .chain([Transaction::new(NamespaceId::from(2), vec![0; 96])])
+            .chain([Transaction::new(NamespaceId::from(1), vec![4; 10])])
+            .collect::<Vec<_>>();
+
+        let kept = limit_namespace_bytes(txs.clone(), chain_config.max_namespace_bytes())
+            .collect::<Vec<_>>();
+        // A later, smaller transaction still fits in the space left over.
+        assert_eq!(
+            kept,
+            [
+                txs[0].clone(),
+                txs[1].clone(),
+                txs[4].clone(),
+                txs[5].clone()
+            ]
+        );
+
+        let block = Payload::from_txs(txs.clone(), &chain_config).unwrap();
+        let ns_table = block.get_ns_table();
+        for ns_index in 0..ns_table.len() {
+            let len = ns_table.namespace_byte_len(ns_index);
+            assert!(len as u64 <= chain_config.max_namespace_bytes().unwrap());
+        }
+        assert_eq!(block.namespace(1u64.into()).unwrap().len(), 3);
+
+        // Without a limit every transaction is included.
+        let block = Payload::from_txs(txs.clone(), &ChainConfig::default()).unwrap();
+        assert_eq!(block.namespace(1u64.into()).unwrap().len(), 5);
+    }
+
     #[test]
     fn malformed_payloads() {
-        check_malformed_payloads::<u32>();
-        //check_malformed_payloads::<u64>(); // TODO Philippe this test is failing
+        check_malformed_payloads();
+        // TODO Philippe this test is failing with 8-byte table words (formerly
+        // `check_malformed_payloads::<u64>`)
     }
-    fn check_malformed_payloads<TableWord: TableWordTraits>() {
+    fn check_malformed_payloads() {
         // play with this
         let mut rng = jf_utils::test_rng();
         let test_cases = vec![
             // negative-length txs
-            TestCase::<TableWord>::from_entries(&[30, 10, 20], &mut rng), // 1 negative-length tx
-            TestCase::from_entries(&[30, 20, 10], &mut rng),              // 2 negative-length txs
+            TestCase::from_entries(&[30, 10, 20], &mut rng), // 1 negative-length tx
+            TestCase::from_entries(&[30, 20, 10], &mut rng), // 2 negative-length txs
             // truncated payload
             TestCase::with_total_len(&[10, 20, 30], 20, &mut rng), // truncated tx payload
             TestCase::with_trimmed_body(&[10, 20, 30], 0, &mut rng), // 0-length tx payload
@@ -722,16 +1378,15 @@ mod test {
 
     #[test]
     fn malicious_tx_inclusion_proof() {
-        check_malicious_tx_inclusion_proof::<u32>();
-        check_malicious_tx_inclusion_proof::<u64>();
+        check_malicious_tx_inclusion_proof();
     }
 
-    fn check_malicious_tx_inclusion_proof<TableWord: TableWordTraits>() {
+    fn check_malicious_tx_inclusion_proof() {
         setup_logging();
         setup_backtrace();
 
         let mut rng = jf_utils::test_rng();
-        let test_case = TestCase::<TableWord>::from_tx_table_len_unchecked(1, 3, &mut rng); // 3-byte payload too small to store tx table len
+        let test_case = TestCase::from_tx_table_len_unchecked(1, 3, &mut rng); // 3-byte payload too small to store tx table len
 
         // TODO don't initialize Payload with empty namespace table
         let block = Payload::from_bytes(
@@ -751,7 +1406,10 @@ mod test {
         let tx = Transaction::new(Default::default(), Vec::new());
         let proof = queryable::gen_tx_proof_for_testing(
             0..block.raw_payload.len(),
-            TxTableEntry::from_usize(TxTable::get_tx_table_len(&block.raw_payload)),
+            TxTableEntry::from_usize(TxTable::get_tx_table_len(
+                &block.raw_payload,
+                PayloadVersion::V0,
+            )),
             vid.payload_proof(
                 &block.raw_payload,
                 0..std::cmp::min(TxTableEntry::byte_len(), block.raw_payload.len()),
@@ -778,11 +1436,11 @@ mod test {
 
     #[test]
     fn arbitrary_payloads() {
-        check_arbitrary_ns_table::<TxTableEntryWord>();
-        check_arbitrary_tx_table::<TxTableEntryWord>();
+        check_arbitrary_ns_table();
+        check_arbitrary_tx_table();
     }
 
-    fn check_arbitrary_ns_table<TableWord: TableWordTraits>() {
+    fn check_arbitrary_ns_table() {
         setup_logging();
         setup_backtrace();
         let mut rng = jf_utils::test_rng();
@@ -813,7 +1471,7 @@ mod test {
             (NamespaceId::from(3), 50),
             (NamespaceId::from(4), 150),
         ];
-        let ns4 = NameSpaceTable::<TableWord>::from_namespace_offsets(namespace_offsets)
+        let ns4 = NameSpaceTable::from_namespace_offsets(namespace_offsets)
             .unwrap()
             .get_bytes()
             .to_vec();
@@ -912,7 +1570,7 @@ mod test {
         }
     }
 
-    fn check_arbitrary_tx_table<TableWord: TableWordTraits>() {
+    fn check_arbitrary_tx_table() {
         setup_logging();
         setup_backtrace();
         let mut rng = jf_utils::test_rng();
@@ -924,7 +1582,7 @@ mod test {
             (NamespaceId::from(1), 200),
             (NamespaceId::from(2), 300),
         ];
-        let ns1 = NameSpaceTable::<TableWord>::from_namespace_offsets(namespace_offsets)
+        let ns1 = NameSpaceTable::from_namespace_offsets(namespace_offsets)
             .unwrap()
             .get_bytes()
             .to_vec();
@@ -954,7 +1612,7 @@ mod test {
             (NamespaceId::from(1), 1300),
             (NamespaceId::from(2), 2300),
         ];
-        let ns4 = NameSpaceTable::<TableWord>::from_namespace_offsets(namespace_offsets)
+        let ns4 = NameSpaceTable::from_namespace_offsets(namespace_offsets)
             .unwrap()
             .get_bytes()
             .to_vec();
@@ -996,7 +1654,7 @@ mod test {
                 let ns_bytes = &block.raw_payload[ns_range.clone()];
 
                 // ns cannot hold more than max num of txs
-                let tx_table_len = TxTable::get_tx_table_len(ns_bytes);
+                let tx_table_len = TxTable::get_tx_table_len(ns_bytes, PayloadVersion::V0);
                 let max_tx_table_len = ns_bytes.len().saturating_sub(TxTableEntry::byte_len())
                     / TxTableEntry::byte_len();
                 assert!(
@@ -1006,7 +1664,8 @@ mod test {
                     max_tx_table_len
                 );
 
-                let txs = parse_ns_payload(ns_bytes, ns_id);
+                let txs =
+                    parse_ns_payload(ns_bytes, ns_id, PayloadVersion::V0, NsPayloadFormat::Raw);
                 total_tx_num += txs.len();
 
                 let actual_tx_table_len = read_usize(ns_bytes, 0);
@@ -1022,7 +1681,12 @@ mod test {
                 for (tx_idx, tx) in txs.iter().enumerate() {
                     assert!(tx_iter.next().is_some());
 
-                    let tx_range = TxTable::get_payload_range(ns_bytes, tx_idx, tx_table_len);
+                    let tx_range = TxTable::get_payload_range(
+                        ns_bytes,
+                        tx_idx,
+                        tx_table_len,
+                        PayloadVersion::V0,
+                    );
                     // read tx end offset directly from raw payload bytes
                     tx_offset_bytes[..TxTableEntry::byte_len()].copy_from_slice(
                         &actual_payload_bytes[ns_range.start
@@ -1087,12 +1751,11 @@ mod test {
         }
     }
 
-    struct TestCase<TableWord: TableWordTraits> {
+    struct TestCase {
         payload: Vec<u8>,
         num_txs: usize,
-        phantomdata: PhantomData<TableWord>,
     }
-    impl<TableWord: TableWordTraits> TestCase<TableWord> {
+    impl TestCase {
         /// Return a well-formed random block whose tx table is derived from `lengths`.
         #[allow(dead_code)]
         fn from_lengths<R: RngCore>(lengths: &[usize], rng: &mut R) -> Self {
@@ -1103,7 +1766,7 @@ mod test {
         ///
         /// If `entries` is well-formed then the result is well-formed.
         fn from_entries<R: RngCore>(entries: &[usize], rng: &mut R) -> Self {
-            let tx_table = TxTableTest::<TableWord>::from_entries(entries);
+            let tx_table = TxTableTest::from_entries(entries);
             Self {
                 payload: [
                     tx_table.get_payload(),
@@ -1111,7 +1774,6 @@ mod test {
                 ]
                 .concat(),
                 num_txs: entries.len(),
-                phantomdata: Default::default(),
             }
         }
 
@@ -1123,11 +1785,10 @@ mod test {
                 body_len < tx_bodies_byte_len(entries),
                 "body_len too large to trim the body"
             );
-            let tx_table = TxTableTest::<TableWord>::from_entries(entries);
+            let tx_table = TxTableTest::from_entries(entries);
             Self {
                 payload: [tx_table.get_payload(), random_bytes(body_len, rng)].concat(),
                 num_txs: entries.len(),
-                phantomdata: Default::default(),
             }
         }
 
@@ -1143,9 +1804,9 @@ mod test {
             rng: &mut R,
         ) -> Self {
             assert!(
-                tx_table_byte_len::<TableWord>(entries) <= block_byte_len,
+                tx_table_byte_len(entries) <= block_byte_len,
                 "tx table size {} for entries {:?} exceeds block_byte_len {}",
-                tx_table_byte_len::<TableWord>(entries),
+                tx_table_byte_len(entries),
                 entries,
                 block_byte_len
             );
@@ -1158,20 +1819,16 @@ mod test {
             block_byte_len: usize,
             rng: &mut R,
         ) -> Self {
-            let tx_table = TxTableTest::<TableWord>::from_entries(entries);
+            let tx_table = TxTableTest::from_entries(entries);
             let mut payload = tx_table.get_payload();
             let num_txs = if block_byte_len > payload.len() {
                 payload.extend(random_bytes(block_byte_len - payload.len(), rng));
                 entries.len()
             } else {
                 payload.truncate(block_byte_len);
-                (block_byte_len / TxTableTest::<TableWord>::byte_len()).saturating_sub(1)
+                (block_byte_len / TxTableTest::byte_len()).saturating_sub(1)
             };
-            Self {
-                payload,
-                num_txs,
-                phantomdata: Default::default(),
-            }
+            Self { payload, num_txs }
         }
 
         /// Return a random block whose tx table indicates `tx_table_len` txs and whose total byte length is `block_byte_len`.
@@ -1185,7 +1842,7 @@ mod test {
             block_byte_len: usize,
             rng: &mut R,
         ) -> Self {
-            let tx_table_byte_len = (tx_table_len + 1) * TxTableTest::<TableWord>::byte_len();
+            let tx_table_byte_len = (tx_table_len + 1) * TxTableTest::byte_len();
             assert!(
                 tx_table_byte_len <= block_byte_len,
                 "tx table size {} exceeds block size {}",
@@ -1202,8 +1859,7 @@ mod test {
             rng: &mut R,
         ) -> Self {
             // accommodate extremely small block payload
-            let header_byte_len =
-                std::cmp::min(TxTableTest::<TableWord>::byte_len(), block_byte_len);
+            let header_byte_len = std::cmp::min(TxTableTest::byte_len(), block_byte_len);
             let mut payload = vec![0; block_byte_len];
             rng.fill_bytes(&mut payload);
             payload[..header_byte_len].copy_from_slice(
@@ -1213,22 +1869,21 @@ mod test {
                 payload,
                 num_txs: std::cmp::min(
                     tx_table_len,
-                    (block_byte_len / TxTableTest::<TableWord>::byte_len()).saturating_sub(1),
+                    (block_byte_len / TxTableTest::byte_len()).saturating_sub(1),
                 ),
-                phantomdata: Default::default(),
             }
         }
     }
 
     mod helpers {
-        use crate::block::entry::TxTableEntry;
-        use crate::block::payload::TableWordTraits;
+        use crate::block::entry::{NsTableEntry, TxTableEntry};
+        use crate::block::ordering::{ArrivalOrder, OrderingPolicy, TxOrdering};
         use crate::block::tables::{test::TxTableTest, NameSpaceTable, Table};
         use crate::NamespaceId;
         use rand::RngCore;
 
-        pub fn tx_table_byte_len<TableWord: TableWordTraits>(entries: &[usize]) -> usize {
-            (entries.len() + 1) * TxTableTest::<TableWord>::byte_len()
+        pub fn tx_table_byte_len(entries: &[usize]) -> usize {
+            (entries.len() + 1) * TxTableTest::byte_len()
         }
 
         pub fn entries_from_lengths(lengths: &[usize]) -> Vec<usize> {
@@ -1299,19 +1954,17 @@ mod test {
             result
         }
 
-        pub fn ns_table_iter<TableWord: TableWordTraits>(
+        pub fn ns_table_iter(
             ns_table_bytes: &[u8],
-        ) -> impl Iterator<Item = (NamespaceId, TxTableEntry)> + '_ {
-            ns_table_bytes[NameSpaceTable::<TableWord>::byte_len()..] // first few bytes is the table length, skip that
-                .chunks(2 * TxTableEntry::byte_len())
+        ) -> impl Iterator<Item = (NamespaceId, NsTableEntry)> + '_ {
+            ns_table_bytes[NameSpaceTable::byte_len()..] // first few bytes is the table length, skip that
+                .chunks(2 * NsTableEntry::byte_len())
                 .map(|bytes| {
                     // read (namespace id, entry) from the namespace table
-                    let ns_id = NamespaceId::try_from(
-                        TxTableEntry::from_bytes(&bytes[..TxTableEntry::byte_len()]).unwrap(),
-                    )
-                    .unwrap();
-                    let entry =
-                        TxTableEntry::from_bytes(&bytes[TxTableEntry::byte_len()..]).unwrap();
+                    let ns_id = NamespaceId::from(NsTableEntry::from_bytes_padded(
+                        &bytes[..NsTableEntry::byte_len()],
+                    ));
+                    let entry = NsTableEntry::from_bytes_padded(&bytes[NsTableEntry::byte_len()..]);
                     (ns_id, entry)
                 })
         }
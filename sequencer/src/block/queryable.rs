@@ -1,8 +1,8 @@
-use crate::block::entry::TxTableEntryWord;
+use crate::block::compression::NsPayloadFormat;
 use crate::block::payload::Payload;
-use crate::block::tables::TxTable;
+use crate::block::tables::{NameSpaceTable, PayloadVersion, TxTable};
 use hotshot_query_service::availability::QueryablePayload;
-use hotshot_types::vid::{vid_scheme, SmallRangeProofType};
+use hotshot_types::vid::{vid_scheme, LargeRangeProofType, SmallRangeProofType};
 use jf_primitives::vid::payload_prover::{PayloadProver, Statement};
 use serde::{Deserialize, Serialize};
 use std::ops::Range;
@@ -14,16 +14,15 @@ use super::{
     tx_iterator::{TxIndex, TxIterator},
 };
 
-// TODO don't hard-code TxTableEntryWord generic param
-impl QueryablePayload for Payload<TxTableEntryWord> {
+impl QueryablePayload for Payload {
     type TransactionIndex = TxIndex;
-    type Iter<'a> = TxIterator<'a, TxTableEntryWord>;
+    type Iter<'a> = TxIterator<'a>;
     type InclusionProof = TxInclusionProof;
 
     fn len(&self, ns_table: &Self::Metadata) -> usize {
         (0..ns_table.len())
-            .map(|ns_idx| ns_table.get_payload_range(ns_idx, self.raw_payload.len()).1)
-            .map(|ns_range| TxTable::get_tx_table_len(&self.raw_payload[ns_range]))
+            .map(|ns_idx| self.decoded_ns_payload(ns_table, ns_idx).2)
+            .map(|ns_payload| TxTable::get_tx_table_len(&ns_payload, ns_table.version()))
             .sum()
     }
 
@@ -40,20 +39,16 @@ impl QueryablePayload for Payload<TxTableEntryWord> {
         if ns_idx >= meta.len() {
             return None; // error: index out of bounds
         }
-        let (ns_id, ns_range) = meta.get_payload_range(ns_idx, self.raw_payload.len());
+        let (ns_id, _, ns_payload) = self.decoded_ns_payload(meta, ns_idx);
+        let version = meta.version();
 
-        let tx_table_len = TxTable::get_tx_table_len(&self.raw_payload[ns_range.clone()]);
+        let tx_table_len = TxTable::get_tx_table_len(&ns_payload, version);
         if tx_idx >= tx_table_len {
             return None; // error: index out of bounds
         }
-        let ns_payload = &self.raw_payload[ns_range.clone()];
-
-        let tx_within_ns = TxTable::get_payload_range(ns_payload, tx_idx, tx_table_len);
-        let (start, end) = (tx_within_ns.start, tx_within_ns.end);
-        let ns_start = ns_range.start;
-        let tx_payload_range = start.saturating_add(ns_start)..end.saturating_add(ns_start);
 
-        let tx_payload = self.raw_payload.get(tx_payload_range)?.to_vec();
+        let tx_within_ns = TxTable::get_payload_range(&ns_payload, tx_idx, tx_table_len, version);
+        let tx_payload = ns_payload.get(tx_within_ns)?.to_vec();
 
         Some(Transaction::new(ns_id, tx_payload))
     }
@@ -67,10 +62,15 @@ impl QueryablePayload for Payload<TxTableEntryWord> {
         if ns_idx >= meta.len() {
             return None; // error: index out of bounds
         }
+        let format = meta.get_payload_format(ns_idx);
+        if !format.is_raw() || meta.version() != PayloadVersion::V0 {
+            return self.namespace_transaction_with_proof(meta, index, format);
+        }
         let (ns_id, ns_range) = meta.get_payload_range(ns_idx, self.raw_payload.len());
         let ns_start_offset = ns_range.start;
 
-        let tx_table_len = TxTable::get_tx_table_len(&self.raw_payload[ns_range.clone()]);
+        let tx_table_len =
+            TxTable::get_tx_table_len(&self.raw_payload[ns_range.clone()], PayloadVersion::V0);
         if tx_idx >= tx_table_len {
             return None; // error: index out of bounds
         }
@@ -151,7 +151,7 @@ impl QueryablePayload for Payload<TxTableEntryWord> {
                 ns_id,
                 self.raw_payload.get(tx_payload_range.clone())?.to_vec(),
             ),
-            TxInclusionProof {
+            TxInclusionProof::Raw(RawTxInclusionProof {
                 ns_range: ns_range.clone(),
                 tx_table_len: TxTableEntry::from_usize(tx_table_len),
                 tx_table_len_proof: vid
@@ -165,13 +165,137 @@ impl QueryablePayload for Payload<TxTableEntryWord> {
                 } else {
                     vid.payload_proof(&self.raw_payload, tx_payload_range).ok()
                 },
-            },
+            }),
+        ))
+    }
+}
+
+impl Payload {
+    /// Proof of inclusion for a transaction covering its entire namespace payload.
+    ///
+    /// Used for compressed namespaces, in which individual transactions cannot be located, and for
+    /// layouts other than [`PayloadVersion::V0`].
+    ///
+    /// TODO support tx table range proofs for `PayloadVersion::V1`.
+    fn namespace_transaction_with_proof(
+        &self,
+        meta: &NameSpaceTable,
+        index: &TxIndex,
+        format: NsPayloadFormat,
+    ) -> Option<(Transaction, TxInclusionProof)> {
+        let (_, ns_range) = meta.get_payload_range(index.ns_idx, self.raw_payload.len());
+        let tx = self.transaction(meta, index)?;
+
+        // TODO temporary VID construction, as above.
+        let vid = vid_scheme(10);
+        let ns_proof = vid
+            .payload_proof(&self.raw_payload, ns_range.clone())
+            .ok()?;
+        Some((
+            tx,
+            TxInclusionProof::Namespace(NsTxInclusionProof {
+                ns_payload: self.raw_payload.get(ns_range.clone())?.to_vec(),
+                ns_range,
+                version: meta.version(),
+                format,
+                ns_proof,
+            }),
         ))
     }
 }
 
+/// Proof that a transaction is included in a block.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum TxInclusionProof {
+    /// Proof for a transaction in a namespace stored in the raw V0 layout.
+    Raw(RawTxInclusionProof),
+    /// Proof covering the entire namespace containing the transaction.
+    Namespace(NsTxInclusionProof),
+}
+
+impl TxInclusionProof {
+    // TODO currently broken, fix in https://github.com/EspressoSystems/espresso-sequencer/issues/1010
+    #[allow(dead_code)] // TODO temporary
+    pub fn verify<V>(
+        &self,
+        tx: &Transaction,
+        tx_index: TxIndex,
+        vid: &V,
+        vid_commit: &V::Commit,
+        vid_common: &V::Common,
+    ) -> Option<Result<(), ()>>
+    where
+        V: PayloadProver<SmallRangeProofType> + PayloadProver<LargeRangeProofType>,
+    {
+        match self {
+            Self::Raw(proof) => proof.verify(tx, tx_index, vid, vid_commit, vid_common),
+            Self::Namespace(proof) => proof.verify(tx, tx_index, vid, vid_commit, vid_common),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct NsTxInclusionProof {
+    ns_range: Range<usize>,
+    version: PayloadVersion,
+    format: NsPayloadFormat,
+    #[serde(with = "base64_bytes")]
+    ns_payload: Vec<u8>,
+    ns_proof: LargeRangeProofType,
+}
+
+impl NsTxInclusionProof {
+    /// Verify the namespace payload against the VID commitment, then decode it and check that `tx`
+    /// is the transaction at `tx_index`.
+    ///
+    /// The version and format of the namespace must be checked by the caller against the namespace
+    /// table.
+    pub fn verify<V>(
+        &self,
+        tx: &Transaction,
+        tx_index: TxIndex,
+        vid: &V,
+        vid_commit: &V::Commit,
+        vid_common: &V::Common,
+    ) -> Option<Result<(), ()>>
+    where
+        V: PayloadProver<LargeRangeProofType>,
+    {
+        V::is_consistent(vid_commit, vid_common).ok()?;
+
+        if vid
+            .payload_verify(
+                Statement {
+                    payload_subslice: &self.ns_payload,
+                    range: self.ns_range.clone(),
+                    commit: vid_commit,
+                    common: vid_common,
+                },
+                &self.ns_proof,
+            )
+            .ok()?
+            .is_err()
+        {
+            return Some(Err(()));
+        }
+
+        let ns_payload = self.format.decode(&self.ns_payload)?;
+        let tx_table_len = TxTable::get_tx_table_len(&ns_payload, self.version);
+        if tx_index.tx_idx >= tx_table_len {
+            return None; // error: index out of bounds
+        }
+        let tx_range =
+            TxTable::get_payload_range(&ns_payload, tx_index.tx_idx, tx_table_len, self.version);
+        if ns_payload.get(tx_range)? != tx.payload() {
+            return Some(Err(()));
+        }
+
+        Some(Ok(()))
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
-pub struct TxInclusionProof {
+pub struct RawTxInclusionProof {
     ns_range: Range<usize>,
     tx_table_len: TxTableEntry,
     tx_table_len_proof: SmallRangeProofType,
@@ -183,7 +307,7 @@ pub struct TxInclusionProof {
     tx_payload_proof: Option<SmallRangeProofType>, // `None` if the tx has zero length
 }
 
-impl TxInclusionProof {
+impl RawTxInclusionProof {
     // TODO currently broken, fix in https://github.com/EspressoSystems/espresso-sequencer/issues/1010
     //
     // - We need to decide where to store VID params.
@@ -322,7 +446,7 @@ pub(crate) fn gen_tx_proof_for_testing(
     tx_table_len_proof: SmallRangeProofType,
     payload_proof: SmallRangeProofType,
 ) -> TxInclusionProof {
-    TxInclusionProof {
+    TxInclusionProof::Raw(RawTxInclusionProof {
         ns_range,
         tx_table_len,
         tx_table_len_proof,
@@ -330,5 +454,5 @@ pub(crate) fn gen_tx_proof_for_testing(
         tx_table_range_end: TxTableEntry::from_usize(1),
         tx_table_range_proof: payload_proof,
         tx_payload_proof: None,
-    }
+    })
 }
@@ -1,69 +1,164 @@
-use crate::block::entry::TxTableEntry;
-use crate::block::payload::TableWordTraits;
+use crate::block::compression::NsPayloadFormat;
+use crate::block::entry::{NsTableEntry, TxTableEntry};
+use crate::block::ordering::TxOrdering;
 use crate::{BlockBuildingSnafu, Error, NamespaceId};
-use derivative::Derivative;
 use serde::{Deserialize, Serialize};
 use snafu::OptionExt;
-use std::marker::PhantomData;
-use std::mem::size_of;
 use std::ops::Range;
 
-pub trait Table<TableWord: TableWordTraits> {
-    // Read TxTableEntry::byte_len() bytes from `table_bytes` starting at `offset`.
-    // if `table_bytes` has too few bytes at this `offset` then pad with zero.
-    // Parse these bytes into a `TxTableEntry` and return.
+pub trait Table {
+    type Entry;
+
+    // Read Self::byte_len() bytes from the table starting at `offset`.
+    // if the table has too few bytes at this `offset` then pad with zero.
+    // Parse these bytes into an entry and return.
     // Returns raw bytes, no checking for large values
-    fn get_table_len(&self, offset: usize) -> TxTableEntry;
+    fn get_table_len(&self, offset: usize) -> Self::Entry;
 
     fn byte_len() -> usize {
-        size_of::<TableWord>()
+        TxTableEntry::byte_len()
     }
 }
 
-impl<TableWord: TableWordTraits> Table<TableWord> for NameSpaceTable<TableWord> {
-    // TODO (Philippe) avoid code duplication with similar function in TxTable?
-    fn get_table_len(&self, offset: usize) -> TxTableEntry {
-        let end = std::cmp::min(
-            offset.saturating_add(TxTableEntry::byte_len()),
-            self.bytes.len(),
-        );
-        let start = std::cmp::min(offset, end);
-        let tx_table_len_range = start..end;
-        let mut entry_bytes = [0u8; TxTableEntry::byte_len()];
-        entry_bytes[..tx_table_len_range.len()].copy_from_slice(&self.bytes[tx_table_len_range]);
-        TxTableEntry::from_bytes_array(entry_bytes)
+/// Version of the block payload encoding.
+///
+/// The version is recorded in the namespace table, which is part of the block header, so every
+/// header determines how its payload is decoded. The versions a header may use are set by its
+/// [`HeaderVersion`](crate::HeaderVersion); see
+/// [`allows_payload_version`](crate::HeaderVersion::allows_payload_version).
+///
+/// * [`V0`](Self::V0) is the original layout. The namespace table and every tx table end with an
+///   explicit final offset.
+/// * [`V1`](Self::V1) makes the final offset of every tx table implicit: the last transaction ends
+///   at the end of its namespace payload. The namespace table keeps the end offset of its last
+///   namespace, since validators only see the header and must be able to check the size of every
+///   namespace.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PayloadVersion {
+    #[default]
+    V0,
+    V1,
+    /// A version not recognized by this version of the software.
+    ///
+    /// A namespace table with an unsupported version contains no namespaces.
+    Unsupported(u32),
+}
+
+impl PayloadVersion {
+    fn from_word(word: NsTableEntry) -> Self {
+        match word.as_word() {
+            1 => Self::V1,
+            other => Self::Unsupported(other),
+        }
+    }
+
+    fn to_word(self) -> Option<NsTableEntry> {
+        match self {
+            Self::V0 => None,
+            Self::V1 => Some(1.into()),
+            Self::Unsupported(word) => Some(word.into()),
+        }
+    }
+}
+
+impl Table for NameSpaceTable {
+    type Entry = NsTableEntry;
+
+    fn get_table_len(&self, offset: usize) -> NsTableEntry {
+        NsTableEntry::from_bytes_padded(self.bytes.get(offset..).unwrap_or_default())
     }
 }
 
-#[derive(Clone, Debug, Derivative, Deserialize, Eq, Serialize, Default)]
-#[derivative(Hash, PartialEq)]
-pub struct NameSpaceTable<TableWord: TableWordTraits> {
+/// The namespace table of a block.
+///
+/// V0 table, defined as follows for j>0:
+/// word[0]:    [number n of entries in namespace table]
+/// word[2j-1]: [id for the jth namespace]
+/// word[2j]:   [end byte index of the jth namespace in the payload]
+///
+/// V1 table:
+/// word[0]:     [`NsTableEntry::VERSION_TAG`]
+/// word[1]:     [payload version]
+/// word[2]:     [`TxOrdering` used to build the payload]
+/// word[3]:     [number n of entries in namespace table]
+/// word[3+j]:   [id for the jth namespace]
+/// word[3+n+j]: [end byte index of the jth namespace in the payload]
+///
+/// In both versions the table may be followed by one [`NsPayloadFormat`] byte per namespace.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, Default)]
+pub struct NameSpaceTable {
     #[serde(with = "base64_bytes")]
     pub(super) bytes: Vec<u8>,
-    #[serde(skip)]
-    pub(super) phantom: PhantomData<TableWord>,
 }
 
-impl<TableWord: TableWordTraits> NameSpaceTable<TableWord> {
+impl NameSpaceTable {
     pub fn from_bytes(bytes: impl Into<Vec<u8>>) -> Self {
         Self {
             bytes: bytes.into(),
-            phantom: Default::default(),
         }
     }
 
     pub fn from_namespace_offsets(
         namespace_offsets: Vec<(NamespaceId, usize)>,
     ) -> Result<Self, Error> {
-        let mut ns_table = NameSpaceTable::from_bytes(
-            TxTableEntry::try_from(namespace_offsets.len())
+        Self::from_namespace_entries(
+            namespace_offsets
+                .into_iter()
+                .map(|(id, offset)| (id, offset, NsPayloadFormat::Raw))
+                .collect(),
+            PayloadVersion::V0,
+            TxOrdering::Unspecified,
+        )
+    }
+
+    /// Build a namespace table from the ID, end offset, and payload format of each namespace.
+    ///
+    /// The payload formats are appended to the table only if some namespace uses a format other
+    /// than [`NsPayloadFormat::Raw`], so that tables for uncompressed blocks keep the original
+    /// layout. [`PayloadVersion::V1`] tables also record `ordering`; V0 tables cannot record the
+    /// ordering.
+    pub fn from_namespace_entries(
+        namespace_entries: Vec<(NamespaceId, usize, NsPayloadFormat)>,
+        version: PayloadVersion,
+        ordering: TxOrdering,
+    ) -> Result<Self, Error> {
+        let mut ns_table = NameSpaceTable::default();
+        if let Some(version_word) = version.to_word() {
+            ns_table.push_entry(NsTableEntry::VERSION_TAG);
+            ns_table.push_entry(version_word);
+            ns_table.push_entry(ordering.to_word().into());
+        }
+        ns_table.push_entry(
+            NsTableEntry::try_from(namespace_entries.len())
                 .ok()
-                .context(BlockBuildingSnafu)?
-                .to_bytes(),
+                .context(BlockBuildingSnafu)?,
         );
-        for (id, offset) in namespace_offsets {
-            ns_table.add_new_entry_ns_id(id)?;
-            ns_table.add_new_entry_payload_len(offset)?;
+        match version {
+            PayloadVersion::V0 => {
+                for (id, offset, _) in &namespace_entries {
+                    ns_table.add_new_entry_ns_id(*id)?;
+                    ns_table.add_new_entry_payload_len(*offset)?;
+                }
+            }
+            PayloadVersion::V1 => {
+                for (id, _, _) in &namespace_entries {
+                    ns_table.add_new_entry_ns_id(*id)?;
+                }
+                for (_, offset, _) in &namespace_entries {
+                    ns_table.add_new_entry_payload_len(*offset)?;
+                }
+            }
+            PayloadVersion::Unsupported(_) => return BlockBuildingSnafu.fail(),
+        }
+        if namespace_entries
+            .iter()
+            .any(|(_, _, format)| !format.is_raw())
+        {
+            ns_table.bytes.extend(
+                namespace_entries
+                    .iter()
+                    .map(|(_, _, format)| format.to_byte()),
+            );
         }
         Ok(ns_table)
     }
@@ -72,6 +167,38 @@ impl<TableWord: TableWordTraits> NameSpaceTable<TableWord> {
         &self.bytes
     }
 
+    /// The version of the payload layout described by this table.
+    pub fn version(&self) -> PayloadVersion {
+        if self.get_word(0) == NsTableEntry::VERSION_TAG
+            && self.bytes.len() >= 2 * NsTableEntry::byte_len()
+        {
+            PayloadVersion::from_word(self.get_word(1))
+        } else {
+            PayloadVersion::V0
+        }
+    }
+
+    /// The ordering policy used to build the payload described by this table.
+    pub fn ordering(&self) -> TxOrdering {
+        match self.version() {
+            PayloadVersion::V1 => TxOrdering::from_word(self.get_word(2).as_word()),
+            _ => TxOrdering::Unspecified,
+        }
+    }
+
+    /// Check that the order of the namespaces in this table is consistent with its recorded
+    /// [`ordering`](Self::ordering).
+    ///
+    /// The order of transactions within a namespace cannot be checked from the table alone: for
+    /// [`TxOrdering::PriorityFee`] this requires the fee of each transaction.
+    pub fn is_consistent_with_ordering(&self) -> bool {
+        if !self.ordering().sorts_namespaces() {
+            return true;
+        }
+        (1..self.len())
+            .all(|ns_index| self.get_table_entry(ns_index - 1).0 < self.get_table_entry(ns_index).0)
+    }
+
     /// Find `ns_id` and return its index into this namespace table.
     ///
     /// TODO return Result or Option? Want to avoid catch-all Error type :(
@@ -79,33 +206,54 @@ impl<TableWord: TableWordTraits> NameSpaceTable<TableWord> {
         (0..self.len()).find(|&ns_index| ns_id == self.get_table_entry(ns_index).0)
     }
 
+    fn push_entry(&mut self, entry: NsTableEntry) {
+        self.bytes.extend(entry.to_bytes());
+    }
+
     fn add_new_entry_ns_id(&mut self, id: NamespaceId) -> Result<(), Error> {
-        self.bytes.extend(
-            TxTableEntry::try_from(id)
+        self.push_entry(
+            NsTableEntry::try_from(id)
                 .ok()
-                .context(BlockBuildingSnafu)?
-                .to_bytes(),
+                .context(BlockBuildingSnafu)?,
         );
         Ok(())
     }
 
     fn add_new_entry_payload_len(&mut self, l: usize) -> Result<(), Error> {
-        self.bytes.extend(
-            TxTableEntry::try_from(l)
-                .ok()
-                .context(BlockBuildingSnafu)?
-                .to_bytes(),
-        );
+        self.push_entry(NsTableEntry::try_from(l).ok().context(BlockBuildingSnafu)?);
         Ok(())
     }
 
+    // Parse the `index`th word of the table, padding with zero if the table is too short.
+    fn get_word(&self, index: usize) -> NsTableEntry {
+        self.get_table_len(index.saturating_mul(NsTableEntry::byte_len()))
+    }
+
+    // Number of words preceding the namespace IDs.
+    fn header_words(version: PayloadVersion) -> usize {
+        match version {
+            PayloadVersion::V0 => 1,
+            _ => 4,
+        }
+    }
+
+    // Number of words in a table with `len` namespaces, not counting payload format bytes.
+    fn words_len(version: PayloadVersion, len: usize) -> usize {
+        Self::header_words(version).saturating_add(len.saturating_mul(2))
+    }
+
     // Parse the table length from the beginning of the namespace table.
     // Returned value is guaranteed to be no larger than the number of ns table entries that could possibly fit into `ns_table_bytes`.
     pub fn len(&self) -> usize {
-        let left = self.get_table_len(0).try_into().unwrap_or(0);
-        let right = self.bytes.len().saturating_sub(TxTableEntry::byte_len())
-            / (2 * TxTableEntry::byte_len());
-        std::cmp::min(left, right)
+        let version = self.version();
+        if let PayloadVersion::Unsupported(_) = version {
+            return 0;
+        }
+        let header_words = Self::header_words(version);
+        let left = self.get_word(header_words - 1).try_into().unwrap_or(0);
+        let available_words =
+            (self.bytes.len() / NsTableEntry::byte_len()).saturating_sub(header_words);
+        std::cmp::min(left, available_words / 2)
     }
 
     pub fn is_empty(&self) -> bool {
@@ -115,53 +263,50 @@ impl<TableWord: TableWordTraits> NameSpaceTable<TableWord> {
     // returns (ns_id, ns_offset)
     // ns_offset is not checked, could be anything
     pub fn get_table_entry(&self, ns_index: usize) -> (NamespaceId, usize) {
-        // get the range for ns_id bytes in ns table
-        // ensure `range` is within range for ns_table_bytes
-        let start = std::cmp::min(
-            ns_index
-                .saturating_mul(2)
-                .saturating_add(1)
-                .saturating_mul(TxTableEntry::byte_len()),
-            self.bytes.len(),
-        );
-        let end = std::cmp::min(
-            start.saturating_add(TxTableEntry::byte_len()),
-            self.bytes.len(),
-        );
-        let ns_id_range = start..end;
+        let version = self.version();
+        let (ns_id_word, ns_offset_word) = match version {
+            PayloadVersion::V0 => {
+                let ns_id_word = ns_index.saturating_mul(2).saturating_add(1);
+                (ns_id_word, ns_id_word.saturating_add(1))
+            }
+            _ => {
+                let ns_id_word = Self::header_words(version).saturating_add(ns_index);
+                (ns_id_word, ns_id_word.saturating_add(self.len()))
+            }
+        };
 
         // parse ns_id bytes from ns table
         // any failure -> NamespaceId::default()
-        let mut ns_id_bytes = [0u8; TxTableEntry::byte_len()];
-        ns_id_bytes[..ns_id_range.len()].copy_from_slice(&self.bytes[ns_id_range]);
-        let ns_id = NamespaceId::try_from(
-            TxTableEntry::from_bytes(&ns_id_bytes).unwrap_or(TxTableEntry::zero()),
-        )
-        .unwrap_or_default();
-
-        // get the range for ns_offset bytes in ns table
-        // ensure `range` is within range for ns_table_bytes
-        // TODO refactor range checking code
-        let start = end;
-        let end = std::cmp::min(
-            start.saturating_add(TxTableEntry::byte_len()),
-            self.bytes.len(),
-        );
-        let ns_offset_range = start..end;
+        let ns_id = NamespaceId::from(self.get_word(ns_id_word));
 
         // parse ns_offset bytes from ns table
         // any failure -> 0 offset (?)
-        // TODO refactor parsing code?
-        let mut ns_offset_bytes = [0u8; TxTableEntry::byte_len()];
-        ns_offset_bytes[..ns_offset_range.len()].copy_from_slice(&self.bytes[ns_offset_range]);
-        let ns_offset = usize::try_from(
-            TxTableEntry::from_bytes(&ns_offset_bytes).unwrap_or(TxTableEntry::zero()),
-        )
-        .unwrap_or(0);
+        let ns_offset = usize::try_from(self.get_word(ns_offset_word)).unwrap_or(0);
 
         (ns_id, ns_offset)
     }
 
+    /// The number of bytes used by the `ns_index`th namespace, according to the table alone.
+    pub fn namespace_byte_len(&self, ns_index: usize) -> usize {
+        let (_, end) = self.get_table_entry(ns_index);
+        self.get_payload_range(ns_index, end).1.len()
+    }
+
+    /// The format in which the payload of the `ns_index`th namespace is encoded.
+    ///
+    /// The namespace table may be followed by one format byte per namespace. The formats are
+    /// present only if the table has exactly the right length to hold them; otherwise (including for
+    /// all tables which predate compression) every namespace uses [`NsPayloadFormat::Raw`].
+    pub fn get_payload_format(&self, ns_index: usize) -> NsPayloadFormat {
+        let len = self.len();
+        let formats_start =
+            Self::words_len(self.version(), len).saturating_mul(NsTableEntry::byte_len());
+        if ns_index >= len || self.bytes.len() != formats_start.saturating_add(len) {
+            return NsPayloadFormat::Raw;
+        }
+        NsPayloadFormat::from_byte(self.bytes[formats_start + ns_index])
+    }
+
     /// Like `tx_payload_range` except for namespaces.
     /// Returns the ns id and the ns byte range in the block payload bytes.
     ///
@@ -201,11 +346,16 @@ impl TxTable {
     //
     // Returned value is guaranteed to be no larger than the number of tx table entries that could possibly fit into `ns_bytes`.
     // TODO tidy this is a sloppy wrapper for get_len
-    pub(crate) fn get_tx_table_len(ns_bytes: &[u8]) -> usize {
-        std::cmp::min(
-            Self::get_len(ns_bytes, 0).try_into().unwrap_or(0),
-            (ns_bytes.len().saturating_sub(TxTableEntry::byte_len())) / TxTableEntry::byte_len(),
-        )
+    pub(crate) fn get_tx_table_len(ns_bytes: &[u8], version: PayloadVersion) -> usize {
+        let words = ns_bytes.len() / TxTableEntry::byte_len();
+        let max_len = match version {
+            // word[0] is the length, followed by one word per tx
+            PayloadVersion::V0 => words.saturating_sub(1),
+            // word[0] is the length, followed by one word per tx except the last
+            PayloadVersion::V1 => words,
+            PayloadVersion::Unsupported(_) => 0,
+        };
+        std::cmp::min(Self::get_len(ns_bytes, 0).try_into().unwrap_or(0), max_len)
     }
 
     // returns tx_offset
@@ -234,19 +384,38 @@ impl TxTable {
             .unwrap_or(0)
     }
 
+    /// Byte length of a tx table with `tx_len` entries, which is also the offset of the first tx
+    /// payload in the namespace.
+    pub(crate) fn get_tx_table_byte_len(tx_len: usize, version: PayloadVersion) -> usize {
+        let words = match version {
+            PayloadVersion::V0 => tx_len.saturating_add(1),
+            _ => std::cmp::max(tx_len, 1),
+        };
+        words.saturating_mul(TxTableEntry::byte_len())
+    }
+
     /// Ensures that the returned range is valid: `start <= end <= ns_bytes`.
-    pub fn get_payload_range(ns_bytes: &[u8], tx_idx: usize, tx_len: usize) -> Range<usize> {
-        let tx_payloads_offset = tx_len
-            .saturating_add(1)
-            .saturating_mul(TxTableEntry::byte_len());
+    pub fn get_payload_range(
+        ns_bytes: &[u8],
+        tx_idx: usize,
+        tx_len: usize,
+        version: PayloadVersion,
+    ) -> Range<usize> {
+        let tx_payloads_offset =
+            std::cmp::min(Self::get_tx_table_byte_len(tx_len, version), ns_bytes.len());
 
-        let end = std::cmp::min(
-            TxTable::get_table_entry(ns_bytes, tx_idx).saturating_add(tx_payloads_offset),
-            ns_bytes.len(),
-        );
+        // In V1 the last tx implicitly ends at the end of the namespace.
+        let end = if version != PayloadVersion::V0 && tx_idx.saturating_add(1) == tx_len {
+            ns_bytes.len()
+        } else {
+            std::cmp::min(
+                TxTable::get_table_entry(ns_bytes, tx_idx).saturating_add(tx_payloads_offset),
+                ns_bytes.len(),
+            )
+        };
 
         let start = if tx_idx == 0 {
-            tx_payloads_offset
+            std::cmp::min(tx_payloads_offset, end)
         } else {
             std::cmp::min(
                 TxTable::get_table_entry(ns_bytes, tx_idx - 1).saturating_add(tx_payloads_offset),
@@ -260,21 +429,20 @@ impl TxTable {
 #[cfg(test)]
 pub(super) mod test {
     use crate::block::entry::TxTableEntry;
-    use crate::block::payload::TableWordTraits;
     use crate::block::tables::{Table, TxTable};
-    use std::marker::PhantomData;
 
-    pub struct TxTableTest<TableWord: TableWordTraits> {
+    pub struct TxTableTest {
         raw_payload: Vec<u8>,
-        phantom: PhantomData<TableWord>,
     }
 
-    impl<TableWord: TableWordTraits> Table<TableWord> for TxTableTest<TableWord> {
+    impl Table for TxTableTest {
+        type Entry = TxTableEntry;
+
         fn get_table_len(&self, offset: usize) -> TxTableEntry {
             TxTable::get_len(&self.raw_payload, offset)
         }
     }
-    impl<TableWord: TableWordTraits> TxTableTest<TableWord> {
+    impl TxTableTest {
         #[cfg(test)]
         pub fn from_entries(entries: &[usize]) -> Self {
             let tx_table_byte_len = entries.len() + 1;
@@ -286,7 +454,6 @@ pub(super) mod test {
 
             Self {
                 raw_payload: tx_table,
-                phantom: Default::default(),
             }
         }
 
@@ -1,6 +1,6 @@
 use std::ops::Range;
 
-use crate::block::payload::{Payload, TableWordTraits};
+use crate::block::payload::Payload;
 use crate::block::tables::{NameSpaceTable, TxTable};
 use serde::{Deserialize, Serialize};
 
@@ -15,19 +15,16 @@ pub struct TxIndex {
 /// TODO Decompose this iterator into
 /// - a tx iterator `T` over only 1 namespace
 /// - a namespace-tx iterator that reuses `T` over all namespaces
-pub struct TxIterator<'a, TableWord: TableWordTraits> {
+pub struct TxIterator<'a> {
     ns_idx: usize, // simpler than using `Peekable`
     ns_iter: Range<usize>,
     tx_iter: Range<usize>,
-    block_payload: &'a Payload<TableWord>,
-    ns_table: &'a NameSpaceTable<TableWord>,
+    block_payload: &'a Payload,
+    ns_table: &'a NameSpaceTable,
 }
 
-impl<'a, TableWord: TableWordTraits> TxIterator<'a, TableWord> {
-    pub(super) fn new(
-        ns_table: &'a NameSpaceTable<TableWord>,
-        block_payload: &'a Payload<TableWord>,
-    ) -> Self {
+impl<'a> TxIterator<'a> {
+    pub(super) fn new(ns_table: &'a NameSpaceTable, block_payload: &'a Payload) -> Self {
         Self {
             ns_idx: 0, // arbitrary value, changed in first call to next()
             ns_iter: 0..ns_table.len(),
@@ -38,7 +35,7 @@ impl<'a, TableWord: TableWordTraits> TxIterator<'a, TableWord> {
     }
 }
 
-impl<'a, TableWord: TableWordTraits> Iterator for TxIterator<'a, TableWord> {
+impl<'a> Iterator for TxIterator<'a> {
     type Item = TxIndex;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -50,11 +47,13 @@ impl<'a, TableWord: TableWordTraits> Iterator for TxIterator<'a, TableWord> {
             })
         } else {
             // move to the next name space
-            let payload = &self.block_payload.raw_payload;
             for ns_idx in self.ns_iter.by_ref() {
                 self.ns_idx = ns_idx;
-                let ns_range = self.ns_table.get_payload_range(ns_idx, payload.len()).1;
-                let tx_table_len = TxTable::get_tx_table_len(&payload[ns_range]);
+                let ns_payload = self
+                    .block_payload
+                    .decoded_ns_payload(self.ns_table, ns_idx)
+                    .2;
+                let tx_table_len = TxTable::get_tx_table_len(&ns_payload, self.ns_table.version());
                 self.tx_iter = 0..tx_table_len;
                 if let Some(tx_idx) = self.tx_iter.next() {
                     return Some(TxIndex { ns_idx, tx_idx });
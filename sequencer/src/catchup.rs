@@ -1,8 +1,13 @@
 use crate::{
     api::endpoints::{AccountQueryData, BlocksFrontier},
+    genesis::Genesis,
     state::{BlockMerkleTree, FeeAccount, FeeMerkleCommitment},
+    ChainConfig, Header,
 };
+use anyhow::{bail, ensure};
 use async_trait::async_trait;
+use commit::{Commitment, Committable};
+use ethers::types::U256;
 use hotshot_types::{data::ViewNumber, traits::node_implementation::ConsensusTime as _};
 use jf_primitives::merkle_tree::ForgetableMerkleTreeScheme;
 use serde::de::DeserializeOwned;
@@ -43,6 +48,9 @@ pub trait StateCatchup: Send + Sync + std::fmt::Debug {
     ) -> Vec<AccountQueryData>;
 
     async fn remember_blocks_merkle_tree(&self, view: ViewNumber, mt: &mut BlockMerkleTree);
+
+    /// Fetch the full chain config with the given commitment.
+    async fn fetch_chain_config(&self, commitment: Commitment<ChainConfig>) -> ChainConfig;
 }
 
 #[derive(Debug, Clone, Default)]
@@ -63,6 +71,92 @@ impl<Ver: StaticVersionType> StatePeers<Ver> {
         }
     }
 
+    /// Check that our genesis matches the genesis of our peers.
+    ///
+    /// Fails if any peer reports a different genesis. Peers which are unreachable or which do not
+    /// know their genesis are skipped with a warning, but the check is retried until at least one
+    /// peer has confirmed our genesis, so that a node never joins a chain it has not checked.
+    pub async fn check_genesis(&self, genesis: Commitment<Genesis>) -> anyhow::Result<()> {
+        ensure!(!self.clients.is_empty(), "no peers to check genesis with");
+        loop {
+            let mut confirmed = false;
+            for client in &self.clients {
+                tracing::info!("Checking genesis with {}", client.url);
+                match client
+                    .get::<Commitment<Genesis>>("catchup/genesis")
+                    .send()
+                    .await
+                {
+                    Ok(theirs) if theirs == genesis => confirmed = true,
+                    Ok(theirs) => bail!(
+                        "genesis mismatch with peer {}: ours is {genesis}, theirs is {theirs}",
+                        client.url
+                    ),
+                    Err(err) => {
+                        tracing::warn!("Could not check genesis with peer {}: {}", client.url, err);
+                    }
+                }
+            }
+            if confirmed {
+                return Ok(());
+            }
+            tracing::warn!("No peer has confirmed our genesis yet, retrying");
+            async_std::task::sleep(self.interval).await;
+        }
+    }
+
+    /// Fetch the latest decided header known to any peer.
+    ///
+    /// Asks every peer and returns the highest header. Retries until at least one peer responds.
+    /// Fails if there are no peers to ask.
+    pub async fn fetch_latest_header(&self) -> anyhow::Result<Header> {
+        ensure!(
+            !self.clients.is_empty(),
+            "no peers to fetch the latest header from"
+        );
+        loop {
+            let mut latest: Option<Header> = None;
+            for client in &self.clients {
+                tracing::info!("Fetching latest header from {}", client.url);
+                match client.get::<Header>("catchup/header").send().await {
+                    Ok(header)
+                        if latest
+                            .as_ref()
+                            .is_some_and(|latest| latest.height >= header.height) => {}
+                    Ok(header) => latest = Some(header),
+                    Err(err) => {
+                        tracing::warn!("Error fetching latest header from peer: {}", err);
+                    }
+                }
+            }
+            if let Some(header) = latest {
+                return Ok(header);
+            }
+            tracing::warn!("Could not fetch latest header from any peer, retrying");
+            async_std::task::sleep(self.interval).await;
+        }
+    }
+
+    /// Fetch the balance of `account` in the latest state of the first peer to respond.
+    ///
+    /// The balance is not verified against any state commitment, so it should be used only for
+    /// informational purposes such as monitoring.
+    pub async fn fetch_latest_balance(&self, account: FeeAccount) -> anyhow::Result<U256> {
+        for client in &self.clients {
+            match client
+                .get::<AccountQueryData>(&format!("catchup/account/{account}"))
+                .send()
+                .await
+            {
+                Ok(res) => return Ok(res.balance),
+                Err(err) => {
+                    tracing::warn!("Error fetching account from {}: {}", client.url, err);
+                }
+            }
+        }
+        bail!("could not fetch account {account} from any peer")
+    }
+
     async fn fetch_account(
         &self,
         view: ViewNumber,
@@ -151,6 +245,33 @@ impl<Ver: StaticVersionType> StateCatchup for StatePeers<Ver> {
             async_std::task::sleep(self.interval).await;
         }
     }
+
+    async fn fetch_chain_config(&self, commitment: Commitment<ChainConfig>) -> ChainConfig {
+        if self.clients.is_empty() {
+            panic!("No peers to fetch chain config from");
+        }
+        loop {
+            for client in self.clients.iter() {
+                tracing::info!("Fetching chain config {commitment} from {}", client.url);
+                match client
+                    .get::<ChainConfig>(&format!("catchup/chain-config/{commitment}"))
+                    .send()
+                    .await
+                {
+                    Ok(chain_config) if chain_config.commit() == commitment => return chain_config,
+                    Ok(chain_config) => tracing::warn!(
+                        "Peer returned chain config {} for {commitment}",
+                        chain_config.commit()
+                    ),
+                    Err(err) => {
+                        tracing::warn!("Error fetching chain config from peer: {}", err);
+                    }
+                }
+            }
+            tracing::warn!("Could not fetch chain config from any peer, retrying");
+            async_std::task::sleep(self.interval).await;
+        }
+    }
 }
 
 #[async_trait]
@@ -169,6 +290,10 @@ impl<T: StateCatchup + ?Sized> StateCatchup for Box<T> {
     async fn remember_blocks_merkle_tree(&self, view: ViewNumber, mt: &mut BlockMerkleTree) {
         (**self).remember_blocks_merkle_tree(view, mt).await
     }
+
+    async fn fetch_chain_config(&self, commitment: Commitment<ChainConfig>) -> ChainConfig {
+        (**self).fetch_chain_config(commitment).await
+    }
 }
 
 #[async_trait]
@@ -187,6 +312,10 @@ impl<T: StateCatchup + ?Sized> StateCatchup for Arc<T> {
     async fn remember_blocks_merkle_tree(&self, view: ViewNumber, mt: &mut BlockMerkleTree) {
         (**self).remember_blocks_merkle_tree(view, mt).await
     }
+
+    async fn fetch_chain_config(&self, commitment: Commitment<ChainConfig>) -> ChainConfig {
+        (**self).fetch_chain_config(commitment).await
+    }
 }
 
 #[cfg(any(test, feature = "testing"))]
@@ -199,12 +328,28 @@ pub mod mock {
     #[derive(Debug, Clone, Default)]
     pub struct MockStateCatchup {
         state: HashMap<ViewNumber, Arc<ValidatedState>>,
+        chain_configs: HashMap<Commitment<ChainConfig>, ChainConfig>,
+    }
+
+    impl MockStateCatchup {
+        pub fn with_chain_configs(
+            mut self,
+            chain_configs: impl IntoIterator<Item = ChainConfig>,
+        ) -> Self {
+            self.chain_configs.extend(
+                chain_configs
+                    .into_iter()
+                    .map(|chain_config| (chain_config.commit(), chain_config)),
+            );
+            self
+        }
     }
 
     impl FromIterator<(ViewNumber, Arc<ValidatedState>)> for MockStateCatchup {
         fn from_iter<I: IntoIterator<Item = (ViewNumber, Arc<ValidatedState>)>>(iter: I) -> Self {
             Self {
                 state: iter.into_iter().collect(),
+                chain_configs: Default::default(),
             }
         }
     }
@@ -247,5 +392,13 @@ pub mod mock {
             mt.remember(index, elem, proof.clone())
                 .expect("Proof verifies");
         }
+
+        async fn fetch_chain_config(&self, commitment: Commitment<ChainConfig>) -> ChainConfig {
+            tracing::info!("catchup: fetching chain config {commitment}");
+            *self
+                .chain_configs
+                .get(&commitment)
+                .unwrap_or_else(|| panic!("Chain config {commitment} not in memory"))
+        }
     }
 }
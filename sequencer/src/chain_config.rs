@@ -1,10 +1,10 @@
-use crate::state::FeeAmount;
+use crate::{block::NsTable, state::FeeAmount};
 use commit::{Commitment, Committable};
 use derive_more::{From, Into};
 use ethers::types::U256;
 use itertools::Either;
 use sequencer_utils::impl_to_fixed_bytes;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Default, Hash, Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq, From, Into)]
 pub struct ChainId(U256);
@@ -18,7 +18,7 @@ impl From<u16> for ChainId {
 }
 
 /// Global variables for an Espresso blockchain.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ChainConfig {
     /// Espresso chain ID
     chain_id: ChainId,
@@ -26,6 +26,99 @@ pub struct ChainConfig {
     max_block_size: u64,
     /// Minimum fee in WEI per byte of payload
     base_fee: FeeAmount,
+    /// Maximum share of `max_block_size`, in basis points, that a single namespace may use.
+    ///
+    /// `None` means a namespace may fill the whole block. Only V2 and later headers can carry a
+    /// chain config with a limit; see [`ChainConfigV1`].
+    max_namespace_share_bps: Option<u16>,
+}
+
+/// A chain config as carried by V1 headers, which predate namespace limits.
+///
+/// This is the original layout of [`ChainConfig`]. V1 headers keep encoding their chain config in
+/// this layout, so that the binary encoding of existing headers does not change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub(crate) struct ChainConfigV1 {
+    chain_id: ChainId,
+    max_block_size: u64,
+    base_fee: FeeAmount,
+}
+
+impl From<ChainConfigV1> for ChainConfig {
+    fn from(config: ChainConfigV1) -> Self {
+        Self::new(config.chain_id, config.max_block_size, config.base_fee)
+    }
+}
+
+impl TryFrom<ChainConfig> for ChainConfigV1 {
+    type Error = String;
+
+    fn try_from(config: ChainConfig) -> Result<Self, Self::Error> {
+        if config.max_namespace_share_bps.is_some() {
+            return Err(format!(
+                "chain config {} limits namespaces and cannot be carried by a V1 header",
+                config.commit()
+            ));
+        }
+        Ok(Self {
+            chain_id: config.chain_id,
+            max_block_size: config.max_block_size,
+            base_fee: config.base_fee,
+        })
+    }
+}
+
+/// The human-readable serialization of a [`ChainConfig`].
+///
+/// The namespace limit is omitted when it is not set, so that unlimited configs serialize exactly
+/// as they did before the limit existed.
+#[derive(Deserialize, Serialize)]
+struct ChainConfigJson {
+    #[serde(flatten)]
+    config: ChainConfigV1,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_namespace_share_bps: Option<u16>,
+}
+
+/// The binary serialization of a [`ChainConfig`].
+///
+/// Binary formats are not self-describing, so the namespace limit is always present.
+#[derive(Deserialize, Serialize)]
+struct BinaryChainConfig(ChainConfigV1, Option<u16>);
+
+impl Serialize for ChainConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let config = ChainConfigV1 {
+            chain_id: self.chain_id,
+            max_block_size: self.max_block_size,
+            base_fee: self.base_fee,
+        };
+        if serializer.is_human_readable() {
+            ChainConfigJson {
+                config,
+                max_namespace_share_bps: self.max_namespace_share_bps,
+            }
+            .serialize(serializer)
+        } else {
+            BinaryChainConfig(config, self.max_namespace_share_bps).serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ChainConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (config, max_namespace_share_bps) = if deserializer.is_human_readable() {
+            let json = ChainConfigJson::deserialize(deserializer)?;
+            (json.config, json.max_namespace_share_bps)
+        } else {
+            let BinaryChainConfig(config, bps) = BinaryChainConfig::deserialize(deserializer)?;
+            (config, bps)
+        };
+        Ok(Self {
+            max_namespace_share_bps,
+            ..config.into()
+        })
+    }
 }
 
 impl Default for ChainConfig {
@@ -48,8 +141,43 @@ impl ChainConfig {
             chain_id: chain_id.into(),
             max_block_size,
             base_fee: base_fee.into(),
+            max_namespace_share_bps: None,
         }
     }
+
+    /// Limit the share of each block that a single namespace may use, in basis points of
+    /// `max_block_size`.
+    pub fn with_max_namespace_share_bps(mut self, bps: u16) -> Self {
+        self.max_namespace_share_bps = Some(bps);
+        self
+    }
+
+    pub fn max_block_size(&self) -> u64 {
+        self.max_block_size
+    }
+
+    /// The maximum number of bytes a single namespace may use in a block, if limited.
+    pub fn max_namespace_bytes(&self) -> Option<u64> {
+        self.max_namespace_share_bps
+            .map(|bps| (u128::from(self.max_block_size) * u128::from(bps) / 10_000) as u64)
+    }
+
+    /// The minimum fee for a namespace of `ns_bytes` bytes: the base fee for each byte it uses.
+    pub fn namespace_fee(&self, ns_bytes: u64) -> FeeAmount {
+        U256::from(self.base_fee)
+            .saturating_mul(ns_bytes.into())
+            .into()
+    }
+
+    /// The minimum fee for a block with namespace table `ns_table`.
+    ///
+    /// Each namespace pays for the space it uses; see [`namespace_fee`](Self::namespace_fee).
+    pub fn block_fee(&self, ns_table: &NsTable) -> FeeAmount {
+        (0..ns_table.len())
+            .map(|ns_index| self.namespace_fee(ns_table.namespace_byte_len(ns_index) as u64))
+            .fold(U256::zero(), |total, fee| total.saturating_add(fee.into()))
+            .into()
+    }
 }
 
 impl Committable for ChainConfig {
@@ -58,14 +186,28 @@ impl Committable for ChainConfig {
     }
 
     fn commit(&self) -> Commitment<Self> {
-        commit::RawCommitmentBuilder::new(&Self::tag())
+        let comm = commit::RawCommitmentBuilder::new(&Self::tag())
             .fixed_size_field("chain_id", &self.chain_id.to_fixed_bytes())
             .u64_field("max_block_size", self.max_block_size)
-            .fixed_size_field("base_fee", &self.base_fee.to_fixed_bytes())
-            .finalize()
+            .fixed_size_field("base_fee", &self.base_fee.to_fixed_bytes());
+        // Only commit to the namespace limit if it is set, so that the commitment of existing
+        // configs does not change.
+        let comm = match self.max_namespace_share_bps {
+            Some(bps) => comm.u64_field("max_namespace_share_bps", bps.into()),
+            None => comm,
+        };
+        comm.finalize()
     }
 }
 
+/// A chain config which replaces the previous one starting at a given block height.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ChainConfigUpgrade {
+    /// Height of the first block built with the new chain config.
+    pub height: u64,
+    pub chain_config: ChainConfig,
+}
+
 #[derive(Clone, Debug, Copy, PartialEq, Deserialize, Serialize, Eq, Hash)]
 pub struct ResolvableChainConfig {
     chain_config: Either<ChainConfig, Commitment<ChainConfig>>,
@@ -78,6 +220,24 @@ impl ResolvableChainConfig {
             Either::Right(commitment) => commitment,
         }
     }
+
+    /// The full chain config, if it is known.
+    pub fn resolve(self) -> Option<ChainConfig> {
+        match self.chain_config {
+            Either::Left(config) => Some(config),
+            Either::Right(_) => None,
+        }
+    }
+
+    /// This chain config in the layout of V1 headers.
+    ///
+    /// Fails if the full config is known and cannot be carried by a V1 header.
+    pub(crate) fn to_v1(self) -> Result<Either<ChainConfigV1, Commitment<ChainConfig>>, String> {
+        Ok(match self.chain_config {
+            Either::Left(config) => Either::Left(config.try_into()?),
+            Either::Right(commitment) => Either::Right(commitment),
+        })
+    }
 }
 
 impl From<Commitment<ChainConfig>> for ResolvableChainConfig {
@@ -99,6 +259,7 @@ impl From<ChainConfig> for ResolvableChainConfig {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{Payload, Transaction};
 
     #[test]
     fn test_chain_config_equality() {
@@ -112,4 +273,62 @@ mod tests {
         let other_config = ChainConfig::new(chain_id, max_block_size, 1);
         assert!(chain_config != other_config);
     }
+
+    #[test]
+    fn test_chain_config_namespace_share() {
+        let chain_config = ChainConfig::default();
+        assert_eq!(chain_config.max_namespace_bytes(), None);
+
+        let limited = chain_config.with_max_namespace_share_bps(2_500);
+        assert_eq!(limited.max_namespace_bytes(), Some(2560));
+        assert_ne!(limited.commit(), chain_config.commit());
+
+        // Configs serialized before the limit existed are still valid, and unlimited.
+        let json = serde_json::json!({
+            "chain_id": "0x8a19",
+            "max_block_size": 10240,
+            "base_fee": "0x0",
+        });
+        let legacy: ChainConfig = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(legacy.max_namespace_bytes(), None);
+        assert_eq!(legacy, chain_config);
+        // ... and they still serialize without the limit.
+        assert_eq!(serde_json::to_value(chain_config).unwrap(), json);
+        assert_eq!(
+            serde_json::to_value(limited).unwrap()["max_namespace_share_bps"],
+            2_500
+        );
+
+        for config in [chain_config, limited] {
+            let json = serde_json::to_string(&config).unwrap();
+            assert_eq!(serde_json::from_str::<ChainConfig>(&json).unwrap(), config);
+            let bytes = bincode::serialize(&config).unwrap();
+            assert_eq!(bincode::deserialize::<ChainConfig>(&bytes).unwrap(), config);
+        }
+
+        // Only unlimited configs have a V1 layout.
+        let v1 = ChainConfigV1::try_from(chain_config).unwrap();
+        assert_eq!(ChainConfig::from(v1), chain_config);
+        ChainConfigV1::try_from(limited).unwrap_err();
+    }
+
+    #[test]
+    fn test_chain_config_block_fee() {
+        let chain_config = ChainConfig::new(1, 10240, 3);
+        assert_eq!(chain_config.namespace_fee(0), 0u64.into());
+        assert_eq!(chain_config.namespace_fee(10), 30u64.into());
+
+        // Each namespace pays for its own bytes.
+        let txs = [
+            Transaction::new(1u64.into(), vec![0; 10]),
+            Transaction::new(2u64.into(), vec![0; 100]),
+        ];
+        let payload = Payload::from_txs(txs, &chain_config).unwrap();
+        let ns_table = payload.get_ns_table();
+        let ns_bytes: u64 = (0..ns_table.len())
+            .map(|ns_index| ns_table.namespace_byte_len(ns_index) as u64)
+            .sum();
+        assert_eq!(chain_config.block_fee(ns_table), (3 * ns_bytes).into());
+        assert_eq!(ChainConfig::default().block_fee(ns_table), 0u64.into());
+    }
 }
@@ -2,6 +2,7 @@ use async_std::{
     sync::{Arc, RwLock},
     task::{spawn, JoinHandle},
 };
+use commit::Commitment;
 use derivative::Derivative;
 use futures::{
     future::{join_all, Future},
@@ -9,12 +10,13 @@ use futures::{
 };
 use hotshot::{
     traits::election::static_committee::GeneralStaticCommittee,
-    types::{Event, SystemContextHandle},
+    types::{Event, EventType, SystemContextHandle},
     Memberships, Networks, SystemContext,
 };
 use hotshot_orchestrator::client::OrchestratorClient;
 use hotshot_types::{
     consensus::ConsensusMetricsValue,
+    event::LeafInfo,
     traits::{election::Membership, metrics::Metrics},
     HotShotConfig,
 };
@@ -23,7 +25,13 @@ use url::Url;
 use versioned_binary_serialization::version::StaticVersionType;
 
 use crate::{
-    network, persistence::SequencerPersistence, state_signature::StateSigner,
+    api::participation::VoteParticipation,
+    block::{self, BuildContext},
+    catchup::{StateCatchup, StatePeers},
+    genesis::Genesis,
+    network,
+    persistence::SequencerPersistence,
+    state_signature::StateSigner,
     static_stake_table_commitment, ElectionConfig, Node, NodeState, PubKey, SeqTypes, Transaction,
 };
 use hotshot_events_service::events_source::{EventConsumer, EventsStreamer};
@@ -59,6 +67,20 @@ pub struct SequencerContext<
     /// events streamer to stream hotshot events to external clients
     events_streamer: Arc<RwLock<EventsStreamer<SeqTypes>>>,
 
+    /// Voting participation of the validators
+    vote_participation: Arc<RwLock<VoteParticipation>>,
+
+    /// Commitment to the genesis this node started from, if known.
+    genesis: Option<Commitment<Genesis>>,
+
+    /// Peers to check our genesis with before starting consensus.
+    #[derivative(Debug = "ignore")]
+    genesis_peers: Option<StatePeers<Ver>>,
+
+    /// Persistent storage for consensus state.
+    #[derivative(Debug = "ignore")]
+    persistence: Arc<RwLock<P>>,
+
     detached: bool,
 }
 
@@ -69,7 +91,7 @@ impl<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType + 'static
     pub async fn init(
         config: HotShotConfig<PubKey, ElectionConfig>,
         instance_state: NodeState,
-        persistence: P,
+        mut persistence: P,
         networks: Networks<SeqTypes, Node<N, P>>,
         state_relay_server: Option<Url>,
         metrics: &dyn Metrics,
@@ -77,7 +99,17 @@ impl<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType + 'static
         stake_table_capacity: usize,
         _: Ver,
     ) -> anyhow::Result<Self> {
+        // Remember every chain config we know of, so that we can serve them to peers.
+        for chain_config in instance_state.known_chain_configs() {
+            persistence.save_chain_config(&chain_config).await?;
+        }
+
+        // HotShot builds this node's payloads without access to its instance state.
+        block::build_payloads_with(instance_state.clone());
+        let build_context = instance_state.build_context().clone();
+
         // Load saved consensus state from storage.
+        let catchup = instance_state.peers.clone();
         let initializer = persistence.load_consensus_state(instance_state).await?;
 
         let election_config = GeneralStaticCommittee::<SeqTypes, PubKey>::default_election_config(
@@ -105,6 +137,14 @@ impl<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType + 'static
             config.num_nodes_without_stake,
         )));
 
+        let vote_participation = VoteParticipation::new(
+            config
+                .known_nodes_with_stake
+                .iter()
+                .map(|peer| peer.stake_table_entry.stake_key)
+                .collect(),
+        );
+
         let persistence = Arc::new(RwLock::new(persistence));
 
         let handle = SystemContext::init(
@@ -129,21 +169,29 @@ impl<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType + 'static
         Ok(Self::new(
             handle,
             persistence,
+            catchup,
+            build_context,
             node_id,
             state_signer,
             event_streamer,
+            vote_participation,
         ))
     }
 
     /// Constructor
+    #[allow(clippy::too_many_arguments)]
     fn new(
         handle: Consensus<N, P>,
         persistence: Arc<RwLock<P>>,
+        catchup: Arc<dyn StateCatchup>,
+        build_context: Arc<BuildContext>,
         node_index: u64,
         state_signer: StateSigner<Ver>,
         event_streamer: Arc<RwLock<EventsStreamer<SeqTypes>>>,
+        vote_participation: VoteParticipation,
     ) -> Self {
         let events = handle.get_event_stream();
+        let vote_participation = Arc::new(RwLock::new(vote_participation));
 
         let mut ctx = Self {
             handle,
@@ -153,13 +201,20 @@ impl<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType + 'static
             detached: false,
             wait_for_orchestrator: None,
             events_streamer: event_streamer.clone(),
+            vote_participation: vote_participation.clone(),
+            genesis: None,
+            genesis_peers: None,
+            persistence: persistence.clone(),
         };
         ctx.spawn(
             "main event handler",
             handle_events(
                 events,
                 persistence,
+                catchup,
+                build_context,
                 ctx.state_signer.clone(),
+                vote_participation,
                 Some(event_streamer.clone()),
             ),
         );
@@ -173,6 +228,28 @@ impl<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType + 'static
         self
     }
 
+    /// Record the genesis this node started from, so that it can be served to peers.
+    pub fn with_genesis(mut self, genesis: Commitment<Genesis>) -> Self {
+        self.genesis = Some(genesis);
+        self
+    }
+
+    /// Check our genesis with `peers` before starting consensus.
+    pub fn check_genesis_with(mut self, peers: StatePeers<Ver>) -> Self {
+        self.genesis_peers = Some(peers);
+        self
+    }
+
+    /// Commitment to the genesis this node started from, if known.
+    pub fn genesis(&self) -> Option<Commitment<Genesis>> {
+        self.genesis
+    }
+
+    /// Return a reference to the persistent storage of this node.
+    pub fn persistence(&self) -> Arc<RwLock<P>> {
+        self.persistence.clone()
+    }
+
     /// Return a reference to the consensus state signer.
     pub fn state_signer(&self) -> Arc<StateSigner<Ver>> {
         self.state_signer.clone()
@@ -188,6 +265,11 @@ impl<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType + 'static
         Ok(())
     }
 
+    /// Return a reference to the voting participation of the validators.
+    pub fn vote_participation(&self) -> Arc<RwLock<VoteParticipation>> {
+        self.vote_participation.clone()
+    }
+
     /// get event streamer
     pub fn get_event_streamer(&self) -> Arc<RwLock<EventsStreamer<SeqTypes>>> {
         self.events_streamer.clone()
@@ -203,6 +285,17 @@ impl<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType + 'static
         &mut self.handle
     }
 
+    /// Check that our peers started from the same genesis, if we were given peers to check with.
+    ///
+    /// This waits until at least one peer has confirmed our genesis. It is done after the node has
+    /// been set up, so that nodes starting together can answer each other's checks.
+    pub async fn check_genesis(&self) -> anyhow::Result<()> {
+        if let (Some(genesis), Some(peers)) = (self.genesis, &self.genesis_peers) {
+            peers.check_genesis(genesis).await?;
+        }
+        Ok(())
+    }
+
     /// Start participating in consensus.
     pub async fn start_consensus(&self) {
         if let Some(orchestrator_client) = &self.wait_for_orchestrator {
@@ -268,7 +361,10 @@ impl<N: network::Type, P: SequencerPersistence, Ver: StaticVersionType + 'static
 async fn handle_events<Ver: StaticVersionType>(
     mut events: impl Stream<Item = Event<SeqTypes>> + Unpin,
     persistence: Arc<RwLock<impl SequencerPersistence>>,
+    catchup: Arc<dyn StateCatchup>,
+    build_context: Arc<BuildContext>,
     state_signer: Arc<StateSigner<Ver>>,
+    vote_participation: Arc<RwLock<VoteParticipation>>,
     events_streamer: Option<Arc<RwLock<EventsStreamer<SeqTypes>>>>,
 ) {
     while let Some(event) = events.next().await {
@@ -277,11 +373,21 @@ async fn handle_events<Ver: StaticVersionType>(
         {
             let mut p = persistence.write().await;
             // Store latest consensus state.
-            p.handle_event(&event).await;
+            p.handle_event(&event, &*catchup).await;
         }
         // Generate state signature.
         state_signer.handle_event(&event).await;
 
+        // Track which validators voted.
+        vote_participation.write().await.handle_event(&event);
+
+        // Build new payloads for the heights after the latest decided block.
+        if let EventType::Decide { leaf_chain, .. } = &event.event {
+            if let Some(LeafInfo { leaf, .. }) = leaf_chain.first() {
+                build_context.record_decided(leaf.get_block_header().height);
+            }
+        }
+
         // Send the event via the event streaming service
         if let Some(events_streamer) = events_streamer.as_ref() {
             events_streamer.write().await.handle_event(event).await;
@@ -0,0 +1,541 @@
+//! The genesis of an Espresso chain.
+//!
+//! Every node of a chain must start from exactly the same genesis: the same chain config, the same
+//! initial fee balances and stake table, and the same genesis header. These parameters are collected
+//! in a versioned genesis file (TOML or JSON) which is distributed to all operators, so that a node
+//! can compare its genesis with its peers' before joining consensus.
+
+use crate::{
+    l1_client::L1BlockInfo,
+    state::{FeeAccount, FeeAmount},
+    ChainConfig, ChainConfigUpgrade, HeaderUpgrade, HeaderVersion, PubKey, ValidatedState,
+};
+use anyhow::{bail, ensure, Context};
+use commit::{Commitment, Committable, RawCommitmentBuilder};
+use ethers::types::U256;
+use hotshot_types::{light_client::StateVerKey, PeerConfig};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+/// The genesis file format version understood by this node.
+pub const GENESIS_VERSION: u32 = 1;
+
+/// An entry in the initial stake table.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GenesisStaker {
+    /// Consensus (BLS) public key.
+    pub stake_key: PubKey,
+    /// Light client state (Schnorr) verification key.
+    pub state_key: StateVerKey,
+    /// Stake delegated to this node.
+    pub stake: U256,
+}
+
+/// Deterministic parameters of the genesis header.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GenesisHeader {
+    /// Timestamp of the genesis block.
+    pub timestamp: u64,
+    /// The L1 block the chain is anchored to, if any.
+    pub l1_finalized: Option<L1BlockInfo>,
+}
+
+/// The contents of a genesis file.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Genesis {
+    /// Version of the genesis file format.
+    pub version: u32,
+    /// Chain config in effect from the first block.
+    pub chain_config: ChainConfig,
+    /// Scheduled chain config upgrades, in order of activation height.
+    #[serde(default)]
+    pub upgrades: Vec<ChainConfigUpgrade>,
+    /// Scheduled header version upgrades, in order of activation height.
+    ///
+    /// Without any, all headers use the original (V1) format.
+    #[serde(default)]
+    pub header_upgrades: Vec<HeaderUpgrade>,
+    /// Timestamp of the genesis block.
+    #[serde(default)]
+    pub timestamp: u64,
+    /// The L1 block the chain is anchored to, if any.
+    #[serde(default)]
+    pub l1_finalized: Option<L1BlockInfo>,
+    /// Initial fee balances.
+    #[serde(default)]
+    pub accounts: BTreeMap<FeeAccount, FeeAmount>,
+    /// Initial stake table, in order.
+    ///
+    /// If empty, the stake table is taken from the orchestrator without being checked.
+    #[serde(default)]
+    pub stake_table: Vec<GenesisStaker>,
+}
+
+impl Genesis {
+    /// A genesis with the given chain config and no other parameters.
+    pub fn new(chain_config: ChainConfig) -> Self {
+        Self {
+            version: GENESIS_VERSION,
+            chain_config,
+            upgrades: Default::default(),
+            header_upgrades: Default::default(),
+            timestamp: 0,
+            l1_finalized: None,
+            accounts: Default::default(),
+            stake_table: Default::default(),
+        }
+    }
+
+    /// Load a genesis file.
+    ///
+    /// Files with a `.json` extension are parsed as JSON, anything else as TOML.
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read genesis file {}", path.display()))?;
+        let genesis: Self = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&contents)
+                .with_context(|| format!("malformed genesis file {}", path.display()))?
+        } else {
+            toml::from_str(&contents)
+                .with_context(|| format!("malformed genesis file {}", path.display()))?
+        };
+        ensure!(
+            genesis.version == GENESIS_VERSION,
+            "unsupported genesis file version {} (expected {GENESIS_VERSION})",
+            genesis.version
+        );
+        genesis.check_upgrades()?;
+        Ok(genesis)
+    }
+
+    /// Check that upgrades are scheduled in strictly increasing order of height.
+    ///
+    /// Chain config upgrades must come after genesis. Header upgrades may take effect at genesis,
+    /// but can never go back to an older version. A chain config which limits namespaces can only
+    /// take effect once headers are at least V2, since V1 headers cannot carry the limit.
+    pub fn check_upgrades(&self) -> anyhow::Result<()> {
+        let mut prev = 0;
+        for upgrade in &self.upgrades {
+            ensure!(
+                upgrade.height > prev,
+                "chain config upgrade at height {} must come after height {prev}",
+                upgrade.height
+            );
+            prev = upgrade.height;
+        }
+
+        let mut prev: Option<&HeaderUpgrade> = None;
+        for upgrade in &self.header_upgrades {
+            if let Some(prev) = prev {
+                ensure!(
+                    upgrade.height > prev.height,
+                    "header upgrade at height {} must come after height {}",
+                    upgrade.height,
+                    prev.height
+                );
+                ensure!(
+                    upgrade.version > prev.version,
+                    "header upgrade at height {} downgrades from {} to {}",
+                    upgrade.height,
+                    prev.version,
+                    upgrade.version
+                );
+            }
+            prev = Some(upgrade);
+        }
+
+        let configs = self
+            .upgrades
+            .iter()
+            .map(|upgrade| (upgrade.height, &upgrade.chain_config));
+        for (height, chain_config) in [(0, &self.chain_config)].into_iter().chain(configs) {
+            let version = self.header_version(height);
+            ensure!(
+                chain_config.max_namespace_bytes().is_none() || version >= HeaderVersion::V2,
+                "chain config at height {height} limits namespaces, but headers are still {version}"
+            );
+        }
+        Ok(())
+    }
+
+    /// The header version in effect at `height`.
+    fn header_version(&self, height: u64) -> HeaderVersion {
+        self.header_upgrades
+            .iter()
+            .take_while(|upgrade| upgrade.height <= height)
+            .last()
+            .map(|upgrade| upgrade.version)
+            .unwrap_or_default()
+    }
+
+    /// The initial state, with all genesis accounts funded.
+    pub fn validated_state(&self) -> ValidatedState {
+        let mut state = ValidatedState::default();
+        for (account, amount) in &self.accounts {
+            state.prefund_account(*account, *amount);
+        }
+        state
+    }
+
+    /// The header version of the genesis block, if it is not the original one.
+    pub fn genesis_header_version(&self) -> Option<HeaderVersion> {
+        self.header_upgrades
+            .iter()
+            .take_while(|upgrade| upgrade.height == 0)
+            .last()
+            .map(|upgrade| upgrade.version)
+    }
+
+    /// Parameters of the genesis header.
+    pub fn header(&self) -> GenesisHeader {
+        GenesisHeader {
+            timestamp: self.timestamp,
+            l1_finalized: self.l1_finalized,
+        }
+    }
+
+    /// Check that `stake_table` matches the genesis stake table.
+    ///
+    /// The check is skipped if the genesis does not specify a stake table.
+    pub fn check_stake_table(&self, stake_table: &[PeerConfig<PubKey>]) -> anyhow::Result<()> {
+        if self.stake_table.is_empty() {
+            return Ok(());
+        }
+        ensure!(
+            stake_table.len() == self.stake_table.len(),
+            "stake table has {} entries, but genesis has {}",
+            stake_table.len(),
+            self.stake_table.len()
+        );
+        for (i, (peer, staker)) in stake_table.iter().zip(&self.stake_table).enumerate() {
+            let entry = &peer.stake_table_entry;
+            if entry.stake_key != staker.stake_key
+                || peer.state_ver_key != staker.state_key
+                || entry.stake_amount != staker.stake
+            {
+                bail!("stake table entry {i} does not match genesis: {peer:?} != {staker:?}");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Committable for Genesis {
+    fn commit(&self) -> Commitment<Self> {
+        let mut comm = RawCommitmentBuilder::new(&Self::tag())
+            .u64_field("version", self.version.into())
+            .field("chain_config", self.chain_config.commit());
+        // Scheduled upgrades are not part of the genesis, so that they can be added to the genesis
+        // file of a live network without changing its genesis. Only a header version in effect from
+        // genesis on changes the genesis block itself, and only that is committed to.
+        if let Some(version) = self.genesis_header_version() {
+            comm = comm.u64_field("header_version", u8::from(version).into());
+        }
+        comm = comm
+            .u64_field("timestamp", self.timestamp)
+            .optional("l1_finalized", &self.l1_finalized)
+            .u64_field("accounts", self.accounts.len() as u64);
+        for (account, amount) in &self.accounts {
+            comm = comm
+                .fixed_size_bytes(&account.to_fixed_bytes())
+                .fixed_size_bytes(&amount.to_fixed_bytes());
+        }
+        comm = comm.u64_field("stake_table", self.stake_table.len() as u64);
+        for staker in &self.stake_table {
+            comm = comm
+                .var_size_bytes(&bincode::serialize(&staker.stake_key).unwrap())
+                .var_size_bytes(&bincode::serialize(&staker.state_key).unwrap())
+                .fixed_size_bytes(&<[u8; 32]>::from(staker.stake));
+        }
+        comm.finalize()
+    }
+
+    fn tag() -> String {
+        "GENESIS".into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::types::{Address, H256};
+    use hotshot_types::{light_client::StateKeyPair, traits::signature_key::SignatureKey};
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn write_genesis(contents: &str, suffix: &str) -> NamedTempFile {
+        let mut file = tempfile::Builder::new().suffix(suffix).tempfile().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_genesis_from_toml() {
+        let file = write_genesis(
+            r#"
+            version = 1
+            timestamp = 1234
+
+            [chain_config]
+            chain_id = "0x2"
+            max_block_size = 4096
+            base_fee = "0x3"
+
+            [l1_finalized]
+            number = 42
+            timestamp = "0x4"
+            hash = "0x0000000000000000000000000000000000000000000000000000000000000005"
+
+            [accounts]
+            "0x0000000000000000000000000000000000000001" = "0x10"
+            "#,
+            ".toml",
+        );
+        let genesis = Genesis::from_file(file.path()).unwrap();
+
+        assert_eq!(genesis.chain_config, ChainConfig::new(2, 4096, 3));
+        assert_eq!(
+            genesis.header(),
+            GenesisHeader {
+                timestamp: 1234,
+                l1_finalized: Some(L1BlockInfo {
+                    number: 42,
+                    timestamp: 4.into(),
+                    hash: H256::from_low_u64_be(5),
+                }),
+            }
+        );
+        let account = FeeAccount::from(Address::from_low_u64_be(1));
+        assert_eq!(genesis.accounts[&account], FeeAmount::from(16));
+        assert!(genesis.stake_table.is_empty());
+    }
+
+    #[test]
+    fn test_genesis_demo_file() {
+        let genesis = Genesis::from_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../data/genesis/demo.toml"
+        ))
+        .unwrap();
+        assert_eq!(genesis.chain_config, ChainConfig::new(0, 10240, 0));
+        assert_eq!(genesis.header(), GenesisHeader::default());
+        assert_eq!(genesis.accounts.len(), 1);
+    }
+
+    #[test]
+    fn test_genesis_from_json() {
+        let mut genesis = Genesis::new(ChainConfig::default());
+        genesis.accounts.insert(
+            Address::from_low_u64_be(1).into(),
+            U256::from(1_000_000).into(),
+        );
+        let file = write_genesis(&serde_json::to_string(&genesis).unwrap(), ".json");
+        assert_eq!(Genesis::from_file(file.path()).unwrap(), genesis);
+    }
+
+    #[test]
+    fn test_genesis_unsupported_version() {
+        let file = write_genesis(
+            r#"
+            version = 2
+
+            [chain_config]
+            chain_id = "0x2"
+            max_block_size = 4096
+            base_fee = "0x0"
+            "#,
+            ".toml",
+        );
+        Genesis::from_file(file.path()).unwrap_err();
+    }
+
+    #[test]
+    fn test_genesis_upgrades() {
+        let file = write_genesis(
+            r#"
+            version = 1
+
+            [chain_config]
+            chain_id = "0x2"
+            max_block_size = 4096
+            base_fee = "0x0"
+
+            [[upgrades]]
+            height = 100
+            chain_config = { chain_id = "0x2", max_block_size = 8192, base_fee = "0x1" }
+
+            [[upgrades]]
+            height = 200
+            chain_config = { chain_id = "0x2", max_block_size = 16384, base_fee = "0x1" }
+            "#,
+            ".toml",
+        );
+        let genesis = Genesis::from_file(file.path()).unwrap();
+        assert_eq!(
+            genesis.upgrades,
+            [
+                ChainConfigUpgrade {
+                    height: 100,
+                    chain_config: ChainConfig::new(2, 8192, 1),
+                },
+                ChainConfigUpgrade {
+                    height: 200,
+                    chain_config: ChainConfig::new(2, 16384, 1),
+                },
+            ]
+        );
+
+        // Upgrades can be scheduled without changing the genesis.
+        let mut without_upgrades = genesis.clone();
+        without_upgrades.upgrades.clear();
+        assert_eq!(genesis.commit(), without_upgrades.commit());
+
+        // Upgrades must be scheduled in order, after genesis.
+        let mut unordered = genesis.clone();
+        unordered.upgrades.reverse();
+        unordered.check_upgrades().unwrap_err();
+        let mut at_genesis = genesis.clone();
+        at_genesis.upgrades[0].height = 0;
+        at_genesis.check_upgrades().unwrap_err();
+    }
+
+    #[test]
+    fn test_genesis_header_upgrades() {
+        let file = write_genesis(
+            r#"
+            version = 1
+
+            [chain_config]
+            chain_id = "0x2"
+            max_block_size = 4096
+            base_fee = "0x0"
+
+            [[header_upgrades]]
+            height = 100
+            version = 2
+            "#,
+            ".toml",
+        );
+        let genesis = Genesis::from_file(file.path()).unwrap();
+        assert_eq!(
+            genesis.header_upgrades,
+            [HeaderUpgrade {
+                height: 100,
+                version: HeaderVersion::V2,
+            }]
+        );
+
+        // Header upgrades can be scheduled without changing the genesis.
+        let mut without_upgrades = genesis.clone();
+        without_upgrades.header_upgrades.clear();
+        assert_eq!(genesis.commit(), without_upgrades.commit());
+
+        // A chain may use a newer header version from genesis on, which changes its genesis.
+        let mut at_genesis = genesis.clone();
+        at_genesis.header_upgrades[0].height = 0;
+        at_genesis.check_upgrades().unwrap();
+        assert_eq!(at_genesis.genesis_header_version(), Some(HeaderVersion::V2));
+        assert_ne!(genesis.commit(), at_genesis.commit());
+
+        // But it can never go back to an older version.
+        let mut downgrade = genesis.clone();
+        downgrade.header_upgrades.push(HeaderUpgrade {
+            height: 200,
+            version: HeaderVersion::V1,
+        });
+        downgrade.check_upgrades().unwrap_err();
+
+        // Namespace limits can only take effect once headers are V2.
+        let limited = ChainConfig::new(2, 4096, 0u64).with_max_namespace_share_bps(5_000);
+        let mut early_limit = genesis.clone();
+        early_limit.upgrades.push(ChainConfigUpgrade {
+            height: 50,
+            chain_config: limited,
+        });
+        early_limit.check_upgrades().unwrap_err();
+        early_limit.upgrades[0].height = 100;
+        early_limit.check_upgrades().unwrap();
+        let mut limited_genesis = genesis.clone();
+        limited_genesis.chain_config = limited;
+        limited_genesis.check_upgrades().unwrap_err();
+        at_genesis.chain_config = limited;
+        at_genesis.check_upgrades().unwrap();
+
+        // Unknown versions are rejected.
+        let file = write_genesis(
+            r#"
+            version = 1
+
+            [chain_config]
+            chain_id = "0x2"
+            max_block_size = 4096
+            base_fee = "0x0"
+
+            [[header_upgrades]]
+            height = 100
+            version = 3
+            "#,
+            ".toml",
+        );
+        Genesis::from_file(file.path()).unwrap_err();
+    }
+
+    #[test]
+    fn test_genesis_commitment() {
+        let genesis = Genesis::new(ChainConfig::default());
+        assert_eq!(genesis.commit(), genesis.clone().commit());
+
+        let mut funded = genesis.clone();
+        funded
+            .accounts
+            .insert(Address::from_low_u64_be(1).into(), 1.into());
+        assert_ne!(genesis.commit(), funded.commit());
+
+        let mut anchored = genesis.clone();
+        anchored.l1_finalized = Some(Default::default());
+        assert_ne!(genesis.commit(), anchored.commit());
+
+        let mut later = genesis.clone();
+        later.timestamp = 1;
+        assert_ne!(genesis.commit(), later.commit());
+    }
+
+    #[test]
+    fn test_genesis_check_stake_table() {
+        let peers = (0..2)
+            .map(|i| {
+                let (stake_key, _) = PubKey::generated_from_seed_indexed([0; 32], i);
+                PeerConfig::<PubKey> {
+                    stake_table_entry: stake_key.get_stake_table_entry(1),
+                    state_ver_key: StateKeyPair::generate().ver_key(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Without a stake table in the genesis, anything goes.
+        let mut genesis = Genesis::new(ChainConfig::default());
+        genesis.check_stake_table(&peers).unwrap();
+
+        genesis.stake_table = peers
+            .iter()
+            .map(|peer| GenesisStaker {
+                stake_key: peer.stake_table_entry.stake_key,
+                state_key: peer.state_ver_key.clone(),
+                stake: peer.stake_table_entry.stake_amount,
+            })
+            .collect();
+        genesis.check_stake_table(&peers).unwrap();
+
+        // Wrong order.
+        let reversed = peers.iter().rev().cloned().collect::<Vec<_>>();
+        genesis.check_stake_table(&reversed).unwrap_err();
+
+        // Missing entry.
+        genesis.check_stake_table(&peers[..1]).unwrap_err();
+
+        // Wrong stake.
+        genesis.stake_table[0].stake = 2.into();
+        genesis.check_stake_table(&peers).unwrap_err();
+    }
+}
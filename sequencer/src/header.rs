@@ -1,8 +1,12 @@
 use crate::{
-    block::{entry::TxTableEntryWord, tables::NameSpaceTable, NsTable},
-    chain_config::ResolvableChainConfig,
+    block::{
+        tables::{NameSpaceTable, PayloadVersion},
+        NsTable,
+    },
+    chain_config::{ChainConfigV1, ResolvableChainConfig},
+    genesis::GenesisHeader,
     l1_client::L1Snapshot,
-    state::{BlockMerkleCommitment, FeeAccount, FeeInfo, FeeMerkleCommitment},
+    state::{BlockMerkleCommitment, FeeAccount, FeeAmount, FeeInfo, FeeMerkleCommitment},
     ChainConfig, L1BlockInfo, Leaf, NodeState, SeqTypes, ValidatedState,
 };
 use ark_serialize::CanonicalSerialize;
@@ -24,12 +28,108 @@ use hotshot_types::{
 };
 use jf_primitives::merkle_tree::prelude::*;
 
-use serde::{Deserialize, Serialize};
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    ser::{self, SerializeTuple},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
 use time::OffsetDateTime;
 
+/// The version of a [`Header`], which determines its commitment scheme.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize,
+)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum HeaderVersion {
+    /// The original header format, which predates versioning.
+    #[default]
+    V1,
+    /// Same fields as V1, but the commitment binds the version number, so that headers of later
+    /// versions can never collide with headers of earlier ones. New payloads use the V1 layout.
+    V2,
+}
+
+impl HeaderVersion {
+    /// The layout of new payloads in blocks with headers of this version.
+    pub fn payload_version(self) -> PayloadVersion {
+        match self {
+            Self::V1 => PayloadVersion::V0,
+            Self::V2 => PayloadVersion::V1,
+        }
+    }
+
+    /// Whether a header of this version may describe a payload with layout `version`.
+    ///
+    /// Besides its own layout, a header accepts the layouts of earlier header versions. HotShot
+    /// builds a payload before it knows the height, and hence the header version, of its block, so
+    /// the first few blocks after an upgrade may still use the previous layout.
+    pub fn allows_payload_version(self, version: PayloadVersion) -> bool {
+        match version {
+            PayloadVersion::V0 => true,
+            PayloadVersion::V1 => self >= Self::V2,
+            PayloadVersion::Unsupported(_) => false,
+        }
+    }
+}
+
+impl From<HeaderVersion> for u8 {
+    fn from(version: HeaderVersion) -> Self {
+        match version {
+            HeaderVersion::V1 => 1,
+            HeaderVersion::V2 => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for HeaderVersion {
+    type Error = String;
+
+    fn try_from(version: u8) -> Result<Self, Self::Error> {
+        match version {
+            1 => Ok(Self::V1),
+            2 => Ok(Self::V2),
+            _ => Err(format!("unsupported header version {version}")),
+        }
+    }
+}
+
+impl fmt::Display for HeaderVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", u8::from(*self))
+    }
+}
+
+/// A header version which replaces the previous one starting at a given block height.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct HeaderUpgrade {
+    /// Height of the first block built with the new header version.
+    pub height: u64,
+    pub version: HeaderVersion,
+}
+
 /// A header is like a [`Block`] with the body replaced by a digest.
+///
+/// The header is versioned, so that its format and commitment scheme can change without a hard
+/// fork: all validators switch to a new version at the same, pre-scheduled block height (see
+/// [`HeaderUpgrade`]). The fields common to all versions are accessible directly through
+/// [`Deref`].
+///
+/// V1 headers are serialized exactly as they were before versioning was introduced. Later versions
+/// add an explicit version number: a `version` field in human-readable formats, and a leading
+/// integer in binary formats, which can never be confused with the leading enum tag of a V1 header.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum Header {
+    V1(HeaderFields),
+    V2(HeaderFields),
+}
+
+/// The fields of a [`Header`].
 #[derive(Clone, Debug, Deserialize, Serialize, Hash, PartialEq, Eq)]
-pub struct Header {
+pub struct HeaderFields {
     /// A commitment to a ChainConfig or a full ChainConfig.
     pub chain_config: ResolvableChainConfig,
 
@@ -78,7 +178,7 @@ pub struct Header {
     pub l1_finalized: Option<L1BlockInfo>,
 
     pub payload_commitment: VidCommitment,
-    pub ns_table: NameSpaceTable<TxTableEntryWord>,
+    pub ns_table: NameSpaceTable,
     /// Root Commitment of Block Merkle Tree
     pub block_merkle_tree_root: BlockMerkleCommitment,
     /// Root Commitment of `FeeMerkleTree`
@@ -88,8 +188,9 @@ pub struct Header {
     pub fee_info: FeeInfo,
 }
 
-impl Committable for Header {
-    fn commit(&self) -> Commitment<Self> {
+impl HeaderFields {
+    /// Append the fields common to all header versions to a commitment.
+    fn commit_fields(&self, comm: RawCommitmentBuilder<Header>) -> RawCommitmentBuilder<Header> {
         let mut bmt_bytes = vec![];
         self.block_merkle_tree_root
             .serialize_with_mode(&mut bmt_bytes, ark_serialize::Compress::Yes)
@@ -99,8 +200,7 @@ impl Committable for Header {
             .serialize_with_mode(&mut fmt_bytes, ark_serialize::Compress::Yes)
             .unwrap();
 
-        RawCommitmentBuilder::new(&Self::tag())
-            .field("chain_config", self.chain_config.commit())
+        comm.field("chain_config", self.chain_config.commit())
             .u64_field("height", self.height)
             .u64_field("timestamp", self.timestamp)
             .u64_field("l1_head", self.l1_head)
@@ -111,7 +211,20 @@ impl Committable for Header {
             .var_size_field("block_merkle_tree_root", &bmt_bytes)
             .var_size_field("fee_merkle_tree_root", &fmt_bytes)
             .field("fee_info", self.fee_info.commit())
-            .finalize()
+    }
+}
+
+impl Committable for Header {
+    fn commit(&self) -> Commitment<Self> {
+        let comm = RawCommitmentBuilder::new(&Self::tag());
+        match self {
+            // V1 headers do not commit to their version, so that the commitments of existing
+            // blocks do not change.
+            Self::V1(fields) => fields.commit_fields(comm).finalize(),
+            Self::V2(fields) => fields
+                .commit_fields(comm.u64_field("version", u8::from(HeaderVersion::V2).into()))
+                .finalize(),
+        }
     }
 
     fn tag() -> String {
@@ -119,7 +232,157 @@ impl Committable for Header {
     }
 }
 
-impl Committable for NameSpaceTable<TxTableEntryWord> {
+impl Deref for Header {
+    type Target = HeaderFields;
+
+    fn deref(&self) -> &HeaderFields {
+        match self {
+            Self::V1(fields) | Self::V2(fields) => fields,
+        }
+    }
+}
+
+impl DerefMut for Header {
+    fn deref_mut(&mut self) -> &mut HeaderFields {
+        match self {
+            Self::V1(fields) | Self::V2(fields) => fields,
+        }
+    }
+}
+
+/// The human-readable serialization of a versioned header: its fields plus a version number.
+#[derive(Deserialize, Serialize)]
+struct VersionedHeader<F> {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<HeaderVersion>,
+    #[serde(flatten)]
+    fields: F,
+}
+
+impl Serialize for Header {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::V1(fields) if serializer.is_human_readable() => {
+                fields.chain_config.to_v1().map_err(ser::Error::custom)?;
+                fields.serialize(serializer)
+            }
+            // V1 headers keep the chain config layout they had before namespace limits existed.
+            // Otherwise, their fields are encoded in order, just like the struct they once were.
+            Self::V1(fields) => {
+                let chain_config = fields.chain_config.to_v1().map_err(ser::Error::custom)?;
+                let mut tuple = serializer.serialize_tuple(11)?;
+                tuple.serialize_element(&chain_config)?;
+                tuple.serialize_element(&fields.height)?;
+                tuple.serialize_element(&fields.timestamp)?;
+                tuple.serialize_element(&fields.l1_head)?;
+                tuple.serialize_element(&fields.l1_finalized)?;
+                tuple.serialize_element(&fields.payload_commitment)?;
+                tuple.serialize_element(&fields.ns_table)?;
+                tuple.serialize_element(&fields.block_merkle_tree_root)?;
+                tuple.serialize_element(&fields.fee_merkle_tree_root)?;
+                tuple.serialize_element(&fields.builder_signature)?;
+                tuple.serialize_element(&fields.fee_info)?;
+                tuple.end()
+            }
+            Self::V2(fields) if serializer.is_human_readable() => VersionedHeader {
+                version: Some(self.version()),
+                fields,
+            }
+            .serialize(serializer),
+            Self::V2(fields) => {
+                let mut tuple = serializer.serialize_tuple(2)?;
+                tuple.serialize_element(&u32::from(u8::from(self.version())))?;
+                tuple.serialize_element(fields)?;
+                tuple.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Header {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let VersionedHeader { version, fields } =
+                VersionedHeader::<HeaderFields>::deserialize(deserializer)?;
+            let version = version.unwrap_or_default();
+            if version == HeaderVersion::V1 {
+                fields.chain_config.to_v1().map_err(de::Error::custom)?;
+            }
+            Ok(Self::from_fields(version, fields))
+        } else {
+            // The binary encoding of a V1 header starts with the enum tag of its chain config
+            // (0 or 1), followed by the config in the V1 layout or its commitment. Later versions
+            // start with their version number (2 or more).
+            // Since binary formats are not self-describing, read the header as a tuple: first the
+            // leading integer, then the rest of the fields as implied by it.
+            deserializer.deserialize_tuple(3, BinaryHeaderVisitor)
+        }
+    }
+}
+
+struct BinaryHeaderVisitor;
+
+impl<'de> Visitor<'de> for BinaryHeaderVisitor {
+    type Value = Header;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a binary-encoded header")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Header, A::Error> {
+        let tag: u32 = next_element(&mut seq, 0)?;
+        let chain_config: ResolvableChainConfig = match tag {
+            0 => ChainConfig::from(next_element::<_, ChainConfigV1>(&mut seq, 1)?).into(),
+            1 => next_element::<_, Commitment<ChainConfig>>(&mut seq, 1)?.into(),
+            version => {
+                let version = u8::try_from(version)
+                    .map_err(de::Error::custom)?
+                    .try_into()
+                    .map_err(de::Error::custom)?;
+                return Ok(Header::from_fields(version, next_element(&mut seq, 1)?));
+            }
+        };
+        let rest: HeaderFieldsAfterChainConfig = next_element(&mut seq, 2)?;
+        Ok(Header::V1(HeaderFields {
+            chain_config,
+            height: rest.height,
+            timestamp: rest.timestamp,
+            l1_head: rest.l1_head,
+            l1_finalized: rest.l1_finalized,
+            payload_commitment: rest.payload_commitment,
+            ns_table: rest.ns_table,
+            block_merkle_tree_root: rest.block_merkle_tree_root,
+            fee_merkle_tree_root: rest.fee_merkle_tree_root,
+            builder_signature: rest.builder_signature,
+            fee_info: rest.fee_info,
+        }))
+    }
+}
+
+fn next_element<'de, A: SeqAccess<'de>, T: Deserialize<'de>>(
+    seq: &mut A,
+    index: usize,
+) -> Result<T, A::Error> {
+    seq.next_element()?
+        .ok_or_else(|| de::Error::invalid_length(index, &BinaryHeaderVisitor))
+}
+
+/// The fields of a binary-encoded V1 header which follow the chain config, in order.
+#[derive(Deserialize)]
+struct HeaderFieldsAfterChainConfig {
+    height: u64,
+    timestamp: u64,
+    l1_head: u64,
+    l1_finalized: Option<L1BlockInfo>,
+    payload_commitment: VidCommitment,
+    ns_table: NameSpaceTable,
+    block_merkle_tree_root: BlockMerkleCommitment,
+    fee_merkle_tree_root: FeeMerkleCommitment,
+    builder_signature: Option<types::Signature>,
+    fee_info: FeeInfo,
+}
+
+impl Committable for NameSpaceTable {
     fn commit(&self) -> Commitment<Self> {
         RawCommitmentBuilder::new(&Self::tag())
             .var_size_bytes(self.get_bytes())
@@ -132,6 +395,21 @@ impl Committable for NameSpaceTable<TxTableEntryWord> {
 }
 
 impl Header {
+    /// A header of the given version.
+    pub fn from_fields(version: HeaderVersion, fields: HeaderFields) -> Self {
+        match version {
+            HeaderVersion::V1 => Self::V1(fields),
+            HeaderVersion::V2 => Self::V2(fields),
+        }
+    }
+
+    pub fn version(&self) -> HeaderVersion {
+        match self {
+            Self::V1(_) => HeaderVersion::V1,
+            Self::V2(_) => HeaderVersion::V2,
+        }
+    }
+
     #[allow(clippy::too_many_arguments)]
     // TODO pub or merely pub(super)?
     pub fn from_info(
@@ -143,7 +421,9 @@ impl Header {
         mut timestamp: u64,
         parent_state: &ValidatedState,
         builder_address: Wallet<SigningKey>,
+        builder_fee: FeeAmount,
         chain_config: ChainConfig,
+        version: HeaderVersion,
     ) -> Self {
         // Increment height.
         let parent_header = parent_leaf.get_block_header();
@@ -209,19 +489,22 @@ impl Header {
 
         let fee_merkle_tree_root = state.fee_merkle_tree.commitment();
 
-        let header = Self {
-            chain_config: chain_config.into(),
-            height,
-            timestamp,
-            l1_head: l1.head,
-            l1_finalized: l1.finalized,
-            payload_commitment,
-            ns_table,
-            fee_merkle_tree_root,
-            block_merkle_tree_root,
-            fee_info: FeeInfo::base_fee(builder_address.address().into()),
-            builder_signature: None,
-        };
+        let mut header = Self::from_fields(
+            version,
+            HeaderFields {
+                chain_config: chain_config.into(),
+                height,
+                timestamp,
+                l1_head: l1.head,
+                l1_finalized: l1.finalized,
+                payload_commitment,
+                ns_table,
+                fee_merkle_tree_root,
+                block_merkle_tree_root,
+                fee_info: FeeInfo::new(builder_address.address(), builder_fee),
+                builder_signature: None,
+            },
+        );
 
         // Sign our header using its `Commitment` as a prehash.
         let builder_signature = builder_address
@@ -229,10 +512,8 @@ impl Header {
             .unwrap();
 
         // Finally store the signature on the Header
-        Self {
-            builder_signature: Some(builder_signature),
-            ..header
-        }
+        header.builder_signature = Some(builder_signature);
+        header
     }
 }
 
@@ -311,6 +592,11 @@ impl BlockHeader<SeqTypes> for Header {
                 .await;
         }
 
+        let height = parent_leaf.get_block_header().height + 1;
+        let chain_config = instance_state.chain_config(height);
+        let builder_fee = instance_state
+            .builder_fee
+            .builder_fee(&chain_config, &metadata);
         Self::from_info(
             payload_commitment,
             metadata,
@@ -320,7 +606,9 @@ impl BlockHeader<SeqTypes> for Header {
             OffsetDateTime::now_utc().unix_timestamp() as u64,
             &validated_state,
             instance_state.builder_address.clone(),
-            instance_state.chain_config,
+            builder_fee,
+            chain_config,
+            instance_state.header_version(height),
         )
     }
 
@@ -336,21 +624,29 @@ impl BlockHeader<SeqTypes> for Header {
         let block_merkle_tree_root = block_merkle_tree.commitment();
         let fee_merkle_tree_root = fee_merkle_tree.commitment();
 
-        Self {
-            // The genesis header needs to be completely deterministic, so we can't sample real
-            // timestamps or L1 values.
-            chain_config: instance_state.chain_config.into(),
-            height: 0,
-            timestamp: 0,
-            l1_head: 0,
-            l1_finalized: None,
-            payload_commitment,
-            ns_table,
-            block_merkle_tree_root,
-            fee_merkle_tree_root,
-            fee_info: FeeInfo::genesis(),
-            builder_signature: None,
-        }
+        // The genesis header needs to be completely deterministic, so we can't sample real
+        // timestamps or L1 values. Instead, they are fixed by the genesis file.
+        let GenesisHeader {
+            timestamp,
+            l1_finalized,
+        } = instance_state.genesis_header;
+
+        Self::from_fields(
+            instance_state.header_version(0),
+            HeaderFields {
+                chain_config: instance_state.chain_config(0).into(),
+                height: 0,
+                timestamp,
+                l1_head: l1_finalized.map_or(0, |block| block.number),
+                l1_finalized,
+                payload_commitment,
+                ns_table,
+                block_merkle_tree_root,
+                fee_merkle_tree_root,
+                fee_info: FeeInfo::genesis(),
+                builder_signature: None,
+            },
+        )
     }
 
     fn block_number(&self) -> u64 {
@@ -378,16 +674,22 @@ mod test_headers {
 
     use super::*;
     use crate::{
+        block::{
+            compression::NsPayloadFormat,
+            ordering::{OrderingOption, TxOrdering},
+            BuildContext,
+        },
         catchup::mock::MockStateCatchup,
         l1_client::L1Client,
         state::{validate_and_apply_proposal, BlockMerkleTree, Delta, FeeMerkleTree},
-        NodeState,
+        ChainConfigUpgrade, NodeState, Payload, Transaction,
     };
     use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
     use ethers::{
         types::{Address, RecoveryMessage, U256},
         utils::Anvil,
     };
+    use itertools::Either;
 
     #[derive(Debug, Default)]
     #[must_use]
@@ -454,7 +756,9 @@ mod test_headers {
                 self.timestamp,
                 &validated_state,
                 genesis.instance_state.builder_address,
+                Default::default(),
                 genesis.instance_state.chain_config,
+                HeaderVersion::V1,
             );
             assert_eq!(header.height, parent.height + 1);
             assert_eq!(header.timestamp, self.expected_timestamp);
@@ -626,7 +930,7 @@ mod test_headers {
         pub validated_state: ValidatedState,
         pub leaf: Leaf,
         pub header: Header,
-        pub ns_table: NameSpaceTable<TxTableEntryWord>,
+        pub ns_table: NameSpaceTable,
     }
 
     impl Default for GenesisForTest {
@@ -646,6 +950,28 @@ mod test_headers {
         }
     }
 
+    #[test]
+    fn test_genesis_header_from_genesis_file() {
+        let l1_finalized = L1BlockInfo {
+            number: 42,
+            timestamp: 1000.into(),
+            ..Default::default()
+        };
+        let instance_state = NodeState::mock().with_genesis_header(GenesisHeader {
+            timestamp: 1234,
+            l1_finalized: Some(l1_finalized),
+        });
+        let header = Leaf::genesis(&instance_state).get_block_header().clone();
+        assert_eq!(header.height, 0);
+        assert_eq!(header.timestamp, 1234);
+        assert_eq!(header.l1_head, 42);
+        assert_eq!(header.l1_finalized, Some(l1_finalized));
+
+        // The default genesis is unchanged.
+        assert_eq!(GenesisForTest::default().header.timestamp, 0);
+        assert_eq!(GenesisForTest::default().header.l1_finalized, None);
+    }
+
     #[test]
     fn test_validate_proposal_error_cases() {
         let genesis = GenesisForTest::default();
@@ -668,6 +994,7 @@ mod test_headers {
         // Pass a different chain config to trigger a chain config validation error.
         let result = validate_and_apply_proposal(
             ChainConfig::new(U256::zero(), 0u64, U256::zero()),
+            HeaderVersion::V1,
             &mut validated_state,
             &mut delta,
             &parent_leaf,
@@ -680,6 +1007,7 @@ mod test_headers {
         // Advance `proposal.height` to trigger validation error.
         let result = validate_and_apply_proposal(
             genesis.instance_state.chain_config,
+            HeaderVersion::V1,
             &mut validated_state,
             &mut delta,
             &parent_leaf,
@@ -696,6 +1024,7 @@ mod test_headers {
         proposal.height += 1;
         let result = validate_and_apply_proposal(
             genesis.instance_state.chain_config,
+            HeaderVersion::V1,
             &mut validated_state,
             &mut delta,
             &parent_leaf,
@@ -707,6 +1036,165 @@ mod test_headers {
         assert!(format!("{}", result.root_cause()).contains("Invalid Block Root Error"));
     }
 
+    #[test]
+    fn test_validate_proposal_namespace_limit() {
+        let genesis = GenesisForTest::default();
+        let mut validated_state = genesis.validated_state.clone();
+        let mut delta = Delta::default();
+
+        // Allow each namespace a quarter of the block.
+        let chain_config = genesis
+            .instance_state
+            .chain_config
+            .with_max_namespace_share_bps(2500);
+        let max_namespace_bytes = chain_config.max_namespace_bytes().unwrap() as usize;
+
+        // Build a payload which ignores the limit.
+        let txs = [
+            Transaction::new(1u64.into(), vec![0; max_namespace_bytes]),
+            Transaction::new(2u64.into(), vec![0; 1]),
+        ];
+        let payload = Payload::from_txs(txs, &ChainConfig::default()).unwrap();
+
+        // Only V2 headers can carry a namespace limit.
+        let mut proposal = Header::from_fields(HeaderVersion::V2, (*genesis.header).clone());
+        proposal.chain_config = chain_config.into();
+        proposal.height += 1;
+        proposal.ns_table = payload.get_ns_table().clone();
+
+        let result = validate_and_apply_proposal(
+            chain_config,
+            HeaderVersion::V2,
+            &mut validated_state,
+            &mut delta,
+            &genesis.leaf,
+            &proposal,
+            vec![],
+        )
+        .unwrap_err();
+        assert!(format!("{}", result.root_cause()).starts_with("Namespace Too Large Error:"));
+    }
+
+    #[test]
+    fn test_validate_proposal_insufficient_fee() {
+        let genesis = GenesisForTest::default();
+        let mut validated_state = genesis.validated_state.clone();
+        let mut delta = Delta::default();
+
+        // Charge a base fee for every byte of every namespace.
+        let chain_config = ChainConfig::new(1, 10240, 1);
+        let txs = [
+            Transaction::new(1u64.into(), vec![0; 10]),
+            Transaction::new(2u64.into(), vec![0; 20]),
+        ];
+        let payload = Payload::from_txs(txs, &chain_config).unwrap();
+
+        let mut proposal = genesis.header.clone();
+        proposal.chain_config = chain_config.into();
+        proposal.height += 1;
+        proposal.ns_table = payload.get_ns_table().clone();
+
+        // The builder pays for only one of the namespaces.
+        let ns_bytes = proposal.ns_table.namespace_byte_len(0) as u64;
+        proposal.fee_info = FeeInfo::new(
+            proposal.fee_info.account(),
+            chain_config.namespace_fee(ns_bytes),
+        );
+
+        let result = validate_and_apply_proposal(
+            chain_config,
+            HeaderVersion::V1,
+            &mut validated_state,
+            &mut delta,
+            &genesis.leaf,
+            &proposal,
+            vec![],
+        )
+        .unwrap_err();
+        assert!(format!("{}", result.root_cause()).starts_with("Insufficient Fee Error:"));
+    }
+
+    #[test]
+    fn test_validate_proposal_namespace_order() {
+        let genesis = GenesisForTest::default();
+        let mut validated_state = genesis.validated_state.clone();
+        let mut delta = Delta::default();
+
+        // A V1 namespace table which claims to be sorted by namespace ID, but is not.
+        let ns_table = NameSpaceTable::from_namespace_entries(
+            vec![
+                (2u64.into(), 10, NsPayloadFormat::Raw),
+                (1u64.into(), 20, NsPayloadFormat::Raw),
+            ],
+            PayloadVersion::V1,
+            TxOrdering::NamespaceId,
+        )
+        .unwrap();
+        let mut fields = (*genesis.header).clone();
+        fields.height += 1;
+        fields.ns_table = ns_table;
+        let proposal = Header::from_fields(HeaderVersion::V2, fields);
+
+        let result = validate_and_apply_proposal(
+            genesis.instance_state.chain_config,
+            HeaderVersion::V2,
+            &mut validated_state,
+            &mut delta,
+            &genesis.leaf,
+            &proposal,
+            vec![],
+        )
+        .unwrap_err();
+        assert!(format!("{}", result.root_cause()).starts_with("Invalid Namespace Order:"));
+    }
+
+    #[test]
+    fn test_validate_proposal_arrival_order() {
+        setup_logging();
+        setup_backtrace();
+
+        // A node which orders transactions by arrival, on a chain upgraded to V2 headers right
+        // after genesis.
+        let instance_state = NodeState::mock()
+            .with_header_upgrades([HeaderUpgrade {
+                height: 1,
+                version: HeaderVersion::V2,
+            }])
+            .with_build_context(BuildContext::new(false).with_ordering(OrderingOption::Arrival));
+        instance_state.build_context().record_decided(0);
+        let genesis = GenesisForTest::default();
+
+        let txs = [3u64, 1, 2].map(|ns_id| Transaction::new(ns_id.into(), vec![ns_id as u8]));
+        let payload = instance_state.build_payload(txs).unwrap();
+        let ns_table = payload.get_ns_table().clone();
+        assert_eq!(ns_table.version(), PayloadVersion::V1);
+        assert_eq!(ns_table.ordering(), TxOrdering::Arrival);
+        let ns_ids = (0..ns_table.len())
+            .map(|ns_index| u64::from(ns_table.get_table_entry(ns_index).0))
+            .collect::<Vec<_>>();
+        assert_eq!(ns_ids, [3, 1, 2]);
+
+        // Validators accept the block, although its namespaces are not sorted.
+        let mut validated_state = genesis.validated_state.clone();
+        let mut block_merkle_tree = validated_state.block_merkle_tree.clone();
+        block_merkle_tree.push(genesis.header.commit()).unwrap();
+        let mut fields = (*genesis.header).clone();
+        fields.height += 1;
+        fields.ns_table = ns_table;
+        fields.block_merkle_tree_root = block_merkle_tree.commitment();
+        let proposal = Header::from_fields(HeaderVersion::V2, fields);
+        validate_and_apply_proposal(
+            instance_state.chain_config(proposal.height),
+            instance_state.header_version(proposal.height),
+            &mut validated_state,
+            &mut Delta::default(),
+            &genesis.leaf,
+            &proposal,
+            vec![],
+        )
+        .unwrap();
+    }
+
     #[async_std::test]
     async fn test_validate_proposal_success() {
         setup_logging();
@@ -773,6 +1261,7 @@ mod test_headers {
         let mut delta = Delta::default();
         validate_and_apply_proposal(
             genesis.instance_state.chain_config,
+            HeaderVersion::V1,
             &mut proposal_state,
             &mut delta,
             &parent_leaf,
@@ -786,6 +1275,243 @@ mod test_headers {
         );
     }
 
+    #[async_std::test]
+    async fn test_chain_config_upgrade() {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = Anvil::new().block_time(1u32).spawn();
+        let genesis = GenesisForTest::default();
+        let upgraded = ChainConfig::new(U256::from(35353), 20480, 1);
+        let instance_state = NodeState::mock()
+            .with_l1(L1Client::new(
+                anvil.endpoint().parse().unwrap(),
+                Address::default(),
+            ))
+            .with_chain_config_upgrades([ChainConfigUpgrade {
+                height: 1,
+                chain_config: upgraded,
+            }]);
+        assert_eq!(
+            instance_state.chain_config(0),
+            genesis.instance_state.chain_config
+        );
+        assert_eq!(instance_state.chain_config(1), upgraded);
+        assert_eq!(instance_state.chain_config(100), upgraded);
+
+        // The genesis header still uses the genesis chain config.
+        let genesis_header = Leaf::genesis(&instance_state).get_block_header().clone();
+        assert_eq!(genesis_header, genesis.header);
+
+        // The first block after genesis switches to the upgraded chain config.
+        let proposal = Header::new(
+            &genesis.validated_state,
+            &instance_state,
+            &genesis.leaf,
+            genesis.header.payload_commitment,
+            genesis.ns_table,
+        )
+        .await;
+        assert_eq!(proposal.height, 1);
+        assert_eq!(proposal.chain_config, upgraded.into());
+
+        // Validators expect the upgraded chain config at this height, and nothing else.
+        let mut delta = Delta::default();
+        let err = validate_and_apply_proposal(
+            instance_state.chain_config(0),
+            HeaderVersion::V1,
+            &mut genesis.validated_state.clone(),
+            &mut delta,
+            &genesis.leaf,
+            &proposal,
+            vec![],
+        )
+        .unwrap_err();
+        assert!(format!("{}", err.root_cause()).starts_with("Invalid Chain Config:"));
+        validate_and_apply_proposal(
+            instance_state.chain_config(proposal.height),
+            HeaderVersion::V1,
+            &mut genesis.validated_state.clone(),
+            &mut delta,
+            &genesis.leaf,
+            &proposal,
+            vec![],
+        )
+        .unwrap();
+
+        // A header carrying only the commitment of the chain config is just as valid.
+        let mut commitment_only = proposal.clone();
+        commitment_only.chain_config = upgraded.commit().into();
+        assert_eq!(commitment_only.commit(), proposal.commit());
+        validate_and_apply_proposal(
+            instance_state.chain_config(proposal.height),
+            HeaderVersion::V1,
+            &mut genesis.validated_state.clone(),
+            &mut delta,
+            &genesis.leaf,
+            &commitment_only,
+            vec![],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_header_version_upgrade() {
+        setup_logging();
+        setup_backtrace();
+
+        let genesis = GenesisForTest::default();
+        let instance_state = NodeState::mock().with_header_upgrades([HeaderUpgrade {
+            height: 1,
+            version: HeaderVersion::V2,
+        }]);
+        assert_eq!(instance_state.header_version(0), HeaderVersion::V1);
+        assert_eq!(instance_state.header_version(1), HeaderVersion::V2);
+        assert_eq!(instance_state.header_version(100), HeaderVersion::V2);
+
+        // The genesis header is unaffected by a later upgrade.
+        let genesis_header = Leaf::genesis(&instance_state).get_block_header().clone();
+        assert_eq!(genesis_header, genesis.header);
+        assert_eq!(genesis_header.version(), HeaderVersion::V1);
+
+        // Validators reject a header of the old version after the upgrade.
+        let mut proposal = genesis.header.clone();
+        proposal.height = 1;
+        let err = validate_and_apply_proposal(
+            instance_state.chain_config(1),
+            instance_state.header_version(1),
+            &mut genesis.validated_state.clone(),
+            &mut Delta::default(),
+            &genesis.leaf,
+            &proposal,
+            vec![],
+        )
+        .unwrap_err();
+        assert!(format!("{}", err.root_cause()).starts_with("Invalid Header Version:"));
+
+        // A chain can also start with the new version.
+        let instance_state = NodeState::mock().with_header_upgrades([HeaderUpgrade {
+            height: 0,
+            version: HeaderVersion::V2,
+        }]);
+        let genesis_header = Leaf::genesis(&instance_state).get_block_header().clone();
+        assert_eq!(genesis_header.version(), HeaderVersion::V2);
+        assert_eq!(*genesis_header, *genesis.header);
+        assert_ne!(genesis_header.commit(), genesis.header.commit());
+    }
+
+    /// The binary layout of headers before they were versioned and before chain configs could
+    /// limit namespaces.
+    #[derive(Serialize)]
+    struct LegacyHeader<'a> {
+        chain_config: Either<LegacyChainConfig, Commitment<ChainConfig>>,
+        height: u64,
+        timestamp: u64,
+        l1_head: u64,
+        l1_finalized: &'a Option<L1BlockInfo>,
+        payload_commitment: &'a VidCommitment,
+        ns_table: &'a NameSpaceTable,
+        block_merkle_tree_root: &'a BlockMerkleCommitment,
+        fee_merkle_tree_root: &'a FeeMerkleCommitment,
+        builder_signature: &'a Option<types::Signature>,
+        fee_info: &'a FeeInfo,
+    }
+
+    #[derive(Serialize)]
+    struct LegacyChainConfig {
+        chain_id: U256,
+        max_block_size: u64,
+        base_fee: U256,
+    }
+
+    impl<'a> LegacyHeader<'a> {
+        fn new(
+            chain_config: Either<LegacyChainConfig, Commitment<ChainConfig>>,
+            fields: &'a HeaderFields,
+        ) -> Self {
+            Self {
+                chain_config,
+                height: fields.height,
+                timestamp: fields.timestamp,
+                l1_head: fields.l1_head,
+                l1_finalized: &fields.l1_finalized,
+                payload_commitment: &fields.payload_commitment,
+                ns_table: &fields.ns_table,
+                block_merkle_tree_root: &fields.block_merkle_tree_root,
+                fee_merkle_tree_root: &fields.fee_merkle_tree_root,
+                builder_signature: &fields.builder_signature,
+                fee_info: &fields.fee_info,
+            }
+        }
+    }
+
+    #[test]
+    fn test_header_serialization() {
+        let mut fields: HeaderFields = (*GenesisForTest::default().header).clone();
+        let chain_config = ChainConfig::new(35353u16, 10240, 3u64);
+        fields.chain_config = chain_config.into();
+        let v1 = Header::from_fields(HeaderVersion::V1, fields.clone());
+        let v2 = Header::from_fields(HeaderVersion::V2, fields.clone());
+        let mut commitment_only = v1.clone();
+        commitment_only.chain_config = commitment_only.chain_config.commit().into();
+
+        // V1 headers serialize exactly as unversioned headers did.
+        assert_eq!(
+            serde_json::to_value(&v1).unwrap(),
+            serde_json::to_value(&*v1).unwrap()
+        );
+        assert_eq!(serde_json::to_value(&v1).unwrap().get("version"), None);
+
+        // Headers encoded before chain configs could limit namespaces still decode, and encode
+        // to the same bytes.
+        let legacy_config = LegacyChainConfig {
+            chain_id: 35353.into(),
+            max_block_size: 10240,
+            base_fee: 3.into(),
+        };
+        for (legacy, header) in [
+            (LegacyHeader::new(Either::Left(legacy_config), &fields), &v1),
+            (
+                LegacyHeader::new(Either::Right(chain_config.commit()), &fields),
+                &commitment_only,
+            ),
+        ] {
+            let bytes = bincode::serialize(&legacy).unwrap();
+            assert_eq!(bincode::deserialize::<Header>(&bytes).unwrap(), *header);
+            assert_eq!(bincode::serialize(header).unwrap(), bytes);
+        }
+
+        // Later versions carry their version number.
+        assert_eq!(serde_json::to_value(&v2).unwrap()["version"], 2);
+
+        // Only later versions can carry a namespace limit.
+        let mut limited = v2.clone();
+        limited.chain_config = chain_config.with_max_namespace_share_bps(2_500).into();
+        let limited_v1 = Header::from_fields(HeaderVersion::V1, (*limited).clone());
+        serde_json::to_value(&limited_v1).unwrap_err();
+        bincode::serialize(&limited_v1).unwrap_err();
+        let mut json = serde_json::to_value(&limited).unwrap();
+        json.as_object_mut().unwrap().remove("version");
+        serde_json::from_value::<Header>(json).unwrap_err();
+
+        for header in [v1, v2, commitment_only, limited] {
+            let json = serde_json::to_string(&header).unwrap();
+            assert_eq!(serde_json::from_str::<Header>(&json).unwrap(), header);
+            let bytes = bincode::serialize(&header).unwrap();
+            assert_eq!(bincode::deserialize::<Header>(&bytes).unwrap(), header);
+        }
+
+        // An explicit version 1 is also accepted.
+        let mut json = serde_json::to_value(&fields).unwrap();
+        json["version"] = 1.into();
+        assert_eq!(
+            serde_json::from_value::<Header>(json.clone()).unwrap(),
+            Header::from_fields(HeaderVersion::V1, fields)
+        );
+        json["version"] = 3.into();
+        serde_json::from_value::<Header>(json).unwrap_err();
+    }
+
     // These two tests are here for reference.
     #[test]
     fn verify_header_signature_easy_way() {
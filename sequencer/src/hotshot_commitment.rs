@@ -1,25 +1,77 @@
-use anyhow::anyhow;
-use async_std::{sync::Arc, task::sleep};
+use anyhow::{anyhow, ensure};
+use async_std::{
+    sync::{Arc, RwLock},
+    task::sleep,
+};
 use async_trait::async_trait;
-use contract_bindings::hot_shot::{HotShot, HotShotErrors, Qc};
+use clap::ValueEnum;
+use contract_bindings::{
+    hot_shot::{HotShot, HotShotErrors, Qc},
+    light_client::{LightClient, LightClientState},
+};
 use ethers::prelude::*;
 use futures::{
     future,
     stream::{self, StreamExt},
 };
 use hotshot_query_service::{availability::LeafQueryData, types::HeightIndexed};
+use hotshot_types::traits::metrics::Metrics;
 use rand::SeedableRng;
 use rand_chacha::ChaChaRng;
 use rand_distr::Distribution;
-use sequencer_utils::{commitment_to_u256, contract_send, init_signer, Signer};
+use sequencer_utils::{
+    commitment_to_u256, init_signer,
+    l1_sender::{L1Sender, L1SenderMetrics, L1SenderOptions},
+    Signer,
+};
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::time::Duration;
 use surf_disco::Url;
 use versioned_binary_serialization::version::StaticVersionType;
 
-use crate::{Header, SeqTypes};
+use crate::{
+    state::BlockMerkleCommitment,
+    state_signature::{block_comm_root, field_to_u256},
+    Header, SeqTypes,
+};
 
 const RETRY_DELAY: Duration = Duration::from_secs(1);
+const LIGHT_CLIENT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// What the commitment task commits to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum CommitmentMode {
+    /// Post the commitment of every block to the HotShot contract.
+    ///
+    /// The HotShot contract does not verify quorum certificates yet, so this costs L1 gas for every
+    /// block without making the commitments any more trustworthy.
+    #[default]
+    HotShot,
+    /// Publish the block Merkle tree root verified by the light client contract.
+    ///
+    /// The light client contract already stores a commitment to the block Merkle tree of each
+    /// state it verifies, so nothing is posted to L1. Any historical header can be proven against
+    /// the published root.
+    LightClient,
+}
+
+/// A block Merkle tree root verified by the light client contract.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifiedBlockRoot {
+    /// Block height of the finalized light client state.
+    pub height: u64,
+    /// Root of the block Merkle tree committed to by that state.
+    ///
+    /// This tree contains every header below `height`.
+    pub block_merkle_tree_root: BlockMerkleCommitment,
+    /// The light client state read from the contract.
+    #[serde(skip)]
+    pub light_client_state: LightClientState,
+}
+
+/// The latest verified block Merkle tree root, shared with the commitment task's HTTP server.
+pub type LatestBlockRoot = Arc<RwLock<Option<VerifiedBlockRoot>>>;
 
 type HotShotClient<Ver> = surf_disco::Client<hotshot_query_service::Error, Ver>;
 
@@ -58,10 +110,23 @@ pub struct CommitmentTaskOptions {
 
     /// If specified, sequencing attempts will be delayed by duration sampled from an exponential distribution with mean DELAY.
     pub delay: Option<Duration>,
+
+    /// Options for sending commitments to layer 1.
+    pub l1_sender: L1SenderOptions,
 }
 
 /// main logic for the commitment task, which sync the latest blocks from HotShot to L1 contracts
-pub async fn run_hotshot_commitment_task<Ver: StaticVersionType>(opt: &CommitmentTaskOptions) {
+///
+/// Metrics of the L1 transactions sent by the task are registered in `metrics`.
+pub async fn run_hotshot_commitment_task<Ver: StaticVersionType>(
+    opt: &CommitmentTaskOptions,
+    metrics: &dyn Metrics,
+) {
+    tracing::warn!(
+        "the HotShot contract does not verify quorum certificates, consider the light client \
+         commitment mode instead"
+    );
+
     // init a client connecting to HotShot query service
     let hotshot = HotShotClient::<Ver>::builder(
         opt.query_service_url
@@ -82,13 +147,80 @@ pub async fn run_hotshot_commitment_task<Ver: StaticVersionType>(opt: &Commitmen
     .map(Arc::new)
     .unwrap();
     let contract = HotShot::new(opt.hotshot_address, signer.clone());
+    let sender = L1Sender::new(
+        signer,
+        opt.l1_sender.clone(),
+        Arc::new(L1SenderMetrics::new(metrics)),
+    );
 
-    sequence(hotshot, contract, opt.delay).await;
+    sequence(hotshot, contract, sender, opt.delay).await;
+}
+
+/// Follow the states finalized by the light client contract at `light_client_address`.
+///
+/// Instead of posting block commitments to L1, this keeps `latest` up to date with the block Merkle
+/// tree root of the latest finalized light client state, after checking it against the header
+/// served by the query service.
+pub async fn run_light_client_commitment_task<Ver: StaticVersionType>(
+    opt: &CommitmentTaskOptions,
+    light_client_address: Address,
+    latest: LatestBlockRoot,
+) {
+    let hotshot = HotShotClient::<Ver>::builder(
+        opt.query_service_url
+            .clone()
+            .expect("query service URL must be specified"),
+    )
+    .set_timeout(Some(opt.request_timeout))
+    .build();
+    hotshot.connect(None).await;
+
+    let provider =
+        Provider::<Http>::try_from(opt.l1_provider.to_string()).expect("invalid L1 provider URL");
+    let contract = LightClient::new(light_client_address, Arc::new(provider));
+
+    loop {
+        match sync_light_client_root(&hotshot, &contract).await {
+            Ok(root) => {
+                let mut latest = latest.write().await;
+                if latest.as_ref() != Some(&root) {
+                    tracing::info!(
+                        "light client verified block Merkle root at height {}",
+                        root.height
+                    );
+                    *latest = Some(root);
+                }
+            }
+            Err(err) => tracing::error!("error following the light client contract: {err:#}"),
+        }
+        sleep(LIGHT_CLIENT_POLL_INTERVAL).await;
+    }
+}
+
+/// Read the block Merkle tree root of the latest finalized light client state.
+async fn sync_light_client_root<M: Middleware + 'static>(
+    hotshot: &impl HotShotDataSource,
+    contract: &LightClient<M>,
+) -> anyhow::Result<VerifiedBlockRoot> {
+    let state = contract.get_finalized_state().call().await?;
+    let leaf = hotshot.get_leaf(state.block_height).await?;
+    let root = leaf.leaf().get_block_header().block_merkle_tree_root;
+    ensure!(
+        field_to_u256(block_comm_root(&root)?) == state.block_comm_root,
+        "block Merkle tree root of header {} does not match the light client contract",
+        state.block_height
+    );
+    Ok(VerifiedBlockRoot {
+        height: state.block_height,
+        block_merkle_tree_root: root,
+        light_client_state: state,
+    })
 }
 
 async fn sequence<Ver: StaticVersionType>(
     hotshot: HotShotClient<Ver>,
     contract: HotShot<Signer>,
+    sender: L1Sender<Signer>,
     delay: Option<Duration>,
 ) {
     // Get the maximum number of blocks the contract will allow at a time.
@@ -107,7 +239,7 @@ async fn sequence<Ver: StaticVersionType>(
     let mut soft_block_limit = hard_block_limit;
     let mut rng = ChaChaRng::from_entropy();
     loop {
-        if let Err(sync_err) = sync_with_l1(soft_block_limit, &hotshot, &contract).await {
+        if let Err(sync_err) = sync_with_l1(soft_block_limit, &hotshot, &contract, &sender).await {
             match sync_err {
                 SyncError::Other(err) => {
                     tracing::error!("error synchronizing with HotShot contract: {err}");
@@ -184,6 +316,7 @@ async fn sync_with_l1(
     max_blocks: usize,
     hotshot: &impl HotShotDataSource,
     contract: &HotShot<Signer>,
+    sender: &L1Sender<Signer>,
 ) -> Result<(), SyncError> {
     let contract_block_height = contract
         .block_height()
@@ -249,7 +382,8 @@ async fn sync_with_l1(
     // error. We will retry, and may end up changing the transaction we send if the contract state
     // has changed, which is one possible cause of the transaction failure. This can happen, for
     // example, if there are multiple commitment tasks racing.
-    contract_send::<_, _, HotShotErrors>(&txn)
+    sender
+        .send_call::<_, HotShotErrors>(&txn)
         .await
         .map_err(|e| SyncError::TransactionFailed { err: e, num_leaves })?;
 
@@ -386,7 +520,12 @@ mod test {
         tracing::info!("sequencing batches: {:?}", data.leaves);
 
         // Connect to the HotShot contract with the expected L1 client.
-        let hotshot = HotShot::new(l1.hotshot.address(), adaptor_l1_signer);
+        let hotshot = HotShot::new(l1.hotshot.address(), adaptor_l1_signer.clone());
+        let sender = Arc::new(L1Sender::new(
+            adaptor_l1_signer,
+            Default::default(),
+            Default::default(),
+        ));
 
         // Ensure the transaction we're going to execute is less than the Geth RPC size limit.
         let txn = build_sequence_batches_txn(
@@ -399,7 +538,9 @@ mod test {
         assert!(size < 131072);
 
         // Sequence them in the HotShot contract.
-        sync_with_l1(num_batches, &data, &hotshot).await.unwrap();
+        sync_with_l1(num_batches, &data, &hotshot, &sender)
+            .await
+            .unwrap();
 
         // Check the NewBatches event.
         let (event, meta) = wait_for_new_batches(&l1, l1_initial_block.as_u64()).await;
@@ -454,10 +595,15 @@ mod test {
         data.leaves.push(Some(mock_leaf(0, &node_state)));
 
         // Connect to the HotShot contract with the expected L1 client.
-        let hotshot = HotShot::new(l1.hotshot.address(), adaptor_l1_signer);
+        let hotshot = HotShot::new(l1.hotshot.address(), adaptor_l1_signer.clone());
+        let sender = Arc::new(L1Sender::new(
+            adaptor_l1_signer,
+            Default::default(),
+            Default::default(),
+        ));
 
         // Sequence them in the HotShot contract.
-        sync_with_l1(1, &data, &hotshot).await.unwrap();
+        sync_with_l1(1, &data, &hotshot, &sender).await.unwrap();
 
         // Check the NewBatches event.
         let (event, meta) = wait_for_new_batches(&l1, from_block.as_u64()).await;
@@ -468,7 +614,8 @@ mod test {
         let fut = {
             let data = data.clone();
             let hotshot = hotshot.clone();
-            spawn(async move { sync_with_l1(1, &data, &hotshot).await })
+            let sender = sender.clone();
+            spawn(async move { sync_with_l1(1, &data, &hotshot, &sender).await })
         };
         // Sleep for a few seconds and make sure nothing happened.
         sleep(Duration::from_secs(3)).await;
@@ -477,7 +624,7 @@ mod test {
 
         // Once a new batch is available, we can sequence it.
         data.leaves.push(Some(mock_leaf(1, &node_state)));
-        sync_with_l1(1, &data, &hotshot).await.unwrap();
+        sync_with_l1(1, &data, &hotshot, &sender).await.unwrap();
         let (event, _) = wait_for_new_batches(&l1, from_block.as_u64()).await;
         assert_eq!(event.first_block_number.as_u64(), 1);
 
@@ -522,16 +669,21 @@ mod test {
             .extend([None, Some(mock_leaf(1, &node_state)), None]);
 
         // Connect to the HotShot contract with the expected L1 client.
-        let hotshot = HotShot::new(l1.hotshot.address(), adaptor_l1_signer);
+        let hotshot = HotShot::new(l1.hotshot.address(), adaptor_l1_signer.clone());
+        let sender = Arc::new(L1Sender::new(
+            adaptor_l1_signer,
+            Default::default(),
+            Default::default(),
+        ));
 
         // If the first leaf is missing, we cannot make any progress, and sync should fail.
-        sync_with_l1(3, &data, &hotshot).await.unwrap_err();
+        sync_with_l1(3, &data, &hotshot, &sender).await.unwrap_err();
 
         // If the first leaf is present but subsequent leaves are missing, we should sequence the
         // leaves that are available.
 
         data.leaves[0] = Some(mock_leaf(0, &node_state));
-        sync_with_l1(3, &data, &hotshot).await.unwrap();
+        sync_with_l1(3, &data, &hotshot, &sender).await.unwrap();
 
         // Check the NewBatches event.
         let event = wait_for_new_batches(&l1, l1_initial_block.as_u64()).await.0;
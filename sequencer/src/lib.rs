@@ -3,6 +3,7 @@ pub mod block;
 pub mod catchup;
 mod chain_config;
 pub mod context;
+pub mod genesis;
 mod header;
 pub mod hotshot_commitment;
 pub mod options;
@@ -11,18 +12,21 @@ pub mod state_signature;
 use anyhow::Context;
 use async_std::sync::RwLock;
 use async_trait::async_trait;
-use block::entry::TxTableEntryWord;
+use block::{ordering::OrderingOption, BuildContext};
 use catchup::{StateCatchup, StatePeers};
+use commit::Committable;
 use context::SequencerContext;
 use ethers::{
     core::k256::ecdsa::SigningKey,
     signers::{coins_bip39::English, MnemonicBuilder, Signer as _, Wallet},
     types::{Address, U256},
 };
+use genesis::{Genesis, GenesisHeader};
 
 // Should move `STAKE_TABLE_CAPACITY` in the sequencer repo when we have variate stake table support
 
 use l1_client::L1Client;
+use state::{BaseFee, BuilderFee, FeeAccount};
 
 use state_signature::static_stake_table_commitment;
 use url::Url;
@@ -78,8 +82,8 @@ use {
 };
 
 pub use block::payload::Payload;
-pub use chain_config::ChainConfig;
-pub use header::Header;
+pub use chain_config::{ChainConfig, ChainConfigUpgrade, ResolvableChainConfig};
+pub use header::{Header, HeaderFields, HeaderUpgrade, HeaderVersion};
 pub use l1_client::L1BlockInfo;
 pub use options::Options;
 pub use state::ValidatedState;
@@ -159,10 +163,15 @@ impl<P: SequencerPersistence> Storage<SeqTypes> for Arc<RwLock<P>> {
 #[derive(Debug, Clone)]
 pub struct NodeState {
     chain_config: ChainConfig,
+    chain_config_upgrades: BTreeMap<u64, ChainConfig>,
+    header_upgrades: BTreeMap<u64, HeaderVersion>,
     l1_client: L1Client,
     peers: Arc<dyn StateCatchup>,
     genesis_state: ValidatedState,
+    genesis_header: GenesisHeader,
     builder_address: Wallet<SigningKey>,
+    builder_fee: Arc<dyn BuilderFee>,
+    build_context: Arc<BuildContext>,
 }
 
 impl NodeState {
@@ -174,10 +183,15 @@ impl NodeState {
     ) -> Self {
         Self {
             chain_config,
+            chain_config_upgrades: Default::default(),
+            header_upgrades: Default::default(),
             l1_client,
             peers: Arc::new(catchup),
             genesis_state: Default::default(),
+            genesis_header: Default::default(),
             builder_address,
+            builder_fee: Arc::new(BaseFee),
+            build_context: Default::default(),
         }
     }
 
@@ -201,11 +215,102 @@ impl NodeState {
         self
     }
 
+    /// Set the policy deciding the fee this node's builder pays for the blocks it proposes.
+    pub fn with_builder_fee(mut self, builder_fee: impl BuilderFee + 'static) -> Self {
+        self.builder_fee = Arc::new(builder_fee);
+        self
+    }
+
+    /// Set how this node builds the payloads of new blocks.
+    pub fn with_build_context(mut self, build_context: BuildContext) -> Self {
+        self.build_context = Arc::new(build_context);
+        self
+    }
+
+    /// How this node builds the payloads of new blocks.
+    pub fn build_context(&self) -> &Arc<BuildContext> {
+        &self.build_context
+    }
+
     pub fn with_genesis(mut self, state: ValidatedState) -> Self {
         self.genesis_state = state;
         self
     }
 
+    pub fn with_genesis_header(mut self, header: GenesisHeader) -> Self {
+        self.genesis_header = header;
+        self
+    }
+
+    /// Schedule chain config upgrades.
+    ///
+    /// Each upgrade replaces the chain config starting at its height. If two upgrades have the same
+    /// height, the last one wins.
+    pub fn with_chain_config_upgrades(
+        mut self,
+        upgrades: impl IntoIterator<Item = ChainConfigUpgrade>,
+    ) -> Self {
+        self.chain_config_upgrades.extend(
+            upgrades
+                .into_iter()
+                .map(|upgrade| (upgrade.height, upgrade.chain_config)),
+        );
+        self
+    }
+
+    /// All chain configs this node knows of: the genesis config and every scheduled upgrade.
+    pub fn known_chain_configs(&self) -> Vec<ChainConfig> {
+        std::iter::once(self.chain_config)
+            .chain(self.chain_config_upgrades.values().copied())
+            .collect()
+    }
+
+    /// The chain config in force for the block at `height`.
+    pub fn chain_config(&self, height: u64) -> ChainConfig {
+        self.chain_config_upgrades
+            .range(..=height)
+            .next_back()
+            .map_or(self.chain_config, |(_, chain_config)| *chain_config)
+    }
+
+    /// The chain configs which may be in force for a block at `height` or later.
+    pub fn chain_configs_from(&self, height: u64) -> impl Iterator<Item = ChainConfig> + '_ {
+        std::iter::once(self.chain_config(height)).chain(
+            self.chain_config_upgrades
+                .range(height + 1..)
+                .map(|(_, chain_config)| *chain_config),
+        )
+    }
+
+    /// Schedule header version upgrades.
+    ///
+    /// Each upgrade switches the header version starting at its height. If two upgrades have the
+    /// same height, the last one wins.
+    pub fn with_header_upgrades(
+        mut self,
+        upgrades: impl IntoIterator<Item = HeaderUpgrade>,
+    ) -> Self {
+        self.header_upgrades.extend(
+            upgrades
+                .into_iter()
+                .map(|upgrade| (upgrade.height, upgrade.version)),
+        );
+        self
+    }
+
+    /// The header version in force for the block at `height`.
+    pub fn header_version(&self, height: u64) -> HeaderVersion {
+        self.header_upgrades
+            .range(..=height)
+            .next_back()
+            .map_or(HeaderVersion::V1, |(_, version)| *version)
+    }
+
+    /// The account which pays the fees for blocks built by this node.
+    pub fn builder_account(&self) -> FeeAccount {
+        self.builder_address.address().into()
+    }
+
     fn l1_client(&self) -> &L1Client {
         &self.l1_client
     }
@@ -216,7 +321,7 @@ impl InstanceState for NodeState {}
 impl NodeType for SeqTypes {
     type Time = ViewNumber;
     type BlockHeader = Header;
-    type BlockPayload = Payload<TxTableEntryWord>;
+    type BlockPayload = Payload;
     type SignatureKey = PubKey;
     type Transaction = Transaction;
     type ElectionConfigType = ElectionConfig;
@@ -270,6 +375,10 @@ pub struct BuilderParams {
     pub mnemonic: String,
     pub eth_account_index: u32,
     pub prefunded_accounts: Vec<Address>,
+    /// Compress the namespaces of the blocks this node builds, where that makes them smaller.
+    pub compress_namespaces: bool,
+    /// How this node orders the transactions of the blocks it builds.
+    pub tx_ordering: OrderingOption,
 }
 
 pub struct L1Params {
@@ -285,7 +394,7 @@ pub async fn init_node<P: SequencerPersistence, Ver: StaticVersionType + 'static
     l1_params: L1Params,
     stake_table_capacity: usize,
     bind_version: Ver,
-    chain_config: ChainConfig,
+    mut genesis: Genesis,
 ) -> anyhow::Result<SequencerContext<network::Production, P, Ver>> {
     // Orchestrator client
     let validator_args = ValidatorArgs {
@@ -336,6 +445,18 @@ pub async fn init_node<P: SequencerPersistence, Ver: StaticVersionType + 'static
     };
     let node_index = config.node_index;
 
+    // Make sure we are joining the chain we think we are joining before doing anything else.
+    genesis.check_stake_table(&config.config.known_nodes_with_stake)?;
+    for address in &builder_params.prefunded_accounts {
+        tracing::warn!("Prefunding account {:?} for demo", address);
+        genesis
+            .accounts
+            .insert((*address).into(), U256::max_value().into());
+    }
+    let genesis_commitment = genesis.commit();
+    tracing::info!(%genesis_commitment, "starting from genesis");
+    let peers = StatePeers::<Ver>::from_urls(network_params.state_peers);
+
     // Initialize the push CDN network (and perform the initial connection)
     let cdn_network = PushCdnNetwork::new(
         network_params.cdn_endpoint,
@@ -400,21 +521,25 @@ pub async fn init_node<P: SequencerPersistence, Ver: StaticVersionType + 'static
         .build()?;
     tracing::info!("Builder account address {:?}", wallet.address());
 
-    let mut genesis_state = ValidatedState::default();
-    for address in builder_params.prefunded_accounts {
-        tracing::warn!("Prefunding account {:?} for demo", address);
-        genesis_state.prefund_account(address.into(), U256::max_value().into());
-    }
-
     let l1_client = L1Client::new(l1_params.url, Address::default());
 
     let instance_state = NodeState {
-        chain_config,
+        chain_config: genesis.chain_config,
+        chain_config_upgrades: Default::default(),
+        header_upgrades: Default::default(),
         l1_client,
         builder_address: wallet,
-        genesis_state,
-        peers: Arc::new(StatePeers::<Ver>::from_urls(network_params.state_peers)),
-    };
+        builder_fee: Arc::new(BaseFee),
+        genesis_state: genesis.validated_state(),
+        genesis_header: genesis.header(),
+        peers: Arc::new(peers.clone()),
+        build_context: Arc::new(
+            BuildContext::new(builder_params.compress_namespaces)
+                .with_ordering(builder_params.tx_ordering),
+        ),
+    }
+    .with_chain_config_upgrades(genesis.upgrades)
+    .with_header_upgrades(genesis.header_upgrades);
 
     let mut ctx = SequencerContext::init(
         config.config,
@@ -427,7 +552,9 @@ pub async fn init_node<P: SequencerPersistence, Ver: StaticVersionType + 'static
         stake_table_capacity,
         bind_version,
     )
-    .await?;
+    .await?
+    .with_genesis(genesis_commitment)
+    .check_genesis_with(peers);
     if wait_for_orchestrator {
         ctx = ctx.wait_for_orchestrator(orchestrator_client);
     }
@@ -589,8 +716,9 @@ pub mod testing {
 
             let wallet = Self::builder_wallet(i);
             tracing::info!("node {i} is builder {:x}", wallet.address());
+            let genesis = Genesis::new(ChainConfig::default());
             let node_state = NodeState::new(
-                ChainConfig::default(),
+                genesis.chain_config,
                 L1Client::new(self.anvil.endpoint().parse().unwrap(), Address::default()),
                 wallet,
                 catchup,
@@ -610,6 +738,7 @@ pub mod testing {
             )
             .await
             .unwrap()
+            .with_genesis(genesis.commit())
         }
 
         pub fn builder_wallet(i: usize) -> Wallet<SigningKey> {
@@ -9,7 +9,7 @@ use sequencer::{
     api::{self, data_source::DataSourceOptions},
     init_node,
     options::{Modules, Options},
-    persistence, BuilderParams, ChainConfig, L1Params, NetworkParams,
+    persistence, BuilderParams, L1Params, NetworkParams,
 };
 use versioned_binary_serialization::version::StaticVersionType;
 
@@ -50,13 +50,15 @@ where
 {
     let (private_staking_key, private_state_key) = opt.private_keys()?;
     let stake_table_capacity = opt.stake_table_capacity;
-    let chain_config = ChainConfig::new(opt.chain_id, opt.max_block_size, opt.base_fee);
+    let genesis = opt.genesis()?;
     let l1_params = L1Params {
         url: opt.l1_provider_url,
     };
     let builder_params = BuilderParams {
         mnemonic: opt.eth_mnemonic,
         prefunded_accounts: opt.prefunded_builder_accounts,
+        compress_namespaces: opt.compress_namespaces,
+        tx_ordering: opt.tx_ordering,
         eth_account_index: opt.eth_account_index,
     };
 
@@ -125,7 +127,7 @@ where
                             l1_params,
                             stake_table_capacity,
                             bind_version,
-                            chain_config,
+                            genesis,
                         )
                         .await
                         .unwrap()
@@ -145,12 +147,16 @@ where
                 l1_params,
                 stake_table_capacity,
                 bind_version,
-                chain_config,
+                genesis,
             )
             .await?
         }
     };
 
+    // Check our genesis with our peers before taking part in consensus.
+    // Our own API is up by now, so that peers starting at the same time can check with us.
+    ctx.check_genesis().await?;
+
     // Start doing consensus.
     ctx.start_consensus().await;
     ctx.join().await;
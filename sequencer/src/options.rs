@@ -1,4 +1,4 @@
-use crate::{api, persistence};
+use crate::{api, block::ordering::OrderingOption, genesis::Genesis, persistence, ChainConfig};
 use anyhow::{bail, Context};
 use clap::{error::ErrorKind, Args, FromArgMatches, Parser};
 use cld::ClDuration;
@@ -38,7 +38,14 @@ use url::Url;
 #[derive(Parser, Clone, Debug)]
 pub struct Options {
     /// Unique identifier for this instance of the sequencer network.
-    #[clap(long, env = "ESPRESSO_SEQUENCER_CHAIN_ID", default_value = "0")]
+    ///
+    /// Not allowed with a genesis file, which sets the chain ID.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_CHAIN_ID",
+        default_value = "0",
+        conflicts_with = "genesis_file"
+    )]
     pub chain_id: u16,
 
     /// URL of the HotShot orchestrator.
@@ -151,14 +158,33 @@ pub struct Options {
 
     /// Prefunded the builder accounts. Use for demo purposes only.
     ///
-    /// Comma-separated list of Ethereum addresses.
+    /// Comma-separated list of Ethereum addresses. The accounts are part of the genesis, so every
+    /// node must be given the same list. Not allowed with a genesis file, which lists its own
+    /// accounts.
     #[clap(
         long,
         env = "ESPRESSO_SEQUENCER_PREFUNDED_BUILDER_ACCOUNTS",
-        value_delimiter = ','
+        value_delimiter = ',',
+        conflicts_with = "genesis_file"
     )]
     pub prefunded_builder_accounts: Vec<Address>,
 
+    /// Compress the namespaces of the blocks this node builds, where that makes them smaller.
+    #[clap(long, env = "ESPRESSO_SEQUENCER_COMPRESS_NAMESPACES")]
+    pub compress_namespaces: bool,
+
+    /// How to order the transactions of the blocks this node builds.
+    ///
+    /// The ordering is recorded in the namespace table of each block, once headers are upgraded to
+    /// a version whose payloads record it.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_TX_ORDERING",
+        value_enum,
+        default_value = "namespace-id"
+    )]
+    pub tx_ordering: OrderingOption,
+
     /// Url we will use for RPC communication with L1.
     #[clap(long, env = "ESPRESSO_SEQUENCER_L1_PROVIDER")]
     pub l1_provider_url: Url,
@@ -171,12 +197,45 @@ pub struct Options {
     #[clap(short, long, env = "ESPRESSO_SEQUENCER_STAKE_TABLE_CAPACITY", default_value_t = STAKE_TABLE_CAPACITY)]
     pub stake_table_capacity: usize,
     /// Maximum size in bytes of a block
-    #[clap(long, env = "ESPRESSO_SEQUENCER_MAX_BLOCK_SIZE")]
-    pub max_block_size: u64,
+    ///
+    /// Required unless a genesis file is given, and not allowed with one.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_MAX_BLOCK_SIZE",
+        required_unless_present = "genesis_file",
+        conflicts_with = "genesis_file"
+    )]
+    pub max_block_size: Option<u64>,
 
-    #[clap(long, env = "ESPRESSO_SEQUENCER_BASE_FEE")]
     /// Minimum fee in WEI per byte of payload
-    pub base_fee: U256,
+    ///
+    /// Required unless a genesis file is given, and not allowed with one.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_BASE_FEE",
+        required_unless_present = "genesis_file",
+        conflicts_with = "genesis_file"
+    )]
+    pub base_fee: Option<U256>,
+
+    /// Maximum share of a block, in basis points of the maximum block size, that a single
+    /// namespace may use
+    ///
+    /// Not allowed with a genesis file.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_MAX_NAMESPACE_SHARE_BPS",
+        conflicts_with = "genesis_file"
+    )]
+    pub max_namespace_share_bps: Option<u16>,
+
+    /// Path to the genesis file (TOML, or JSON with a `.json` extension).
+    ///
+    /// If given, the chain config, initial balances, stake table and genesis header are taken from
+    /// this file, and the chain ID, maximum block size, base fee, namespace share and prefunded
+    /// account options must not be given.
+    #[clap(long, env = "ESPRESSO_SEQUENCER_GENESIS_FILE")]
+    pub genesis_file: Option<PathBuf>,
 }
 
 impl Options {
@@ -205,6 +264,21 @@ impl Options {
             bail!("neither key file nor full set of private keys was provided")
         }
     }
+
+    /// The genesis of the chain, either from the genesis file or from the chain config options.
+    pub fn genesis(&self) -> anyhow::Result<Genesis> {
+        if let Some(path) = &self.genesis_file {
+            return Genesis::from_file(path);
+        }
+        let (Some(max_block_size), Some(base_fee)) = (self.max_block_size, self.base_fee) else {
+            bail!("neither genesis file nor full set of chain config options was provided");
+        };
+        let mut chain_config = ChainConfig::new(self.chain_id, max_block_size, base_fee);
+        if let Some(bps) = self.max_namespace_share_bps {
+            chain_config = chain_config.with_max_namespace_share_bps(bps);
+        }
+        Ok(Genesis::new(chain_config))
+    }
 }
 
 #[derive(Clone, Debug, Snafu)]
@@ -8,11 +8,14 @@
 //! an extension that node operators can opt into. This module defines the minimum level of
 //! persistence which is _required_ to run a node.
 
-use crate::{ElectionConfig, Leaf, NodeState, PubKey, SeqTypes, ValidatedState, ViewNumber};
-use anyhow::Context;
+use crate::{
+    catchup::StateCatchup, ChainConfig, ElectionConfig, Leaf, NodeState, PubKey,
+    ResolvableChainConfig, SeqTypes, Transaction, ValidatedState, ViewNumber,
+};
+use anyhow::{ensure, Context};
 use async_std::sync::Arc;
 use async_trait::async_trait;
-use commit::Committable;
+use commit::{Commitment, Committable};
 use hotshot::{
     traits::ValidatedState as _,
     types::{Event, EventType},
@@ -80,12 +83,53 @@ pub trait SequencerPersistence: Send + Sync + 'static {
     /// Load the validated state after block `height`, if available.
     async fn load_validated_state(&self, height: u64) -> anyhow::Result<ValidatedState>;
 
+    /// Load a chain config previously saved with [`save_chain_config`](Self::save_chain_config).
+    async fn load_chain_config(
+        &self,
+        commitment: Commitment<ChainConfig>,
+    ) -> anyhow::Result<Option<ChainConfig>>;
+
+    /// Save a chain config, so it can be looked up by its commitment.
+    async fn save_chain_config(&mut self, chain_config: &ChainConfig) -> anyhow::Result<()>;
+
+    /// Resolve a chain config which may be given only by its commitment.
+    ///
+    /// If the full config is not known locally, it is fetched from `catchup`, verified against the
+    /// commitment and saved for next time.
+    async fn resolve_chain_config(
+        &mut self,
+        chain_config: ResolvableChainConfig,
+        catchup: &dyn StateCatchup,
+    ) -> anyhow::Result<ChainConfig> {
+        if let Some(chain_config) = chain_config.resolve() {
+            return Ok(chain_config);
+        }
+        let commitment = chain_config.commit();
+        if let Some(chain_config) = self
+            .load_chain_config(commitment)
+            .await
+            .context("loading chain config")?
+        {
+            return Ok(chain_config);
+        }
+        tracing::info!(%commitment, "chain config not available locally, fetching from peers");
+        let chain_config = catchup.fetch_chain_config(commitment).await;
+        self.save_chain_config(&chain_config)
+            .await
+            .context("saving chain config")?;
+        Ok(chain_config)
+    }
+
     /// Load the latest known consensus state.
     ///
     /// Returns an initializer to resume HotShot from the latest saved state (or start from genesis,
     /// if there is no saved state).
+    ///
+    /// The chain config of a saved leaf is resolved, fetching it from peers if necessary, and
+    /// checked against the config `state` schedules for its height, so that a node whose genesis
+    /// file is missing an upgrade the chain has gone through fails at startup.
     async fn load_consensus_state(
-        &self,
+        &mut self,
         state: NodeState,
     ) -> anyhow::Result<HotShotInitializer<SeqTypes>> {
         let highest_voted_view = match self
@@ -109,6 +153,17 @@ pub trait SequencerPersistence: Send + Sync + 'static {
         {
             Some(leaf) => {
                 tracing::info!(?leaf, "starting from saved leaf");
+                let height = leaf.get_height();
+                let chain_config = self
+                    .resolve_chain_config(leaf.get_block_header().chain_config, &*state.peers)
+                    .await
+                    .context("resolving chain config of saved leaf")?;
+                let expected = state.chain_config(height);
+                ensure!(
+                    chain_config == expected,
+                    "saved leaf {height} has chain config {chain_config:?}, but {expected:?} is \
+                     scheduled for that height; check the upgrades in the genesis file"
+                );
                 let validated_state = match self.load_validated_state(leaf.get_height()).await {
                     Ok(validated_state) => Some(Arc::new(validated_state)),
                     Err(err) => {
@@ -153,9 +208,25 @@ pub trait SequencerPersistence: Send + Sync + 'static {
     }
 
     /// Update storage based on an event from consensus.
-    async fn handle_event(&mut self, event: &Event<SeqTypes>) {
+    ///
+    /// Chain configs which are not known locally are fetched from `catchup`.
+    async fn handle_event(&mut self, event: &Event<SeqTypes>, catchup: &dyn StateCatchup) {
         if let EventType::Decide { leaf_chain, .. } = &event.event {
             if let Some(LeafInfo { leaf, .. }) = leaf_chain.first() {
+                // Keep full chain configs of decided headers, so we can serve them to peers which
+                // only see their commitments.
+                let chain_config = leaf.get_block_header().chain_config;
+                let res = match chain_config.resolve() {
+                    Some(chain_config) => self.save_chain_config(&chain_config).await,
+                    None => self
+                        .resolve_chain_config(chain_config, catchup)
+                        .await
+                        .map(|_| ()),
+                };
+                if let Err(err) = res {
+                    tracing::warn!("Failed to save chain config. {err:#}");
+                }
+
                 if let Err(err) = self.save_anchor_leaf(leaf).await {
                     tracing::error!(
                         ?leaf,
@@ -187,6 +258,35 @@ pub trait SequencerPersistence: Send + Sync + 'static {
     async fn update_high_qc(&mut self, high_qc: QuorumCertificate<SeqTypes>) -> anyhow::Result<()>;
 }
 
+/// Persistence of a builder's pool of pending transactions.
+///
+/// A builder resumes from the last decided leaf like a node does, so this extends
+/// [`SequencerPersistence`], but only backends which a builder can run on implement it.
+#[async_trait]
+pub trait PendingTransactionPersistence: SequencerPersistence {
+    /// Load the pending transactions, in the order they were added.
+    async fn load_pending_transactions(&self) -> anyhow::Result<Vec<Transaction>>;
+
+    /// Add transactions to the pool at `time`, in seconds since the Unix epoch.
+    ///
+    /// Transactions already in the pool are ignored, and keep the time they were first added.
+    async fn append_pending_transactions(
+        &mut self,
+        txs: &[Transaction],
+        time: u64,
+    ) -> anyhow::Result<()>;
+
+    /// Remove transactions from the pool, for example because they were decided.
+    async fn remove_pending_transactions(
+        &mut self,
+        txs: &[Commitment<Transaction>],
+    ) -> anyhow::Result<()>;
+
+    /// Remove transactions added to the pool before `time`, in seconds since the Unix epoch,
+    /// which will most likely never be decided.
+    async fn expire_pending_transactions(&mut self, time: u64) -> anyhow::Result<()>;
+}
+
 #[cfg(test)]
 mod testing {
     use super::*;
@@ -205,7 +305,7 @@ mod testing {
 mod persistence_tests {
 
     use super::*;
-    use crate::{NodeState, Transaction};
+    use crate::{catchup::mock::MockStateCatchup, ChainConfigUpgrade, NodeState, Transaction};
     use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
 
     use hotshot::types::SignatureKey;
@@ -243,6 +343,153 @@ mod persistence_tests {
         assert_eq!(storage.load_anchor_leaf().await.unwrap().unwrap(), leaf2);
     }
 
+    #[async_std::test]
+    pub async fn test_chain_config<P: TestablePersistence>() {
+        setup_logging();
+        setup_backtrace();
+
+        let tmp = P::tmp_storage().await;
+        let mut storage = P::connect(&tmp).await;
+
+        let chain_config = ChainConfig::default().with_max_namespace_share_bps(5_000);
+        let commitment = chain_config.commit();
+        assert_eq!(storage.load_chain_config(commitment).await.unwrap(), None);
+
+        // Resolving a full config does not need catchup.
+        let no_catchup = MockStateCatchup::default();
+        assert_eq!(
+            storage
+                .resolve_chain_config(chain_config.into(), &no_catchup)
+                .await
+                .unwrap(),
+            chain_config
+        );
+
+        // Resolving a commitment fetches the config from catchup and caches it.
+        let catchup = MockStateCatchup::default().with_chain_configs([chain_config]);
+        assert_eq!(
+            storage
+                .resolve_chain_config(commitment.into(), &catchup)
+                .await
+                .unwrap(),
+            chain_config
+        );
+        assert_eq!(
+            storage.load_chain_config(commitment).await.unwrap(),
+            Some(chain_config)
+        );
+
+        // Once cached, catchup is no longer needed.
+        assert_eq!(
+            storage
+                .resolve_chain_config(commitment.into(), &no_catchup)
+                .await
+                .unwrap(),
+            chain_config
+        );
+
+        // Saving the same config again is harmless.
+        storage.save_chain_config(&chain_config).await.unwrap();
+        assert_eq!(
+            storage.load_chain_config(commitment).await.unwrap(),
+            Some(chain_config)
+        );
+    }
+
+    #[async_std::test]
+    pub async fn test_resume_chain_config<P: TestablePersistence>() {
+        setup_logging();
+        setup_backtrace();
+
+        let tmp = P::tmp_storage().await;
+        let mut storage = P::connect(&tmp).await;
+
+        // Save a leaf after an upgrade, carrying only the commitment of the upgraded config.
+        let upgraded = ChainConfig::default().with_max_namespace_share_bps(5_000);
+        let mut leaf = Leaf::genesis(&NodeState::mock());
+        leaf.get_block_header_mut().height = 1;
+        leaf.get_block_header_mut().chain_config = upgraded.commit().into();
+        storage.save_anchor_leaf(&leaf).await.unwrap();
+        storage.save_chain_config(&upgraded).await.unwrap();
+
+        // A node which knows of the upgrade resumes from the leaf.
+        let state = NodeState::mock().with_chain_config_upgrades([ChainConfigUpgrade {
+            height: 1,
+            chain_config: upgraded,
+        }]);
+        storage.load_consensus_state(state).await.unwrap();
+
+        // A node which does not refuses to start.
+        storage
+            .load_consensus_state(NodeState::mock())
+            .await
+            .unwrap_err();
+    }
+
+    #[async_std::test]
+    pub async fn test_pending_transactions<
+        P: TestablePersistence + PendingTransactionPersistence,
+    >() {
+        setup_logging();
+        setup_backtrace();
+
+        let tmp = P::tmp_storage().await;
+        let mut storage = P::connect(&tmp).await;
+
+        // Initially, there are no pending transactions.
+        assert_eq!(storage.load_pending_transactions().await.unwrap(), vec![]);
+
+        let txs = (0..4u64)
+            .map(|i| Transaction::new(i.into(), vec![i as u8]))
+            .collect::<Vec<_>>();
+        storage
+            .append_pending_transactions(&txs[..3], 1)
+            .await
+            .unwrap();
+        assert_eq!(storage.load_pending_transactions().await.unwrap(), txs[..3]);
+
+        // Appending a transaction which is already pending does not duplicate it.
+        storage
+            .append_pending_transactions(&txs[2..], 2)
+            .await
+            .unwrap();
+        assert_eq!(storage.load_pending_transactions().await.unwrap(), txs);
+
+        // Remove some transactions, including one which is not pending.
+        storage
+            .remove_pending_transactions(&[
+                txs[0].commit(),
+                txs[2].commit(),
+                Transaction::new(5u64.into(), vec![]).commit(),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.load_pending_transactions().await.unwrap(),
+            [txs[1].clone(), txs[3].clone()]
+        );
+
+        // The pool persists across connections.
+        let mut storage = P::connect(&tmp).await;
+        assert_eq!(
+            storage.load_pending_transactions().await.unwrap(),
+            [txs[1].clone(), txs[3].clone()]
+        );
+
+        // Expire the transactions added before time 2. A transaction appended again keeps the time
+        // it was first added.
+        storage.expire_pending_transactions(1).await.unwrap();
+        assert_eq!(
+            storage.load_pending_transactions().await.unwrap(),
+            [txs[1].clone(), txs[3].clone()]
+        );
+        storage.expire_pending_transactions(2).await.unwrap();
+        assert_eq!(
+            storage.load_pending_transactions().await.unwrap(),
+            [txs[3].clone()]
+        );
+    }
+
     #[async_std::test]
     pub async fn test_voted_view<P: TestablePersistence>() {
         setup_logging();
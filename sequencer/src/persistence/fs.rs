@@ -1,8 +1,11 @@
-use super::{NetworkConfig, PersistenceOptions, SequencerPersistence};
-use crate::{Leaf, SeqTypes, ValidatedState, ViewNumber};
+use super::{
+    NetworkConfig, PendingTransactionPersistence, PersistenceOptions, SequencerPersistence,
+};
+use crate::{ChainConfig, Leaf, SeqTypes, Transaction, ValidatedState, ViewNumber};
 use anyhow::{anyhow, bail, Context};
 use async_trait::async_trait;
 use clap::Parser;
+use commit::{Commitment, Committable};
 
 use hotshot_types::{
     data::{DAProposal, VidDisperseShare},
@@ -13,6 +16,7 @@ use hotshot_types::{
     vote::HasViewNumber,
 };
 use std::{
+    collections::HashSet,
     fs::{self, File, OpenOptions},
     io::{Read, Seek, SeekFrom, Write},
     path::PathBuf,
@@ -73,6 +77,66 @@ impl Persistence {
     fn high_qc_path(&self) -> PathBuf {
         self.0.join("high_qc")
     }
+
+    fn chain_config_dir_path(&self) -> PathBuf {
+        self.0.join("chain_config")
+    }
+
+    fn chain_config_path(&self, commitment: Commitment<ChainConfig>) -> PathBuf {
+        self.chain_config_dir_path().join(commitment.to_string())
+    }
+
+    fn pending_transactions_dir_path(&self) -> PathBuf {
+        self.0.join("pending_transactions")
+    }
+
+    /// The files of the pending transactions, in the order the transactions were added.
+    ///
+    /// Each pending transaction is kept in its own file, named after the time it was added, a
+    /// sequence number ordering transactions added at the same time, and its commitment. The pool
+    /// can thus be ordered, expired and searched by listing the directory, without reading the
+    /// transactions.
+    fn pending_files(&self) -> anyhow::Result<Vec<PendingFile>> {
+        let dir = self.pending_transactions_dir_path();
+        if !dir.is_dir() {
+            return Ok(vec![]);
+        }
+        let mut files = vec![];
+        for entry in fs::read_dir(dir).context("read pending transactions dir")? {
+            let path = entry.context("read pending transactions dir")?.path();
+            // Skip partially written files, which have an extension.
+            if path.extension().is_some() {
+                continue;
+            }
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let mut parts = name.splitn(3, '-');
+            let (Some(time), Some(seq), Some(hash)) = (parts.next(), parts.next(), parts.next())
+            else {
+                tracing::warn!("ignoring unrecognized file {}", path.display());
+                continue;
+            };
+            files.push(PendingFile {
+                time: time.parse().context("parse pending transaction time")?,
+                seq: seq
+                    .parse()
+                    .context("parse pending transaction sequence number")?,
+                hash: hash.to_string(),
+                path,
+            });
+        }
+        files.sort_by_key(|file| (file.time, file.seq));
+        Ok(files)
+    }
+}
+
+/// The file of a pending transaction, see [`Persistence::pending_files`].
+struct PendingFile {
+    time: u64,
+    seq: u64,
+    hash: String,
+    path: PathBuf,
 }
 
 #[async_trait]
@@ -338,6 +402,102 @@ impl SequencerPersistence for Persistence {
     async fn load_validated_state(&self, _height: u64) -> anyhow::Result<ValidatedState> {
         bail!("state persistence not implemented");
     }
+
+    async fn load_chain_config(
+        &self,
+        commitment: Commitment<ChainConfig>,
+    ) -> anyhow::Result<Option<ChainConfig>> {
+        let path = self.chain_config_path(commitment);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let bytes = fs::read(path).context("read chain config")?;
+        Ok(Some(
+            bincode::deserialize(&bytes).context("deserialize chain config")?,
+        ))
+    }
+
+    async fn save_chain_config(&mut self, chain_config: &ChainConfig) -> anyhow::Result<()> {
+        let path = self.chain_config_path(chain_config.commit());
+        if path.is_file() {
+            // Chain configs are immutable, so there is nothing to update.
+            return Ok(());
+        }
+        fs::create_dir_all(self.chain_config_dir_path()).context("create chain config dir")?;
+        let bytes = bincode::serialize(chain_config).context("serialize chain config")?;
+        fs::write(path, bytes).context("write chain config")?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PendingTransactionPersistence for Persistence {
+    async fn load_pending_transactions(&self) -> anyhow::Result<Vec<Transaction>> {
+        self.pending_files()?
+            .into_iter()
+            .map(|file| -> anyhow::Result<Transaction> {
+                let bytes = fs::read(&file.path).context("read pending transaction")?;
+                bincode::deserialize(&bytes).context("deserialize pending transaction")
+            })
+            .collect()
+    }
+
+    async fn append_pending_transactions(
+        &mut self,
+        txs: &[Transaction],
+        time: u64,
+    ) -> anyhow::Result<()> {
+        let files = self.pending_files()?;
+        let mut seq = files.iter().map(|file| file.seq + 1).max().unwrap_or(0);
+        let mut hashes = files
+            .into_iter()
+            .map(|file| file.hash)
+            .collect::<HashSet<_>>();
+
+        let dir = self.pending_transactions_dir_path();
+        fs::create_dir_all(&dir).context("create pending transactions dir")?;
+        for tx in txs {
+            let hash = tx.commit().to_string();
+            if hashes.contains(&hash) {
+                continue;
+            }
+            // Write the transaction to a temporary file which is then moved into place, so a crash
+            // part way through never leaves a truncated transaction in the pool.
+            let path = dir.join(format!("{time}-{seq}-{hash}"));
+            let tmp = path.with_extension("tmp");
+            let bytes = bincode::serialize(tx).context("serialize pending transaction")?;
+            let mut file = File::create(&tmp).context("create pending transaction file")?;
+            file.write_all(&bytes)
+                .context("write pending transaction")?;
+            file.sync_all().context("sync pending transaction")?;
+            fs::rename(tmp, path).context("save pending transaction")?;
+            hashes.insert(hash);
+            seq += 1;
+        }
+        Ok(())
+    }
+
+    async fn remove_pending_transactions(
+        &mut self,
+        txs: &[Commitment<Transaction>],
+    ) -> anyhow::Result<()> {
+        let remove = txs.iter().map(|tx| tx.to_string()).collect::<HashSet<_>>();
+        for file in self.pending_files()? {
+            if remove.contains(&file.hash) {
+                fs::remove_file(file.path).context("remove pending transaction")?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn expire_pending_transactions(&mut self, time: u64) -> anyhow::Result<()> {
+        for file in self.pending_files()? {
+            if file.time < time {
+                fs::remove_file(file.path).context("remove pending transaction")?;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
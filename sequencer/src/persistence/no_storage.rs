@@ -2,9 +2,10 @@
 #![cfg(any(test, feature = "testing"))]
 
 use super::{NetworkConfig, PersistenceOptions, SequencerPersistence};
-use crate::{Leaf, SeqTypes, ValidatedState, ViewNumber};
+use crate::{ChainConfig, Leaf, SeqTypes, ValidatedState, ViewNumber};
 use anyhow::bail;
 use async_trait::async_trait;
+use commit::Commitment;
 use hotshot_types::{
     data::{DAProposal, VidDisperseShare},
     event::HotShotAction,
@@ -103,4 +104,15 @@ impl SequencerPersistence for NoStorage {
     async fn load_validated_state(&self, _height: u64) -> anyhow::Result<ValidatedState> {
         bail!("state persistence not implemented");
     }
+
+    async fn load_chain_config(
+        &self,
+        _commitment: Commitment<ChainConfig>,
+    ) -> anyhow::Result<Option<ChainConfig>> {
+        Ok(None)
+    }
+
+    async fn save_chain_config(&mut self, _chain_config: &ChainConfig) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
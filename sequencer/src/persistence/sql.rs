@@ -3,7 +3,11 @@ use std::time::Duration;
 use anyhow::bail;
 use async_trait::async_trait;
 use clap::Parser;
-use futures::future::{BoxFuture, FutureExt};
+use commit::{Commitment, Committable};
+use futures::{
+    future::{BoxFuture, FutureExt},
+    stream::{StreamExt, TryStreamExt},
+};
 use hotshot_query_service::data_source::{
     storage::{
         pruning::PrunerCfg,
@@ -20,8 +24,13 @@ use hotshot_types::{
     vote::HasViewNumber,
 };
 
-use super::{NetworkConfig, PersistenceOptions, SequencerPersistence};
-use crate::{options::parse_duration, Leaf, SeqTypes, ValidatedState, ViewNumber};
+use super::{
+    NetworkConfig, PendingTransactionPersistence, PersistenceOptions, SequencerPersistence,
+};
+use crate::{
+    options::parse_duration, transaction::Transaction as SeqTransaction, ChainConfig, Leaf,
+    SeqTypes, ValidatedState, ViewNumber,
+};
 
 /// Options for Postgres-backed persistence.
 #[derive(Parser, Clone, Debug, Default)]
@@ -459,6 +468,123 @@ impl SequencerPersistence for Persistence {
     async fn load_validated_state(&self, _height: u64) -> anyhow::Result<ValidatedState> {
         bail!("state persistence not implemented");
     }
+
+    async fn load_chain_config(
+        &self,
+        commitment: Commitment<ChainConfig>,
+    ) -> anyhow::Result<Option<ChainConfig>> {
+        self.query_opt(
+            "SELECT data FROM chain_config WHERE commitment = $1",
+            [&commitment.to_string()],
+        )
+        .await?
+        .map(|row| {
+            let bytes: Vec<u8> = row.get("data");
+            Ok(bincode::deserialize(&bytes)?)
+        })
+        .transpose()
+    }
+
+    async fn save_chain_config(&mut self, chain_config: &ChainConfig) -> anyhow::Result<()> {
+        let commitment = chain_config.commit().to_string();
+        let data = bincode::serialize(chain_config)?;
+
+        transaction(self, |mut tx| {
+            async move {
+                // Chain configs are immutable, so there is nothing to update on conflict.
+                let stmt = "
+                    INSERT INTO chain_config (commitment, data) VALUES ($1, $2)
+                    ON CONFLICT (commitment) DO NOTHING
+                ";
+                tx.execute(stmt, [sql_param(&commitment), sql_param(&data)])
+                    .await?;
+                Ok(())
+            }
+            .boxed()
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl PendingTransactionPersistence for Persistence {
+    async fn load_pending_transactions(&self) -> anyhow::Result<Vec<SeqTransaction>> {
+        self.query_static("SELECT data FROM pending_transaction ORDER BY id")
+            .await?
+            .map(|row| -> anyhow::Result<SeqTransaction> {
+                let bytes: Vec<u8> = row?.get("data");
+                Ok(bincode::deserialize(&bytes)?)
+            })
+            .try_collect()
+            .await
+    }
+
+    async fn append_pending_transactions(
+        &mut self,
+        txs: &[SeqTransaction],
+        time: u64,
+    ) -> anyhow::Result<()> {
+        let rows = txs
+            .iter()
+            .map(|tx| Ok((tx.commit().to_string(), bincode::serialize(tx)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let created = i64::try_from(time)?;
+
+        transaction(self, |mut tx| {
+            async move {
+                let stmt = "
+                    INSERT INTO pending_transaction (hash, data, created) VALUES ($1, $2, $3)
+                    ON CONFLICT (hash) DO NOTHING
+                ";
+                for (hash, data) in rows {
+                    tx.execute(
+                        stmt,
+                        [sql_param(&hash), sql_param(&data), sql_param(&created)],
+                    )
+                    .await?;
+                }
+                Ok(())
+            }
+            .boxed()
+        })
+        .await
+    }
+
+    async fn remove_pending_transactions(
+        &mut self,
+        txs: &[Commitment<SeqTransaction>],
+    ) -> anyhow::Result<()> {
+        let hashes = txs.iter().map(|hash| hash.to_string()).collect::<Vec<_>>();
+
+        transaction(self, |mut tx| {
+            async move {
+                let stmt = "DELETE FROM pending_transaction WHERE hash = $1";
+                for hash in hashes {
+                    tx.execute(stmt, [&hash]).await?;
+                }
+                Ok(())
+            }
+            .boxed()
+        })
+        .await
+    }
+
+    async fn expire_pending_transactions(&mut self, time: u64) -> anyhow::Result<()> {
+        let before = i64::try_from(time)?;
+
+        transaction(self, |mut tx| {
+            async move {
+                tx.execute(
+                    "DELETE FROM pending_transaction WHERE created < $1",
+                    [&before],
+                )
+                .await?;
+                Ok(())
+            }
+            .boxed()
+        })
+        .await
+    }
 }
 
 fn sql_param<T: ToSql + Sync>(param: &T) -> &(dyn ToSql + Sync) {
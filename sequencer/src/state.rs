@@ -1,4 +1,6 @@
-use crate::{ChainConfig, Header, Leaf, NodeState, SeqTypes};
+use crate::{
+    block::NsTable, header::HeaderVersion, ChainConfig, Header, Leaf, NodeState, SeqTypes,
+};
 use anyhow::{bail, ensure, Context};
 use ark_serialize::{
     CanonicalDeserialize, CanonicalSerialize, Compress, Read, SerializationError, Valid, Validate,
@@ -32,7 +34,7 @@ use jf_primitives::{
 use num_traits::CheckedSub;
 use sequencer_utils::impl_to_fixed_bytes;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, ops::Add, str::FromStr};
+use std::{collections::HashSet, fmt::Debug, ops::Add, str::FromStr};
 use typenum::{Unsigned, U3};
 
 const BLOCK_MERKLE_TREE_HEIGHT: usize = 32;
@@ -141,6 +143,7 @@ impl ValidatedState {
 
 pub fn validate_and_apply_proposal(
     expected_chain_config: ChainConfig,
+    expected_version: HeaderVersion,
     state: &mut ValidatedState,
     delta: &mut Delta,
     parent_leaf: &Leaf,
@@ -149,9 +152,10 @@ pub fn validate_and_apply_proposal(
 ) -> anyhow::Result<()> {
     let parent_header = parent_leaf.get_block_header();
 
-    // validate `ChainConfig`
+    // validate `ChainConfig`. The proposal may carry either the full config or just its
+    // commitment, so compare commitments.
     anyhow::ensure!(
-        proposal.chain_config == expected_chain_config.into(),
+        proposal.chain_config.commit() == expected_chain_config.commit(),
         anyhow::anyhow!(
             "Invalid Chain Config: local={:?}, proposal={:?}",
             expected_chain_config,
@@ -159,6 +163,35 @@ pub fn validate_and_apply_proposal(
         )
     );
 
+    // validate header version
+    anyhow::ensure!(
+        proposal.version() == expected_version,
+        anyhow::anyhow!(
+            "Invalid Header Version: local={}, proposal={}",
+            expected_version,
+            proposal.version()
+        )
+    );
+
+    // validate payload version
+    anyhow::ensure!(
+        expected_version.allows_payload_version(proposal.ns_table.version()),
+        anyhow::anyhow!(
+            "Invalid Payload Version: header={}, payload={:?}",
+            expected_version,
+            proposal.ns_table.version()
+        )
+    );
+
+    // validate namespace order
+    anyhow::ensure!(
+        proposal.ns_table.is_consistent_with_ordering(),
+        anyhow::anyhow!(
+            "Invalid Namespace Order: namespaces are not ordered by {:?}",
+            proposal.ns_table.ordering()
+        )
+    );
+
     // validate height
     anyhow::ensure!(
         proposal.height == parent_header.height + 1,
@@ -169,6 +202,34 @@ pub fn validate_and_apply_proposal(
         )
     );
 
+    // validate namespace sizes
+    if let Some(max_namespace_bytes) = expected_chain_config.max_namespace_bytes() {
+        let ns_table = &proposal.ns_table;
+        for ns_index in 0..ns_table.len() {
+            let ns_bytes = ns_table.namespace_byte_len(ns_index);
+            anyhow::ensure!(
+                ns_bytes as u64 <= max_namespace_bytes,
+                anyhow::anyhow!(
+                    "Namespace Too Large Error: namespace={}, bytes={}, max={}",
+                    ns_table.get_table_entry(ns_index).0,
+                    ns_bytes,
+                    max_namespace_bytes
+                )
+            );
+        }
+    }
+
+    // validate builder fee
+    let min_fee = expected_chain_config.block_fee(&proposal.ns_table);
+    anyhow::ensure!(
+        proposal.fee_info.amount() >= min_fee,
+        anyhow::anyhow!(
+            "Insufficient Fee Error: fee={:?}, min={:?}",
+            proposal.fee_info.amount(),
+            min_fee
+        )
+    );
+
     let ValidatedState {
         block_merkle_tree,
         fee_merkle_tree,
@@ -289,6 +350,7 @@ fn validate_and_charge_builder(
 /// is available in the `validated_state`.
 fn validate_and_apply_header(
     chain_config: ChainConfig,
+    version: HeaderVersion,
     validated_state: &mut ValidatedState,
     delta: &mut Delta,
     parent_leaf: &Leaf,
@@ -298,6 +360,7 @@ fn validate_and_apply_header(
     // validate proposed header against parent
     match validate_and_apply_proposal(
         chain_config,
+        version,
         validated_state,
         delta,
         parent_leaf,
@@ -412,7 +475,8 @@ impl HotShotState<SeqTypes> for ValidatedState {
 
         // Lastly validate and apply the header
         validate_and_apply_header(
-            instance.chain_config,
+            instance.chain_config(proposed_header.height),
+            instance.header_version(proposed_header.height),
             &mut validated_state,
             &mut delta,
             parent_leaf,
@@ -461,6 +525,7 @@ impl hotshot_types::traits::states::TestableState<SeqTypes> for ValidatedState {
 
 pub type BlockMerkleTree = LightWeightSHA3MerkleTree<Commitment<Header>>;
 pub type BlockMerkleCommitment = <BlockMerkleTree as MerkleTreeScheme>::Commitment;
+pub type BlockMerkleProof = <BlockMerkleTree as MerkleTreeScheme>::MembershipProof;
 
 impl MerklizedState<SeqTypes> for BlockMerkleTree {
     type Arity = U3;
@@ -508,12 +573,12 @@ impl FeeInfo {
         }
     }
     /// The minimum fee paid by the given builder account for a proposed block.
-    // TODO this function should take the block size as an input, we need to get this information
-    // from HotShot.
-    pub fn base_fee(account: FeeAccount) -> Self {
+    ///
+    /// See [`ChainConfig::block_fee`].
+    pub fn base_fee(account: FeeAccount, chain_config: &ChainConfig, ns_table: &NsTable) -> Self {
         Self {
             account,
-            amount: FeeAmount::default(),
+            amount: chain_config.block_fee(ns_table),
         }
     }
 
@@ -533,6 +598,24 @@ impl FeeInfo {
     }
 }
 
+/// Decides the fee a builder pays for a block it proposes.
+///
+/// A node proposing a block signs a [`FeeInfo`] charging this amount to its builder account.
+pub trait BuilderFee: Send + Sync + Debug {
+    /// The fee to pay for a block with namespace table `ns_table`.
+    fn builder_fee(&self, chain_config: &ChainConfig, ns_table: &NsTable) -> FeeAmount;
+}
+
+/// Always pay the base fee; see [`FeeInfo::base_fee`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BaseFee;
+
+impl BuilderFee for BaseFee {
+    fn builder_fee(&self, chain_config: &ChainConfig, ns_table: &NsTable) -> FeeAmount {
+        FeeInfo::base_fee(Default::default(), chain_config, ns_table).amount()
+    }
+}
+
 impl From<DepositFilter> for FeeInfo {
     fn from(item: DepositFilter) -> Self {
         Self {
@@ -557,7 +640,21 @@ impl Committable for FeeInfo {
 // New Type for `U256` in order to implement `CanonicalSerialize` and
 // `CanonicalDeserialize`
 #[derive(
-    Default, Hash, Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Add, Sub, From, Into,
+    Default,
+    Hash,
+    Copy,
+    Clone,
+    Debug,
+    Deserialize,
+    Serialize,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Add,
+    Sub,
+    From,
+    Into,
 )]
 pub struct FeeAmount(U256);
 impl_to_fixed_bytes!(FeeAmount, U256);
@@ -803,6 +900,51 @@ impl FeeAccountProof {
     }
 }
 
+/// A proof that a header is included in the block Merkle tree.
+///
+/// The block Merkle tree in the header at height `h` commits to all headers below `h`, so a header
+/// can be proven against the block Merkle tree root of any later header, or of a light client
+/// state at a later height.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HeaderInclusionProof {
+    header: Header,
+    proof: BlockMerkleProof,
+}
+
+impl HeaderInclusionProof {
+    pub fn new(header: Header, proof: BlockMerkleProof) -> Self {
+        Self { header, proof }
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Verify that the header is included in the block Merkle tree with root `comm`.
+    pub fn verify(&self, comm: &BlockMerkleCommitment) -> anyhow::Result<&Header> {
+        ensure!(
+            self.proof.elem() == Some(&self.header.commit()),
+            "proof is for a different header"
+        );
+        ensure!(
+            BlockMerkleTree::verify(comm.digest(), self.header.height, &self.proof)?.is_ok(),
+            "invalid proof"
+        );
+        Ok(&self.header)
+    }
+
+    /// Verify that the header is included in the block Merkle tree of a later `reference` header.
+    pub fn verify_with_header(&self, reference: &Header) -> anyhow::Result<&Header> {
+        ensure!(
+            self.header.height < reference.height,
+            "header {} is not below the reference header {}",
+            self.header.height,
+            reference.height
+        );
+        self.verify(&reference.block_merkle_tree_root)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -846,4 +988,49 @@ mod test {
         FeeAccountProof::prove(&tree, account1).unwrap();
         FeeAccountProof::prove(&tree, account2).unwrap();
     }
+
+    #[test]
+    fn test_header_inclusion_proofs() {
+        setup_logging();
+        setup_backtrace();
+
+        let genesis = Leaf::genesis(&NodeState::mock()).get_block_header().clone();
+        let headers = (0..3)
+            .map(|height| {
+                let mut header = genesis.clone();
+                header.height = height;
+                header
+            })
+            .collect::<Vec<_>>();
+        let tree = BlockMerkleTree::from_elems(
+            Some(BLOCK_MERKLE_TREE_HEIGHT),
+            headers.iter().map(Committable::commit),
+        )
+        .unwrap();
+        let comm = tree.commitment();
+
+        let (_, proof) = tree.lookup(1).expect_ok().unwrap();
+        let inclusion = HeaderInclusionProof::new(headers[1].clone(), proof.clone());
+        assert_eq!(inclusion.verify(&comm).unwrap(), &headers[1]);
+
+        // The tree is the one committed to by the next header.
+        let mut reference = genesis.clone();
+        reference.height = 3;
+        reference.block_merkle_tree_root = comm;
+        assert_eq!(
+            inclusion.verify_with_header(&reference).unwrap(),
+            &headers[1]
+        );
+        inclusion.verify_with_header(&headers[1]).unwrap_err();
+
+        // A proof for another header does not verify.
+        HeaderInclusionProof::new(headers[2].clone(), proof.clone())
+            .verify(&comm)
+            .unwrap_err();
+
+        // Nor does a proof against another tree.
+        let mut longer = tree.clone();
+        longer.push(headers[2].commit()).unwrap();
+        inclusion.verify(&longer.commitment()).unwrap_err();
+    }
 }
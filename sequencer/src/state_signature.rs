@@ -1,9 +1,15 @@
 //! Utilities for generating and storing the most recent light client state signatures.
 
-use crate::{Leaf, SeqTypes, StateKeyPair};
-use ark_ff::PrimeField;
+use crate::{
+    state::{BlockMerkleCommitment, HeaderInclusionProof},
+    Header, Leaf, SeqTypes, StateKeyPair,
+};
+use anyhow::ensure;
+use ark_ff::{BigInteger, PrimeField};
 use ark_serialize::CanonicalSerialize;
 use async_std::sync::RwLock;
+use contract_bindings::light_client::{LightClient, LightClientState as ContractState};
+use ethers::{providers::Middleware, types::U256};
 use hotshot::types::{Event, EventType};
 use hotshot_stake_table::vec_based::StakeTable;
 use hotshot_types::light_client::{
@@ -25,6 +31,7 @@ use jf_primitives::{
     errors::PrimitivesError,
     signatures::SignatureScheme,
 };
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
 use surf_disco::{Client, Url};
 use tide_disco::error::ServerError;
@@ -32,6 +39,8 @@ use versioned_binary_serialization::version::StaticVersionType;
 
 /// A relay server that's collecting and serving the light client state signatures
 pub mod relay_server;
+/// Stake tables for verifying light client state signatures
+pub mod stake_table;
 
 /// Capacity for the in memory signature storage.
 const SIGNATURE_STORAGE_CAPACITY: usize = 100;
@@ -153,11 +162,6 @@ fn form_light_client_state(
     stake_table_comm: &StakeTableCommitmentType,
 ) -> Result<LightClientState, PrimitivesError> {
     let header = leaf.get_block_header();
-    let mut block_comm_root_bytes = vec![];
-    header
-        .block_merkle_tree_root
-        .serialize_compressed(&mut block_comm_root_bytes)?;
-
     let mut fee_ledger_comm_bytes = vec![];
     header
         .fee_merkle_tree_root
@@ -165,12 +169,70 @@ fn form_light_client_state(
     Ok(LightClientState {
         view_number: leaf.get_view_number().get_u64() as usize,
         block_height: leaf.get_height() as usize,
-        block_comm_root: hash_bytes_to_field(&block_comm_root_bytes)?,
+        block_comm_root: block_comm_root(&header.block_merkle_tree_root)?,
         fee_ledger_comm: hash_bytes_to_field(&fee_ledger_comm_bytes)?,
         stake_table_comm: *stake_table_comm,
     })
 }
 
+/// The light client commitment to the block Merkle tree with root `root`.
+///
+/// This is the `blockCommRoot` of light client states.
+pub fn block_comm_root(root: &BlockMerkleCommitment) -> Result<CircuitField, PrimitivesError> {
+    let mut bytes = vec![];
+    root.serialize_compressed(&mut bytes)?;
+    hash_bytes_to_field(&bytes)
+}
+
+pub(crate) fn field_to_u256(f: CircuitField) -> U256 {
+    let bytes = f.into_bigint().to_bytes_le();
+    U256::from_little_endian(&bytes)
+}
+
+/// A proof that a header is committed to by a light client state.
+///
+/// Light client states only commit to a hash of the block Merkle tree root, so the proof carries
+/// the root itself along with the inclusion proof of the header.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LightClientHeaderProof {
+    /// Block height of the light client state.
+    pub light_client_height: u64,
+    /// The block Merkle tree root committed to by the light client state.
+    pub block_merkle_tree_root: BlockMerkleCommitment,
+    /// Inclusion of the header in the block Merkle tree.
+    pub inclusion: HeaderInclusionProof,
+}
+
+impl LightClientHeaderProof {
+    /// Verify the proof against a light client state read from the light client contract.
+    pub fn verify(&self, state: &ContractState) -> anyhow::Result<&Header> {
+        ensure!(
+            state.block_height == self.light_client_height,
+            "proof is for the light client state at height {}, not {}",
+            self.light_client_height,
+            state.block_height
+        );
+        let root = block_comm_root(&self.block_merkle_tree_root)?;
+        ensure!(
+            field_to_u256(root) == state.block_comm_root,
+            "block Merkle tree root does not match the light client state"
+        );
+        self.inclusion.verify(&self.block_merkle_tree_root)
+    }
+
+    /// Verify the proof against the latest state finalized by the light client `contract`.
+    ///
+    /// Fails if the contract has finalized a newer state than the one the proof is for; fetch a
+    /// new proof at the height of the finalized state in that case.
+    pub async fn verify_on_chain<M: Middleware + 'static>(
+        &self,
+        contract: &LightClient<M>,
+    ) -> anyhow::Result<&Header> {
+        let state = contract.get_finalized_state().call().await?;
+        self.verify(&state)
+    }
+}
+
 /// A rolling in-memory storage for the most recent light client state signatures.
 #[derive(Debug, Default)]
 pub struct StateSignatureMemStorage {
@@ -200,18 +262,58 @@ pub fn static_stake_table_commitment(
     known_nodes_with_stakes: &[PeerConfig<BLSPubKey>],
     capacity: usize,
 ) -> (CircuitField, CircuitField, CircuitField) {
+    // This `unwrap()` wont fail unless number of entries exceeds `capacity`
+    let st = static_stake_table(known_nodes_with_stakes, capacity).unwrap();
+    // This `unwrap()` won't fail
+    st.commitment(SnapshotVersion::LastEpochStart).unwrap()
+}
+
+/// The stake table of `known_nodes_with_stakes`, as used for light client states.
+pub fn static_stake_table(
+    known_nodes_with_stakes: &[PeerConfig<BLSPubKey>],
+    capacity: usize,
+) -> anyhow::Result<StakeTable<BLSPubKey, StateVerKey, CircuitField>> {
     let mut st = StakeTable::<BLSPubKey, StateVerKey, CircuitField>::new(capacity);
-    known_nodes_with_stakes.iter().for_each(|peer| {
-        // This `unwrap()` wont fail unless number of entries exceeds `capacity`
+    for peer in known_nodes_with_stakes {
         st.register(
             *peer.stake_table_entry.get_key(),
             peer.stake_table_entry.get_stake(),
             peer.state_ver_key.clone(),
         )
-        .unwrap();
-    });
+        .map_err(|err| anyhow::anyhow!("registering stake table entry: {err}"))?;
+    }
     st.advance();
     st.advance();
-    // This `unwrap()` won't fail
-    st.commitment(SnapshotVersion::LastEpochStart).unwrap()
+    Ok(st)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{state::BlockMerkleTree, NodeState};
+    use commit::Committable;
+    use jf_primitives::merkle_tree::{AppendableMerkleTreeScheme, MerkleTreeScheme};
+
+    #[test]
+    fn test_light_client_header_proof() {
+        let header = Leaf::genesis(&NodeState::mock()).get_block_header().clone();
+        let tree = BlockMerkleTree::from_elems(Some(32), [header.commit()]).unwrap();
+        let (_, proof) = tree.lookup(0).expect_ok().unwrap();
+        let proof = LightClientHeaderProof {
+            light_client_height: 1,
+            block_merkle_tree_root: tree.commitment(),
+            inclusion: HeaderInclusionProof::new(header.clone(), proof),
+        };
+
+        let mut state = ContractState {
+            block_height: 1,
+            block_comm_root: field_to_u256(block_comm_root(&tree.commitment()).unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(proof.verify(&state).unwrap(), &header);
+
+        // The proof does not verify against another light client state.
+        state.block_comm_root += U256::one();
+        proof.verify(&state).unwrap_err();
+    }
 }
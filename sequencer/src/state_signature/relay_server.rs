@@ -1,6 +1,15 @@
-use super::{LightClientState, StateSignatureRequestBody};
+use super::{
+    stake_table::{StakeTableSnapshot, StakeTableSource},
+    LightClientState, StateSignatureRequestBody,
+};
+use crate::api::participation::{
+    self, ParticipationDataSource, ParticipationQueryData, ParticipationTracker,
+};
 use async_compatibility_layer::channel::OneShotReceiver;
-use async_std::sync::RwLock;
+use async_std::{
+    sync::{Arc, RwLock},
+    task::{sleep, spawn},
+};
 use clap::Args;
 use ethers::types::U256;
 use futures::FutureExt;
@@ -10,7 +19,10 @@ use hotshot_types::light_client::{
 };
 use jf_primitives::signatures::SignatureScheme;
 use std::{
-    collections::{BTreeSet, HashMap},
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    convert::Infallible,
+    fmt::Write as _,
     path::PathBuf,
 };
 use tide_disco::{
@@ -22,13 +34,28 @@ use tide_disco::{
 use url::Url;
 use versioned_binary_serialization::version::StaticVersionType;
 
+/// The number of available bundles for epoch-ending blocks kept by the relay server.
+const MAX_EPOCH_BUNDLES: usize = 16;
+
+/// The number of stake tables against which the relay server accepts signatures.
+///
+/// Signatures for states at the end of the previous epoch may still arrive after the stake table
+/// of the new epoch is loaded.
+const MAX_STAKE_TABLES: usize = 2;
+
 /// State that checks the light client state update and the signature collection
 #[derive(Default)]
 struct StateRelayServerState {
-    /// Minimum weight to form an available state signature bundle
+    /// Minimum weight to form an available state signature bundle, if there is no stake table
     threshold: U256,
-    /// Stake table
-    known_nodes: HashMap<StateVerKey, U256>,
+    /// Whether signatures are verified against `stake_tables`
+    ///
+    /// Otherwise, every valid signature has weight one and bundles need `threshold` signatures.
+    has_stake_table: bool,
+    /// The most recently loaded stake tables, newest last
+    stake_tables: VecDeque<StakeTableSnapshot>,
+    /// Signing participation of each validator over the most recent signed heights
+    participation: ParticipationTracker<StateVerKey>,
     /// Signatures bundles for each block height
     bundles: HashMap<u64, HashMap<LightClientState, StateSignaturesBundle>>,
 
@@ -40,6 +67,14 @@ struct StateRelayServerState {
     /// A ordered queue of block heights, used for garbage collection.
     queue: BTreeSet<u64>,
 
+    /// Number of blocks per epoch of the light client contract, if it uses epochs.
+    blocks_per_epoch: Option<u64>,
+    /// Available state signatures bundles for the last blocks of recent epochs.
+    ///
+    /// The light client contract requires the last block of each epoch to be submitted before any
+    /// later block, so these are kept even after a later bundle becomes available.
+    epoch_bundles: BTreeMap<u64, StateSignaturesBundle>,
+
     /// shutdown signal
     shutdown: Option<OneShotReceiver<()>>,
 }
@@ -59,19 +94,225 @@ impl StateRelayServerState {
         self.shutdown = shutdown_listener;
         self
     }
+
+    pub fn with_stake_table(mut self) -> Self {
+        self.has_stake_table = true;
+        self
+    }
+
+    /// Verify signatures against `stake_table` from now on.
+    fn update_stake_table(&mut self, stake_table: StakeTableSnapshot) {
+        if let Some(current) = self.stake_tables.back_mut() {
+            if current.comm == stake_table.comm {
+                *current = stake_table;
+                return;
+            }
+        }
+        tracing::info!(
+            "Loaded stake table with {} validators, total stake {}, threshold {} (epoch {:?}).",
+            stake_table.stakes.len(),
+            stake_table.total_stake(),
+            stake_table.threshold,
+            stake_table.epoch,
+        );
+        self.participation
+            .retain(&stake_table.stakes.keys().cloned().collect());
+        self.stake_tables.push_back(stake_table);
+        while self.stake_tables.len() > MAX_STAKE_TABLES {
+            self.stake_tables.pop_front();
+        }
+    }
+
+    /// The weight of a signature by `key` on `state`, and the threshold of its bundle.
+    fn signature_weight(
+        &self,
+        key: &StateVerKey,
+        state: &LightClientState,
+    ) -> Result<(U256, U256), Error> {
+        if !self.has_stake_table {
+            return Ok((U256::one(), self.threshold));
+        }
+        if self.stake_tables.is_empty() {
+            return Err(ServerError::catch_all(
+                StatusCode::ServiceUnavailable,
+                "The stake table is not loaded yet.".to_owned(),
+            ));
+        }
+        let stake_table = self
+            .stake_tables
+            .iter()
+            .rev()
+            .find(|stake_table| stake_table.comm == state.stake_table_comm)
+            .ok_or_else(|| {
+                ServerError::catch_all(
+                    StatusCode::BadRequest,
+                    "The light client state commits to an unknown stake table.".to_owned(),
+                )
+            })?;
+        let weight = stake_table.stakes.get(key).ok_or_else(|| {
+            ServerError::catch_all(
+                StatusCode::Unauthorized,
+                "The posted key is not found in the stake table.".to_owned(),
+            )
+        })?;
+        Ok((*weight, stake_table.threshold))
+    }
+
+    /// Signing participation in the Prometheus text format.
+    fn metrics(&self) -> RelayMetrics {
+        let mut out = String::new();
+        let mut gauge = |name: &str, help: &str, values: Vec<(Option<&StateVerKey>, String)>| {
+            writeln!(out, "# HELP {name} {help}").ok();
+            writeln!(out, "# TYPE {name} gauge").ok();
+            for (key, value) in values {
+                match key {
+                    Some(key) => writeln!(out, "{name}{{key=\"{key}\"}} {value}").ok(),
+                    None => writeln!(out, "{name} {value}").ok(),
+                };
+            }
+        };
+
+        gauge(
+            "relay_latest_available_height",
+            "Block height of the latest available state signatures bundle",
+            vec![(None, self.latest_block_height.unwrap_or(0).to_string())],
+        );
+        if let Some(stake_table) = self.stake_tables.back() {
+            if let Some(epoch) = stake_table.epoch {
+                gauge(
+                    "relay_stake_table_epoch",
+                    "Light client epoch of the current stake table",
+                    vec![(None, epoch.to_string())],
+                );
+            }
+            gauge(
+                "relay_stake_table_threshold",
+                "Minimum stake of the signers of a light client state",
+                vec![(None, stake_table.threshold.to_string())],
+            );
+            gauge(
+                "relay_validator_stake",
+                "Stake of each validator in the current stake table",
+                stake_table
+                    .stakes
+                    .iter()
+                    .map(|(key, stake)| (Some(key), stake.to_string()))
+                    .collect(),
+            );
+        }
+        let participation = self.participation();
+        gauge(
+            "relay_validator_last_signed_height",
+            "Highest block height signed by each validator",
+            participation
+                .keys
+                .iter()
+                .map(|p| (Some(&p.key), p.last_height.unwrap_or(0).to_string()))
+                .collect(),
+        );
+        gauge(
+            "relay_validator_missed_signatures",
+            "Number of recent signed block heights each validator did not sign",
+            participation
+                .keys
+                .iter()
+                .map(|p| (Some(&p.key), p.missed.to_string()))
+                .collect(),
+        );
+        gauge(
+            "relay_validator_uptime",
+            "Fraction of recent signed block heights each validator signed",
+            participation
+                .keys
+                .iter()
+                .map(|p| (Some(&p.key), p.uptime.to_string()))
+                .collect(),
+        );
+        RelayMetrics(out)
+    }
+
+    /// Signing participation of the validators in the current stake table, or of every validator
+    /// which signed if there is no stake table.
+    ///
+    /// Heights past the latest available bundle are still being signed, so they do not count.
+    fn participation(&self) -> ParticipationQueryData<StateVerKey> {
+        let keys = match self.stake_tables.back() {
+            Some(stake_table) => stake_table.stakes.keys().cloned().collect::<Vec<_>>(),
+            None => self.participation.known_keys().cloned().collect(),
+        };
+        let up_to = self.latest_block_height.unwrap_or(0);
+        self.participation.query(keys, up_to)
+    }
+
+    pub fn with_blocks_per_epoch(mut self, blocks_per_epoch: Option<u64>) -> Self {
+        self.blocks_per_epoch = blocks_per_epoch.filter(|&n| n > 0);
+        self
+    }
+
+    /// The last block of the latest epoch which ended at or before `block_height`.
+    fn epoch_ending_height(&self, block_height: u64) -> Option<u64> {
+        self.blocks_per_epoch
+            .map(|n| block_height / n * n)
+            .filter(|&height| height > 0)
+    }
+
+    /// Whether signatures for `block_height` are still needed.
+    fn needs_signatures(&self, block_height: u64) -> bool {
+        let latest = self.latest_block_height.unwrap_or(0);
+        // Past the latest available bundle, or the end of the latest epoch which has not been
+        // signed yet.
+        block_height > latest
+            || (self.epoch_ending_height(latest) == Some(block_height)
+                && !self.epoch_bundles.contains_key(&block_height))
+    }
+
+    /// Drop the signatures collected for heights which are no longer needed.
+    fn collect_garbage(&mut self) {
+        let latest = self.latest_block_height.unwrap_or(0);
+        let stale = self.queue.range(..=latest).copied().collect::<Vec<_>>();
+        for height in stale {
+            // Keep collecting signatures for the end of the latest epoch.
+            if self.needs_signatures(height) {
+                continue;
+            }
+            self.queue.remove(&height);
+            self.bundles.remove(&height);
+        }
+    }
 }
 
 // TODO(Chengyu): move this `RwLock` inside `StateRelayServerState` so that when nodes are submitting
 //                signatures, it won't block the prover from fetching the available signatures.
-type State = RwLock<StateRelayServerState>;
+type State = Arc<RwLock<StateRelayServerState>>;
 type Error = ServerError;
 
+/// Metrics of the relay server, already rendered in the Prometheus text format.
+#[derive(Clone, Debug)]
+pub struct RelayMetrics(String);
+
+impl tide_disco::metrics::Metrics for RelayMetrics {
+    type Error = Infallible;
+
+    fn export(&self) -> Result<String, Self::Error> {
+        Ok(self.0.clone())
+    }
+}
+
 pub trait StateRelayServerDataSource {
     /// Get the latest available signatures bundle.
     /// # Errors
     /// Errors if there's no available signatures bundle.
     fn get_latest_signature_bundle(&self) -> Result<StateSignaturesBundle, Error>;
 
+    /// Get the available signatures bundle for the given block height.
+    /// # Errors
+    /// Errors if there's no available signatures bundle for this height. Apart from the latest
+    /// bundle, bundles are only kept for the last blocks of recent epochs.
+    fn get_signature_bundle(&self, block_height: u64) -> Result<StateSignaturesBundle, Error>;
+
+    /// Get the signing participation of the validators, as Prometheus metrics.
+    fn get_metrics(&self) -> RelayMetrics;
+
     /// Post a signature to the relay server
     /// # Errors
     /// Errors if the signature is invalid, already posted, or no longer needed.
@@ -94,32 +335,48 @@ impl StateRelayServerDataSource for StateRelayServerState {
         }
     }
 
+    fn get_metrics(&self) -> RelayMetrics {
+        self.metrics()
+    }
+
+    fn get_signature_bundle(&self, block_height: u64) -> Result<StateSignaturesBundle, Error> {
+        let bundle = if self.latest_block_height == Some(block_height) {
+            self.latest_available_bundle.as_ref()
+        } else {
+            self.epoch_bundles.get(&block_height)
+        };
+        bundle.cloned().ok_or_else(|| {
+            tide_disco::error::ServerError::catch_all(
+                StatusCode::NotFound,
+                format!("No light client state signatures are available at height {block_height}."),
+            )
+        })
+    }
+
     fn post_signature(
         &mut self,
         key: StateVerKey,
         state: LightClientState,
         signature: StateSignature,
     ) -> Result<(), Error> {
-        if (state.block_height as u64) <= self.latest_block_height.unwrap_or(0) {
-            // This signature is no longer needed
+        let block_height = state.block_height as u64;
+        let state_msg: [FieldType; 7] = (&state).into();
+        if !self.needs_signatures(block_height) {
+            // This signature is no longer needed, but a valid one still counts as participation.
+            if self.signature_weight(&key, &state).is_ok()
+                && StateSignatureScheme::verify(&(), &key, state_msg, &signature).is_ok()
+            {
+                self.participation.record(block_height, key);
+            }
             return Ok(());
         }
-        let one = U256::one();
-        let weight = self.known_nodes.get(&key).unwrap_or(&one);
-        // TODO(Chengyu): We don't know where to fetch the stake table yet.
-        // Related issue: [https://github.com/EspressoSystems/espresso-sequencer/issues/1022]
-        // .ok_or(tide_disco::error::ServerError::catch_all(
-        //     StatusCode::Unauthorized,
-        //     "The posted key is not found in the stake table.".to_owned(),
-        // ))?;
-        let state_msg: [FieldType; 7] = (&state).into();
+        let (weight, threshold) = self.signature_weight(&key, &state)?;
         if StateSignatureScheme::verify(&(), &key, state_msg, &signature).is_err() {
             return Err(tide_disco::error::ServerError::catch_all(
                 StatusCode::BadRequest,
                 "The posted signature is not valid.".to_owned(),
             ));
         }
-        let block_height = state.block_height as u64;
         // TODO(Chengyu): this serialization should be removed once `LightClientState` implements `Eq`.
         let bundles_at_height = self.bundles.entry(block_height).or_insert_with(|| {
             self.queue.insert(block_height);
@@ -137,7 +394,7 @@ impl StateRelayServerDataSource for StateRelayServerState {
             block_height,
             key
         );
-        match bundle.signatures.entry(key) {
+        match bundle.signatures.entry(key.clone()) {
             std::collections::hash_map::Entry::Occupied(_) => {
                 // A signature is already posted for this key with this state
                 return Err(tide_disco::error::ServerError::catch_all(
@@ -147,28 +404,42 @@ impl StateRelayServerDataSource for StateRelayServerState {
             }
             std::collections::hash_map::Entry::Vacant(entry) => {
                 entry.insert(signature);
-                bundle.accumulated_weight += *weight;
+                bundle.accumulated_weight += weight;
             }
         }
+        self.participation.record(block_height, key);
 
-        if bundle.accumulated_weight >= self.threshold {
+        if bundle.accumulated_weight >= threshold {
             tracing::info!(
                 "State signature bundle at block height {} is ready to serve.",
                 block_height
             );
-            self.latest_block_height = Some(block_height);
-            self.latest_available_bundle = Some(bundle.clone());
-            while let Some(height) = self.queue.pop_first() {
-                self.bundles.remove(&height);
-                if height == block_height {
-                    break;
+            let bundle = bundle.clone();
+            if self.epoch_ending_height(block_height) == Some(block_height) {
+                self.epoch_bundles.insert(block_height, bundle.clone());
+                while self.epoch_bundles.len() > MAX_EPOCH_BUNDLES {
+                    self.epoch_bundles.pop_first();
                 }
             }
+            if self
+                .latest_block_height
+                .map_or(true, |latest| block_height > latest)
+            {
+                self.latest_block_height = Some(block_height);
+                self.latest_available_bundle = Some(bundle);
+            }
+            self.collect_garbage();
         }
         Ok(())
     }
 }
 
+impl ParticipationDataSource<StateVerKey> for StateRelayServerState {
+    async fn get_participation(&self) -> ParticipationQueryData<StateVerKey> {
+        self.participation()
+    }
+}
+
 /// configurability options for the web server
 #[derive(Args, Default)]
 pub struct Options {
@@ -205,6 +476,18 @@ where
     api.get("getlateststate", |_req, state| {
         async move { state.get_latest_signature_bundle() }.boxed()
     })?
+    .get("getstate", |req, state| {
+        async move {
+            let height = req
+                .integer_param("height")
+                .map_err(Error::from_request_error)?;
+            state.get_signature_bundle(height)
+        }
+        .boxed()
+    })?
+    .metrics("metrics", |_req, state| {
+        async move { Ok(Cow::Owned(state.get_metrics())) }.boxed()
+    })?
     .post("poststatesignature", |req, state| {
         async move {
             let StateSignatureRequestBody {
@@ -222,9 +505,23 @@ where
     Ok(api)
 }
 
+/// Reload the stake table from `source` periodically, so that signatures are verified against the
+/// stake table of the current epoch.
+async fn refresh_stake_table(state: State, source: StakeTableSource) {
+    loop {
+        match source.load().await {
+            Ok(stake_table) => state.write().await.update_stake_table(stake_table),
+            Err(err) => tracing::warn!("Cannot load the stake table: {err:#}"),
+        }
+        sleep(source.refresh_interval).await;
+    }
+}
+
 pub async fn run_relay_server<Ver: StaticVersionType + 'static>(
     shutdown_listener: Option<OneShotReceiver<()>>,
     threshold: u64,
+    blocks_per_epoch: Option<u64>,
+    stake_table_source: Option<StakeTableSource>,
     url: Url,
     bind_version: Ver,
 ) -> std::io::Result<()> {
@@ -232,16 +529,164 @@ pub async fn run_relay_server<Ver: StaticVersionType + 'static>(
 
     let api = define_api(&options, bind_version).unwrap();
 
-    // We don't have a stake table yet, putting some temporary value here.
-    // Related issue: [https://github.com/EspressoSystems/espresso-sequencer/issues/1022]
+    // Without a stake table source, every signature has weight one and `threshold` is the number
+    // of signatures needed.
     let threshold = U256::from(threshold);
-    let state =
-        State::new(StateRelayServerState::new(threshold).with_shutdown_signal(shutdown_listener));
+    let mut relay_state = StateRelayServerState::new(threshold)
+        .with_shutdown_signal(shutdown_listener)
+        .with_blocks_per_epoch(blocks_per_epoch);
+    if stake_table_source.is_some() {
+        relay_state = relay_state.with_stake_table();
+    }
+    let state = Arc::new(RwLock::new(relay_state));
+    if let Some(source) = stake_table_source {
+        spawn(refresh_stake_table(state.clone(), source));
+    }
     let mut app = App::<State, Error, Ver>::with_state(state);
 
     app.register_module("api", api).unwrap();
+    app.register_module(
+        "participation",
+        participation::define_api::<_, StateVerKey, Error, Ver>(bind_version).unwrap(),
+    )
+    .unwrap();
 
     let app_future = app.serve(url, bind_version);
 
     app_future.await
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::StateKeyPair;
+    use hotshot_types::{
+        signature_key::BLSPubKey, traits::signature_key::SignatureKey, PeerConfig,
+    };
+    use tide_disco::metrics::Metrics as _;
+
+    fn state(block_height: u64) -> LightClientState {
+        LightClientState {
+            view_number: block_height as usize,
+            block_height: block_height as usize,
+            block_comm_root: Default::default(),
+            fee_ledger_comm: Default::default(),
+            stake_table_comm: Default::default(),
+        }
+    }
+
+    fn sign(
+        relay: &mut StateRelayServerState,
+        key: &StateKeyPair,
+        block_height: u64,
+    ) -> Result<(), Error> {
+        sign_state(relay, key, state(block_height))
+    }
+
+    fn sign_state(
+        relay: &mut StateRelayServerState,
+        key: &StateKeyPair,
+        state: LightClientState,
+    ) -> Result<(), Error> {
+        let msg: [FieldType; 7] = (&state).into();
+        let signature =
+            StateSignatureScheme::sign(&(), key.sign_key_ref(), msg, &mut rand::thread_rng())
+                .unwrap();
+        relay.post_signature(key.ver_key(), state, signature)
+    }
+
+    #[test]
+    fn test_epoch_ending_bundles() {
+        let keys = [StateKeyPair::generate(), StateKeyPair::generate()];
+        let mut relay = StateRelayServerState::new(U256::from(2)).with_blocks_per_epoch(Some(10));
+
+        // The first node signs past the end of the epoch before the second node signs its end.
+        sign(&mut relay, &keys[0], 10).unwrap();
+        sign(&mut relay, &keys[0], 11).unwrap();
+        sign(&mut relay, &keys[1], 11).unwrap();
+        assert_eq!(
+            relay.get_latest_signature_bundle().unwrap().state,
+            state(11)
+        );
+        relay.get_signature_bundle(10).unwrap_err();
+
+        // Signatures for the end of the epoch are still collected.
+        sign(&mut relay, &keys[1], 10).unwrap();
+        assert_eq!(relay.get_signature_bundle(10).unwrap().state, state(10));
+        assert_eq!(relay.get_signature_bundle(11).unwrap().state, state(11));
+        assert_eq!(
+            relay.get_latest_signature_bundle().unwrap().state,
+            state(11)
+        );
+
+        // Other old heights are not.
+        sign(&mut relay, &keys[0], 9).unwrap();
+        sign(&mut relay, &keys[1], 9).unwrap();
+        relay.get_signature_bundle(9).unwrap_err();
+        assert!(relay.bundles.is_empty());
+        assert!(relay.queue.is_empty());
+    }
+
+    #[test]
+    fn test_stake_table_verification() {
+        let keys = [
+            StateKeyPair::generate(),
+            StateKeyPair::generate(),
+            StateKeyPair::generate(),
+        ];
+        let peers = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| PeerConfig {
+                stake_table_entry: BLSPubKey::generated_from_seed_indexed([0; 32], i as u64)
+                    .0
+                    .get_stake_table_entry(1),
+                state_ver_key: key.ver_key(),
+            })
+            .collect::<Vec<_>>();
+        let stake_table = StakeTableSnapshot::new(&peers[..2], 10).unwrap();
+        assert_eq!(stake_table.total_stake(), U256::from(2));
+        assert_eq!(stake_table.threshold, U256::from(1));
+        let signed = LightClientState {
+            stake_table_comm: stake_table.comm,
+            ..state(1)
+        };
+
+        // Signatures are rejected until the stake table is loaded.
+        let mut relay = StateRelayServerState::new(U256::from(100)).with_stake_table();
+        sign_state(&mut relay, &keys[0], signed.clone()).unwrap_err();
+        relay.update_stake_table(stake_table);
+
+        // States committing to another stake table are rejected.
+        sign(&mut relay, &keys[0], 1).unwrap_err();
+        // So are keys outside the stake table.
+        sign_state(&mut relay, &keys[2], signed.clone()).unwrap_err();
+        relay.get_latest_signature_bundle().unwrap_err();
+
+        // The threshold comes from the stake table, not the fixed threshold.
+        sign_state(&mut relay, &keys[0], signed.clone()).unwrap();
+        assert_eq!(relay.get_latest_signature_bundle().unwrap().state, signed);
+
+        let metrics = relay.get_metrics().export().unwrap();
+        assert!(
+            metrics.contains("relay_latest_available_height 1"),
+            "{metrics}"
+        );
+        assert!(
+            metrics.contains(&format!(
+                "relay_validator_last_signed_height{{key=\"{}\"}} 1",
+                keys[0].ver_key()
+            )),
+            "{metrics}"
+        );
+
+        // A late signature still counts as participation.
+        sign_state(&mut relay, &keys[1], signed.clone()).unwrap();
+        let participation = relay.participation();
+        assert_eq!(participation.window, 1);
+        assert!(participation
+            .keys
+            .iter()
+            .all(|p| p.last_height == Some(1) && p.missed == 0));
+    }
+}
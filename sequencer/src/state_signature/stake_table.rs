@@ -0,0 +1,194 @@
+//! Stake tables against which the relay server verifies light client state signatures.
+
+use super::{field_to_u256, static_stake_table, StakeTableCommitmentType};
+use crate::persistence::NetworkConfig;
+use anyhow::{anyhow, ensure, Context};
+use async_std::task::sleep;
+use contract_bindings::light_client::{LightClient, LightClientState as ContractState};
+use ethers::{
+    providers::{Http, Provider},
+    types::{Address, U256},
+};
+use hotshot_orchestrator::OrchestratorVersion;
+use hotshot_types::{
+    light_client::StateVerKey,
+    signature_key::BLSPubKey,
+    traits::{
+        signature_key::StakeTableEntryType,
+        stake_table::{SnapshotVersion, StakeTableScheme as _},
+    },
+    PeerConfig,
+};
+use serde::Deserialize;
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+use surf_disco::Client;
+use tide_disco::error::ServerError;
+use url::Url;
+
+/// A stake table, in the form needed to verify and weigh state signatures.
+#[derive(Clone, Debug)]
+pub struct StakeTableSnapshot {
+    /// The epoch of the light client contract in which this stake table is used, if known.
+    pub epoch: Option<u64>,
+    /// The commitment to the stake table, which signed light client states must carry.
+    pub comm: StakeTableCommitmentType,
+    /// The stake of each state key.
+    pub stakes: HashMap<StateVerKey, U256>,
+    /// The minimum total stake of the signers of a light client state.
+    pub threshold: U256,
+}
+
+impl StakeTableSnapshot {
+    /// Build the stake table of `peers`, in the same way the sequencer does.
+    pub fn new(peers: &[PeerConfig<BLSPubKey>], capacity: usize) -> anyhow::Result<Self> {
+        let st = static_stake_table(peers, capacity)?;
+        let comm = st
+            .commitment(SnapshotVersion::LastEpochStart)
+            .map_err(|err| anyhow!("computing stake table commitment: {err}"))?;
+        let total_stake = st
+            .total_stake(SnapshotVersion::LastEpochStart)
+            .map_err(|err| anyhow!("computing total stake: {err}"))?;
+        let stakes = peers
+            .iter()
+            .map(|peer| {
+                (
+                    peer.state_ver_key.clone(),
+                    peer.stake_table_entry.get_stake(),
+                )
+            })
+            .collect();
+        Ok(Self {
+            epoch: None,
+            comm,
+            stakes,
+            // Same threshold as the light client genesis state.
+            threshold: total_stake * 2 / 3,
+        })
+    }
+
+    /// The total stake of the stake table.
+    pub fn total_stake(&self) -> U256 {
+        self.stakes
+            .values()
+            .fold(U256::zero(), |sum, stake| sum + stake)
+    }
+}
+
+/// Where the relay server loads the entries of its stake table from.
+#[derive(Clone, Debug)]
+pub enum StakeTableEntries {
+    /// The network config of a HotShot orchestrator.
+    Orchestrator(Url),
+    /// A TOML or JSON file with a `known_nodes_with_stake` list, in the same format as the
+    /// orchestrator's network config.
+    ///
+    /// The file is read again on every refresh, so the stake table can be updated in place.
+    File(PathBuf),
+}
+
+#[derive(Deserialize)]
+struct StakeTableFile {
+    known_nodes_with_stake: Vec<PeerConfig<BLSPubKey>>,
+}
+
+impl StakeTableEntries {
+    async fn load(&self) -> anyhow::Result<Vec<PeerConfig<BLSPubKey>>> {
+        match self {
+            Self::Orchestrator(url) => fetch_orchestrator_peers(url).await,
+            Self::File(path) => {
+                let contents = async_std::fs::read_to_string(path)
+                    .await
+                    .with_context(|| format!("reading stake table file {path:?}"))?;
+                let file: StakeTableFile = if path.extension().is_some_and(|ext| ext == "json") {
+                    serde_json::from_str(&contents)?
+                } else {
+                    toml::from_str(&contents)?
+                };
+                Ok(file.known_nodes_with_stake)
+            }
+        }
+    }
+}
+
+/// The light client contract on L1.
+#[derive(Clone, Debug)]
+pub struct LightClientContract {
+    pub l1_provider: Url,
+    pub address: Address,
+}
+
+/// Where the relay server loads its stake table from.
+#[derive(Clone, Debug)]
+pub struct StakeTableSource {
+    /// Where to load the entries of the stake table from.
+    pub entries: StakeTableEntries,
+    /// The light client contract whose voting stake table the entries must match, if any.
+    ///
+    /// The contract only stores a commitment to its stake table, so the entries themselves still
+    /// come from `entries`. The contract determines the epoch and the signature threshold.
+    pub contract: Option<LightClientContract>,
+    /// Capacity of the stake table.
+    pub capacity: usize,
+    /// How often to load the stake table again, to pick up changes.
+    pub refresh_interval: Duration,
+}
+
+impl StakeTableSource {
+    /// Load the current stake table.
+    pub async fn load(&self) -> anyhow::Result<StakeTableSnapshot> {
+        let peers = self.entries.load().await?;
+        let mut snapshot = StakeTableSnapshot::new(&peers, self.capacity)?;
+        let Some(contract) = &self.contract else {
+            return Ok(snapshot);
+        };
+
+        let provider = Provider::<Http>::try_from(contract.l1_provider.to_string())?;
+        let contract = LightClient::new(contract.address, Arc::new(provider));
+        let epoch = contract.current_epoch().call().await?;
+        let voting_comm = contract.voting_stake_table_commitment().call().await?;
+        let local_comm = contract
+            .compute_stake_table_comm(contract_state(&snapshot.comm))
+            .call()
+            .await?;
+        ensure!(
+            local_comm == voting_comm,
+            "stake table does not match the voting stake table of the light client contract in \
+             epoch {epoch}"
+        );
+        snapshot.epoch = Some(epoch);
+        snapshot.threshold = contract.voting_threshold().call().await?;
+        Ok(snapshot)
+    }
+}
+
+async fn fetch_orchestrator_peers(url: &Url) -> anyhow::Result<Vec<PeerConfig<BLSPubKey>>> {
+    let client = Client::<ServerError, OrchestratorVersion>::new(url.clone());
+    loop {
+        let ready = client
+            .get::<bool>("api/peer_pub_ready")
+            .send()
+            .await
+            .map_err(|err| anyhow!("orchestrator error: {err}"))?;
+        if ready {
+            let config = client
+                .get::<NetworkConfig>("api/get_config_after_peer_collected")
+                .send()
+                .await
+                .map_err(|err| anyhow!("orchestrator error: {err}"))?;
+            return Ok(config.config.known_nodes_with_stake);
+        }
+        tracing::info!("Peers' keys are not ready, retrying.");
+        sleep(Duration::from_secs(2)).await;
+    }
+}
+
+/// A light client state which commits to the stake table `comm`, for computing its commitment on
+/// the contract.
+fn contract_state(comm: &StakeTableCommitmentType) -> ContractState {
+    ContractState {
+        stake_table_bls_key_comm: field_to_u256(comm.0),
+        stake_table_schnorr_key_comm: field_to_u256(comm.1),
+        stake_table_amount_comm: field_to_u256(comm.2),
+        ..Default::default()
+    }
+}
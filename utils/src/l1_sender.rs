@@ -0,0 +1,538 @@
+//! Sending transactions to L1.
+//!
+//! [`L1Sender`] tracks the nonce of its account, prices transactions with EIP-1559 fee estimates
+//! up to a configurable maximum, and replaces transactions which are not mined in time with copies
+//! paying higher fees.
+
+use anyhow::{anyhow, bail, Context};
+use async_std::{sync::Mutex, task::sleep};
+use clap::Args;
+use cld::ClDuration;
+use ethers::{
+    abi::Detokenize,
+    contract::{builders::ContractCall, ContractRevert},
+    providers::Middleware,
+    types::{
+        transaction::eip2718::TypedTransaction, BlockNumber, Eip1559TransactionRequest,
+        TransactionReceipt, H256, U256,
+    },
+};
+use hotshot_types::traits::metrics::{Counter, Gauge, Metrics, NoMetrics};
+use std::{fmt::Debug, str::FromStr, sync::Arc, time::Duration};
+
+/// Options for sending transactions to L1.
+#[derive(Args, Clone, Debug)]
+pub struct L1SenderOptions {
+    /// Maximum fee per gas for L1 transactions, in gwei.
+    ///
+    /// No transaction is sent while the L1 base fee exceeds this, and fees of replacement
+    /// transactions are not raised beyond it.
+    #[clap(long = "l1-max-fee-gwei", env = "ESPRESSO_L1_MAX_FEE_GWEI")]
+    pub max_fee_gwei: Option<u64>,
+
+    /// How long to wait for a transaction to be mined before replacing it with higher fees.
+    #[clap(
+        long = "l1-replacement-timeout",
+        env = "ESPRESSO_L1_REPLACEMENT_TIMEOUT",
+        value_parser = parse_duration,
+        default_value = "1m"
+    )]
+    pub replacement_timeout: Duration,
+
+    /// Percentage by which the fees of a replacement transaction exceed those it replaces.
+    ///
+    /// Most L1 clients refuse replacements which do not raise fees by at least 10%.
+    #[clap(
+        long = "l1-fee-bump-percent",
+        env = "ESPRESSO_L1_FEE_BUMP_PERCENT",
+        default_value = "20"
+    )]
+    pub fee_bump_percent: u64,
+
+    /// Maximum number of times a transaction is replaced before giving up on it.
+    #[clap(
+        long = "l1-max-replacements",
+        env = "ESPRESSO_L1_MAX_REPLACEMENTS",
+        default_value = "5"
+    )]
+    pub max_replacements: usize,
+
+    /// Interval between checks for the receipt of a pending transaction.
+    #[clap(
+        long = "l1-receipt-poll-interval",
+        env = "ESPRESSO_L1_RECEIPT_POLL_INTERVAL",
+        value_parser = parse_duration,
+        default_value = "1s"
+    )]
+    pub poll_interval: Duration,
+}
+
+impl Default for L1SenderOptions {
+    fn default() -> Self {
+        Self {
+            max_fee_gwei: None,
+            replacement_timeout: Duration::from_secs(60),
+            fee_bump_percent: 20,
+            max_replacements: 5,
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl L1SenderOptions {
+    fn max_fee(&self) -> Option<U256> {
+        self.max_fee_gwei
+            .map(|gwei| U256::from(gwei) * U256::exp10(9))
+    }
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    ClDuration::from_str(s)
+        .map(Duration::from)
+        .map_err(|err| err.to_string())
+}
+
+/// Metrics of an [`L1Sender`].
+#[derive(Debug)]
+pub struct L1SenderMetrics {
+    sent: Box<dyn Counter>,
+    replaced: Box<dyn Counter>,
+    confirmed: Box<dyn Counter>,
+    reverted: Box<dyn Counter>,
+    dropped: Box<dyn Counter>,
+    gas_used: Box<dyn Counter>,
+    max_fee_per_gas: Box<dyn Gauge>,
+}
+
+impl L1SenderMetrics {
+    pub fn new(metrics: &dyn Metrics) -> Self {
+        Self {
+            sent: metrics.create_counter("l1_transactions_sent".into(), None),
+            replaced: metrics.create_counter("l1_transactions_replaced".into(), None),
+            confirmed: metrics.create_counter("l1_transactions_confirmed".into(), None),
+            reverted: metrics.create_counter("l1_transactions_reverted".into(), None),
+            dropped: metrics.create_counter("l1_transactions_dropped".into(), None),
+            gas_used: metrics.create_counter("l1_gas_used".into(), Some("gas".into())),
+            max_fee_per_gas: metrics.create_gauge("l1_max_fee_per_gas".into(), Some("gwei".into())),
+        }
+    }
+}
+
+impl Default for L1SenderMetrics {
+    fn default() -> Self {
+        Self::new(&NoMetrics)
+    }
+}
+
+/// Fees of an EIP-1559 transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Fees {
+    max_fee_per_gas: U256,
+    max_priority_fee_per_gas: U256,
+}
+
+impl Fees {
+    /// Fees raised by `percent`, but not beyond `max`.
+    fn bump(self, percent: u64, max: Option<U256>) -> Self {
+        let bump = |fee: U256| fee * (100 + percent) / 100 + 1;
+        let mut fees = Self {
+            max_fee_per_gas: bump(self.max_fee_per_gas),
+            max_priority_fee_per_gas: bump(self.max_priority_fee_per_gas),
+        };
+        if let Some(max) = max {
+            fees.max_fee_per_gas = fees.max_fee_per_gas.min(max);
+            fees.max_priority_fee_per_gas = fees.max_priority_fee_per_gas.min(max);
+        }
+        fees
+    }
+
+    fn apply(self, tx: &mut Eip1559TransactionRequest) {
+        tx.max_fee_per_gas = Some(self.max_fee_per_gas);
+        tx.max_priority_fee_per_gas = Some(self.max_priority_fee_per_gas);
+    }
+}
+
+/// Sends transactions from a single L1 account, one at a time.
+#[derive(Debug)]
+pub struct L1Sender<M> {
+    client: Arc<M>,
+    opt: L1SenderOptions,
+    metrics: Arc<L1SenderMetrics>,
+    /// The nonce of the next transaction, once known.
+    ///
+    /// This is loaded from the latest L1 block rather than the pending one, so that transactions
+    /// left pending by an earlier sender are replaced rather than queued behind.
+    nonce: Mutex<Option<U256>>,
+}
+
+impl<M: Middleware + 'static> L1Sender<M> {
+    pub fn new(client: Arc<M>, opt: L1SenderOptions, metrics: Arc<L1SenderMetrics>) -> Self {
+        Self {
+            client,
+            opt,
+            metrics,
+            nonce: Default::default(),
+        }
+    }
+
+    /// The client through which transactions are sent.
+    pub fn client(&self) -> &Arc<M> {
+        &self.client
+    }
+
+    /// Send a contract call and wait for it to be mined.
+    ///
+    /// Returns the receipt and the L1 block in which the transaction was included. Calls which
+    /// would revert fail before anything is sent, with the revert decoded as `E`.
+    pub async fn send_call<T: Detokenize, E: ContractRevert + Debug>(
+        &self,
+        call: &ContractCall<M, T>,
+    ) -> anyhow::Result<(TransactionReceipt, u64)> {
+        let gas = match call.estimate_gas().await {
+            Ok(gas) => gas,
+            Err(err) => match err.decode_contract_revert::<E>() {
+                Some(revert) => {
+                    tracing::error!("contract revert: {revert:?}");
+                    bail!("error sending transaction: {revert:?}");
+                }
+                None => bail!("error estimating gas: {err}"),
+            },
+        };
+        let mut tx = call.tx.clone();
+        tx.set_gas(gas);
+        self.send(tx).await
+    }
+
+    /// Send a transaction and wait for it to be mined.
+    ///
+    /// Returns the receipt and the L1 block in which the transaction was included.
+    pub async fn send(&self, tx: TypedTransaction) -> anyhow::Result<(TransactionReceipt, u64)> {
+        // Hold the nonce until the transaction is mined, so transactions are sent one at a time.
+        let mut next_nonce = self.nonce.lock().await;
+        let nonce = match *next_nonce {
+            Some(nonce) => nonce,
+            None => self.latest_nonce(&tx).await?,
+        };
+
+        let res = self.send_with_nonce(tx, nonce).await;
+        *next_nonce = match &res {
+            // A mined transaction uses up its nonce, even if it reverted.
+            Ok(_) | Err(SendError::Reverted(_)) => Some(nonce + 1),
+            // Otherwise the transaction may still be pending, or may never have been sent. Load the
+            // nonce again, so that the next transaction replaces it if it is stuck.
+            Err(_) => None,
+        };
+        res.map_err(anyhow::Error::from)
+    }
+
+    async fn latest_nonce(&self, tx: &TypedTransaction) -> anyhow::Result<U256> {
+        let from = tx
+            .from()
+            .copied()
+            .or(self.client.default_sender())
+            .context("no sender address")?;
+        self.client
+            .get_transaction_count(from, Some(BlockNumber::Latest.into()))
+            .await
+            .map_err(|err| anyhow!("error getting nonce: {err}"))
+    }
+
+    async fn send_with_nonce(
+        &self,
+        tx: TypedTransaction,
+        nonce: U256,
+    ) -> Result<(TransactionReceipt, u64), SendError> {
+        let mut tx = match tx {
+            TypedTransaction::Eip1559(tx) => tx,
+            TypedTransaction::Legacy(tx) => tx.into(),
+            TypedTransaction::Eip2930(tx) => tx.tx.into(),
+        };
+        tx.nonce = Some(nonce);
+        let mut fees = self.estimate_fees().await?;
+
+        // Hashes of every version of the transaction sent so far. Any of them may be mined.
+        let mut hashes: Vec<H256> = vec![];
+        let mut replacements = 0;
+        loop {
+            fees.apply(&mut tx);
+            match self.client.send_transaction(tx.clone(), None).await {
+                Ok(pending) => {
+                    let hash = pending.tx_hash();
+                    tracing::info!(
+                        %nonce,
+                        ?fees,
+                        "sent L1 transaction {hash:x} (replacement {replacements})"
+                    );
+                    self.metrics.sent.add(1);
+                    if replacements > 0 {
+                        self.metrics.replaced.add(1);
+                    }
+                    self.metrics
+                        .max_fee_per_gas
+                        .set((fees.max_fee_per_gas / U256::exp10(9)).low_u64() as usize);
+                    hashes.push(hash);
+                }
+                Err(err) if hashes.is_empty() && !is_underpriced(&err.to_string()) => {
+                    return Err(SendError::Other(anyhow!(
+                        "error sending transaction: {err}"
+                    )));
+                }
+                Err(err) => {
+                    // A replacement may be rejected because its fees are too low, or because an
+                    // earlier version was mined in the meantime. Keep waiting for the earlier
+                    // versions either way.
+                    tracing::warn!(%nonce, "L1 transaction replacement not accepted: {err}");
+                }
+            }
+
+            if let Some((receipt, block)) = self.wait_for_receipt(&hashes).await? {
+                if receipt.status != Some(1.into()) {
+                    self.metrics.reverted.add(1);
+                    return Err(SendError::Reverted(receipt.transaction_hash));
+                }
+                self.metrics.confirmed.add(1);
+                if let Some(gas_used) = receipt.gas_used {
+                    self.metrics.gas_used.add(gas_used.low_u64() as usize);
+                }
+                return Ok((receipt, block));
+            }
+
+            if replacements >= self.opt.max_replacements {
+                self.metrics.dropped.add(1);
+                return Err(SendError::Other(anyhow!(
+                    "transaction with nonce {nonce} not mined after {replacements} replacements"
+                )));
+            }
+            let bumped = fees.bump(self.opt.fee_bump_percent, self.opt.max_fee());
+            if bumped.max_fee_per_gas <= fees.max_fee_per_gas {
+                tracing::warn!(%nonce, "L1 transaction is stuck at the maximum fee");
+            }
+            fees = bumped;
+            replacements += 1;
+        }
+    }
+
+    /// Fees for a new transaction, capped at the configured maximum.
+    async fn estimate_fees(&self) -> Result<Fees, SendError> {
+        let (max_fee_per_gas, max_priority_fee_per_gas) = self
+            .client
+            .estimate_eip1559_fees(None)
+            .await
+            .map_err(|err| SendError::Other(anyhow!("error estimating fees: {err}")))?;
+        let mut fees = Fees {
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+        };
+        let Some(max) = self.opt.max_fee() else {
+            return Ok(fees);
+        };
+
+        let base_fee = self
+            .client
+            .get_block(BlockNumber::Latest)
+            .await
+            .map_err(|err| SendError::Other(anyhow!("error getting latest block: {err}")))?
+            .and_then(|block| block.base_fee_per_gas)
+            .unwrap_or_default();
+        if base_fee > max {
+            return Err(SendError::Other(anyhow!(
+                "L1 base fee {base_fee} exceeds the maximum fee {max}"
+            )));
+        }
+        fees.max_fee_per_gas = fees.max_fee_per_gas.min(max);
+        fees.max_priority_fee_per_gas = fees.max_priority_fee_per_gas.min(max);
+        Ok(fees)
+    }
+
+    /// Wait up to the replacement timeout for any of `hashes` to be mined.
+    async fn wait_for_receipt(
+        &self,
+        hashes: &[H256],
+    ) -> Result<Option<(TransactionReceipt, u64)>, SendError> {
+        let polls = (self.opt.replacement_timeout.as_millis()
+            / self.opt.poll_interval.as_millis().max(1))
+        .max(1);
+        for _ in 0..polls {
+            sleep(self.opt.poll_interval).await;
+            for hash in hashes {
+                match self.client.get_transaction_receipt(*hash).await {
+                    Ok(Some(receipt)) => {
+                        // If a transaction is mined and we get a receipt for it, the block number
+                        // should _always_ be set.
+                        let block = receipt
+                            .block_number
+                            .context("transaction mined but block number not set")?
+                            .as_u64();
+                        return Ok(Some((receipt, block)));
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        tracing::warn!("error getting receipt of L1 transaction {hash:x}: {err}")
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn is_underpriced(err: &str) -> bool {
+    let err = err.to_lowercase();
+    err.contains("underpriced") || err.contains("already known") || err.contains("fee too low")
+}
+
+#[derive(Debug)]
+enum SendError {
+    Reverted(H256),
+    Other(anyhow::Error),
+}
+
+impl From<anyhow::Error> for SendError {
+    fn from(err: anyhow::Error) -> Self {
+        Self::Other(err)
+    }
+}
+
+impl From<SendError> for anyhow::Error {
+    fn from(err: SendError) -> Self {
+        match err {
+            SendError::Reverted(hash) => anyhow!("contract call {hash:x}: transaction reverted"),
+            SendError::Other(err) => err,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::AnvilOptions;
+    use ethers::{
+        prelude::SignerMiddleware,
+        providers::{Http, Provider},
+        signers::{coins_bip39::English, LocalWallet, MnemonicBuilder, Signer as _},
+        types::{Address, TransactionRequest},
+    };
+
+    const TEST_MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+    type Client = SignerMiddleware<Provider<Http>, LocalWallet>;
+
+    async fn client(provider: Provider<Http>) -> Arc<Client> {
+        let chain_id = provider.get_chainid().await.unwrap().as_u64();
+        let wallet = MnemonicBuilder::<English>::default()
+            .phrase(TEST_MNEMONIC)
+            .build()
+            .unwrap()
+            .with_chain_id(chain_id);
+        Arc::new(SignerMiddleware::new(provider, wallet))
+    }
+
+    fn transfer(to: Address, value: u64) -> TypedTransaction {
+        Eip1559TransactionRequest::new()
+            .to(to)
+            .value(value)
+            .gas(21000)
+            .into()
+    }
+
+    fn options() -> L1SenderOptions {
+        L1SenderOptions {
+            replacement_timeout: Duration::from_millis(500),
+            poll_interval: Duration::from_millis(100),
+            ..Default::default()
+        }
+    }
+
+    #[async_std::test]
+    async fn test_nonce_tracking() {
+        let anvil = AnvilOptions::default().spawn().await;
+        let client = client(anvil.provider()).await;
+        let sender = Arc::new(L1Sender::new(client.clone(), options(), Default::default()));
+
+        let to = Address::random();
+        let sends = (1..=3).map(|value| {
+            let sender = sender.clone();
+            async move { sender.send(transfer(to, value)).await.unwrap() }
+        });
+        let receipts = futures::future::join_all(sends).await;
+        assert_eq!(receipts.len(), 3);
+
+        let nonce = client
+            .get_transaction_count(client.address(), None)
+            .await
+            .unwrap();
+        assert_eq!(nonce, 3.into());
+        assert_eq!(client.get_balance(to, None).await.unwrap(), 6.into());
+    }
+
+    #[async_std::test]
+    async fn test_replace_stuck_transaction() {
+        let anvil = AnvilOptions::default().spawn().await;
+        let provider = anvil.provider();
+        let client = client(provider.clone()).await;
+
+        // Leave a transaction pending, as if an earlier run had sent it before crashing.
+        provider
+            .request::<_, serde_json::Value>("evm_setAutomine", [false])
+            .await
+            .unwrap();
+        let stuck = Address::random();
+        let stuck_tx = TransactionRequest::new().to(stuck).value(1).gas(21000);
+        client.send_transaction(stuck_tx, None).await.unwrap();
+
+        // The sender replaces it, since it reuses the nonce of the latest block.
+        let sender = L1Sender::new(client.clone(), options(), Default::default());
+        let to = Address::random();
+        let send = async_std::task::spawn(async move { sender.send(transfer(to, 1)).await });
+        sleep(Duration::from_secs(2)).await;
+        provider
+            .request::<_, serde_json::Value>("evm_mine", None::<()>)
+            .await
+            .unwrap();
+        send.await.unwrap();
+
+        assert_eq!(client.get_balance(to, None).await.unwrap(), 1.into());
+        assert_eq!(client.get_balance(stuck, None).await.unwrap(), 0.into());
+    }
+
+    #[async_std::test]
+    async fn test_max_fee() {
+        let anvil = AnvilOptions::default().spawn().await;
+        let client = client(anvil.provider()).await;
+
+        // The base fee of a fresh Anvil chain is 1 gwei, so a zero maximum prevents sending.
+        let sender = L1Sender::new(
+            client.clone(),
+            L1SenderOptions {
+                max_fee_gwei: Some(0),
+                ..options()
+            },
+            Default::default(),
+        );
+        sender
+            .send(transfer(Address::random(), 1))
+            .await
+            .unwrap_err();
+        let nonce = client
+            .get_transaction_count(client.address(), None)
+            .await
+            .unwrap();
+        assert_eq!(nonce, 0.into());
+    }
+
+    #[test]
+    fn test_fee_bump() {
+        let fees = Fees {
+            max_fee_per_gas: 100.into(),
+            max_priority_fee_per_gas: 10.into(),
+        };
+        assert_eq!(
+            fees.bump(20, None),
+            Fees {
+                max_fee_per_gas: 121.into(),
+                max_priority_fee_per_gas: 13.into(),
+            }
+        );
+        assert_eq!(fees.bump(20, Some(110.into())).max_fee_per_gas, 110.into());
+    }
+}